@@ -0,0 +1,69 @@
+/*
+ * lazyserial decoder plugin ABI.
+ *
+ * Build a shared library exporting the functions below and drop it in
+ * the plugins directory (~/.config/lazyserial/plugins on Linux); it shows
+ * up in the packet list's decoder cycle under lazyserial_plugin_name().
+ *
+ *   cc -shared -fPIC -o ~/.config/lazyserial/plugins/mine.so mine.c
+ *
+ * Strings returned by the lazyserial_plugin_* functions must stay valid
+ * for as long as the library is loaded. A decoder instance is only ever
+ * called from one thread at a time, but not always the same one.
+ */
+#ifndef LAZYSERIAL_PLUGIN_H
+#define LAZYSERIAL_PLUGIN_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#define LAZYSERIAL_PLUGIN_ABI 1
+
+/* Directions. */
+#define LAZYSERIAL_RX 0
+#define LAZYSERIAL_TX 1
+
+/* Checksum validity of a record. */
+#define LAZYSERIAL_VALID_NONE (-1) /* the protocol has no checksum */
+#define LAZYSERIAL_VALID_BAD 0
+#define LAZYSERIAL_VALID_OK 1
+
+/*
+ * Reports one decoded record. `fields` holds one value per column,
+ * separated by tabs; `details` is free text; `raw` is the record's bytes
+ * and may be NULL. Everything is copied before emit returns.
+ */
+typedef void (*lazyserial_emit)(void *ctx, uint8_t direction, const char *fields, int32_t valid,
+                                const char *details, const uint8_t *raw, size_t raw_len);
+
+/* Returns LAZYSERIAL_PLUGIN_ABI. */
+uint32_t lazyserial_plugin_abi(void);
+
+/* Name to select the decoder by, without spaces, e.g. "acme". */
+const char *lazyserial_plugin_name(void);
+
+/* Heading of the packet list, e.g. "ACME telemetry". */
+const char *lazyserial_plugin_title(void);
+
+/* Columns as name:width pairs separated by commas, e.g. "id:4,cmd:12". */
+const char *lazyserial_plugin_columns(void);
+
+/* A new decoder instance, one per time the decoder is turned on. */
+void *lazyserial_decoder_new(void);
+
+void lazyserial_decoder_free(void *decoder);
+
+/*
+ * Feeds bytes seen in `direction`; calls `emit` with `ctx` once for every
+ * record they complete.
+ */
+void lazyserial_decoder_push(void *decoder, uint8_t direction, const uint8_t *data, size_t len,
+                             lazyserial_emit emit, void *ctx);
+
+/*
+ * Optional. Called several times a second so records that end with
+ * silence rather than a delimiter can be emitted.
+ */
+void lazyserial_decoder_flush(void *decoder, lazyserial_emit emit, void *ctx);
+
+#endif
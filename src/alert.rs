@@ -0,0 +1,163 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use regex::Regex;
+
+use crate::config::AlertConfig;
+
+/// After firing, a rule stays quiet (no bell or notification) this long,
+/// so a burst of matching lines pings once.
+const QUIET: Duration = Duration::from_secs(10);
+/// Longest excerpt of the matching line put in a notification.
+const MAX_BODY: usize = 200;
+
+pub struct Rule {
+    pub name: String,
+    pub regex: Regex,
+    pub bell: bool,
+    pub notify: bool,
+    pub bookmark: bool,
+    pub enabled: bool,
+    fired: Option<Instant>,
+}
+
+/// What a received line set off, merged over the rules it matched.
+#[derive(Debug, Default)]
+pub struct Hit {
+    /// The first rule that matched.
+    pub name: String,
+    pub bell: bool,
+    pub notify: bool,
+    pub bookmark: bool,
+}
+
+/// Rules that ping the user when received lines match, for long runs
+/// nobody is watching.
+pub struct Alerts {
+    pub enabled: bool,
+    pub rules: Vec<Rule>,
+    /// Lines that matched any rule since startup.
+    pub count: usize,
+}
+
+impl Default for Alerts {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            rules: Vec::new(),
+            count: 0,
+        }
+    }
+}
+
+impl Alerts {
+    pub fn from_config(configs: &[AlertConfig]) -> Result<Self> {
+        let rules = configs
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                let name = c.name.clone().unwrap_or_else(|| format!("alert{}", i + 1));
+                let regex =
+                    Regex::new(&c.pattern).with_context(|| format!("alert {name}: bad regex"))?;
+                Ok(Rule {
+                    name,
+                    regex,
+                    bell: c.bell,
+                    notify: c.notify,
+                    bookmark: c.bookmark,
+                    enabled: c.enabled,
+                    fired: None,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            rules,
+            ..Self::default()
+        })
+    }
+
+    /// Checks a received line against the enabled rules. The bell and
+    /// notification of a rule still in its quiet period are left out.
+    pub fn check(&mut self, line: &str) -> Option<Hit> {
+        if !self.enabled {
+            return None;
+        }
+        let now = Instant::now();
+        let mut hit: Option<Hit> = None;
+        for rule in self.rules.iter_mut().filter(|r| r.enabled) {
+            if !rule.regex.is_match(line) {
+                continue;
+            }
+            let loud = match rule.fired {
+                Some(fired) => now.duration_since(fired) >= QUIET,
+                None => true,
+            };
+            if loud {
+                rule.fired = Some(now);
+            }
+            let hit = hit.get_or_insert_with(|| Hit {
+                name: rule.name.clone(),
+                ..Hit::default()
+            });
+            hit.bell |= loud && rule.bell;
+            hit.notify |= loud && rule.notify;
+            hit.bookmark |= rule.bookmark;
+        }
+        if hit.is_some() {
+            self.count += 1;
+        }
+        hit
+    }
+}
+
+pub fn ring_bell() -> Result<()> {
+    let mut out = std::io::stdout();
+    out.write_all(b"\x07").context("ring bell")?;
+    out.flush().context("ring bell")
+}
+
+/// Shows a desktop notification: `notify-send` or `osascript` when a
+/// desktop session is around, otherwise an OSC 9 sequence, which many
+/// terminals (iTerm2, WezTerm, Windows Terminal, kitty) turn into one and
+/// which also works over SSH.
+pub fn notify(title: &str, body: &str) -> Result<()> {
+    let body: String = body.chars().take(MAX_BODY).collect();
+    if let Some(mut command) = desktop_command(title, &body) {
+        // Not waited for here; a thread reaps it.
+        let child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        if let Ok(mut child) = child {
+            std::thread::spawn(move || child.wait());
+            return Ok(());
+        }
+    }
+    let clean = |s: &str| s.replace(|c: char| c.is_control() || c == ';', " ");
+    let mut out = std::io::stdout();
+    write!(out, "\x1b]9;{}: {}\x07", clean(title), clean(&body)).context("write OSC 9")?;
+    out.flush().context("write OSC 9")
+}
+
+fn desktop_command(title: &str, body: &str) -> Option<Command> {
+    if cfg!(target_os = "macos") {
+        let quote = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+        let mut command = Command::new("osascript");
+        command.arg("-e").arg(format!(
+            "display notification \"{}\" with title \"{}\"",
+            quote(body),
+            quote(title)
+        ));
+        return Some(command);
+    }
+    let desktop = ["DISPLAY", "WAYLAND_DISPLAY"].iter().any(|v| std::env::var_os(v).is_some());
+    if cfg!(unix) && desktop {
+        let mut command = Command::new("notify-send");
+        command.arg("--app-name=lazyserial").arg(title).arg(body);
+        return Some(command);
+    }
+    None
+}
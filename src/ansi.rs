@@ -0,0 +1,257 @@
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use serde::Deserialize;
+use unicode_width::UnicodeWidthChar;
+
+const ESC: char = '\x1b';
+const BEL: char = '\x07';
+/// Columns between tab stops.
+const TAB: usize = 8;
+/// SGR codes left out of the allowed set while sanitizing: blinking and
+/// concealed text, which would let a device hide what it sent.
+const UNSAFE_SGR: [u16; 3] = [5, 6, 8];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnsiMode {
+    /// Turn SGR sequences into colors and text attributes.
+    #[default]
+    Render,
+    /// Drop every escape sequence and show plain text.
+    Strip,
+}
+
+impl AnsiMode {
+    pub fn toggle(self) -> Self {
+        match self {
+            AnsiMode::Render => AnsiMode::Strip,
+            AnsiMode::Strip => AnsiMode::Render,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AnsiMode::Render => "render",
+            AnsiMode::Strip => "strip",
+        }
+    }
+}
+
+enum Piece<'a> {
+    Text(&'a str),
+    Sgr(&'a str),
+}
+
+/// Splits `line` into text runs and SGR parameter strings. Any other escape
+/// sequence (cursor movement, OSC titles, ...) is dropped.
+fn tokenize(line: &str) -> Vec<Piece<'_>> {
+    let mut pieces = Vec::new();
+    let mut chars = line.char_indices().peekable();
+    let mut text_start = 0;
+
+    while let Some((i, c)) = chars.next() {
+        if c != ESC {
+            continue;
+        }
+        if text_start < i {
+            pieces.push(Piece::Text(&line[text_start..i]));
+        }
+        match chars.next() {
+            Some((_, '[')) => {
+                let params_start = i + 2;
+                let mut end = line.len();
+                let mut final_byte = None;
+                for (j, c) in chars.by_ref() {
+                    if ('\x40'..='\x7e').contains(&c) {
+                        end = j;
+                        final_byte = Some(c);
+                        break;
+                    }
+                }
+                if final_byte == Some('m') {
+                    pieces.push(Piece::Sgr(&line[params_start..end]));
+                }
+                text_start = end + final_byte.map_or(0, |c| c.len_utf8());
+            }
+            Some((_, ']')) => {
+                // OSC runs until BEL or ST (ESC \).
+                text_start = line.len();
+                while let Some((j, c)) = chars.next() {
+                    if c == BEL {
+                        text_start = j + 1;
+                        break;
+                    }
+                    if c == ESC && chars.peek().map(|(_, c)| *c) == Some('\\') {
+                        chars.next();
+                        text_start = j + 2;
+                        break;
+                    }
+                }
+            }
+            Some((_, c)) if ('\x20'..='\x2f').contains(&c) => {
+                // nF sequences such as charset selection (ESC ( B).
+                text_start = line.len();
+                for (k, c) in chars.by_ref() {
+                    if ('\x30'..='\x7e').contains(&c) {
+                        text_start = k + 1;
+                        break;
+                    }
+                }
+            }
+            Some((j, c)) => text_start = j + c.len_utf8(),
+            None => text_start = line.len(),
+        }
+    }
+    if text_start < line.len() {
+        pieces.push(Piece::Text(&line[text_start..]));
+    }
+    pieces
+}
+
+/// Returns `line` with all escape sequences removed.
+pub fn strip(line: &str) -> String {
+    tokenize(line)
+        .into_iter()
+        .filter_map(|p| match p {
+            Piece::Text(t) => Some(t),
+            Piece::Sgr(_) => None,
+        })
+        .collect()
+}
+
+/// Renders `line` as styled spans. SGR state starts from `base` on every
+/// line; devices that rely on colors carrying across newlines lose them.
+/// With `safe`, blink and conceal attributes are ignored.
+pub fn to_line(line: &str, base: Style, safe: bool) -> Line<'static> {
+    let mut style = base;
+    let mut spans = Vec::new();
+    for piece in tokenize(line) {
+        match piece {
+            Piece::Text(t) => spans.push(Span::styled(t.to_string(), style)),
+            Piece::Sgr(params) => style = apply_sgr(style, base, params, safe),
+        }
+    }
+    Line::from(spans)
+}
+
+/// Makes device text safe to hand to the terminal: tabs become spaces up to
+/// the next stop and every other control character is dropped, so none can
+/// move the cursor, ring the bell or switch character sets.
+pub fn sanitize(line: Line<'static>) -> Line<'static> {
+    let mut col = 0;
+    let spans: Vec<Span> = line
+        .spans
+        .into_iter()
+        .map(|span| {
+            if !span.content.contains(char::is_control) {
+                col += span.width();
+                return span;
+            }
+            let mut text = String::with_capacity(span.content.len());
+            for c in span.content.chars() {
+                if c == '\t' {
+                    let n = TAB - col % TAB;
+                    text.push_str(&" ".repeat(n));
+                    col += n;
+                } else if !c.is_control() {
+                    text.push(c);
+                    col += c.width().unwrap_or(0);
+                }
+            }
+            Span::styled(text, span.style)
+        })
+        .collect();
+    Line::from(spans)
+}
+
+/// Shows `line` as it came, escape sequences included, with each control
+/// character as a symbol in `symbol` style: `␀`, `␍`, `␊`, `␛`, or `·` for
+/// the rest.
+pub fn to_control_pictures(line: &str, symbol: Style) -> Line<'static> {
+    let mut spans = Vec::new();
+    let mut text_start = 0;
+    for (i, c) in line.char_indices() {
+        let picture = match c {
+            '\0' => '\u{2400}',
+            '\r' => '\u{240d}',
+            '\n' => '\u{240a}',
+            ESC => '\u{241b}',
+            c if c.is_control() => '\u{b7}',
+            _ => continue,
+        };
+        if text_start < i {
+            spans.push(Span::raw(line[text_start..i].to_string()));
+        }
+        spans.push(Span::styled(picture.to_string(), symbol));
+        text_start = i + c.len_utf8();
+    }
+    if text_start < line.len() {
+        spans.push(Span::raw(line[text_start..].to_string()));
+    }
+    Line::from(spans)
+}
+
+fn apply_sgr(mut style: Style, base: Style, params: &str, safe: bool) -> Style {
+    let codes: Vec<u16> = if params.is_empty() {
+        vec![0]
+    } else {
+        params
+            .split([';', ':'])
+            .map(|p| p.parse().unwrap_or(0))
+            .collect()
+    };
+    let mut iter = codes.into_iter();
+    while let Some(code) = iter.next() {
+        if safe && UNSAFE_SGR.contains(&code) {
+            continue;
+        }
+        style = match code {
+            0 => base,
+            1 => style.add_modifier(Modifier::BOLD),
+            2 => style.add_modifier(Modifier::DIM),
+            3 => style.add_modifier(Modifier::ITALIC),
+            4 => style.add_modifier(Modifier::UNDERLINED),
+            5 | 6 => style.add_modifier(Modifier::SLOW_BLINK),
+            7 => style.add_modifier(Modifier::REVERSED),
+            8 => style.add_modifier(Modifier::HIDDEN),
+            9 => style.add_modifier(Modifier::CROSSED_OUT),
+            22 => style.remove_modifier(Modifier::BOLD | Modifier::DIM),
+            23 => style.remove_modifier(Modifier::ITALIC),
+            24 => style.remove_modifier(Modifier::UNDERLINED),
+            25 => style.remove_modifier(Modifier::SLOW_BLINK),
+            27 => style.remove_modifier(Modifier::REVERSED),
+            28 => style.remove_modifier(Modifier::HIDDEN),
+            29 => style.remove_modifier(Modifier::CROSSED_OUT),
+            30..=37 => style.fg(Color::Indexed((code - 30) as u8)),
+            38 => match extended_color(&mut iter) {
+                Some(c) => style.fg(c),
+                None => style,
+            },
+            39 => Style { fg: base.fg, ..style },
+            40..=47 => style.bg(Color::Indexed((code - 40) as u8)),
+            48 => match extended_color(&mut iter) {
+                Some(c) => style.bg(c),
+                None => style,
+            },
+            49 => Style { bg: base.bg, ..style },
+            90..=97 => style.fg(Color::Indexed((code - 90 + 8) as u8)),
+            100..=107 => style.bg(Color::Indexed((code - 100 + 8) as u8)),
+            _ => style,
+        };
+    }
+    style
+}
+
+/// Parses the `5;n` or `2;r;g;b` tail of a 38/48 sequence.
+fn extended_color(iter: &mut impl Iterator<Item = u16>) -> Option<Color> {
+    match iter.next()? {
+        5 => Some(Color::Indexed(iter.next()?.min(255) as u8)),
+        2 => {
+            let r = iter.next()?.min(255) as u8;
+            let g = iter.next()?.min(255) as u8;
+            let b = iter.next()?.min(255) as u8;
+            Some(Color::Rgb(r, g, b))
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,267 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Sender, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::ansi;
+
+/// Largest request body accepted.
+const MAX_BODY: usize = 1024 * 1024;
+/// How long a request waits for the main loop to answer it.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+/// Gives up on a client that stops sending mid-request.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+/// Gives up on an `/events` client that stops reading.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Lines waiting to be sent to one `/events` client; one that lets this
+/// many pile up is dropped.
+const EVENT_QUEUE: usize = 4096;
+
+/// What a client asked the running session to do.
+#[derive(Debug)]
+pub enum Command {
+    Ports,
+    Open(OpenRequest),
+    Close,
+    Send(SendRequest),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OpenRequest {
+    pub port: String,
+    pub baud: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SendRequest {
+    pub text: String,
+    /// Append the port's line ending (and checksum), as Enter does; off
+    /// sends `text` exactly.
+    #[serde(default = "default_true")]
+    pub line_ending: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// A request for the main loop, answered through `reply`.
+#[derive(Debug)]
+pub struct Request {
+    pub command: Command,
+    reply: Sender<Reply>,
+}
+
+impl Request {
+    pub fn ok(self, body: Value) {
+        let _ = self.reply.send(Reply { status: 200, body });
+    }
+
+    /// Turns the request down with `status` (409 when the port's state
+    /// doesn't allow it, 400 for bad input).
+    pub fn fail(self, status: u16, error: impl std::fmt::Display) {
+        let body = json!({ "error": error.to_string() });
+        let _ = self.reply.send(Reply { status, body });
+    }
+}
+
+#[derive(Debug)]
+struct Reply {
+    status: u16,
+    body: Value,
+}
+
+type Streams = Arc<Mutex<Vec<SyncSender<String>>>>;
+
+/// A local HTTP server for driving the TUI from scripts: `GET /ports`,
+/// `POST /open`, `POST /close`, `POST /send`, and `GET /events`, a
+/// server-sent event stream of the output lines.
+///
+/// Only local clients are served, and not web pages: a request from a
+/// browser (with an `Origin` header, or a `Host` that isn't loopback, as
+/// after DNS rebinding) is turned down, and bodies must be JSON so a page
+/// can't post one without a preflight, which is never answered.
+pub struct ApiHandle {
+    pub addr: SocketAddr,
+    streams: Streams,
+}
+
+impl ApiHandle {
+    /// Queues an output line for every `/events` client, dropping any that
+    /// have gone or fallen behind.
+    pub fn publish(&self, line: &str) {
+        let mut streams = self.streams.lock().unwrap();
+        if streams.is_empty() {
+            return;
+        }
+        let data = ansi::strip(line);
+        streams.retain(|tx| match tx.try_send(data.clone()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_) | TrySendError::Disconnected(_)) => false,
+        });
+    }
+}
+
+/// Starts serving on `addr` (e.g. `127.0.0.1:8080`); each request is handed
+/// to `submit` for the main loop to answer.
+pub fn spawn(
+    addr: &str,
+    submit: impl Fn(Request) + Send + Sync + 'static,
+) -> Result<ApiHandle> {
+    let listener = TcpListener::bind(addr).with_context(|| format!("listen on {addr}"))?;
+    let local = listener.local_addr()?;
+    if !local.ip().is_loopback() {
+        bail!("{addr}: the API can only listen on a loopback address, e.g. 127.0.0.1:8080");
+    }
+    let streams: Streams = Arc::default();
+    let submit = Arc::new(submit);
+
+    let thread_streams = streams.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let streams = thread_streams.clone();
+            let submit = submit.clone();
+            thread::spawn(move || {
+                let _ = serve(stream, &streams, &*submit);
+            });
+        }
+    });
+
+    Ok(ApiHandle {
+        addr: local,
+        streams,
+    })
+}
+
+fn serve(stream: TcpStream, streams: &Streams, submit: &dyn Fn(Request)) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    // Query strings aren't used; ignore them.
+    let path = parts.next().unwrap_or_default().split('?').next().unwrap_or_default().to_string();
+
+    let mut length = 0;
+    let mut json = false;
+    let mut trusted = true;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            let (name, value) = (name.trim(), value.trim());
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.parse().unwrap_or(0);
+            } else if name.eq_ignore_ascii_case("content-type") {
+                let media = value.split(';').next().unwrap_or_default().trim();
+                json = media.eq_ignore_ascii_case("application/json");
+            } else if name.eq_ignore_ascii_case("origin") {
+                trusted = false;
+            } else if name.eq_ignore_ascii_case("host") {
+                trusted &= is_loopback_host(value);
+            }
+        }
+    }
+    if !trusted {
+        return respond(&mut writer, 403, &json!({ "error": "only local clients are served" }));
+    }
+    if method == "POST" && !json {
+        let error = json!({ "error": "Content-Type must be application/json" });
+        return respond(&mut writer, 415, &error);
+    }
+    if length > MAX_BODY {
+        return respond(&mut writer, 413, &json!({ "error": "body too large" }));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+
+    let command = match (method.as_str(), path.as_str()) {
+        ("GET", "/events") => return stream_events(writer, streams),
+        ("GET", "/ports") => Command::Ports,
+        ("POST", "/close") => Command::Close,
+        ("POST", "/open") => match serde_json::from_slice(&body) {
+            Ok(open) => Command::Open(open),
+            Err(e) => return respond(&mut writer, 400, &json!({ "error": e.to_string() })),
+        },
+        ("POST", "/send") => match serde_json::from_slice(&body) {
+            Ok(send) => Command::Send(send),
+            Err(e) => return respond(&mut writer, 400, &json!({ "error": e.to_string() })),
+        },
+        (_, "/events" | "/ports" | "/open" | "/close" | "/send") => {
+            return respond(&mut writer, 405, &json!({ "error": "method not allowed" }));
+        }
+        _ => return respond(&mut writer, 404, &json!({ "error": "not found" })),
+    };
+
+    let (reply, replies) = mpsc::channel();
+    submit(Request { command, reply });
+    match replies.recv_timeout(REPLY_TIMEOUT) {
+        Ok(reply) => respond(&mut writer, reply.status, &reply.body),
+        Err(_) => respond(&mut writer, 503, &json!({ "error": "no answer from lazyserial" })),
+    }
+}
+
+/// Whether a `Host` header names this machine: `localhost` or a loopback
+/// address, with or without a port.
+fn is_loopback_host(host: &str) -> bool {
+    let name = match host.strip_prefix('[') {
+        Some(rest) => rest.split(']').next().unwrap_or_default(),
+        None => host.rsplit_once(':').map_or(host, |(name, _)| name),
+    };
+    name.eq_ignore_ascii_case("localhost")
+        || name.parse::<std::net::IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+fn respond(writer: &mut TcpStream, status: u16, body: &Value) -> std::io::Result<()> {
+    let body = body.to_string();
+    write!(
+        writer,
+        "HTTP/1.1 {status} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{body}",
+        reason(status),
+        body.len()
+    )
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        415 => "Unsupported Media Type",
+        _ => "Service Unavailable",
+    }
+}
+
+/// Writes output lines to the client as `data:` events until it goes away
+/// or falls too far behind.
+fn stream_events(mut writer: TcpStream, streams: &Streams) -> std::io::Result<()> {
+    writer.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    writer.write_all(
+        b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n\
+          Connection: close\r\n\r\n",
+    )?;
+    let (tx, rx) = mpsc::sync_channel(EVENT_QUEUE);
+    streams.lock().unwrap().push(tx);
+    for line in rx {
+        writer.write_all(format!("data: {line}\n\n").as_bytes())?;
+    }
+    Ok(())
+}
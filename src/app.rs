@@ -1,18 +1,102 @@
-use std::collections::VecDeque;
-use std::io;
-use std::time::{Duration, Instant};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
 use anyhow::{anyhow, Context, Result};
-use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::event::{
+    self, DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture,
+    Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
 use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
 use crossterm::{execute, terminal};
+use lazyserial::{ble, bluetooth};
+use lazyserial::capture::{Capture, Recorder, Replay, Speed};
+use lazyserial::checksum::Algorithm;
+use lazyserial::decoder::{Monitor, Registry};
+use lazyserial::defmt::{self, Level};
+use lazyserial::encoding::TextDecoder;
+use lazyserial::espcrash::{self, Symbolizer};
+use lazyserial::framing::{FrameDecoder, Framing as FrameMode};
+use lazyserial::gcode::{Flavor, Streamer};
+use lazyserial::linebuf::LineAssembler;
+use lazyserial::linktest::LinkTest;
+#[cfg(unix)]
+use lazyserial::loopback::VirtualDevice;
+use lazyserial::modbus;
+use lazyserial::nmea;
+use lazyserial::plugin;
+use lazyserial::serial::{
+    self, IoStats, ModemLines, OpenFailure, SerialEvent, SerialHandle, TxProgress,
+};
+use lazyserial::{Direction, Parity, PortSettings, Rs485, Session};
+use lazyserial::stm32::{self, Flasher};
+use lazyserial::transfer::{Protocol, Transfer};
 use ratatui::backend::CrosstermBackend;
+use ratatui::layout::Rect;
 use ratatui::Terminal;
+use unicode_width::UnicodeWidthStr;
 
-use crate::serial::{self, SerialEvent, SerialHandle};
+use crate::alert::{self, Alerts, Hit};
+use crate::api::{self, ApiHandle};
+use crate::ansi::{self, AnsiMode};
+use crate::at::AtAssistant;
+use crate::autoresponse::AutoResponder;
+use crate::bookmark::Bookmarks;
+use crate::bridge::{self, BridgeEvent, BridgeHandle};
+use crate::cli::Args;
+use crate::clipboard::Clipboard;
+use crate::compare::{Compare, Side};
+use crate::compose::{self, Compose};
+use crate::direction::LineDirections;
+use crate::config::{
+    self, LayoutConfig, LinkTestConfig, MqttConfig, PingConfig, SerialConfig, Split,
+};
+use crate::export;
+use crate::filter::Filter;
+use crate::hexdump;
+use crate::highlight::{self, Highlighter};
+use crate::hotplug;
+use crate::inspect::Inspector;
+use crate::keymap::{Action, Key, Keymap};
+use crate::macros::{self, Macros};
+use crate::mqtt::{self, MqttEvent, MqttHandle};
+use crate::shell::{self, ShellCommand, ShellEvent};
+use crate::toast::Toasts;
+use crate::palette::{self, Command, Palette};
+use crate::plot::Plotter;
+use crate::passthrough::{self, Passthrough};
+use crate::ping::Ping;
+use crate::profile::{self, LineEnding};
+use crate::recent::{self, RecentSession};
+use crate::scrollback::{Limits, Scrollback};
+use crate::script::{self, ScriptEvent, ScriptHandle};
+use crate::state::{self, SavedState};
+use crate::theme::Theme;
 use crate::ui;
 
-const MAX_OUTPUT_LINES: usize = 5000;
+/// Cap on lines held back while the view is paused.
+const MAX_PAUSED_LINES: usize = 5000;
+const DEFAULT_REPEAT_INTERVAL: Duration = Duration::from_millis(1000);
+const DEFAULT_BREAK: Duration = Duration::from_millis(250);
+const WHEEL_LINES: isize = 3;
+/// Received bytes handled per tick; the rest waits for the next one, so a
+/// burst can't hold off redraws and keys.
+const MAX_DRAIN_BYTES: usize = 256 * 1024;
+/// Columns Left/Right move unwrapped output.
+const HSCROLL_STEP: isize = 8;
+/// Link test bytes kept queued for the port, enough to keep it busy
+/// between ticks at high baud rates.
+const LINK_TEST_QUEUE: usize = 16 * 1024;
+/// How long a link test waits for the last bytes to come back.
+const LINK_TEST_IDLE: Duration = Duration::from_secs(2);
+/// Starting text of the open-path prompt.
+const DEFAULT_PATH_PREFIX: &str = if cfg!(windows) { "COM" } else { "/dev/" };
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Focus {
@@ -21,48 +105,1174 @@ pub enum Focus {
     Input,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PromptKind {
+    SendFile(Protocol),
+    ReceiveFile(Protocol),
+    RunScript,
+    EditMacro(usize),
+    RepeatInterval,
+    SaveScrollback,
+    Connect,
+    /// A device path that port discovery doesn't list.
+    OpenPath,
+    /// Name for a profile of the selected port.
+    SaveProfile,
+    Bridge,
+    Filter,
+    /// Edit rule `n`, or add one when `None`.
+    EditHighlight(Option<usize>),
+    DefmtElf,
+    ModbusRead,
+    EspElf,
+    /// File for the plotted samples; the extension picks the format.
+    Export,
+    Record,
+    Replay(Speed),
+    /// Second port for sniff mode.
+    Sniff,
+    /// Port to show beside the open one.
+    Compare,
+    /// Other ports typed lines go to as well.
+    Broadcast,
+    /// Probe for a ping run.
+    Ping,
+    /// Filter command for received data.
+    Pipe,
+    BreakDuration,
+    /// Name of a reset sequence; Tab cycles through them.
+    Reset,
+    /// Firmware binary, optionally `@address`.
+    Stm32Flash,
+    /// G-code file to stream; Tab switches the firmware dialect.
+    Gcode(Flavor),
+    /// Note for a marker placed at this time.
+    Marker(SystemTime),
+}
+
+impl PromptKind {
+    pub fn title(self) -> String {
+        match self {
+            PromptKind::SendFile(p) => format!("{} send file (Tab: protocol)", p.name()),
+            PromptKind::ReceiveFile(Protocol::Ymodem) => {
+                "YMODEM receive into directory (Tab: protocol)".to_string()
+            }
+            PromptKind::ReceiveFile(p) => format!("{} receive to file (Tab: protocol)", p.name()),
+            PromptKind::RunScript => "Run Rhai script".to_string(),
+            PromptKind::EditMacro(idx) => {
+                format!("{} macro (\\r \\n \\t \\xNN escapes, empty clears)", macros::key_name(idx))
+            }
+            PromptKind::RepeatInterval => "Repeat input every N ms (0 or empty stops)".to_string(),
+            PromptKind::BreakDuration => "Send break for N ms".to_string(),
+            PromptKind::Reset => "Run reset sequence (Tab: next)".to_string(),
+            PromptKind::Stm32Flash => "STM32 flash binary (file or file@0x08000000)".to_string(),
+            PromptKind::Gcode(Flavor::Marlin) => {
+                "Stream G-code to Marlin, numbered with checksums (Tab: firmware)".to_string()
+            }
+            PromptKind::Gcode(f) => format!("Stream G-code to {} (Tab: firmware)", f.name()),
+            PromptKind::SaveScrollback => "Save scrollback to file".to_string(),
+            PromptKind::Connect => {
+                "Connect to rfc2217://host:port or telnet://host:port".to_string()
+            }
+            PromptKind::OpenPath => "Open device path (e.g. /dev/pts/3, COM12)".to_string(),
+            PromptKind::Bridge => {
+                "Share the port over TCP (ws:// for WebSocket): listen address".to_string()
+            }
+            PromptKind::SaveProfile => "Save baud, parity and line ending as profile".to_string(),
+            PromptKind::Filter => {
+                "Filter output: regex, !regex hides matches, empty clears".to_string()
+            }
+            PromptKind::EditHighlight(_) => {
+                "Highlight: regex => [color] [on color] [bold] [line]".to_string()
+            }
+            PromptKind::DefmtElf => "Decode defmt logs with firmware ELF".to_string(),
+            PromptKind::Export => "Export plotted data to .csv or .jsonl".to_string(),
+            PromptKind::Record => "Record RX/TX traffic to capture file".to_string(),
+            PromptKind::Sniff => "Sniff: second port to forward to (e.g. /dev/pts/4)".to_string(),
+            PromptKind::Ping => "Ping: probe to send (\\r \\n escapes)".to_string(),
+            PromptKind::Pipe => "Pipe received data through (e.g. jq --unbuffered .)".to_string(),
+            PromptKind::Broadcast => {
+                "Broadcast: more ports to send typed lines to (empty: the compared one)".to_string()
+            }
+            PromptKind::Compare => {
+                "Compare: second port to show beside this one (e.g. /dev/ttyUSB1)".to_string()
+            }
+            PromptKind::Replay(speed) => {
+                format!("Replay capture file at {} (Tab: speed)", speed.name())
+            }
+            PromptKind::Marker(_) => "Marker note (optional)".to_string(),
+            PromptKind::EspElf => "Decode ESP panic backtraces with firmware ELF".to_string(),
+            PromptKind::ModbusRead => {
+                "Modbus read holding registers: unit address count".to_string()
+            }
+        }
+    }
+}
+
+pub struct Prompt {
+    pub kind: PromptKind,
+    pub buffer: String,
+}
+
+/// A port that couldn't be opened, shown with what to do about it until
+/// it is retried or dismissed.
+pub struct OpenError {
+    pub target: String,
+    pub failure: OpenFailure,
+    /// The system's error.
+    pub message: String,
+}
+
+impl OpenError {
+    /// What might fix it.
+    pub fn hints(&self) -> Vec<String> {
+        match self.failure {
+            OpenFailure::PermissionDenied if cfg!(target_os = "linux") => vec![
+                "Your user may not be in the group that owns serial ports:".to_string(),
+                "  sudo usermod -aG dialout $USER   (uucp on Arch), then log in again".to_string(),
+                format!("Check who owns it with: ls -l {}", self.target),
+            ],
+            OpenFailure::PermissionDenied => vec![format!(
+                "Check the permissions on {}, or that no other user holds it",
+                self.target
+            )],
+            OpenFailure::Busy => vec![
+                "Close the other program, then retry.".to_string(),
+                "To share the port instead: exclusive = false under [serial].".to_string(),
+            ],
+            OpenFailure::NotFound => vec![
+                "The device is gone: check the cable or adapter, then refresh the port list."
+                    .to_string(),
+            ],
+            OpenFailure::Other => vec!["Check the port and its settings, then retry.".to_string()],
+        }
+    }
+}
+
+/// A popup that takes the keyboard until it is answered; see
+/// `ui::draw_modal`.
+pub struct Modal {
+    pub title: String,
+    pub kind: ModalKind,
+    then: Then,
+}
+
+pub enum ModalKind {
+    /// Text to read; Enter or Esc closes it.
+    Message(String),
+    /// A question; `yes` is the highlighted answer, no by default.
+    Confirm { text: String, yes: bool },
+    /// A line of text being typed; how `Prompt`s are drawn.
+    Prompt(String),
+    Select {
+        text: String,
+        items: Vec<String>,
+        selected: usize,
+    },
+}
+
+/// How a modal was answered.
+enum Answer {
+    /// Esc, or no.
+    Dismissed,
+    Yes,
+    Picked(usize),
+}
+
+/// What happens once a modal is answered.
+enum Then {
+    Nothing,
+    Quit,
+    ClosePort,
+    /// The prompt's file exists: write over it, edit the name, or cancel.
+    Overwrite(Prompt),
+    /// Restore the session saved at the last exit, or start fresh.
+    Restore(Box<SavedState>),
+}
+
+impl Modal {
+    fn message(title: &str, text: String) -> Self {
+        Self {
+            title: title.to_string(),
+            kind: ModalKind::Message(text),
+            then: Then::Nothing,
+        }
+    }
+
+    fn confirm(title: &str, text: String, then: Then) -> Self {
+        Self {
+            title: title.to_string(),
+            kind: ModalKind::Confirm { text, yes: false },
+            then,
+        }
+    }
+
+    fn select(title: &str, text: String, items: &[&str], then: Then) -> Self {
+        Self {
+            title: title.to_string(),
+            kind: ModalKind::Select {
+                text,
+                items: items.iter().map(|i| i.to_string()).collect(),
+                selected: 0,
+            },
+            then,
+        }
+    }
+}
+
+/// How long a clear can be undone.
+const UNDO_CLEAR: Duration = Duration::from_secs(10);
+
+/// What a clear took away, kept for [`Action::UndoClear`].
+enum Cleared {
+    /// The view start before the view was cleared.
+    View(usize),
+    /// The emptied scrollback with its lines' directions, and the view
+    /// start then.
+    Scrollback(Scrollback, LineDirections, usize),
+}
+
+/// One discovery's result: BLE (`true`) or classic devices.
+type Discovered = (bool, io::Result<Vec<bluetooth::Device>>);
+
+/// Output lines selected with the mouse, as absolute line numbers (see
+/// `Scrollback::evicted`) so the selection survives scrollback trimming.
+#[derive(Clone, Copy, Debug)]
+pub struct Selection {
+    pub anchor: usize,
+    pub cursor: usize,
+    /// Still being dragged.
+    pub active: bool,
+}
+
+/// I/O totals for the current port plus rates over the last second.
+#[derive(Debug)]
+pub struct Throughput {
+    pub totals: IoStats,
+    pub rx_rate: f64,
+    pub tx_rate: f64,
+    sample: IoStats,
+    sampled_at: Instant,
+}
+
+impl Throughput {
+    const WINDOW: Duration = Duration::from_secs(1);
+
+    fn new() -> Self {
+        Self {
+            totals: IoStats::default(),
+            rx_rate: 0.0,
+            tx_rate: 0.0,
+            sample: IoStats::default(),
+            sampled_at: Instant::now(),
+        }
+    }
+
+    /// Recomputes the rates once per window, so they decay to zero when idle.
+    fn tick(&mut self) {
+        let elapsed = self.sampled_at.elapsed();
+        if elapsed < Self::WINDOW {
+            return;
+        }
+        let secs = elapsed.as_secs_f64();
+        self.rx_rate = (self.totals.rx_bytes - self.sample.rx_bytes) as f64 / secs;
+        self.tx_rate = (self.totals.tx_bytes - self.sample.tx_bytes) as f64 / secs;
+        self.sample = self.totals;
+        self.sampled_at = Instant::now();
+    }
+}
+
+/// The second port of sniff mode, with its own text decoding.
+pub struct Sniff {
+    pub session: Session,
+    decoder: TextDecoder,
+    lines: LineAssembler,
+}
+
+/// A port typed lines are also sent to while broadcasting, with its own
+/// text decoding for what it answers.
+pub struct Peer {
+    pub session: Session,
+    decoder: TextDecoder,
+    lines: LineAssembler,
+}
+
+/// A payload resent on a fixed interval until stopped.
+pub struct Repeat {
+    pub label: String,
+    pub payload: Vec<u8>,
+    pub interval: Duration,
+    pub count: u64,
+    next: Instant,
+}
+
+/// Lines of a multi-line paste still waiting to be sent.
+pub struct Paste {
+    pub lines: VecDeque<String>,
+    pub sent: usize,
+    next: Instant,
+}
+
 pub struct AppState {
     pub ports: Vec<serialport::SerialPortInfo>,
     pub selected_port: Option<usize>,
+    /// Fuzzy query narrowing the Ports pane; empty shows every port.
+    pub port_filter: String,
+    /// Keys in the Ports pane edit `port_filter`.
+    pub port_filter_editing: bool,
+    /// Bluetooth serial devices from the last discovery, listed after the
+    /// other ports.
+    pub bluetooth: Vec<bluetooth::Device>,
+    /// Discovery running in the background, and whether it was asked for
+    /// (so its errors are worth reporting). Classic and BLE (`true`) results
+    /// arrive separately.
+    bluetooth_rx: Option<(Receiver<Discovered>, bool)>,
+    /// Fresh port lists from the hotplug watcher.
+    port_watch_rx: std::sync::mpsc::Receiver<Vec<serialport::SerialPortInfo>>,
     pub baud_rate: u32,
+    pub parity: Parity,
+    /// From the open port's profile.
+    pub rs485: Option<Rs485>,
+    pub line_ending: LineEnding,
+    pub favorites: Vec<String>,
+    pub profiles: Vec<config::ProfileConfig>,
+    /// Name of the profile applied to the open port.
+    pub profile: Option<String>,
+    /// Settings the profile replaced, put back when its port closes.
+    before_profile: Option<BeforeProfile>,
     pub is_open: bool,
+    pub dtr: bool,
+    pub rts: bool,
+    /// Offered by the next break prompt.
+    pub break_duration: Duration,
+    /// `[reset]` sequences from the config.
+    reset_sequences: BTreeMap<String, Vec<config::ResetStepConfig>>,
+    /// The open port's profile's reset sequence, run without asking.
+    profile_reset: Option<String>,
+    /// Offered by the next reset prompt.
+    last_reset: String,
+    pub modem_lines: Option<ModemLines>,
+    pub throughput: Throughput,
+    /// Progress through a send still being written.
+    pub tx_progress: Option<TxProgress>,
 
-    pub serial_handle: Option<SerialHandle>,
-    pub serial_event_rx: Option<std::sync::mpsc::Receiver<SerialEvent>>,
+    pub session: Option<Session>,
 
-    pub output_lines: VecDeque<String>,
-    pub output_scroll: u16,
+    pub output_lines: Scrollback,
+    /// Lines scrolled back from the bottom of the output.
+    pub output_scroll: usize,
+    /// Absolute number of the first line shown; a clear moves it past what
+    /// is there, leaving those lines in the scrollback.
+    view_start: usize,
+    /// The last clear, for undoing it within `UNDO_CLEAR`.
+    undo_clear: Option<(Cleared, Instant)>,
+    /// Tail-follow new output; off once the user scrolls up.
+    pub follow: bool,
+    /// Wrap long output lines; otherwise they are cut at the pane edge.
+    pub wrap: bool,
+    /// Show back-to-back repeats of a received line once, with a count.
+    pub collapse_repeats: bool,
+    /// The received line last shown, while repeats of it can be counted
+    /// on it.
+    repeat_run: Option<RepeatRun>,
+    /// Show control characters and line endings as symbols.
+    pub control_chars: bool,
+    /// Sent lines right-aligned, received ones left.
+    pub chat: bool,
+    /// Columns scrolled right while not wrapping.
+    pub output_hscroll: usize,
+    /// Show each line's bytes in hex beside the text.
+    pub hex_split: bool,
+    /// While paused, new lines collect here instead of `output_lines`.
+    pub paused_lines: Option<VecDeque<String>>,
+    pub selection: Option<Selection>,
+    pub bookmarks: Bookmarks,
+    /// Which way each output line went.
+    pub directions: LineDirections,
+    /// Narrows the rendered output without touching `output_lines`.
+    pub filter: Option<Filter>,
+    pub mouse: bool,
+    /// Terminal size as of the last frame, for mouse hit-testing and yanks.
+    pub screen: Rect,
+    /// Shortest time between two redraws.
+    frame_interval: Duration,
+    /// Receive buffer size and overflow policy for opened ports.
+    serial: SerialConfig,
+    wakeups: Wakeups,
+    clipboard: Clipboard,
+    pub ansi_mode: AnsiMode,
+    /// Drop control characters and unsafe attributes from shown output.
+    pub sanitize: bool,
+    pub highlighter: Highlighter,
+    /// Selected row while the highlight popup is open.
+    pub highlight_view: Option<usize>,
+    /// Scroll offset while the help overlay is open.
+    pub help_view: Option<u16>,
+    pub toasts: Toasts,
+    /// Errors scrolled back from the newest while the error history is
+    /// open.
+    pub error_view: Option<usize>,
+    pub palette: Option<Palette>,
+    pub decoder: TextDecoder,
+    pub line_assembler: LineAssembler,
+    /// Silence worth a separator line; see `note_idle_gap`.
+    idle_gap: Option<Duration>,
+    /// When the open port last received data.
+    last_rx: Option<Instant>,
 
     pub input_buffer: String,
+    /// Byte offset of the cursor in `input_buffer`, on a char boundary.
+    pub input_cursor: usize,
+    /// Ctrl-V was pressed: the next key goes into the input as a control
+    /// character.
+    pub input_literal: bool,
     pub focus: Focus,
+    pub keymap: Keymap,
+    pub theme: Theme,
+
+    pub prompt: Option<Prompt>,
+    /// Over everything else, prompts included.
+    pub modal: Option<Modal>,
+    /// The last failed open, shown over the output.
+    pub open_error: Option<OpenError>,
+    /// Ports opened before, newest first, for reopening the last one.
+    recent: Vec<RecentSession>,
+    pub transfer: Option<Transfer>,
+    /// STM32 bootloader flashing in progress.
+    pub flash: Option<Flasher>,
+    pub gcode: Option<Streamer>,
+
+    pub auto_responder: AutoResponder,
+    pub alerts: Alerts,
+    /// Selected row while the auto-response popup is open.
+    pub auto_response_view: Option<usize>,
+
+    pub script: Option<ScriptHandle>,
+    pub script_event_rx: Option<std::sync::mpsc::Receiver<ScriptEvent>>,
+
+    pub macros: Macros,
+    /// Selected row while the macro popup is open.
+    pub macro_view: Option<usize>,
+
+    pub repeat: Option<Repeat>,
+    pub repeat_interval: Duration,
+    pub paste: Option<Paste>,
+    pub paste_delay: Duration,
+    /// Hold typed lines, macros and pastes back while earlier data is
+    /// still being written.
+    pub wait_for_tx: bool,
+    /// A `!command` whose output goes to the port.
+    pub shell: Option<ShellCommand>,
+    shell_line_by_line: bool,
+    /// Filter received data goes through before it is shown.
+    pub pipe: Option<ShellCommand>,
+    /// The last filter command, offered again.
+    pipe_command: String,
+    pub compose: Option<Compose>,
+    /// Appended to every line and macro sent.
+    pub checksum: Option<Algorithm>,
+    /// Calculator input while its popup is open.
+    pub checksum_view: Option<String>,
+    /// Round trip measurement, shown in a popup until closed.
+    pub ping: Option<Ping>,
+    ping_config: PingConfig,
+    /// Loopback bit error test, shown in a popup until closed.
+    pub link_test: Option<LinkTest>,
+    link_test_config: LinkTestConfig,
+    pub inspector: Option<Inspector>,
+    /// Text of the compose popup while it is closed unsent.
+    pub compose_draft: String,
+
+    pub bridge: Option<BridgeHandle>,
+    pub bridge_event_rx: Option<std::sync::mpsc::Receiver<BridgeEvent>>,
+    pub layout: LayoutConfig,
+    /// The Output pane fills the terminal; `unzoom_focus` is where focus
+    /// goes back to.
+    pub zoomed: bool,
+    unzoom_focus: Focus,
+    /// The Ports/Output border is being dragged.
+    resizing: bool,
+    pub mqtt: Option<MqttHandle>,
+    /// The `--api` HTTP server.
+    pub api: Option<ApiHandle>,
+    mqtt_event_rx: Option<std::sync::mpsc::Receiver<MqttEvent>>,
+    mqtt_config: MqttConfig,
+
+    /// Set while keys go straight to the port and the TUI is hidden.
+    pub passthrough: Option<Passthrough>,
+
+    /// Decodes received bytes as defmt logs instead of text while set.
+    pub defmt: Option<defmt::Decoder>,
+    /// ELF of the last defmt session, offered again by the prompt.
+    defmt_elf: Option<PathBuf>,
+    /// Shows received data as COBS or SLIP frames instead of text while set.
+    pub frames: Option<FrameDecoder>,
+    /// Decodes traffic into the packet list while set.
+    pub packets: Option<Monitor>,
+    /// Decoders the packet list can run.
+    pub decoders: Registry,
+    /// Follows GPS sentences in the received text while set.
+    pub nmea: Option<nmea::Tracker>,
+    /// Input gets AT completion and history, lines end in `\r\n` and
+    /// result codes are tracked and colored.
+    pub at_mode: bool,
+    pub at: AtAssistant,
+    /// Resolves ESP panic backtraces in received lines while set.
+    pub esp: Option<Symbolizer>,
+    /// ELF of the last ESP decoding, offered again by the prompt.
+    esp_elf: Option<PathBuf>,
+    /// Charts numbers from received lines while set.
+    pub plot: Option<Plotter>,
+    /// Writes every byte sent and received to a capture file while set.
+    pub recorder: Option<Recorder>,
+    /// Plays a capture file into the output while set, with no port open.
+    pub replay: Option<Replay>,
+    /// Forwards between the open port and a second one while set.
+    pub sniff: Option<Sniff>,
+    /// Second port whose output is shown beside the main one's.
+    pub compare: Option<Compare>,
+    /// Ports besides the compared one that typed lines are also sent to;
+    /// `Some` while broadcasting.
+    pub broadcast: Option<Vec<Peer>>,
+    /// Pty loopback device listed last among the ports.
+    #[cfg(unix)]
+    virtual_device: Option<VirtualDevice>,
 }
 
 impl AppState {
     fn new() -> Result<Self> {
-        let ports = serial::list_ports()?;
-        Ok(Self {
+        let mut notes = Vec::new();
+        let config = config::load().unwrap_or_else(|e| {
+            notes.push(format!("[config] {e:#}"));
+            config::Config::default()
+        });
+        let mut ports = serial::list_ports()?;
+        serial::pair_callout(&mut ports, config.serial.prefer_callout);
+        profile::sort_ports(&mut ports, &config.favorites);
+        let auto_responder =
+            AutoResponder::from_config(&config.auto_response).unwrap_or_else(|e| {
+                notes.push(format!("[config] {e:#}"));
+                AutoResponder::default()
+            });
+        let alerts = Alerts::from_config(&config.alert).unwrap_or_else(|e| {
+            notes.push(format!("[config] {e:#}"));
+            Alerts::default()
+        });
+        let bookmarks =
+            Bookmarks::from_config(&config.display.auto_bookmark).unwrap_or_else(|e| {
+                notes.push(format!("[config] {e:#}"));
+                Bookmarks::default()
+            });
+        let macros = Macros::from_config(&config.macros).unwrap_or_else(|e| {
+            notes.push(format!("[config] {e:#}"));
+            Macros::default()
+        });
+        let highlighter = Highlighter::from_config(&config.highlight).unwrap_or_else(|e| {
+            notes.push(format!("[config] {e:#}"));
+            Highlighter::default()
+        });
+        let keymap = Keymap::from_config(&config.keymap).unwrap_or_else(|e| {
+            notes.push(format!("[config] {e:#}"));
+            Keymap::default()
+        });
+        let theme = Theme::from_config(&config.theme).unwrap_or_else(|e| {
+            notes.push(format!("[config] {e:#}"));
+            Theme::default()
+        });
+        let max_lines = config.display.scrollback_lines.max().unwrap_or_else(|e| {
+            notes.push(format!("[config] scrollback_lines: {e:#}"));
+            None
+        });
+        let scrollback = Limits {
+            max_lines,
+            max_ram_bytes: (config.display.scrollback_memory_mb * 1024 * 1024) as usize,
+            max_disk_bytes: config.display.scrollback_disk_mb * 1024 * 1024,
+        };
+        let recent = recent::load().unwrap_or_else(|e| {
+            notes.push(format!("[recent] {e:#}"));
+            Vec::new()
+        });
+        let mut decoders = Registry::builtin();
+        if let Some(dir) = config::plugin_dir() {
+            for (path, result) in plugin::load_dir(&dir, &mut decoders) {
+                notes.push(match result {
+                    Ok(name) => format!("[plugin] decoder {name} from {}", path.display()),
+                    Err(e) => format!("[plugin] {e:#}"),
+                });
+            }
+        }
+        let port_watch_rx = hotplug::watch(&ports);
+        #[cfg(unix)]
+        let virtual_device = VirtualDevice::create().map_or_else(
+            |e| {
+                notes.push(format!("[virtual device] {e:#}"));
+                None
+            },
+            Some,
+        );
+        let mut app = Self {
             ports,
             selected_port: None,
+            port_filter: String::new(),
+            port_filter_editing: false,
+            bluetooth: Vec::new(),
+            bluetooth_rx: None,
+            port_watch_rx,
             baud_rate: 115_200,
+            parity: Parity::None,
+            rs485: None,
+            line_ending: LineEnding::default(),
+            favorites: config.favorites,
+            profiles: config.profile,
+            profile: None,
+            before_profile: None,
             is_open: false,
-            serial_handle: None,
-            serial_event_rx: None,
-            output_lines: VecDeque::new(),
+            dtr: true,
+            rts: true,
+            break_duration: DEFAULT_BREAK,
+            reset_sequences: config.reset,
+            profile_reset: None,
+            last_reset: lazyserial::reset::BUILTIN[0].to_string(),
+            modem_lines: None,
+            throughput: Throughput::new(),
+            tx_progress: None,
+            session: None,
+            output_lines: Scrollback::new(scrollback),
             output_scroll: 0,
+            view_start: 0,
+            undo_clear: None,
+            follow: true,
+            wrap: config.display.wrap,
+            collapse_repeats: config.display.collapse_repeats,
+            repeat_run: None,
+            control_chars: config.display.control_chars,
+            chat: config.display.chat,
+            output_hscroll: 0,
+            hex_split: false,
+            paused_lines: None,
+            selection: None,
+            bookmarks,
+            directions: LineDirections::default(),
+            filter: None,
+            mouse: config.display.mouse,
+            screen: Rect::default(),
+            frame_interval: Duration::from_secs(1) / config.display.max_fps.max(1),
+            serial: config.serial,
+            wakeups: Wakeups::new(),
+            clipboard: Clipboard::default(),
+            ansi_mode: config.display.ansi,
+            sanitize: config.display.sanitize,
+            highlighter,
+            highlight_view: None,
+            help_view: None,
+            toasts: Toasts::default(),
+            error_view: None,
+            palette: None,
+            decoder: TextDecoder::new(config.display.encoding),
+            line_assembler: {
+                let timeout = Duration::from_millis(config.display.line_timeout_ms);
+                let mut lines = LineAssembler::new(timeout);
+                lines.set_keep_terminators(config.display.control_chars);
+                lines
+            },
+            idle_gap: (config.display.idle_gap_ms > 0)
+                .then(|| Duration::from_millis(config.display.idle_gap_ms)),
+            last_rx: None,
             input_buffer: String::new(),
+            input_cursor: 0,
+            input_literal: false,
             focus: Focus::Ports,
+            keymap,
+            theme,
+            prompt: None,
+            modal: None,
+            open_error: None,
+            recent,
+            transfer: None,
+            flash: None,
+            gcode: None,
+            auto_responder,
+            alerts,
+            auto_response_view: None,
+            script: None,
+            script_event_rx: None,
+            macros,
+            macro_view: None,
+            repeat: None,
+            repeat_interval: DEFAULT_REPEAT_INTERVAL,
+            paste: None,
+            compose: None,
+            checksum: None,
+            checksum_view: None,
+            ping: None,
+            ping_config: config.ping,
+            link_test: None,
+            link_test_config: config.link_test,
+            inspector: None,
+            compose_draft: String::new(),
+            paste_delay: Duration::from_millis(config.input.paste_line_delay_ms),
+            wait_for_tx: config.input.wait_for_tx,
+            shell: None,
+            shell_line_by_line: config.input.shell_line_by_line,
+            pipe: None,
+            pipe_command: config.display.pipe.clone().unwrap_or_default(),
+            bridge: None,
+            bridge_event_rx: None,
+            layout: config.layout,
+            zoomed: false,
+            unzoom_focus: Focus::Ports,
+            resizing: false,
+            mqtt: None,
+            api: None,
+            mqtt_event_rx: None,
+            mqtt_config: config.mqtt,
+            passthrough: None,
+            defmt: None,
+            defmt_elf: None,
+            frames: None,
+            packets: None,
+            decoders,
+            nmea: None,
+            at_mode: false,
+            at: AtAssistant::new(&config.at_commands),
+            esp: None,
+            esp_elf: None,
+            plot: None,
+            recorder: None,
+            replay: None,
+            sniff: None,
+            compare: None,
+            broadcast: None,
+            #[cfg(unix)]
+            virtual_device,
+        };
+        if let Some(port) = app.virtual_port() {
+            app.ports.push(port);
+        }
+        // Paired devices, without a scan.
+        discover_bluetooth(&mut app, false);
+        if app.mqtt_config.broker.is_some() {
+            start_mqtt(&mut app);
+        }
+        if let Some(command) = config.display.pipe {
+            start_pipe(&mut app, &command);
+        }
+        for note in notes {
+            app.add_output_line(note);
+        }
+        Ok(app)
+    }
+
+    /// Whether the Ports pane is hidden: set to collapse and a port open.
+    pub fn ports_collapsed(&self) -> bool {
+        self.layout.collapse_ports && self.is_open
+    }
+
+    /// Device and peer path of the virtual loopback device.
+    pub fn virtual_device(&self) -> Option<(&str, &str)> {
+        #[cfg(unix)]
+        return self.virtual_device.as_ref().map(|v| (v.path(), v.peer_path()));
+        #[cfg(not(unix))]
+        None
+    }
+
+    /// Indices into `ports` of those matching the port filter, best match
+    /// first, on what the Ports pane shows: name, manufacturer, product and
+    /// serial number. Bluetooth devices come last, in a section of their
+    /// own.
+    pub fn visible_ports(&self) -> Vec<usize> {
+        let mut scored: Vec<(i64, usize)> = self
+            .ports
+            .iter()
+            .enumerate()
+            .filter_map(|(i, p)| {
+                if self.port_filter.is_empty() {
+                    return Some((0, i));
+                }
+                let text = match self.port_note(p) {
+                    Some(note) => format!("{} {note}", p.port_name),
+                    None => p.port_name.clone(),
+                };
+                Some((palette::fuzzy_score(&self.port_filter, &text)?, i))
+            })
+            .collect();
+        // Stable, so equal scores keep the list's order.
+        scored.sort_by_key(|&(score, i)| (is_bluetooth(&self.ports[i]), -score));
+        scored.into_iter().map(|(_, i)| i).collect()
+    }
+
+    /// What the Ports pane shows beside a port's name: its device
+    /// description, loopback peer or Bluetooth name.
+    pub fn port_note(&self, port: &serialport::SerialPortInfo) -> Option<String> {
+        if let Some((_, peer)) = self.virtual_device().filter(|(path, _)| *path == port.port_name) {
+            return Some(format!("loopback (peer {peer})"));
+        }
+        if is_bluetooth(port) {
+            let device = self.bluetooth.iter().find(|d| d.target() == port.port_name)?;
+            return (!device.name.is_empty()).then(|| device.name.clone());
+        }
+        serial::describe_port(port)
+    }
+
+    /// Moves the selection to the best match if the filter hid it.
+    fn refilter_ports(&mut self) {
+        let visible = self.visible_ports();
+        if !self.selected_port.is_some_and(|i| visible.contains(&i)) {
+            self.selected_port = visible.first().copied();
+        }
+    }
+
+    /// Ports list entries for the discovered Bluetooth devices.
+    fn bluetooth_ports(&self) -> impl Iterator<Item = serialport::SerialPortInfo> + '_ {
+        self.bluetooth.iter().map(|d| serialport::SerialPortInfo {
+            port_name: d.target(),
+            port_type: serialport::SerialPortType::BluetoothPort,
+        })
+    }
+
+    /// Ports list entry for the virtual device.
+    fn virtual_port(&self) -> Option<serialport::SerialPortInfo> {
+        self.virtual_device().map(|(path, _)| serialport::SerialPortInfo {
+            port_name: path.to_string(),
+            port_type: serialport::SerialPortType::Unknown,
         })
     }
 
+    /// Write handle of the open connection.
+    fn serial(&self) -> Option<&SerialHandle> {
+        self.session.as_ref().map(Session::handle)
+    }
+
+    /// A link test is running, so what the port receives is its pattern
+    /// coming back.
+    fn link_testing(&self) -> bool {
+        self.link_test.as_ref().is_some_and(|t| !t.is_finished())
+    }
+
+    /// The ports besides the open one typed lines go to while
+    /// broadcasting: those it opened and the compared one.
+    pub fn broadcast_targets(&self) -> Vec<&Session> {
+        let Some(peers) = &self.broadcast else {
+            return Vec::new();
+        };
+        let compare = self.compare.as_ref().map(|c| &c.session);
+        peers.iter().map(|p| &p.session).chain(compare).collect()
+    }
+
     fn add_output_line<S: Into<String>>(&mut self, line: S) {
-        self.output_lines.push_back(line.into());
-        while self.output_lines.len() > MAX_OUTPUT_LINES {
-            self.output_lines.pop_front();
+        self.add_line(line.into(), None);
+    }
+
+    /// Adds the echo of data sent to the port.
+    fn add_sent_line<S: Into<String>>(&mut self, line: S) {
+        self.add_line(line.into(), Some(Direction::Tx));
+    }
+
+    /// Adds a line, recording which way it went over the port, if at all.
+    fn add_line(&mut self, line: String, direction: Option<Direction>) {
+        let number = self.next_line_number();
+        self.directions.push(number, direction);
+        if let Some(api) = &self.api {
+            api.publish(&line);
+        }
+        let Some(pending) = self.paused_lines.as_mut() else {
+            self.push_output(line);
+            if let Some(err) = self.output_lines.take_error() {
+                self.directions.push(number + 1, None);
+                self.push_output(format!("[error] {err}"));
+                self.toasts.error(err.to_string());
+            }
+            return;
+        };
+        pending.push_back(line);
+        while pending.len() > MAX_PAUSED_LINES {
+            pending.pop_front();
+        }
+    }
+
+    /// Shows an error in the output and as a toast, and keeps it for the
+    /// error history.
+    fn report_error(&mut self, text: String) {
+        self.add_output_line(format!("[error] {text}"));
+        self.toasts.error(text);
+    }
+
+    /// Absolute number the next output line will get, counting lines held
+    /// back by a pause.
+    fn next_line_number(&self) -> usize {
+        let pending = self.paused_lines.as_ref().map_or(0, VecDeque::len);
+        self.output_lines.evicted() + self.output_lines.len() + pending
+    }
+
+    fn push_output(&mut self, line: String) {
+        self.output_lines.push(line);
+        self.bookmarks.prune(self.output_lines.evicted());
+        self.directions.prune(self.output_lines.evicted());
+        let shown = match self.filter.as_mut() {
+            Some(filter) => filter.on_push(&self.output_lines) || !filter.active,
+            None => true,
+        };
+        if shown && !self.follow {
+            // Keep the view anchored on what the user is reading.
+            self.output_scroll += 1;
+        }
+    }
+
+    fn active_filter(&self) -> Option<&Filter> {
+        self.filter.as_ref().filter(|f| f.active)
+    }
+
+    /// Lines before `view_start` that a clear hid from the view.
+    fn view_skip(&self) -> usize {
+        match self.active_filter() {
+            Some(filter) => filter.count_before(self.view_start),
+            None => self
+                .view_start
+                .saturating_sub(self.output_lines.evicted())
+                .min(self.output_lines.len()),
+        }
+    }
+
+    /// Number of output lines in the rendered view.
+    pub fn view_len(&self) -> usize {
+        let total = match self.active_filter() {
+            Some(filter) => filter.len(),
+            None => self.output_lines.len(),
+        };
+        total - self.view_skip()
+    }
+
+    /// Index into `output_lines` of the `pos`th line of the rendered view.
+    pub fn view_line(&self, pos: usize) -> Option<usize> {
+        let pos = pos + self.view_skip();
+        match self.active_filter() {
+            Some(filter) => filter.line(&self.output_lines, pos),
+            None => (pos < self.output_lines.len()).then_some(pos),
+        }
+    }
+
+    /// Selected lines as indices into `output_lines`.
+    pub fn selection_range(&self) -> Option<std::ops::RangeInclusive<usize>> {
+        let sel = self.selection?;
+        let (start, end) = (sel.anchor.min(sel.cursor), sel.anchor.max(sel.cursor));
+        let evicted = self.output_lines.evicted();
+        if end < evicted {
+            return None;
+        }
+        let start = start.saturating_sub(evicted);
+        let end = (end - evicted).min(self.output_lines.len().checked_sub(1)?);
+        Some(start..=end)
+    }
+
+    fn scroll_output(&mut self, delta: isize) {
+        if let Some(compare) = self.compare.as_mut() {
+            let max = compare.len().saturating_sub(1);
+            compare.scroll = compare.scroll.saturating_add_signed(delta).min(max);
+            return;
+        }
+        let max = self.view_len();
+        self.output_scroll = self.output_scroll.saturating_add_signed(delta).min(max);
+        self.follow = self.output_scroll == 0;
+    }
+
+    fn follow_output(&mut self) {
+        if let Some(compare) = self.compare.as_mut() {
+            compare.scroll = 0;
+        }
+        self.output_scroll = 0;
+        self.follow = true;
+    }
+
+    fn toggle_wrap(&mut self) {
+        self.wrap = !self.wrap;
+        self.output_hscroll = 0;
+    }
+
+    fn toggle_collapse(&mut self) {
+        self.collapse_repeats = !self.collapse_repeats;
+        self.repeat_run = None;
+        let state = if self.collapse_repeats { "on" } else { "off" };
+        self.toasts.info(format!("Collapse repeated lines: {state}"));
+    }
+
+    /// Scrolls unwrapped output sideways, no further than the widest line
+    /// on screen needs.
+    fn scroll_output_sideways(&mut self, delta: isize) {
+        if self.wrap {
+            return;
+        }
+        let area = ui::layout(self.screen, self).output;
+        let widest = ui::output_rows(self, area)
+            .into_iter()
+            .filter_map(|idx| self.output_lines.get(idx))
+            .map(|line| ansi::strip(&line).width())
+            .max()
+            .unwrap_or(0);
+        let max = widest.saturating_sub(area.width.saturating_sub(2) as usize);
+        self.output_hscroll = self.output_hscroll.saturating_add_signed(delta).min(max);
+    }
+
+    /// Absolute number of the line the bookmark keys start from: the
+    /// selection's end, else the bottom line of the view.
+    fn current_line(&self) -> Option<usize> {
+        if let Some(sel) = self.selection {
+            return Some(sel.cursor);
+        }
+        let pos = self.view_len().checked_sub(1)?.saturating_sub(self.output_scroll);
+        Some(self.view_line(pos)? + self.output_lines.evicted())
+    }
+
+    /// Scrolls so absolute line `number` is at the bottom of the view;
+    /// false when it has been trimmed or is filtered out.
+    fn scroll_to_line(&mut self, number: usize) -> bool {
+        let Some(idx) = number.checked_sub(self.output_lines.evicted()) else {
+            return false;
+        };
+        let pos = match self.active_filter() {
+            Some(filter) => filter.position(number),
+            None => (idx < self.output_lines.len()).then_some(idx),
+        };
+        let Some(pos) = pos.and_then(|pos| pos.checked_sub(self.view_skip())) else {
+            return false;
+        };
+        self.output_scroll = self.view_len() - 1 - pos;
+        self.follow = self.output_scroll == 0;
+        true
+    }
+
+    fn toggle_bookmark(&mut self) {
+        if let Some(line) = self.current_line() {
+            self.bookmarks.toggle(line);
+        }
+    }
+
+    /// Scrolls to the next bookmark after the current line (or before it),
+    /// wrapping around.
+    fn jump_bookmark(&mut self, forward: bool) {
+        let from = self.current_line().unwrap_or(0);
+        self.selection = None;
+        let targets = self.bookmarks.cycle(from, forward);
+        if !targets.into_iter().any(|n| self.scroll_to_line(n))
+            && !self.bookmarks.contains(from)
+        {
+            self.add_output_line(if self.bookmarks.is_empty() {
+                "[no bookmarks]"
+            } else {
+                "[no bookmarks in the view]"
+            });
+        }
+    }
+
+    /// The lines of `range` that pass the filter, as plain text.
+    fn shown_lines(&self, range: std::ops::RangeInclusive<usize>) -> Vec<String> {
+        let filter = self.active_filter();
+        range
+            .filter_map(|i| self.output_lines.get(i))
+            .filter(|line| match filter {
+                Some(f) => f.shows(line),
+                None => true,
+            })
+            .map(|line| ansi::strip(&line))
+            .collect()
+    }
+
+    /// Copies the lines of `range` that pass the filter to the clipboard as
+    /// plain text.
+    fn yank(&mut self, what: &str, range: std::ops::RangeInclusive<usize>) {
+        let text = self.shown_lines(range).join("\n");
+        let count = text.lines().count();
+        match self.clipboard.copy(&text) {
+            Ok(via) => self.add_output_line(format!("[copied {count} lines ({what}) via {via}]")),
+            Err(e) => self.report_error(format!("{e:#}")),
+        }
+    }
+
+    fn yank_selection_or_screen(&mut self) {
+        if let Some(range) = self.selection_range() {
+            self.selection = None;
+            self.yank("selection", range);
+            return;
+        }
+        let rows = ui::output_rows(self, ui::layout(self.screen, self).output);
+        if let (Some(&first), Some(&last)) = (rows.first(), rows.last()) {
+            self.yank("screen", first..=last);
+        }
+    }
+
+    /// Opens the byte inspector on the hex dump rows in the selection.
+    fn inspect_selection(&mut self) {
+        let Some(range) = self.selection_range() else {
+            self.add_output_line("[inspect: select hex dump rows with the mouse first]");
+            return;
+        };
+        self.inspector = Inspector::new(&self.shown_lines(range));
+        if self.inspector.is_none() {
+            self.add_output_line("[inspect: no hex dump rows in the selection]");
+        }
+    }
+
+    fn yank_all(&mut self) {
+        if let Some(last) = self.output_lines.len().checked_sub(1) {
+            self.yank("buffer", 0..=last);
+        }
+    }
+
+    fn set_filter(&mut self, expr: &str) {
+        if expr.is_empty() {
+            self.filter = None;
+        } else {
+            match Filter::new(expr) {
+                Ok(mut filter) => {
+                    filter.rebuild(&self.output_lines);
+                    self.filter = Some(filter);
+                }
+                Err(e) => {
+                    self.report_error(format!("{e:#}"));
+                    return;
+                }
+            }
+        }
+        self.follow_output();
+    }
+
+    fn toggle_filter(&mut self) {
+        if let Some(filter) = self.filter.as_mut() {
+            filter.active = !filter.active;
+            self.follow_output();
+        } else {
+            self.open_filter_prompt();
+        }
+    }
+
+    fn open_filter_prompt(&mut self) {
+        self.prompt = Some(Prompt {
+            kind: PromptKind::Filter,
+            buffer: self.filter.as_ref().map(|f| f.expr().to_string()).unwrap_or_default(),
+        });
+    }
+
+    fn toggle_pause(&mut self) {
+        match self.paused_lines.take() {
+            Some(pending) => {
+                let count = pending.len();
+                for line in pending {
+                    self.add_output_line(line);
+                }
+                self.add_output_line(format!("[resumed: {count} new lines]"));
+            }
+            None => {
+                self.add_output_line("[paused]");
+                self.paused_lines = Some(VecDeque::new());
+            }
+        }
+        let paused = self.paused_lines.is_some();
+        if let Some(plot) = self.plot.as_mut() {
+            plot.set_paused(paused);
         }
     }
 }
 
-pub fn run() -> Result<()> {
+pub fn run(args: Args) -> Result<()> {
     enable_raw_mode().context("enable raw mode")?;
     let mut stdout = io::stdout();
     execute!(stdout, terminal::EnterAlternateScreen)?;
@@ -72,11 +1282,13 @@ pub fn run() -> Result<()> {
     let mut terminal = Terminal::new(backend)?;
     terminal.clear()?;
 
-    let res = run_inner(&mut terminal);
+    let res = run_inner(&mut terminal, args);
 
     disable_raw_mode().ok();
     execute!(
         io::stdout(),
+        DisableBracketedPaste,
+        DisableMouseCapture,
         terminal::LeaveAlternateScreen,
         crossterm::cursor::Show
     )
@@ -86,170 +1298,3220 @@ pub fn run() -> Result<()> {
     res
 }
 
-fn run_inner(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> Result<()> {
+fn run_inner(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, args: Args) -> Result<()> {
     let mut app = AppState::new()?;
+    if let Some(elf) = &args.defmt_elf {
+        start_defmt(&mut app, elf);
+    }
+    if let Some(limit) = &args.scrollback {
+        app.output_lines.set_max_lines(limit.max()?);
+    }
+    if let Some(addr) = &args.api {
+        let tx = app.wakeups.tx.clone();
+        let api = api::spawn(addr, move |request| {
+            let _ = tx.send(Wake::Api(request));
+        })?;
+        app.add_output_line(format!("[api] listening on http://{}", api.addr));
+        app.api = Some(api);
+    }
+    if app.mouse {
+        execute!(io::stdout(), EnableMouseCapture)?;
+    }
+    execute!(io::stdout(), EnableBracketedPaste)?;
+
+    offer_restore(&mut app);
+    spawn_input_thread(app.wakeups.tx.clone());
 
-    let tick_rate = Duration::from_millis(100);
     let mut last_tick = Instant::now();
+    let mut raw_screen = false;
+    // Something changed since the last frame.
+    let mut dirty = true;
+    let mut last_draw: Option<Instant> = None;
+    // Received data left over from the last tick.
+    let mut backlog = false;
 
     loop {
-        terminal.draw(|f| ui::draw(f, &app))?;
+        if app.passthrough.is_some() != raw_screen {
+            raw_screen = app.passthrough.is_some();
+            switch_screen(terminal, &app, raw_screen)?;
+        }
+        let frame_due = last_draw.map_or(Duration::ZERO, |t| {
+            app.frame_interval.saturating_sub(t.elapsed())
+        });
+        if dirty && !raw_screen && frame_due.is_zero() {
+            app.screen = terminal.size()?;
+            terminal.draw(|f| ui::draw(f, &app))?;
+            last_draw = Some(Instant::now());
+            dirty = false;
+        }
 
-        let timeout = tick_rate
+        // Transfers are ACK-paced and repeats may be faster than the default
+        // tick, so poll more often while either needs it.
+        let fast_repeat = app
+            .repeat
+            .as_ref()
+            .is_some_and(|r| r.interval < Duration::from_millis(100));
+        let fast_paste = app.paste.is_some() && app.paste_delay < Duration::from_millis(100);
+        let tick_rate = if backlog {
+            Duration::ZERO
+        } else if app.transfer.is_some()
+            || app.flash.is_some()
+            || app.gcode.is_some()
+            || app.passthrough.is_some()
+            || app.replay.is_some()
+            || app.sniff.is_some()
+            || app.ping.as_ref().is_some_and(|p| !p.done())
+            || app.link_testing()
+            || fast_repeat
+            || fast_paste
+        {
+            Duration::from_millis(10)
+        } else {
+            Duration::from_millis(100)
+        };
+
+        let mut timeout = tick_rate
             .checked_sub(last_tick.elapsed())
             .unwrap_or_else(|| Duration::from_millis(0));
+        if dirty {
+            // Wake up for a frame held back by the limit.
+            timeout = timeout.min(frame_due);
+        }
 
-        if crossterm::event::poll(timeout)? {
-            match event::read()? {
-                Event::Key(key) => {
-                    if handle_key_event(&mut app, key)? {
-                        break;
+        match app.wakeups.rx.recv_timeout(timeout) {
+            Ok(Wake::Input(event)) => {
+                dirty = true;
+                match event? {
+                    Event::Key(key) if app.passthrough.is_some() => {
+                        handle_passthrough_key(&mut app, key)?;
                     }
+                    Event::Key(key) if handle_key_event(&mut app, key)? => break,
+                    Event::Mouse(mouse) => handle_mouse_event(&mut app, mouse),
+                    Event::Paste(text) => handle_paste(&mut app, &text)?,
+                    Event::Resize(_, _) => {
+                    }
+                    _ => {}
                 }
-                Event::Resize(_, _) => {
-                }
-                _ => {}
             }
+            Ok(Wake::Api(request)) => {
+                handle_api_request(&mut app, request)?;
+                dirty = true;
+            }
+            Ok(Wake::Serial) => {
+                // Cleared first so events queued meanwhile wake us again.
+                app.wakeups.serial_pending.store(false, Ordering::Release);
+                backlog = drain_serial_events(&mut app)?;
+                drain_sniff_events(&mut app)?;
+                drain_compare_events(&mut app)?;
+                drain_broadcast_events(&mut app);
+                dirty = true;
+            }
+            Err(_) => {}
         }
 
         if last_tick.elapsed() >= tick_rate {
-            drain_serial_events(&mut app)?;
+            backlog = drain_serial_events(&mut app)?;
+            drain_sniff_events(&mut app)?;
+            drain_compare_events(&mut app)?;
+            drain_broadcast_events(&mut app);
+            app.throughput.tick();
+            tick_transfer(&mut app)?;
+            tick_flash(&mut app)?;
+            tick_gcode(&mut app);
+            tick_auto_responses(&mut app)?;
+            tick_repeat(&mut app)?;
+            tick_ping(&mut app)?;
+            tick_link_test(&mut app)?;
+            tick_paste(&mut app)?;
+            tick_replay(&mut app);
+            if app.undo_clear.as_ref().is_some_and(|(_, at)| at.elapsed() > UNDO_CLEAR) {
+                app.undo_clear = None;
+            }
+            drain_script_events(&mut app)?;
+            drain_bridge_events(&mut app)?;
+            drain_mqtt_events(&mut app)?;
+            drain_shell_events(&mut app)?;
+            drain_pipe_events(&mut app);
+            drain_port_changes(&mut app);
             last_tick = Instant::now();
+            dirty = true;
+        }
+    }
+    save_state(&app)
+}
+
+/// Asks whether to pick up where the last run left off.
+fn offer_restore(app: &mut AppState) {
+    let saved = match state::load() {
+        Ok(Some(saved)) if !saved.is_empty() => saved,
+        Ok(_) => return,
+        Err(e) => {
+            app.report_error(format!("{e:#}"));
+            return;
+        }
+    };
+    let port = match &saved.port {
+        Some(port) => format!("{} was open at {}.", port.target, port.summary()),
+        None => "No port was open.".to_string(),
+    };
+    let text = format!(
+        "Restore the session from the last exit? {port} {} output lines.",
+        saved.output.len()
+    );
+    let items = ["Restore", "Start fresh"];
+    let modal = Modal::select("Restore session", text, &items, Then::Restore(Box::new(saved)));
+    app.modal = Some(modal);
+}
+
+fn restore_state(app: &mut AppState, saved: SavedState) {
+    let lines = saved.output.len();
+    for line in saved.output {
+        app.push_output(line);
+    }
+    app.add_output_line(format!("[restored {lines} lines from the last session]"));
+    app.at.restore_history(saved.history);
+    app.at_mode = saved.at_mode;
+    set_input(app, saved.input);
+    if let Some(expr) = saved.filter {
+        app.set_filter(&expr);
+    }
+    if let Some(port) = saved.port {
+        if let Err(e) = open_with(app, port.target.clone(), Some(&port)) {
+            app.report_error(format!("{e:#}"));
+        }
+    }
+    if let Err(e) = state::discard() {
+        app.report_error(format!("{e:#}"));
+    }
+}
+
+/// Keeps what an accidental quit would lose, for [`offer_restore`].
+fn save_state(app: &AppState) -> Result<()> {
+    // Quitting before answering the offer keeps the older session.
+    if let Some(Modal {
+        then: Then::Restore(_),
+        ..
+    }) = &app.modal
+    {
+        return Ok(());
+    }
+    let held = app.paused_lines.iter().flatten().cloned();
+    let total = app.output_lines.len();
+    let start = total.saturating_sub(state::TAIL_LINES);
+    let mut output: Vec<String> = (start..total)
+        .filter_map(|i| app.output_lines.get(i).map(Cow::into_owned))
+        .chain(held)
+        .collect();
+    output.drain(..output.len().saturating_sub(state::TAIL_LINES));
+    state::save(&SavedState {
+        port: current_session(app).filter(|_| app.is_open),
+        output,
+        history: app.at.history().to_vec(),
+        input: app.input_buffer.clone(),
+        filter: app.filter.as_ref().map(|f| f.expr().to_string()),
+        at_mode: app.at_mode,
+    })
+    .context("saving the session")
+}
+
+/// Why the main loop woke up before its tick.
+enum Wake {
+    Input(io::Result<Event>),
+    /// A serial worker queued events.
+    Serial,
+    Api(api::Request),
+}
+
+/// Channel the main loop waits on, fed by the terminal input thread, the
+/// serial workers and the API server.
+struct Wakeups {
+    tx: Sender<Wake>,
+    rx: Receiver<Wake>,
+    /// A `Wake::Serial` is queued and not yet handled, so a busy port
+    /// doesn't queue one per read.
+    serial_pending: Arc<AtomicBool>,
+}
+
+impl Wakeups {
+    fn new() -> Self {
+        let (tx, rx) = mpsc::channel();
+        Self {
+            tx,
+            rx,
+            serial_pending: Arc::default(),
+        }
+    }
+
+    /// Makes `session`'s events wake the main loop.
+    fn watch(&self, session: &Session) {
+        let tx = self.tx.clone();
+        let pending = self.serial_pending.clone();
+        session.set_wake(move || {
+            if !pending.swap(true, Ordering::AcqRel) {
+                let _ = tx.send(Wake::Serial);
+            }
+        });
+    }
+}
+
+/// Reads terminal events on their own thread, so the main loop can wait
+/// for them and serial data at once.
+fn spawn_input_thread(tx: Sender<Wake>) {
+    thread::spawn(move || loop {
+        let event = event::read();
+        let failed = event.is_err();
+        if tx.send(Wake::Input(event)).is_err() || failed {
+            break;
+        }
+    });
+}
+
+/// Leaves the alternate screen for passthrough, so the device's output lands
+/// in the real terminal, and restores the TUI afterwards.
+fn switch_screen(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    app: &AppState,
+    raw: bool,
+) -> Result<()> {
+    if raw {
+        execute!(
+            io::stdout(),
+            DisableMouseCapture,
+            terminal::LeaveAlternateScreen,
+            crossterm::cursor::Show
+        )?;
+        let target = app.session.as_ref().map_or("port", Session::target);
+        let banner = format!("\r\n[passthrough to {target}, Ctrl-A q returns]\r\n");
+        passthrough::echo(banner.as_bytes())?;
+    } else {
+        execute!(io::stdout(), terminal::EnterAlternateScreen)?;
+        if app.mouse {
+            execute!(io::stdout(), EnableMouseCapture)?;
+        }
+        terminal.clear()?;
+    }
+    Ok(())
+}
+
+fn handle_passthrough_key(app: &mut AppState, key: KeyEvent) -> Result<()> {
+    let Some(pass) = app.passthrough.as_mut() else {
+        return Ok(());
+    };
+    match pass.on_key(key) {
+        passthrough::Action::Send(bytes) => write_raw(app, bytes)?,
+        passthrough::Action::Exit => {
+            app.passthrough = None;
+            app.add_output_line("[passthrough ended]");
         }
+        passthrough::Action::None => {}
     }
     Ok(())
 }
 
-fn drain_serial_events(app: &mut AppState) -> Result<()> {
+/// Handles what the port sent since the last tick, up to
+/// `MAX_DRAIN_BYTES`. Returns whether more is waiting.
+fn drain_serial_events(app: &mut AppState) -> Result<bool> {
     let mut drained: Vec<SerialEvent> = Vec::new();
-    if let Some(rx) = app.serial_event_rx.as_ref() {
-        loop {
-            match rx.try_recv() {
-                Ok(ev) => drained.push(ev),
-                Err(std::sync::mpsc::TryRecvError::Empty) => break,
-                Err(std::sync::mpsc::TryRecvError::Disconnected) => break,
+    let mut received = 0;
+    let mut backlog = false;
+    if let Some(session) = app.session.as_ref() {
+        while let Some(ev) = session.try_event() {
+            if let SerialEvent::Data(bytes) = &ev {
+                received += bytes.len();
+            }
+            // Back-to-back reads are handled as one chunk, so decoding and
+            // line splitting run once per tick rather than once per read.
+            match (drained.last_mut(), ev) {
+                (Some(SerialEvent::Data(pending)), SerialEvent::Data(bytes)) => {
+                    pending.extend_from_slice(&bytes);
+                }
+                (_, ev) => drained.push(ev),
+            }
+            if received >= MAX_DRAIN_BYTES {
+                backlog = true;
+                break;
             }
         }
     }
 
     for ev in drained {
+        if let SerialEvent::Data(bytes) = &ev {
+            record(app, Direction::Rx, bytes);
+        }
         match ev {
             SerialEvent::Opened => {
                 app.is_open = true;
+                app.throughput = Throughput::new();
+                app.last_rx = None;
+                // The OS asserts both lines when the port is opened.
+                app.dtr = true;
+                app.rts = true;
+                if app.ports_collapsed() && app.focus == Focus::Ports {
+                    app.focus = Focus::Input;
+                }
                 app.add_output_line("[opened]");
+                app.open_error = None;
+                if let Some(session) = &app.session {
+                    app.toasts.info(format!("Opened {}", session.target()));
+                }
+                remember_session(app);
             }
-            SerialEvent::Data(bytes) => {
-                if let Ok(s) = String::from_utf8(bytes) {
-                    for line in s.split_inclusive(['\n', '\r']).collect::<Vec<_>>() {
-                        app.add_output_line(line.to_string());
-                    }
-                } else {
-                    app.add_output_line("[binary data]");
+            SerialEvent::Data(bytes) if app.transfer.is_some() => {
+                if let Some(transfer) = app.transfer.as_mut() {
+                    let reply = transfer.on_data(&bytes);
+                    write_raw(app, reply)?;
                 }
             }
-            SerialEvent::Error(err) => {
-                app.add_output_line(format!("[error] {err}"));
+            SerialEvent::Data(bytes) if app.link_testing() => {
+                if let Some(test) = app.link_test.as_mut() {
+                    test.on_data(&bytes);
+                }
+            }
+            SerialEvent::Data(bytes) if app.flash.is_some() => {
+                if let Some(flash) = app.flash.as_mut() {
+                    let next = flash.on_data(&bytes);
+                    write_raw(app, next)?;
+                }
+            }
+            SerialEvent::Data(bytes) => {
+                if let Some(bridge) = &app.bridge {
+                    bridge.broadcast(&bytes);
+                }
+                if let Some(sniff) = &app.sniff {
+                    sniff.session.handle().write(bytes.clone())?;
+                }
+                if app.passthrough.is_some() {
+                    passthrough::echo(&bytes)?;
+                }
+                if let Some(gcode) = app.gcode.as_mut() {
+                    let out = gcode.on_data(&bytes);
+                    write_raw(app, out)?;
+                }
+                if let Some(ping) = app.ping.as_mut() {
+                    ping.on_text(&String::from_utf8_lossy(&bytes), Instant::now());
+                }
+                note_idle_gap(app);
+                show_received(app, &bytes);
+            }
+            SerialEvent::ModemStatus(lines) => {
+                app.modem_lines = Some(lines);
+            }
+            SerialEvent::Stats(stats) => {
+                app.throughput.totals = stats;
+            }
+            SerialEvent::TxProgress(progress) => app.tx_progress = Some(progress),
+            SerialEvent::TxDone => app.tx_progress = None,
+            SerialEvent::Error(err) => app.report_error(err),
+            SerialEvent::OpenFailed(failure, message) => {
+                let target = app.session.as_ref().map_or_else(String::new, |s| s.target().into());
+                app.open_error = Some(OpenError {
+                    target,
+                    failure,
+                    message,
+                });
             }
             SerialEvent::Closed => {
+                if let Some(transfer) = app.transfer.as_mut() {
+                    transfer.fail("port closed");
+                }
+                if let Some(flash) = app.flash.as_mut() {
+                    flash.fail("port closed");
+                }
+                if let Some(gcode) = app.gcode.as_mut() {
+                    gcode.fail("port closed");
+                }
+                if let Some(line) = app.line_assembler.flush() {
+                    add_received_line(app, line);
+                }
+                if app.is_open {
+                    remember_session(app);
+                }
                 app.is_open = false;
+                app.modem_lines = None;
+                app.tx_progress = None;
+                app.passthrough = None;
                 app.add_output_line("[closed]");
-                app.serial_handle = None;
-                app.serial_event_rx = None;
+                // A failed open has its own panel.
+                if let Some(session) = app.session.take().filter(|_| app.open_error.is_none()) {
+                    app.toasts.info(format!("Closed {}", session.target()));
+                }
+                stop_sniff(app);
+                stop_compare(app);
+                stop_broadcast(app);
+                clear_profile(app);
+            }
+        }
+    }
+    if let Some(line) = app.line_assembler.flush_due() {
+        add_received_line(app, line);
+    }
+    if let Some(monitor) = app.packets.as_mut() {
+        monitor.tick();
+    }
+    Ok(backlog)
+}
+
+/// Forwards what the second port of sniff mode receives to the first and
+/// shows it in cyan, labeled with the port. It counts as sent for the
+/// packet list and recordings.
+fn drain_sniff_events(app: &mut AppState) -> Result<()> {
+    let mut drained: Vec<SerialEvent> = Vec::new();
+    if let Some(sniff) = app.sniff.as_ref() {
+        while let Some(ev) = sniff.session.try_event() {
+            drained.push(ev);
+        }
+    }
+    let mut lines = Vec::new();
+    for ev in drained {
+        let Some(sniff) = app.sniff.as_mut() else {
+            break;
+        };
+        match ev {
+            SerialEvent::Opened => {
+                let second = sniff.session.target().to_string();
+                let first = app.session.as_ref().map_or("?", Session::target);
+                let line = format!("[sniff] forwarding between {first} and {second}");
+                app.add_output_line(line);
+            }
+            SerialEvent::Data(bytes) => {
+                let text = sniff.decoder.decode(&bytes);
+                lines.extend(sniff.lines.push(&text));
+                if let Some(handle) = app.serial() {
+                    handle.write(bytes.clone())?;
+                }
+                note_sent(app, &bytes);
+            }
+            SerialEvent::Error(err) => app.add_output_line(format!("[sniff] {err}")),
+            SerialEvent::Closed => {
+                lines.extend(sniff.lines.flush());
+                app.sniff = None;
+                app.add_output_line("[sniff] second port closed");
+            }
+            SerialEvent::ModemStatus(_)
+            | SerialEvent::OpenFailed(..)
+            | SerialEvent::Stats(_)
+            | SerialEvent::TxProgress(_)
+            | SerialEvent::TxDone => {}
+        }
+    }
+    lines.extend(app.sniff.as_mut().and_then(|s| s.lines.flush_due()));
+    if let Some(sniff) = &app.sniff {
+        let name = sniff.session.target().to_string();
+        for line in lines {
+            app.add_output_line(format!("\x1b[36m[{name}] {line}\x1b[0m"));
+        }
+    }
+    Ok(())
+}
+
+fn start_sniff(app: &mut AppState, target: &str) -> Result<()> {
+    let Some(session) = &app.session else {
+        app.add_output_line("[sniff] open the first port before the second");
+        return Ok(());
+    };
+    if session.target() == target {
+        app.add_output_line("[sniff] pick a different port than the open one");
+        return Ok(());
+    }
+    let settings = PortSettings {
+        baud_rate: app.baud_rate,
+        parity: app.parity,
+        rs485: app.rs485,
+        exclusive: app.serial.exclusive,
+    };
+    let session = Session::open_with(target, settings)?;
+    app.wakeups.watch(&session);
+    app.serial.apply(&session);
+    app.sniff = Some(Sniff {
+        session,
+        decoder: TextDecoder::new(app.decoder.encoding()),
+        lines: LineAssembler::new(app.line_assembler.flush_after()),
+    });
+    app.add_output_line(format!("[sniff] opening {target}"));
+    Ok(())
+}
+
+fn stop_sniff(app: &mut AppState) {
+    if let Some(sniff) = app.sniff.take() {
+        sniff.session.close().ok();
+        app.add_output_line(format!("[sniff] closed {}", sniff.session.target()));
+    }
+}
+
+/// Collects what the compared port receives, beside the main port's lines.
+fn drain_compare_events(app: &mut AppState) -> Result<()> {
+    let mut drained: Vec<SerialEvent> = Vec::new();
+    if let Some(compare) = app.compare.as_ref() {
+        while let Some(ev) = compare.session.try_event() {
+            drained.push(ev);
+        }
+    }
+    for ev in drained {
+        let Some(compare) = app.compare.as_mut() else {
+            break;
+        };
+        match ev {
+            SerialEvent::Opened => {
+                let line = format!("[compare] showing {} beside", compare.session.target());
+                app.add_output_line(line);
+            }
+            SerialEvent::Data(bytes) => {
+                let text = compare.decoder.decode(&bytes);
+                for line in compare.lines.push(&text) {
+                    compare.push(Side::Right, line);
+                }
+            }
+            SerialEvent::Error(err) => app.add_output_line(format!("[compare] {err}")),
+            SerialEvent::Closed => {
+                app.compare = None;
+                app.add_output_line("[compare] second port closed");
+            }
+            SerialEvent::ModemStatus(_)
+            | SerialEvent::OpenFailed(..)
+            | SerialEvent::Stats(_)
+            | SerialEvent::TxProgress(_)
+            | SerialEvent::TxDone => {}
+        }
+    }
+    if let Some(compare) = app.compare.as_mut() {
+        if let Some(line) = compare.lines.flush_due() {
+            compare.push(Side::Right, line);
+        }
+    }
+    Ok(())
+}
+
+fn start_compare(app: &mut AppState, target: &str) -> Result<()> {
+    let Some(session) = &app.session else {
+        app.add_output_line("[compare] open the first port before the second");
+        return Ok(());
+    };
+    if session.target() == target {
+        app.add_output_line("[compare] pick a different port than the open one");
+        return Ok(());
+    }
+    let settings = PortSettings {
+        baud_rate: app.baud_rate,
+        parity: app.parity,
+        rs485: app.rs485,
+        exclusive: app.serial.exclusive,
+    };
+    let session = Session::open_with(target, settings)?;
+    app.wakeups.watch(&session);
+    app.serial.apply(&session);
+    app.compare = Some(Compare::new(
+        session,
+        TextDecoder::new(app.decoder.encoding()),
+        LineAssembler::new(app.line_assembler.flush_after()),
+    ));
+    app.add_output_line(format!("[compare] opening {target}"));
+    Ok(())
+}
+
+fn stop_compare(app: &mut AppState) {
+    if let Some(compare) = app.compare.take() {
+        compare.session.close().ok();
+        app.add_output_line(format!("[compare] closed {}", compare.session.target()));
+    }
+}
+
+/// Shows what the broadcast ports answer in magenta, labeled with the port.
+fn drain_broadcast_events(app: &mut AppState) {
+    let Some(peers) = app.broadcast.as_mut() else {
+        return;
+    };
+    let mut notes = Vec::new();
+    peers.retain_mut(|peer| {
+        let name = peer.session.target().to_string();
+        let label = |line: String| format!("\x1b[35m[{name}] {line}\x1b[0m");
+        let mut open = true;
+        while let Some(ev) = peer.session.try_event() {
+            match ev {
+                SerialEvent::Data(bytes) => {
+                    let text = peer.decoder.decode(&bytes);
+                    notes.extend(peer.lines.push(&text).into_iter().map(label));
+                }
+                SerialEvent::Error(err) => notes.push(format!("[broadcast] {name}: {err}")),
+                SerialEvent::Closed => {
+                    notes.extend(peer.lines.flush().map(label));
+                    notes.push(format!("[broadcast] {name} closed"));
+                    open = false;
+                }
+                SerialEvent::Opened
+                | SerialEvent::ModemStatus(_)
+                | SerialEvent::OpenFailed(..)
+                | SerialEvent::Stats(_)
+                | SerialEvent::TxProgress(_)
+                | SerialEvent::TxDone => {}
+            }
+        }
+        notes.extend(peer.lines.flush_due().map(label));
+        open
+    });
+    for note in notes {
+        app.add_output_line(note);
+    }
+}
+
+/// Opens the space- or comma-separated ports in `targets` to send typed
+/// lines to as well; the compared port, if any, gets them without being
+/// listed.
+fn start_broadcast(app: &mut AppState, targets: &str) {
+    let Some(session) = &app.session else {
+        app.add_output_line("[broadcast] open a port first");
+        return;
+    };
+    let mut taken = vec![session.target().to_string()];
+    taken.extend(app.compare.as_ref().map(|c| c.session.target().to_string()));
+    let settings = PortSettings {
+        baud_rate: app.baud_rate,
+        parity: app.parity,
+        rs485: app.rs485,
+        exclusive: app.serial.exclusive,
+    };
+    let mut peers = Vec::new();
+    for target in targets.split([' ', ',']).filter(|t| !t.is_empty()) {
+        if taken.iter().any(|t| t == target) {
+            continue;
+        }
+        match Session::open_with(target, settings) {
+            Ok(session) => {
+                app.wakeups.watch(&session);
+                app.serial.apply(&session);
+                peers.push(Peer {
+                    session,
+                    decoder: TextDecoder::new(app.decoder.encoding()),
+                    lines: LineAssembler::new(app.line_assembler.flush_after()),
+                });
+                taken.push(target.to_string());
+            }
+            Err(e) => app.add_output_line(format!("[broadcast] {target}: {e:#}")),
+        }
+    }
+    if peers.is_empty() && app.compare.is_none() {
+        app.add_output_line("[broadcast] no other port to send to");
+        return;
+    }
+    app.broadcast = Some(peers);
+    let names: Vec<&str> = app.broadcast_targets().into_iter().map(Session::target).collect();
+    let line = format!("[broadcast] typed lines also go to {}", names.join(", "));
+    app.add_output_line(line);
+}
+
+/// Closes the ping popup, noting how far a run that's cut short got.
+fn stop_ping(app: &mut AppState) {
+    let Some(mut ping) = app.ping.take() else {
+        return;
+    };
+    if !ping.done() {
+        app.add_output_line(format!("[ping {} stopped: {}]", ping.label, ping.summary()));
+    } else if ping.take_done() {
+        app.add_output_line(format!("[ping {}: {}]", ping.label, ping.summary()));
+    }
+}
+
+fn stop_broadcast(app: &mut AppState) {
+    let Some(peers) = app.broadcast.take() else {
+        return;
+    };
+    for peer in &peers {
+        peer.session.close().ok();
+    }
+    app.add_output_line("[broadcast] off");
+}
+
+/// Decodes received bytes into the output, or the packet list while a
+/// decoder runs.
+/// Puts a dim separator saying how long the port was quiet before data
+/// that ends a silence of `idle_gap` or more, so boot sequences and resets
+/// stand apart.
+fn note_idle_gap(app: &mut AppState) {
+    let now = Instant::now();
+    let (Some(gap), Some(last)) = (app.idle_gap, app.last_rx.replace(now)) else {
+        return;
+    };
+    let idle = now.duration_since(last);
+    if idle < gap {
+        return;
+    }
+    // Anything unterminated from before belongs above the separator.
+    if let Some(line) = app.line_assembler.flush() {
+        add_received_line(app, line);
+    }
+    app.add_output_line(format!("\x1b[2m— {} idle —\x1b[0m", idle_text(idle)));
+}
+
+/// `12.4 s`, `3 min 05 s` or `2 h 10 min`.
+fn idle_text(idle: Duration) -> String {
+    let secs = idle.as_secs();
+    if secs < 60 {
+        format!("{:.1} s", idle.as_secs_f64())
+    } else if secs < 3600 {
+        format!("{} min {:02} s", secs / 60, secs % 60)
+    } else {
+        format!("{} h {:02} min", secs / 3600, secs % 3600 / 60)
+    }
+}
+
+fn show_received(app: &mut AppState, bytes: &[u8]) {
+    if let Some(pipe) = app.pipe.as_mut() {
+        pipe.write(bytes);
+    } else if let Some(monitor) = app.packets.as_mut() {
+        monitor.push(Direction::Rx, bytes);
+    } else if let Some(decoder) = app.defmt.as_mut() {
+        for message in decoder.push(bytes) {
+            add_defmt_message(app, message);
+        }
+    } else if let Some(frames) = app.frames.as_mut() {
+        let name = frames.framing().name();
+        for frame in frames.push(bytes) {
+            add_frame(app, name, frame);
+        }
+    } else {
+        let text = app.decoder.decode(bytes);
+        for line in app.line_assembler.push(&text) {
+            add_received_line(app, line);
+        }
+    }
+}
+
+fn tick_transfer(app: &mut AppState) -> Result<()> {
+    let Some(transfer) = app.transfer.as_mut() else {
+        return Ok(());
+    };
+    let out = transfer.on_tick();
+    write_raw(app, out)?;
+    if app.transfer.as_ref().is_some_and(|t| t.is_finished()) {
+        if let Some(transfer) = app.transfer.take() {
+            app.add_output_line(format!("[transfer] {}", transfer.summary()));
+        }
+    }
+    Ok(())
+}
+
+fn tick_flash(app: &mut AppState) -> Result<()> {
+    let Some(flash) = app.flash.as_mut() else {
+        return Ok(());
+    };
+    let out = flash.on_tick();
+    write_raw(app, out)?;
+    if app.flash.as_ref().is_some_and(|f| f.is_finished()) {
+        if let Some(flash) = app.flash.take() {
+            app.add_output_line(format!("[stm32] {}", flash.summary()));
+        }
+    }
+    Ok(())
+}
+
+fn tick_gcode(app: &mut AppState) {
+    if app.gcode.as_ref().is_some_and(|g| g.is_finished()) {
+        if let Some(gcode) = app.gcode.take() {
+            app.add_output_line(format!("[gcode] {}", gcode.summary()));
+        }
+    }
+}
+
+fn start_gcode(app: &mut AppState, path: &Path, flavor: Flavor) -> Result<()> {
+    if app.session.is_none() {
+        app.add_output_line("[not open]");
+        return Ok(());
+    }
+    if app.gcode.is_some() {
+        app.add_output_line("[gcode] a stream is already running");
+        return Ok(());
+    }
+    let mut gcode = match Streamer::new(path, flavor) {
+        Ok(gcode) => gcode,
+        Err(e) => {
+            app.report_error(format!("{e:#}"));
+            return Ok(());
+        }
+    };
+    app.add_output_line(format!(
+        "[gcode] streaming {} to {} ({} lines)",
+        path.display(),
+        flavor.name(),
+        gcode.total()
+    ));
+    let out = gcode.start();
+    app.gcode = Some(gcode);
+    write_raw(app, out)
+}
+
+fn add_received_line(app: &mut AppState, mut line: String) {
+    // A terminator left on for showing control characters is only shown.
+    let ending = line.split_off(line.trim_end_matches(['\r', '\n']).len());
+    let nmea_error = app.nmea.as_mut().and_then(|t| t.feed(&line)).and_then(Result::err);
+    let at_result = if app.at_mode { app.at.on_line(&line) } else { None };
+    if let Some(plot) = app.plot.as_mut() {
+        plot.push(&line);
+    }
+    app.auto_responder.on_line(&line);
+    if let Some(mqtt) = app.mqtt.as_mut() {
+        mqtt.publish_line(&line);
+    }
+    let repeat = at_result.is_none() && is_repeat(app, &line, &ending);
+    // A repeat is counted on the line it repeats rather than shown.
+    let number = app.next_line_number() - usize::from(repeat);
+    let alert = app.alerts.check(&line).map(|hit| (hit, number, ansi::strip(&line)));
+    if app.bookmarks.matches(&line) {
+        app.bookmarks.insert(number);
+    }
+    if let Some(script) = &app.script {
+        script.feed_line(&line);
+    }
+    let addresses = match &app.esp {
+        Some(_) => espcrash::crash_addresses(&line),
+        None => Vec::new(),
+    };
+    if let Some(compare) = app.compare.as_mut() {
+        compare.push(Side::Left, line.clone());
+    }
+    line.push_str(&ending);
+    if repeat {
+        if let Some(run) = app.repeat_run.as_mut() {
+            run.count += 1;
+            let shown = format!("{} \x1b[2m(x{})\x1b[0m", run.line, run.count);
+            app.output_lines.replace_last(shown);
+        }
+    } else {
+        app.repeat_run = None;
+        match at_result {
+            Some(true) => line = format!("\x1b[1;32m{line}\x1b[0m"),
+            Some(false) => line = format!("\x1b[1;31m{line}\x1b[0m"),
+            None if app.collapse_repeats => {
+                app.repeat_run = Some(RepeatRun { line: line.clone(), count: 1, number });
+            }
+            None => {}
+        }
+        app.add_line(line, Some(Direction::Rx));
+    }
+    if let Some((hit, number, text)) = alert {
+        fire_alert(app, hit, number, &text);
+    }
+    add_esp_frames(app, &addresses);
+    if let Some(e) = nmea_error {
+        app.add_output_line(format!("[nmea] {e:#}"));
+    }
+}
+
+/// A received line and how many times in a row it came.
+struct RepeatRun {
+    line: String,
+    count: usize,
+    /// Absolute number of its output line.
+    number: usize,
+}
+
+/// Whether `line` and its `ending` repeat the received line that is still
+/// the last one shown, so it can be counted there. Not while paused: held
+/// lines aren't in the scrollback yet.
+fn is_repeat(app: &AppState, line: &str, ending: &str) -> bool {
+    app.collapse_repeats
+        && app.paused_lines.is_none()
+        && app.repeat_run.as_ref().is_some_and(|r| {
+            r.line.strip_suffix(ending) == Some(line) && r.number + 1 == app.next_line_number()
+        })
+}
+
+/// Rings, notifies and bookmarks as the alert rules matching output line
+/// `number` asked.
+fn fire_alert(app: &mut AppState, hit: Hit, number: usize, text: &str) {
+    if hit.bookmark {
+        app.bookmarks.insert(number);
+    }
+    let mut result = Ok(());
+    if hit.bell {
+        result = alert::ring_bell();
+    }
+    if hit.notify {
+        result = result.and(alert::notify(&format!("lazyserial: {}", hit.name), text));
+    }
+    if let Err(e) = result {
+        app.add_output_line(format!("[alert] {e:#}"));
+    }
+}
+
+/// Prints what each crash address resolves to under the line it came
+/// from, in `idf_monitor`'s format.
+fn add_esp_frames(app: &mut AppState, addresses: &[u64]) {
+    let Some(esp) = &app.esp else {
+        return;
+    };
+    let mut lines = Vec::new();
+    for &addr in addresses {
+        for (i, frame) in esp.lookup(addr).iter().enumerate() {
+            let at = frame.location.as_deref().unwrap_or("??:?");
+            let head = if i == 0 { format!("{addr:#010x}:") } else { " (inlined by)".to_string() };
+            lines.push(format!("\x1b[33m{head} {} at {at}\x1b[0m", frame.function));
+        }
+    }
+    for line in lines {
+        app.add_output_line(line);
+    }
+}
+
+fn start_esp(app: &mut AppState, elf: &Path) {
+    match Symbolizer::load(elf) {
+        Ok(esp) => {
+            app.add_output_line(format!(
+                "[esp] decoding backtraces with {} functions from {}",
+                esp.len(),
+                elf.display()
+            ));
+            app.esp = Some(esp);
+            app.esp_elf = Some(elf.to_path_buf());
+        }
+        Err(e) => app.report_error(format!("{e:#}")),
+    }
+}
+
+/// Shows a decoded defmt message as `timestamp LEVEL text`, with the level
+/// colored like probe-run does. Continuation lines are indented under the
+/// text.
+fn add_defmt_message(app: &mut AppState, message: Result<defmt::Message>) {
+    let message = match message {
+        Ok(m) => m,
+        Err(e) => {
+            app.add_output_line(format!("[defmt] {e:#}"));
+            return;
+        }
+    };
+    let mut prefix = String::new();
+    if let Some(ts) = &message.timestamp {
+        prefix.push_str(ts);
+        prefix.push(' ');
+    }
+    let indent = prefix.len() + 6;
+    if let Some(level) = message.level {
+        let color = match level {
+            Level::Trace => "90",
+            Level::Debug => "37",
+            Level::Info => "32",
+            Level::Warn => "33",
+            Level::Error => "31",
+        };
+        prefix.push_str(&format!("\x1b[{color}m{:<5}\x1b[0m ", level.name()));
+    } else {
+        prefix.push_str(&" ".repeat(6));
+    }
+    for (i, line) in message.text.lines().enumerate() {
+        if i == 0 {
+            add_received_line(app, format!("{prefix}{line}"));
+        } else {
+            add_received_line(app, format!("{:indent$}{line}", ""));
+        }
+    }
+}
+
+/// Shows one received frame as a header line and a hex dump.
+fn add_frame(app: &mut AppState, name: &str, frame: Result<Vec<u8>>) {
+    match frame {
+        Ok(data) => {
+            app.add_output_line(format!("[{name} frame, {} bytes]", data.len()));
+            for row in hexdump::rows(&data) {
+                app.add_line(format!("  {row}"), Some(Direction::Rx));
+            }
+        }
+        Err(e) => app.add_output_line(format!("[{name}] {e:#}")),
+    }
+}
+
+/// Switches how received bytes are split up; text mode when `framing` is
+/// `None`. Turns defmt decoding off, which does its own framing.
+fn set_framing(app: &mut AppState, framing: FrameMode) {
+    if framing == FrameMode::None {
+        app.frames = None;
+    } else {
+        if let Some(line) = app.line_assembler.flush() {
+            add_received_line(app, line);
+        }
+        app.defmt = None;
+        app.packets = None;
+        app.frames = Some(FrameDecoder::new(framing));
+    }
+    app.add_output_line(format!("[framing: {}]", framing.name()));
+}
+
+/// Runs the packet list with the decoder registered as `name`, or turns it
+/// off for `None`. While on, received bytes go to the packet list rather
+/// than the output.
+fn set_decoder(app: &mut AppState, name: Option<&str>) {
+    let Some(monitor) = name.and_then(|n| app.decoders.monitor(n)) else {
+        if app.packets.take().is_some() {
+            app.add_output_line("[decoder: off]");
+        }
+        return;
+    };
+    if let Some(line) = app.line_assembler.flush() {
+        add_received_line(app, line);
+    }
+    app.defmt = None;
+    app.frames = None;
+    app.add_output_line(format!("[decoder: {} ({})]", monitor.name, monitor.title()));
+    app.packets = Some(monitor);
+}
+
+fn packets_name(app: &AppState) -> Option<&'static str> {
+    app.packets.as_ref().map(|m| m.name)
+}
+
+/// Parses `unit address count` and sends a read-holding-registers request,
+/// turning the monitor on so the reply lands in the packet list.
+fn send_modbus_read(app: &mut AppState, value: &str) -> Result<()> {
+    let fields: Vec<&str> = value.split_whitespace().collect();
+    let parsed = match fields[..] {
+        [unit, addr, count] => parse_number(unit)
+            .and_then(|u| u8::try_from(u).ok())
+            .zip(parse_number(addr))
+            .zip(parse_number(count).filter(|c| (1..=125).contains(c))),
+        _ => None,
+    };
+    let Some(((unit, addr), count)) = parsed else {
+        app.add_output_line("[modbus] expected: unit address count (count 1-125)");
+        return Ok(());
+    };
+    let Some(handle) = app.serial() else {
+        app.add_output_line("[not open]");
+        return Ok(());
+    };
+    let request = modbus::read_request(unit, 3, addr, count);
+    handle.write(request.clone())?;
+    if packets_name(app) != Some("modbus") {
+        set_decoder(app, Some("modbus"));
+    }
+    note_sent(app, &request);
+    Ok(())
+}
+
+/// Decimal or `0x` hex.
+fn parse_number(s: &str) -> Option<u16> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+/// Passes bytes we wrote to the packet list and the session recording.
+fn note_sent(app: &mut AppState, data: &[u8]) {
+    if let Some(monitor) = app.packets.as_mut() {
+        monitor.push(Direction::Tx, data);
+    }
+    record(app, Direction::Tx, data);
+}
+
+/// Appends to the session recording, stopping it if the file fails.
+fn record(app: &mut AppState, direction: Direction, data: &[u8]) {
+    let Some(recorder) = app.recorder.as_mut() else {
+        return;
+    };
+    if let Err(e) = recorder.record(direction, data) {
+        app.recorder = None;
+        app.add_output_line(format!("[record] stopped: {e:#}"));
+    }
+}
+
+/// Puts a bookmarked marker line for `at` in the output, and the marker in
+/// the session recording.
+fn insert_marker(app: &mut AppState, at: SystemTime, note: &str) {
+    let mut line = format!("──── mark {}", export::iso8601(at));
+    if !note.is_empty() {
+        line.push_str(": ");
+        line.push_str(note);
+    }
+    let number = app.next_line_number();
+    app.add_output_line(format!("\x1b[7m{line} ────\x1b[0m"));
+    app.bookmarks.insert(number);
+    let Some(recorder) = app.recorder.as_mut() else {
+        return;
+    };
+    if let Err(e) = recorder.mark(at, note) {
+        app.recorder = None;
+        app.add_output_line(format!("[record] stopped: {e:#}"));
+    }
+}
+
+/// Stops recording, or asks where to record to.
+fn toggle_record(app: &mut AppState) {
+    if let Some(recorder) = app.recorder.take() {
+        app.add_output_line(format!(
+            "[record] saved {} records to {}",
+            recorder.records,
+            recorder.path.display()
+        ));
+        return;
+    }
+    app.prompt = Some(Prompt {
+        kind: PromptKind::Record,
+        buffer: "session.capture".to_string(),
+    });
+}
+
+/// Stops a running replay, or asks for a capture to play.
+fn toggle_replay(app: &mut AppState) {
+    if app.replay.take().is_some() {
+        app.add_output_line("[replay] stopped");
+        return;
+    }
+    if app.session.is_some() {
+        app.add_output_line("[replay] close the port first");
+        return;
+    }
+    app.prompt = Some(Prompt {
+        kind: PromptKind::Replay(Speed::default()),
+        buffer: String::new(),
+    });
+}
+
+fn start_replay(app: &mut AppState, path: &Path, speed: Speed) {
+    match Capture::load(path) {
+        Ok(capture) => {
+            let replay = Replay::new(capture, speed);
+            app.add_output_line(format!(
+                "[replay] {} records over {:.1}s from {} at {}",
+                replay.len(),
+                replay.duration().as_secs_f64(),
+                path.display(),
+                speed.name()
+            ));
+            app.replay = Some(replay);
+        }
+        Err(e) => app.report_error(format!("{e:#}")),
+    }
+}
+
+/// Feeds due capture records through the same path as live traffic. Sent
+/// bytes are shown as `>>` lines.
+/// Clears the view, or with `all` the whole scrollback, keeping what went
+/// for undoing.
+fn clear_output(app: &mut AppState, all: bool) {
+    let cleared = if all {
+        let old = app.output_lines.take();
+        if let Some(filter) = app.filter.as_mut() {
+            filter.rebuild(&app.output_lines);
+        }
+        Cleared::Scrollback(old, app.directions.clone(), app.view_start)
+    } else {
+        Cleared::View(app.view_start)
+    };
+    app.view_start = app.output_lines.evicted() + app.output_lines.len();
+    app.repeat_run = None;
+    app.selection = None;
+    app.follow_output();
+    app.undo_clear = Some((cleared, Instant::now()));
+    let what = if all { "Scrollback cleared" } else { "Output cleared" };
+    app.toasts.info(format!("{what}; {}: undo", app.keymap.label(Action::UndoClear)));
+}
+
+/// Shows control characters as symbols, and from now on line endings too,
+/// or goes back to rendering the output.
+fn toggle_control_chars(app: &mut AppState) {
+    // The line in progress ends as it began.
+    if let Some(line) = app.line_assembler.flush() {
+        add_received_line(app, line);
+    }
+    app.control_chars = !app.control_chars;
+    app.line_assembler.set_keep_terminators(app.control_chars);
+    let state = if app.control_chars { "shown as symbols" } else { "hidden" };
+    app.toasts.info(format!("Control characters: {state}"));
+}
+
+fn undo_clear(app: &mut AppState) {
+    let Some((cleared, _)) = app.undo_clear.take() else {
+        app.add_output_line("[undo] nothing cleared to undo");
+        return;
+    };
+    match cleared {
+        Cleared::View(start) => app.view_start = start,
+        Cleared::Scrollback(old, directions, start) => {
+            let from = app.output_lines.evicted();
+            if !app.output_lines.restore(old) {
+                app.add_output_line("[undo] too much output since the clear to put it back");
+                return;
+            }
+            app.directions.restore(directions, from);
+            app.view_start = start;
+            if let Some(filter) = app.filter.as_mut() {
+                filter.rebuild(&app.output_lines);
+            }
+        }
+    }
+    app.follow_output();
+    app.toasts.info("Clear undone");
+}
+
+fn show_buffer_usage(app: &mut AppState) {
+    let usage = app.output_lines.usage();
+    let limits = usage.limits;
+    let kept = match limits.max_lines {
+        Some(max) => format!("at most {max}"),
+        None => "no line limit".to_string(),
+    };
+    let disk = match limits.max_disk_bytes {
+        0 => "no disk spill (scrollback_disk_mb = 0)".to_string(),
+        max => format!(
+            "{} lines on disk, {} of {}",
+            usage.disk_lines,
+            ui::format_bytes(usage.disk_bytes as f64),
+            ui::format_bytes(max as f64)
+        ),
+    };
+    let hidden = app.view_start.saturating_sub(usage.evicted).min(app.output_lines.len());
+    let text = format!(
+        "{} lines in the scrollback, {kept}.\n{} lines in memory, {} of {}.\n{disk}.\n{} \
+         older lines dropped or cleared.\n{hidden} lines hidden by a clear.",
+        app.output_lines.len(),
+        usage.ram_lines,
+        ui::format_bytes(usage.ram_bytes as f64),
+        ui::format_bytes(limits.max_ram_bytes as f64),
+        usage.evicted,
+    );
+    app.modal = Some(Modal::message("Scrollback", text));
+}
+
+fn tick_replay(app: &mut AppState) {
+    let Some(replay) = app.replay.as_mut() else {
+        return;
+    };
+    let records = replay.due();
+    let finished = replay.is_finished();
+    for record in records {
+        match record.direction {
+            Direction::Rx => show_received(app, &record.data),
+            Direction::Tx => {
+                if let Some(monitor) = app.packets.as_mut() {
+                    monitor.push(Direction::Tx, &record.data);
+                } else {
+                    let text = String::from_utf8_lossy(&record.data);
+                    app.add_sent_line(format!(">> {}", text.escape_debug()));
+                }
+            }
+        }
+    }
+    if let Some(line) = app.line_assembler.flush_due() {
+        add_received_line(app, line);
+    }
+    if let Some(monitor) = app.packets.as_mut() {
+        monitor.tick();
+    }
+    if finished {
+        if let Some(line) = app.line_assembler.flush() {
+            add_received_line(app, line);
+        }
+        app.replay = None;
+        app.add_output_line("[replay] finished");
+    }
+}
+
+fn start_defmt(app: &mut AppState, elf: &Path) {
+    match defmt::Table::load(elf) {
+        Ok(table) => {
+            let framing = match table.framing() {
+                defmt::Framing::Rzcobs => "rzcobs",
+                defmt::Framing::Raw => "raw",
+            };
+            app.add_output_line(format!(
+                "[defmt] decoding with {} strings from {} ({framing})",
+                table.len(),
+                elf.display()
+            ));
+            if let Some(line) = app.line_assembler.flush() {
+                add_received_line(app, line);
+            }
+            app.frames = None;
+            app.packets = None;
+            app.defmt = Some(defmt::Decoder::new(table));
+            app.defmt_elf = Some(elf.to_path_buf());
+        }
+        Err(e) => app.report_error(format!("{e:#}")),
+    }
+}
+
+fn drain_script_events(app: &mut AppState) -> Result<()> {
+    let mut drained: Vec<ScriptEvent> = Vec::new();
+    if let Some(rx) = app.script_event_rx.as_ref() {
+        while let Ok(ev) = rx.try_recv() {
+            drained.push(ev);
+        }
+    }
+
+    for ev in drained {
+        match ev {
+            ScriptEvent::Log(msg) => app.add_output_line(format!("[script] {msg}")),
+            ScriptEvent::Send(data) => match app.serial() {
+                Some(handle) => {
+                    handle.write(data.clone())?;
+                    note_sent(app, &data);
+                    app.add_sent_line(format!(
+                        ">> {}",
+                        String::from_utf8_lossy(&data).escape_debug()
+                    ));
+                }
+                None => app.add_output_line("[script] send failed: not open"),
+            },
+            ScriptEvent::Break(duration) => match app.serial() {
+                Some(handle) => {
+                    handle.send_break(duration)?;
+                    app.add_output_line(format!("[script] break {} ms", duration.as_millis()));
+                }
+                None => app.add_output_line("[script] break failed: not open"),
+            },
+            ScriptEvent::Finished(result) => {
+                let name = app.script.take().map(|s| s.name).unwrap_or_default();
+                app.script_event_rx = None;
+                match result {
+                    Ok(()) => app.add_output_line(format!("[script] {name} finished")),
+                    Err(e) => app.add_output_line(format!("[script] {name} failed: {e}")),
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn drain_bridge_events(app: &mut AppState) -> Result<()> {
+    let mut drained: Vec<BridgeEvent> = Vec::new();
+    if let Some(rx) = app.bridge_event_rx.as_ref() {
+        while let Ok(ev) = rx.try_recv() {
+            drained.push(ev);
+        }
+    }
+
+    for ev in drained {
+        match ev {
+            BridgeEvent::Connected(peer) => {
+                app.add_output_line(format!("[bridge] {peer} connected"));
+            }
+            BridgeEvent::Disconnected(peer) => {
+                app.add_output_line(format!("[bridge] {peer} disconnected"));
+            }
+            BridgeEvent::Error(e) => app.add_output_line(format!("[bridge] {e}")),
+            // Client bytes would corrupt a running transfer, so drop them.
+            BridgeEvent::Data(_, _) if app.transfer.is_some() || app.flash.is_some() => {}
+            BridgeEvent::Data(peer, data) => {
+                if let Some(handle) = app.serial() {
+                    handle.write(data.clone())?;
+                    note_sent(app, &data);
+                    app.add_output_line(format!(
+                        "[{peer}] >> {}",
+                        String::from_utf8_lossy(&data).escape_debug()
+                    ));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Answers a request to the `--api` server.
+fn handle_api_request(app: &mut AppState, request: api::Request) -> Result<()> {
+    match &request.command {
+        api::Command::Ports => {
+            let open = app.session.as_ref().map(Session::target);
+            let ports: Vec<serde_json::Value> = app
+                .ports
+                .iter()
+                .map(|p| {
+                    serde_json::json!({
+                        "name": p.port_name,
+                        "description": app.port_note(p),
+                        "open": open == Some(p.port_name.as_str()),
+                    })
+                })
+                .collect();
+            request.ok(ports.into());
+        }
+        api::Command::Open(_) if app.session.is_some() => {
+            request.fail(409, "a port is already open");
+        }
+        api::Command::Open(open) => {
+            if let Some(baud) = open.baud {
+                app.baud_rate = baud;
+            }
+            match open_target(app, open.port.clone()) {
+                Ok(()) => {
+                    let target = app.session.as_ref().map(Session::target);
+                    request.ok(serde_json::json!({ "port": target, "baud": app.baud_rate }));
+                }
+                Err(e) => {
+                    app.report_error(format!("{e:#}"));
+                    request.fail(400, format!("{e:#}"));
+                }
+            }
+        }
+        api::Command::Close if app.session.is_none() => request.fail(409, "not open"),
+        api::Command::Close => {
+            close_port(app)?;
+            request.ok(serde_json::json!({}));
+        }
+        api::Command::Send(_) if app.serial().is_none() => request.fail(409, "not open"),
+        api::Command::Send(_) if tx_busy(app) => request.fail(409, "still sending"),
+        api::Command::Send(send) if send.line_ending => {
+            let text = send.text.clone();
+            send_line(app, &text)?;
+            request.ok(serde_json::json!({}));
+        }
+        api::Command::Send(send) => {
+            let data = send.text.as_bytes().to_vec();
+            if let Some(handle) = app.serial() {
+                handle.write(data.clone())?;
+            }
+            note_sent(app, &data);
+            let line = format!(">> {}", caret_text(&send.text));
+            app.add_sent_line(line);
+            request.ok(serde_json::json!({}));
+        }
+    }
+    Ok(())
+}
+
+fn drain_mqtt_events(app: &mut AppState) -> Result<()> {
+    let mut drained: Vec<MqttEvent> = Vec::new();
+    if let Some(rx) = app.mqtt_event_rx.as_ref() {
+        while let Ok(ev) = rx.try_recv() {
+            drained.push(ev);
+        }
+    }
+
+    for ev in drained {
+        match ev {
+            MqttEvent::Connected => {
+                if let Some(mqtt) = &app.mqtt {
+                    let line = format!("[mqtt] connected to {}", mqtt.broker);
+                    app.add_output_line(line);
+                }
+            }
+            MqttEvent::Error(e) => app.add_output_line(format!("[mqtt] {e}")),
+            // Would corrupt a running transfer, so dropped.
+            MqttEvent::Message(_) if app.transfer.is_some() || app.flash.is_some() => {}
+            MqttEvent::Message(data) => {
+                if let Some(handle) = app.serial() {
+                    handle.write(data.clone())?;
+                    note_sent(app, &data);
+                    app.add_output_line(format!(
+                        "[mqtt] >> {}",
+                        String::from_utf8_lossy(&data).escape_debug()
+                    ));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn drain_port_changes(app: &mut AppState) {
+    let mut latest = None;
+    while let Ok(ports) = app.port_watch_rx.try_recv() {
+        latest = Some(ports);
+    }
+    if let Some((rx, asked)) = app.bluetooth_rx.take() {
+        loop {
+            match rx.try_recv() {
+                Ok((le, Ok(devices))) => {
+                    if asked {
+                        let kind = if le { "BLE UART" } else { "Bluetooth serial" };
+                        let count = devices.len();
+                        app.add_output_line(format!("[bluetooth] {kind} devices found: {count}"));
+                    }
+                    app.bluetooth.retain(|d| d.le != le);
+                    app.bluetooth.extend(devices);
+                    // Re-listed with the new devices.
+                    latest = latest.or_else(|| {
+                        Some(app.ports.iter().filter(|p| is_system_port(app, p)).cloned().collect())
+                    });
+                }
+                Ok((_, Err(e))) => {
+                    if asked {
+                        app.add_output_line(format!("[bluetooth] {e}"));
+                    }
+                }
+                Err(mpsc::TryRecvError::Empty) => {
+                    app.bluetooth_rx = Some((rx, asked));
+                    break;
+                }
+                Err(mpsc::TryRecvError::Disconnected) => break,
+            }
+        }
+    }
+    if let Some(ports) = latest {
+        update_ports(app, ports);
+    }
+}
+
+/// Whether `port` is a discovered Bluetooth device rather than a port.
+pub fn is_bluetooth(port: &serialport::SerialPortInfo) -> bool {
+    is_bluetooth_target(&port.port_name)
+}
+
+fn is_bluetooth_target(name: &str) -> bool {
+    name.starts_with(bluetooth::PREFIX) || name.starts_with(ble::PREFIX)
+}
+
+/// Whether `port` came from the system's port list, rather than being the
+/// virtual device or a Bluetooth device added to it.
+fn is_system_port(app: &AppState, port: &serialport::SerialPortInfo) -> bool {
+    match app.virtual_device() {
+        _ if is_bluetooth(port) => false,
+        Some((path, _)) => path != port.port_name,
+        None => true,
+    }
+}
+
+/// Lists Bluetooth serial devices in the background, after scanning for
+/// nearby ones with `scan`; BLE UART devices are only found by scanning.
+fn discover_bluetooth(app: &mut AppState, scan: bool) {
+    if app.bluetooth_rx.is_some() {
+        return;
+    }
+    let (tx, rx) = mpsc::channel();
+    if scan {
+        let tx = tx.clone();
+        thread::spawn(move || tx.send((true, ble::scan(bluetooth::SCAN_TIME))));
+    }
+    thread::spawn(move || tx.send((false, bluetooth::devices(scan))));
+    app.bluetooth_rx = Some((rx, scan));
+    if scan {
+        let secs = bluetooth::SCAN_TIME.as_secs();
+        app.add_output_line(format!("[bluetooth] scanning for {secs} s..."));
+    }
+}
+
+/// Replaces the port list, noting attached and detached devices and keeping
+/// the selection on the same port where it still exists.
+fn update_ports(app: &mut AppState, mut ports: Vec<serialport::SerialPortInfo>) {
+    serial::pair_callout(&mut ports, app.serial.prefer_callout);
+    ports.extend(app.virtual_port());
+    ports.extend(app.bluetooth_ports());
+    // Bluetooth devices come and go with discovery, not plugging.
+    let plugged = |name: &&str| !is_bluetooth_target(name);
+    let attached: Vec<String> = hotplug::added(&app.ports, &ports)
+        .into_iter()
+        .filter(plugged)
+        .map(str::to_string)
+        .collect();
+    let detached: Vec<String> = hotplug::added(&ports, &app.ports)
+        .into_iter()
+        .filter(plugged)
+        .map(str::to_string)
+        .collect();
+    for name in attached {
+        app.add_output_line(format!("[attached {name}]"));
+    }
+    for name in detached {
+        app.add_output_line(format!("[detached {name}]"));
+    }
+    let selected = app
+        .selected_port
+        .and_then(|i| app.ports.get(i))
+        .map(|p| p.port_name.clone());
+    profile::sort_ports(&mut ports, &app.favorites);
+    app.selected_port = selected
+        .and_then(|name| ports.iter().position(|p| p.port_name == name))
+        .or((!ports.is_empty()).then_some(0));
+    app.ports = ports;
+    app.refilter_ports();
+}
+
+fn toggle_bridge(app: &mut AppState) {
+    if let Some(bridge) = app.bridge.take() {
+        bridge.stop();
+        app.bridge_event_rx = None;
+        app.add_output_line(format!("[bridge] stopped listening on {}", bridge.url()));
+        return;
+    }
+    app.prompt = Some(Prompt {
+        kind: PromptKind::Bridge,
+        buffer: "0.0.0.0:7000".to_string(),
+    });
+}
+
+fn start_mqtt(app: &mut AppState) {
+    match mqtt::spawn(&app.mqtt_config) {
+        Ok((handle, rx)) => {
+            app.add_output_line(format!(
+                "[mqtt] publishing received lines to {} on {}",
+                handle.topic, handle.broker
+            ));
+            app.mqtt = Some(handle);
+            app.mqtt_event_rx = Some(rx);
+        }
+        Err(e) => app.add_output_line(format!("[mqtt] {e:#}")),
+    }
+}
+
+fn toggle_mqtt(app: &mut AppState) {
+    if let Some(mqtt) = app.mqtt.take() {
+        mqtt.stop();
+        app.mqtt_event_rx = None;
+        app.add_output_line(format!("[mqtt] stopped publishing to {}", mqtt.broker));
+    } else if app.mqtt_config.broker.is_none() {
+        app.add_output_line("[mqtt] no broker set in [mqtt] in config.toml");
+    } else {
+        start_mqtt(app);
+    }
+}
+
+fn tick_auto_responses(app: &mut AppState) -> Result<()> {
+    for (rule, response) in app.auto_responder.take_due() {
+        let Some(handle) = app.serial() else {
+            continue;
+        };
+        handle.write(response.clone().into_bytes())?;
+        note_sent(app, response.as_bytes());
+        app.add_output_line(format!("[auto:{}] >> {}", rule, response.escape_debug()));
+    }
+    Ok(())
+}
+
+fn tick_repeat(app: &mut AppState) -> Result<()> {
+    if tx_backlog(app).is_some() {
+        // Sent once the port catches up; the rate slips meanwhile.
+        return Ok(());
+    }
+    let Some(repeat) = app.repeat.as_mut() else {
+        return Ok(());
+    };
+    if Instant::now() < repeat.next {
+        return Ok(());
+    }
+    let Some(session) = &app.session else {
+        app.repeat = None;
+        app.add_output_line("[repeat stopped: not open]");
+        return Ok(());
+    };
+    let payload = repeat.payload.clone();
+    session.handle().write(payload.clone())?;
+    repeat.count += 1;
+    // Schedule from the previous deadline so slow ticks don't drift the rate.
+    repeat.next += repeat.interval;
+    if repeat.next < Instant::now() {
+        repeat.next = Instant::now() + repeat.interval;
+    }
+    let line = format!(">> {}", repeat.label);
+    note_sent(app, &payload);
+    app.add_sent_line(line);
+    Ok(())
+}
+
+/// Sends the next ping probe when it's due, without echoing it, and notes
+/// the results in the output when the run completes.
+fn tick_ping(app: &mut AppState) -> Result<()> {
+    let Some(ping) = app.ping.as_mut() else {
+        return Ok(());
+    };
+    if let Some(probe) = ping.poll(Instant::now()) {
+        write_raw(app, probe)?;
+    } else if ping.take_done() {
+        let line = format!("[ping {}: {}]", ping.label, ping.summary());
+        app.add_output_line(line);
+    }
+    Ok(())
+}
+
+/// Keeps the port's send queue topped up with the test pattern, and notes
+/// the results in the output once everything has come back or stopped
+/// coming.
+fn tick_link_test(app: &mut AppState) -> Result<()> {
+    let Some(queued) = app.serial().map(SerialHandle::queued) else {
+        if app.link_testing() {
+            stop_link_test(app);
+        }
+        return Ok(());
+    };
+    let Some(test) = app.link_test.as_mut() else {
+        return Ok(());
+    };
+    if test.is_finished() {
+        return Ok(());
+    }
+    let chunk = test.next_chunk(LINK_TEST_QUEUE.saturating_sub(queued));
+    write_raw(app, chunk)?;
+    if let Some(test) = app.link_test.as_mut() {
+        if test.finish_if_idle(LINK_TEST_IDLE) {
+            let line = format!("[link test: {}]", link_test_summary(test));
+            app.add_output_line(line);
+        }
+    }
+    Ok(())
+}
+
+fn link_test_summary(test: &LinkTest) -> String {
+    let report = test.report();
+    let ber = match report.bit_error_rate() {
+        Some(ber) => format!("{ber:.2e}"),
+        None => "-".to_string(),
+    };
+    format!(
+        "{} {}/{} bytes back at {}/s, {} bit errors (BER {ber}) in {} bytes, {} dropped, {} extra",
+        test.pattern.name(),
+        report.received,
+        report.sent,
+        ui::format_bytes(test.throughput()),
+        report.bit_errors,
+        report.bad_bytes,
+        report.dropped,
+        report.extra
+    )
+}
+
+/// Closes the link test popup, ending a run still going.
+fn stop_link_test(app: &mut AppState) {
+    let Some(mut test) = app.link_test.take() else {
+        return;
+    };
+    if !test.is_finished() {
+        test.finish();
+        app.add_output_line(format!("[link test stopped: {}]", link_test_summary(&test)));
+    }
+}
+
+fn start_repeat(app: &mut AppState, label: String, payload: Vec<u8>) {
+    if app.session.is_none() {
+        app.add_output_line("[not open]");
+        return;
+    }
+    app.add_output_line(format!(
+        "[repeat every {} ms: {}]",
+        app.repeat_interval.as_millis(),
+        label
+    ));
+    app.repeat = Some(Repeat {
+        label,
+        payload,
+        interval: app.repeat_interval,
+        count: 0,
+        next: Instant::now(),
+    });
+}
+
+fn stop_repeat(app: &mut AppState) {
+    if let Some(repeat) = app.repeat.take() {
+        app.add_output_line(format!("[repeat stopped after {} sends]", repeat.count));
+    }
+}
+
+fn write_raw(app: &mut AppState, data: Vec<u8>) -> Result<()> {
+    if data.is_empty() {
+        return Ok(());
+    }
+    match app.serial() {
+        Some(handle) => {
+            handle.write(data.clone())?;
+            record(app, Direction::Tx, &data);
+            Ok(())
+        }
+        None => {
+            if let Some(transfer) = app.transfer.as_mut() {
+                transfer.fail("port not open");
+            }
+            if let Some(flash) = app.flash.as_mut() {
+                flash.fail("port not open");
+            }
+            if let Some(gcode) = app.gcode.as_mut() {
+                gcode.fail("port not open");
+            }
+            Ok(())
+        }
+    }
+}
+
+fn handle_key_event(app: &mut AppState, key: KeyEvent) -> Result<bool> {
+    // Ahead of everything else so that ^V ^C types a Ctrl-C instead of
+    // quitting.
+    if app.input_literal && app.focus == Focus::Input {
+        app.input_literal = false;
+        if let Some(c) = literal_char(key) {
+            app.input_buffer.insert(app.input_cursor, c);
+            app.input_cursor += c.len_utf8();
+        }
+        return Ok(false);
+    }
+    if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+        return Ok(true);
+    }
+    if app.modal.is_some() {
+        return handle_modal_key(app, key);
+    }
+    if app.prompt.is_some() {
+        return handle_prompt_key(app, key).map(|_| false);
+    }
+    if app.auto_response_view.is_some() {
+        handle_auto_response_key(app, key);
+        return Ok(false);
+    }
+    if app.macro_view.is_some() {
+        handle_macro_view_key(app, key);
+        return Ok(false);
+    }
+    if app.highlight_view.is_some() {
+        handle_highlight_view_key(app, key);
+        return Ok(false);
+    }
+    if app.help_view.is_some() {
+        handle_help_key(app, key);
+        return Ok(false);
+    }
+    if app.error_view.is_some() {
+        handle_error_view_key(app, key);
+        return Ok(false);
+    }
+    if app.compose.is_some() {
+        return handle_compose_key(app, key).map(|_| false);
+    }
+    if let Some(input) = app.checksum_view.as_mut() {
+        match key.code {
+            KeyCode::Esc | KeyCode::Enter => app.checksum_view = None,
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Char(c) if Key::from_event(key).is_text() => input.push(c),
+            _ => {}
+        }
+        return Ok(false);
+    }
+    if app.ping.is_some() {
+        if matches!(key.code, KeyCode::Esc | KeyCode::Enter) {
+            stop_ping(app);
+        }
+        return Ok(false);
+    }
+    if app.link_test.is_some() {
+        if matches!(key.code, KeyCode::Esc | KeyCode::Enter) {
+            stop_link_test(app);
+        }
+        return Ok(false);
+    }
+    if let Some(inspector) = app.inspector.as_mut() {
+        if inspector.on_key(key) {
+            app.inspector = None;
+        }
+        return Ok(false);
+    }
+    if app.palette.is_some() {
+        return handle_palette_key(app, key);
+    }
+    if let Some(transfer) = app.transfer.as_mut() {
+        if key.code == KeyCode::Esc {
+            let out = transfer.cancel();
+            write_raw(app, out)?;
+        }
+        return Ok(false);
+    }
+    if let Some(flash) = app.flash.as_mut() {
+        if key.code == KeyCode::Esc {
+            flash.cancel();
+        }
+        return Ok(false);
+    }
+    if app.port_filter_editing && app.focus == Focus::Ports && handle_port_filter_key(app, key)? {
+        return Ok(false);
+    }
+    if let Some(action) = app.keymap.lookup(app.focus, key) {
+        return run_action(app, action);
+    }
+    if let KeyCode::F(n) = key.code {
+        if (1..=macros::MACRO_COUNT as u8).contains(&n) {
+            send_macro(app, (n - 1) as usize)?;
+        }
+        return Ok(false);
+    }
+    if app.focus == Focus::Input {
+        handle_input_key(app, key);
+    }
+    Ok(false)
+}
+
+/// Typing into the port filter. Enter opens the best match, Esc clears the
+/// filter; keys not handled here end editing and go to the keymap.
+fn handle_port_filter_key(app: &mut AppState, key: KeyEvent) -> Result<bool> {
+    match key.code {
+        KeyCode::Esc => {
+            app.port_filter.clear();
+            app.port_filter_editing = false;
+            app.refilter_ports();
+        }
+        KeyCode::Enter => {
+            app.port_filter_editing = false;
+            if !app.is_open && app.selected_port.is_some() {
+                toggle_port(app)?;
             }
         }
+        KeyCode::Up => move_selection(app, -1),
+        KeyCode::Down => move_selection(app, 1),
+        KeyCode::Backspace => {
+            app.port_filter.pop();
+            app.refilter_ports();
+        }
+        KeyCode::Char(c) if Key::from_event(key).is_text() => {
+            app.port_filter.push(c);
+            // The best match, not whichever port was selected before.
+            app.selected_port = None;
+            app.refilter_ports();
+        }
+        _ => {
+            app.port_filter_editing = false;
+            return Ok(false);
+        }
     }
-    Ok(())
+    Ok(true)
 }
 
-fn handle_key_event(app: &mut AppState, key: KeyEvent) -> Result<bool> {
-    if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
-        return Ok(true);
+/// Text editing for keys the keymap leaves to the Input pane.
+fn handle_input_key(app: &mut AppState, key: KeyEvent) {
+    if app.at_mode {
+        let recalled = match key.code {
+            KeyCode::Up => app.at.history_prev(&app.input_buffer),
+            KeyCode::Down => app.at.history_next(),
+            KeyCode::Right if app.input_cursor == app.input_buffer.len() => app
+                .at
+                .suggestions(&app.input_buffer)
+                .into_iter()
+                .next()
+                .map(|s| s.command),
+            _ => None,
+        };
+        if let Some(text) = recalled {
+            set_input(app, text);
+            return;
+        }
     }
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+    let text = &app.input_buffer;
+    let cursor = app.input_cursor;
     match key.code {
-        KeyCode::Char('q') => return Ok(true),
-        KeyCode::Tab => {
+        KeyCode::Left if ctrl => app.input_cursor = word_start(text, cursor),
+        KeyCode::Right if ctrl => app.input_cursor = word_end(text, cursor),
+        KeyCode::Left => app.input_cursor = prev_char(text, cursor),
+        KeyCode::Right => app.input_cursor = next_char(text, cursor),
+        KeyCode::Home => app.input_cursor = 0,
+        KeyCode::End => app.input_cursor = text.len(),
+        KeyCode::Backspace => {
+            let start = prev_char(text, cursor);
+            app.input_buffer.replace_range(start..cursor, "");
+            app.input_cursor = start;
+        }
+        KeyCode::Delete => {
+            let end = next_char(text, cursor);
+            app.input_buffer.replace_range(cursor..end, "");
+        }
+        KeyCode::Char('k') if ctrl => app.input_buffer.truncate(cursor),
+        KeyCode::Char(c) if Key::from_event(key).is_text() => {
+            app.input_buffer.insert(cursor, c);
+            app.input_cursor += c.len_utf8();
+        }
+        _ => {}
+    }
+}
+
+/// The character a key stands for after Ctrl-V: the control byte for Ctrl
+/// keys, Esc, Enter, Tab and Backspace, or the key itself.
+fn literal_char(key: KeyEvent) -> Option<char> {
+    match key.code {
+        KeyCode::Char('?') if key.modifiers.contains(KeyModifiers::CONTROL) => Some('\x7f'),
+        KeyCode::Char(' ') if key.modifiers.contains(KeyModifiers::CONTROL) => Some('\0'),
+        KeyCode::Char(c) if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            let upper = c.to_ascii_uppercase();
+            ('@'..='_').contains(&upper).then_some((upper as u8 & 0x1f) as char)
+        }
+        KeyCode::Char(c) => Some(c),
+        KeyCode::Esc => Some('\x1b'),
+        KeyCode::Enter => Some('\r'),
+        KeyCode::Tab => Some('\t'),
+        KeyCode::Backspace => Some('\x7f'),
+        _ => None,
+    }
+}
+
+/// Caret notation for a control character: `^C` for 0x03, `^?` for DEL.
+pub fn caret(c: char) -> Option<char> {
+    match c {
+        '\0'..='\x1f' => Some((c as u8 + 0x40) as char),
+        '\x7f' => Some('?'),
+        _ => None,
+    }
+}
+
+/// `text` with control characters in caret notation, for echoing what was
+/// sent.
+fn caret_text(text: &str) -> Cow<'_, str> {
+    if !text.chars().any(|c| caret(c).is_some()) {
+        return Cow::Borrowed(text);
+    }
+    let mut out = String::with_capacity(text.len() + 4);
+    for c in text.chars() {
+        match caret(c) {
+            Some(name) => {
+                out.push('^');
+                out.push(name);
+            }
+            None => out.push(c),
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// Writes a single control byte, e.g. Ctrl-C to interrupt a shell on the
+/// target.
+fn send_control(app: &mut AppState, byte: u8) -> Result<()> {
+    let Some(handle) = app.serial() else {
+        app.add_output_line("[not open]");
+        return Ok(());
+    };
+    handle.write(vec![byte])?;
+    note_sent(app, &[byte]);
+    app.add_sent_line(format!(">> ^{}", (byte + 0x40) as char));
+    Ok(())
+}
+
+/// Replaces the input line, leaving the cursor at its end.
+fn set_input(app: &mut AppState, text: String) {
+    app.input_cursor = text.len();
+    app.input_buffer = text;
+}
+
+pub fn prev_char(text: &str, cursor: usize) -> usize {
+    text[..cursor].char_indices().next_back().map_or(0, |(i, _)| i)
+}
+
+pub fn next_char(text: &str, cursor: usize) -> usize {
+    text[cursor..].chars().next().map_or(cursor, |c| cursor + c.len_utf8())
+}
+
+/// Start of the word before the cursor, skipping whitespace first.
+fn word_start(text: &str, cursor: usize) -> usize {
+    let before = text[..cursor].trim_end();
+    before
+        .rfind(char::is_whitespace)
+        .map_or(0, |i| i + before[i..].chars().next().map_or(1, char::len_utf8))
+}
+
+/// End of the word after the cursor, skipping whitespace first.
+fn word_end(text: &str, cursor: usize) -> usize {
+    let after = &text[cursor..];
+    let skipped = after.len() - after.trim_start().len();
+    after[skipped..]
+        .find(char::is_whitespace)
+        .map_or(text.len(), |i| cursor + skipped + i)
+}
+
+/// Performs a keymap action. Returns true to quit.
+fn run_action(app: &mut AppState, action: Action) -> Result<bool> {
+    const BAUDS: &[u32] = &[9600, 19200, 38400, 57600, 115200, 230400];
+    match action {
+        Action::Help => app.help_view = Some(0),
+        Action::Errors => app.error_view = Some(0),
+        Action::Retry => match app.open_error.take() {
+            Some(failed) => open_target(app, failed.target)?,
+            None => app.add_output_line("[retry] no failed open to retry"),
+        },
+        Action::Reopen => reopen_last(app)?,
+        Action::Palette => app.palette = Some(Palette::default()),
+        Action::Quit => return Ok(confirm_quit(app)),
+        Action::Refresh => update_ports(app, serial::list_ports()?),
+        Action::FocusNext | Action::FocusPrev if app.zoomed => {}
+        Action::FocusNext => {
             app.focus = match app.focus {
                 Focus::Ports => Focus::Output,
                 Focus::Output => Focus::Input,
+                Focus::Input if app.ports_collapsed() => Focus::Output,
                 Focus::Input => Focus::Ports,
             };
         }
-        KeyCode::BackTab => {
-            app.focus = match app.focus {
-                Focus::Ports => Focus::Input,
-                Focus::Output => Focus::Ports,
-                Focus::Input => Focus::Output,
+        Action::FocusPrev => {
+            app.focus = match app.focus {
+                Focus::Ports => Focus::Input,
+                Focus::Output if app.ports_collapsed() => Focus::Input,
+                Focus::Output => Focus::Ports,
+                Focus::Input => Focus::Output,
+            };
+        }
+        Action::PortsWider | Action::PortsNarrower => {
+            let step: i16 = if action == Action::PortsWider { 5 } else { -5 };
+            let width = app.layout.ports_width.saturating_add_signed(step);
+            app.layout.ports_width =
+                width.clamp(LayoutConfig::MIN_PORTS_WIDTH, LayoutConfig::MAX_PORTS_WIDTH);
+            save_layout(app);
+        }
+        Action::CollapsePorts => {
+            app.layout.collapse_ports = !app.layout.collapse_ports;
+            if app.ports_collapsed() && app.focus == Focus::Ports {
+                app.focus = Focus::Input;
+            }
+            let state = if app.layout.collapse_ports { "on" } else { "off" };
+            app.add_output_line(format!("[collapse ports while open {state}]"));
+            save_layout(app);
+        }
+        Action::Layout => {
+            app.layout.direction = match app.layout.direction {
+                Split::Horizontal => Split::Vertical,
+                Split::Vertical => Split::Horizontal,
+            };
+            save_layout(app);
+        }
+        Action::BaudUp => {
+            let idx = BAUDS.iter().position(|b| *b == app.baud_rate).unwrap_or(0);
+            app.baud_rate = BAUDS[(idx + 1) % BAUDS.len()];
+        }
+        Action::BaudDown => {
+            let idx = BAUDS.iter().position(|b| *b == app.baud_rate).unwrap_or(0);
+            app.baud_rate = BAUDS[(idx + BAUDS.len() - 1) % BAUDS.len()];
+        }
+        Action::Zoom => toggle_zoom(app),
+        Action::Cancel => {
+            if app.selection.is_some() {
+                app.selection = None;
+            } else if app.open_error.is_some() {
+                app.open_error = None;
+            } else if app.zoomed {
+                toggle_zoom(app);
+            } else if app.focus == Focus::Ports && !app.port_filter.is_empty() {
+                app.port_filter.clear();
+                app.refilter_ports();
+            } else if app.repeat.is_some() {
+                stop_repeat(app);
+            } else if app.paste.is_some() {
+                stop_paste(app);
+            } else if let Some(gcode) = app.gcode.as_mut() {
+                gcode.cancel();
+            }
+        }
+        Action::Gcode => match app.gcode.as_mut() {
+            Some(gcode) if gcode.paused => {
+                let out = gcode.resume();
+                app.add_output_line("[gcode] resumed");
+                write_raw(app, out)?;
+            }
+            Some(gcode) => {
+                gcode.pause();
+                app.add_output_line("[gcode] pausing after the line in flight");
+            }
+            None => {
+                app.prompt = Some(Prompt {
+                    kind: PromptKind::Gcode(Flavor::Marlin),
+                    buffer: String::new(),
+                });
+            }
+        },
+        Action::Checksum => {
+            let all = Algorithm::ALL;
+            app.checksum = match app.checksum {
+                None => Some(all[0]),
+                Some(current) => all.iter().skip_while(|a| **a != current).nth(1).copied(),
+            };
+            let name = app.checksum.map_or("off", Algorithm::name);
+            app.add_output_line(format!("[checksum: {name}]"));
+        }
+        Action::ChecksumCalc => app.checksum_view = Some(String::new()),
+        Action::Compose => app.compose = Some(Compose::new(&app.compose_draft)),
+        Action::Clear => clear_output(app, false),
+        Action::ClearScrollback => clear_output(app, true),
+        Action::UndoClear => undo_clear(app),
+        Action::Buffer => show_buffer_usage(app),
+        Action::Marker => {
+            app.prompt = Some(Prompt {
+                kind: PromptKind::Marker(SystemTime::now()),
+                buffer: String::new(),
+            });
+        }
+        Action::Stm32Flash => {
+            app.prompt = Some(Prompt {
+                kind: PromptKind::Stm32Flash,
+                buffer: String::new(),
+            });
+        }
+        Action::SendFile => {
+            app.prompt = Some(Prompt {
+                kind: PromptKind::SendFile(Protocol::Xmodem),
+                buffer: String::new(),
+            });
+        }
+        Action::ReceiveFile => {
+            app.prompt = Some(Prompt {
+                kind: PromptKind::ReceiveFile(Protocol::Xmodem),
+                buffer: String::new(),
+            });
+        }
+        Action::ToggleDtr => toggle_dtr(app)?,
+        Action::ToggleRts => toggle_rts(app)?,
+        Action::Reset => {
+            if app.session.is_none() {
+                app.add_output_line("[not open]");
+            } else if let Some(name) = app.profile_reset.clone() {
+                run_reset(app, &name)?;
+            } else {
+                app.prompt = Some(Prompt {
+                    kind: PromptKind::Reset,
+                    buffer: app.last_reset.clone(),
+                });
+            }
+        }
+        Action::Break => {
+            if app.session.is_some() {
+                app.prompt = Some(Prompt {
+                    kind: PromptKind::BreakDuration,
+                    buffer: app.break_duration.as_millis().to_string(),
+                });
+            } else {
+                app.add_output_line("[not open]");
+            }
+        }
+        Action::AutoResponses => app.auto_response_view = Some(0),
+        Action::Macros => app.macro_view = Some(0),
+        Action::Pause => app.toggle_pause(),
+        Action::Repeat => {
+            app.prompt = Some(Prompt {
+                kind: PromptKind::RepeatInterval,
+                buffer: app.repeat_interval.as_millis().to_string(),
+            });
+        }
+        Action::Bridge => toggle_bridge(app),
+        Action::Mqtt => toggle_mqtt(app),
+        Action::Passthrough => {
+            if app.session.is_some() {
+                app.passthrough = Some(Passthrough::default());
+            } else {
+                app.add_output_line("[not open]");
+            }
+        }
+        Action::SaveScrollback => {
+            app.prompt = Some(Prompt {
+                kind: PromptKind::SaveScrollback,
+                buffer: "scrollback.log".to_string(),
+            });
+        }
+        Action::Defmt => {
+            if app.defmt.take().is_some() {
+                app.add_output_line("[defmt] off");
+            } else {
+                let elf = app.defmt_elf.as_ref().map(|p| p.display().to_string());
+                app.prompt = Some(Prompt {
+                    kind: PromptKind::DefmtElf,
+                    buffer: elf.unwrap_or_default(),
+                });
+            }
+        }
+        Action::EspCrash => {
+            if app.esp.take().is_some() {
+                app.add_output_line("[esp] off");
+            } else {
+                let elf = app.esp_elf.as_ref().map(|p| p.display().to_string());
+                app.prompt = Some(Prompt {
+                    kind: PromptKind::EspElf,
+                    buffer: elf.unwrap_or_default(),
+                });
+            }
+        }
+        Action::Framing => {
+            let current = app.frames.as_ref().map_or(FrameMode::None, FrameDecoder::framing);
+            set_framing(app, current.next());
+        }
+        Action::Modbus => {
+            let on = packets_name(app) == Some("modbus");
+            set_decoder(app, (!on).then_some("modbus"));
+        }
+        Action::Alerts => {
+            app.alerts.enabled = !app.alerts.enabled;
+            let state = if app.alerts.enabled { "on" } else { "off" };
+            app.add_output_line(format!("[alerts: {state}]"));
+        }
+        Action::Decoder => {
+            let next = app.decoders.next_name(packets_name(app));
+            set_decoder(app, next);
+        }
+        Action::Plot => {
+            if app.plot.take().is_some() {
+                app.add_output_line("[plot] off");
+            } else {
+                let mut plot = Plotter::default();
+                plot.set_paused(app.paused_lines.is_some());
+                app.plot = Some(plot);
+                app.add_output_line("[plot] charting numeric lines (CSV or name:value)");
+            }
+        }
+        Action::Export => {
+            if app.plot.as_ref().is_some_and(|p| !p.rows.is_empty()) {
+                app.prompt = Some(Prompt {
+                    kind: PromptKind::Export,
+                    buffer: "plot.csv".to_string(),
+                });
+            } else {
+                app.add_output_line("[export] nothing parsed yet (turn on plot first)");
+            }
+        }
+        Action::Record => toggle_record(app),
+        Action::Sniff if app.sniff.is_some() => stop_sniff(app),
+        Action::Sniff if app.session.is_some() => {
+            app.prompt = Some(Prompt {
+                kind: PromptKind::Sniff,
+                buffer: DEFAULT_PATH_PREFIX.to_string(),
+            });
+        }
+        Action::Sniff => app.add_output_line("[sniff] open the first port before the second"),
+        Action::Compare if app.compare.is_some() => stop_compare(app),
+        Action::Compare if app.session.is_some() => {
+            app.prompt = Some(Prompt {
+                kind: PromptKind::Compare,
+                buffer: DEFAULT_PATH_PREFIX.to_string(),
+            });
+        }
+        Action::Compare => {
+            app.add_output_line("[compare] open the first port before the second");
+        }
+        Action::Broadcast if app.broadcast.is_some() => stop_broadcast(app),
+        Action::Broadcast if app.session.is_some() => {
+            app.prompt = Some(Prompt {
+                kind: PromptKind::Broadcast,
+                buffer: String::new(),
+            });
+        }
+        Action::Broadcast => app.add_output_line("[broadcast] open a port first"),
+        Action::Ping if app.session.is_some() => {
+            app.prompt = Some(Prompt {
+                kind: PromptKind::Ping,
+                buffer: macros::escape(&app.ping_config.probe),
+            });
+        }
+        Action::Ping => app.add_output_line("[ping] not open"),
+        Action::Pipe => match app.pipe.take() {
+            Some(pipe) => {
+                pipe.kill();
+                app.add_output_line(format!("[pipe] stopped {}", pipe.command));
+            }
+            None => {
+                app.prompt = Some(Prompt {
+                    kind: PromptKind::Pipe,
+                    buffer: app.pipe_command.clone(),
+                });
+            }
+        },
+        Action::LinkTest if app.session.is_some() => {
+            let config = &app.link_test_config;
+            let line = format!(
+                "[link test: sending {} bytes of {}; TX must be looped back to RX]",
+                config.bytes,
+                config.pattern.name()
+            );
+            app.link_test = Some(LinkTest::new(config.pattern, config.bytes));
+            app.add_output_line(line);
+        }
+        Action::LinkTest => app.add_output_line("[link test] not open"),
+        Action::Replay => toggle_replay(app),
+        Action::AtMode => {
+            app.at_mode = !app.at_mode;
+            if app.at_mode {
+                app.add_output_line("[at mode: on, lines end in \\r\\n, Right completes]");
+            } else {
+                app.add_output_line("[at mode: off]");
+            }
+        }
+        Action::Nmea => {
+            if app.nmea.take().is_some() {
+                app.add_output_line("[nmea] off");
+            } else {
+                app.nmea = Some(nmea::Tracker::default());
+                app.add_output_line("[nmea] following GPS sentences");
+            }
+        }
+        Action::ModbusRead => {
+            app.prompt = Some(Prompt {
+                kind: PromptKind::ModbusRead,
+                buffer: "1 0 10".to_string(),
+            });
+        }
+        Action::CycleEncoding => {
+            let next = app.decoder.encoding().next();
+            app.decoder = TextDecoder::new(next);
+            app.add_output_line(format!("[encoding: {}]", next.name()));
+        }
+        Action::ToggleAnsi => {
+            app.ansi_mode = app.ansi_mode.toggle();
+            app.add_output_line(format!("[ansi: {}]", app.ansi_mode.name()));
+        }
+        Action::Script => {
+            if let Some(script) = &app.script {
+                script.cancel();
+                app.add_output_line(format!("[script] stopping {}", script.name));
+            } else {
+                app.prompt = Some(Prompt {
+                    kind: PromptKind::RunScript,
+                    buffer: String::new(),
+                });
+            }
+        }
+        Action::PrevPort => move_selection(app, -1),
+        Action::NextPort => move_selection(app, 1),
+        Action::Open => toggle_port(app)?,
+        Action::Connect if !app.is_open => {
+            app.prompt = Some(Prompt {
+                kind: PromptKind::Connect,
+                buffer: "rfc2217://".to_string(),
+            });
+        }
+        Action::OpenPath if !app.is_open => {
+            app.prompt = Some(Prompt {
+                kind: PromptKind::OpenPath,
+                buffer: DEFAULT_PATH_PREFIX.to_string(),
+            });
+        }
+        Action::Connect | Action::OpenPath => app.add_output_line("[already open]"),
+        Action::Favorite => toggle_favorite(app),
+        Action::PortFilter => app.port_filter_editing = true,
+        Action::Bluetooth => discover_bluetooth(app, true),
+        Action::SaveProfile => {
+            if let Some(port) = app.selected_port.and_then(|i| app.ports.get(i)) {
+                let keys = profile::port_keys(port);
+                let name = profile::find(&app.profiles, &keys)
+                    .map(|p| p.name.clone())
+                    .unwrap_or_default();
+                app.prompt = Some(Prompt {
+                    kind: PromptKind::SaveProfile,
+                    buffer: name,
+                });
+            }
+        }
+        Action::ScrollUp => app.scroll_output(1),
+        Action::ScrollDown => app.scroll_output(-1),
+        Action::PageUp => app.scroll_output(5),
+        Action::PageDown => app.scroll_output(-5),
+        Action::ScrollTop => app.scroll_output(isize::MAX),
+        Action::Follow => app.follow_output(),
+        Action::Yank => app.yank_selection_or_screen(),
+        Action::Inspect => app.inspect_selection(),
+        Action::ToggleWrap => app.toggle_wrap(),
+        Action::Collapse => app.toggle_collapse(),
+        Action::ControlChars => toggle_control_chars(app),
+        Action::Chat => app.chat = !app.chat,
+        Action::HexSplit => app.hex_split = !app.hex_split,
+        Action::Interleave => match app.compare.as_mut() {
+            Some(compare) => compare.interleave = !compare.interleave,
+            None => app.add_output_line("[compare: not comparing; open a second port first]"),
+        },
+        Action::ScrollLeft => app.scroll_output_sideways(-HSCROLL_STEP),
+        Action::ScrollRight => app.scroll_output_sideways(HSCROLL_STEP),
+        Action::Bookmark => app.toggle_bookmark(),
+        Action::NextBookmark => app.jump_bookmark(true),
+        Action::PrevBookmark => app.jump_bookmark(false),
+        Action::YankAll => app.yank_all(),
+        Action::Highlights => app.highlight_view = Some(0),
+        Action::Filter => app.open_filter_prompt(),
+        Action::ToggleFilter => app.toggle_filter(),
+        Action::Send => send_input(app)?,
+        Action::Literal => app.input_literal = true,
+        Action::SendCtrlC => send_control(app, 0x03)?,
+        Action::SendCtrlD => send_control(app, 0x04)?,
+    }
+    Ok(false)
+}
+
+fn handle_mouse_event(app: &mut AppState, mouse: MouseEvent) {
+    let modal = app.modal.is_some()
+        || app.prompt.is_some()
+        || app.transfer.is_some()
+        || app.flash.is_some()
+        || app.auto_response_view.is_some()
+        || app.macro_view.is_some()
+        || app.highlight_view.is_some()
+        || app.help_view.is_some()
+        || app.error_view.is_some()
+        || app.compose.is_some()
+        || app.checksum_view.is_some()
+        || app.ping.is_some()
+        || app.link_test.is_some()
+        || app.inspector.is_some()
+        || app.palette.is_some();
+    if modal {
+        return;
+    }
+    let areas = ui::layout(app.screen, app);
+    let inside = |r: Rect| {
+        mouse.column >= r.x
+            && mouse.column < r.x + r.width
+            && mouse.row >= r.y
+            && mouse.row < r.y + r.height
+    };
+    // The hex pane's rows are the text pane's, so either picks lines.
+    let in_output = inside(areas.output) || areas.hex.is_some_and(inside);
+    match mouse.kind {
+        MouseEventKind::ScrollUp if in_output => app.scroll_output(WHEEL_LINES),
+        MouseEventKind::ScrollDown if in_output => app.scroll_output(-WHEEL_LINES),
+        MouseEventKind::ScrollUp if inside(areas.ports) => move_selection(app, -1),
+        MouseEventKind::ScrollDown if inside(areas.ports) => move_selection(app, 1),
+        MouseEventKind::Down(MouseButton::Left)
+            if ui::on_ports_border(&areas, app.layout.direction, mouse.column, mouse.row) =>
+        {
+            app.resizing = true;
+        }
+        MouseEventKind::Drag(MouseButton::Left) if app.resizing => {
+            app.layout.ports_width =
+                ui::ports_share(&areas, app.layout.direction, mouse.column, mouse.row);
+        }
+        MouseEventKind::Up(MouseButton::Left) if app.resizing => {
+            app.resizing = false;
+            save_layout(app);
+        }
+        MouseEventKind::Down(MouseButton::Left) => {
+            app.selection = None;
+            if inside(areas.ports) {
+                app.focus = Focus::Ports;
+                let row = mouse.row.saturating_sub(areas.ports.y + 1) as usize;
+                let height = areas.ports.height.saturating_sub(2) as usize;
+                let rows = ui::port_rows(app, &app.visible_ports());
+                let selected =
+                    app.selected_port.and_then(|i| rows.iter().position(|&r| r == Some(i)));
+                let idx = ui::port_list_offset(selected, height) + row;
+                if let Some(&Some(port)) = rows.get(idx) {
+                    app.selected_port = Some(port);
+                }
+            } else if in_output {
+                app.focus = Focus::Output;
+                if app.compare.is_some() {
+                    return;
+                }
+                if let Some(line) = output_line_at(app, areas.output, mouse.row) {
+                    app.selection = Some(Selection {
+                        anchor: line,
+                        cursor: line,
+                        active: true,
+                    });
+                }
+            } else if inside(areas.footer) {
+                app.focus = Focus::Input;
+            }
+        }
+        MouseEventKind::Drag(MouseButton::Left) => {
+            if !app.selection.is_some_and(|s| s.active) {
+                return;
+            }
+            // Dragging past the pane edges scrolls so long ranges can be picked.
+            let output = areas.output;
+            let row = if mouse.row <= output.y {
+                app.scroll_output(1);
+                output.y + 1
+            } else if mouse.row + 1 >= output.y + output.height {
+                app.scroll_output(-1);
+                (output.y + output.height).saturating_sub(2)
+            } else {
+                mouse.row
+            };
+            if let Some(line) = output_line_at(app, output, row) {
+                if let Some(sel) = app.selection.as_mut() {
+                    sel.cursor = line;
+                }
+            }
+        }
+        MouseEventKind::Up(MouseButton::Left) => {
+            if let Some(sel) = app.selection.as_mut() {
+                sel.active = false;
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Absolute number of the output line drawn at screen row `row`.
+fn output_line_at(app: &AppState, output: Rect, row: u16) -> Option<usize> {
+    let rows = ui::output_rows(app, output);
+    let offset = row.checked_sub(output.y + 1)? as usize;
+    let idx = *rows.get(offset)?;
+    Some(idx + app.output_lines.evicted())
+}
+
+fn handle_auto_response_key(app: &mut AppState, key: KeyEvent) {
+    let Some(selected) = app.auto_response_view else {
+        return;
+    };
+    let count = app.auto_responder.rules.len();
+    match key.code {
+        KeyCode::Esc => app.auto_response_view = None,
+        _ if app.keymap.is(Action::AutoResponses, key) => app.auto_response_view = None,
+        KeyCode::Up => app.auto_response_view = Some(selected.saturating_sub(1)),
+        KeyCode::Down => {
+            app.auto_response_view = Some((selected + 1).min(count.saturating_sub(1)));
+        }
+        KeyCode::Char(' ') | KeyCode::Enter => app.auto_responder.toggle_rule(selected),
+        KeyCode::Char('a') => app.auto_responder.toggle(),
+        _ => {}
+    }
+}
+
+fn handle_macro_view_key(app: &mut AppState, key: KeyEvent) {
+    let Some(selected) = app.macro_view else {
+        return;
+    };
+    match key.code {
+        KeyCode::Esc => app.macro_view = None,
+        _ if app.keymap.is(Action::Macros, key) => app.macro_view = None,
+        KeyCode::Up => app.macro_view = Some(selected.saturating_sub(1)),
+        KeyCode::Down => app.macro_view = Some((selected + 1).min(macros::MACRO_COUNT - 1)),
+        KeyCode::Enter => {
+            app.prompt = Some(Prompt {
+                kind: PromptKind::EditMacro(selected),
+                buffer: macros::escape(app.macros.get(selected).unwrap_or_default()),
+            });
+        }
+        KeyCode::Delete => store_macro(app, selected, String::new()),
+        KeyCode::Char('r') => {
+            if let Some(text) = app.macros.get(selected).map(str::to_string) {
+                app.macro_view = None;
+                let mut payload = text.clone().into_bytes();
+                let note = append_checksum(app, &mut payload);
+                start_repeat(app, format!("{}{note}", macros::escape(&text)), payload);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Returns true when the chosen command quits.
+fn handle_palette_key(app: &mut AppState, key: KeyEvent) -> Result<bool> {
+    let Some(palette) = app.palette.as_mut() else {
+        return Ok(false);
+    };
+    let count = palette.entries(&app.keymap, &app.macros).len();
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+    match key.code {
+        KeyCode::Esc => app.palette = None,
+        KeyCode::Up => palette.selected = palette.selected.saturating_sub(1),
+        KeyCode::Char('p') if ctrl => palette.selected = palette.selected.saturating_sub(1),
+        KeyCode::Down => palette.selected = (palette.selected + 1).min(count.saturating_sub(1)),
+        KeyCode::Char('n') if ctrl => {
+            palette.selected = (palette.selected + 1).min(count.saturating_sub(1));
+        }
+        KeyCode::Backspace => {
+            palette.query.pop();
+            palette.selected = 0;
+        }
+        KeyCode::Char(c) if !ctrl => {
+            palette.query.push(c);
+            palette.selected = 0;
+        }
+        KeyCode::Enter => {
+            let entries = palette.entries(&app.keymap, &app.macros);
+            let command = entries.get(palette.selected).map(|e| e.command);
+            app.palette = None;
+            match command {
+                Some(Command::Action(action)) => return run_action(app, action),
+                Some(Command::Macro(idx)) => send_macro(app, idx)?,
+                None => {}
+            }
+        }
+        _ => {}
+    }
+    Ok(false)
+}
+
+fn handle_help_key(app: &mut AppState, key: KeyEvent) {
+    let Some(scroll) = app.help_view else {
+        return;
+    };
+    let height = ui::help_area(app.screen).height.saturating_sub(2);
+    let max = (ui::help_lines(app).len() as u16).saturating_sub(height);
+    let page = height.max(1);
+    let scroll = match key.code {
+        KeyCode::Esc | KeyCode::Char('q') => {
+            app.help_view = None;
+            return;
+        }
+        _ if app.keymap.is(Action::Help, key) => {
+            app.help_view = None;
+            return;
+        }
+        KeyCode::Up => scroll.saturating_sub(1),
+        KeyCode::Down => scroll + 1,
+        KeyCode::PageUp => scroll.saturating_sub(page),
+        KeyCode::PageDown | KeyCode::Char(' ') => scroll + page,
+        KeyCode::Home => 0,
+        KeyCode::End => max,
+        _ => scroll,
+    };
+    app.help_view = Some(scroll.min(max));
+}
+
+fn handle_error_view_key(app: &mut AppState, key: KeyEvent) {
+    let Some(back) = app.error_view else {
+        return;
+    };
+    let page = ui::ERROR_VIEW_ROWS;
+    let max = app.toasts.errors.len().saturating_sub(page);
+    let back = match key.code {
+        KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q') => {
+            app.error_view = None;
+            return;
+        }
+        _ if app.keymap.is(Action::Errors, key) => {
+            app.error_view = None;
+            return;
+        }
+        KeyCode::Char('c') => {
+            app.toasts.errors.clear();
+            0
+        }
+        KeyCode::Up => back + 1,
+        KeyCode::Down => back.saturating_sub(1),
+        KeyCode::PageUp => back + page,
+        KeyCode::PageDown => back.saturating_sub(page),
+        KeyCode::Home => max,
+        KeyCode::End => 0,
+        _ => back,
+    };
+    app.error_view = Some(back.min(max));
+}
+
+fn handle_highlight_view_key(app: &mut AppState, key: KeyEvent) {
+    let Some(selected) = app.highlight_view else {
+        return;
+    };
+    let count = app.highlighter.rules.len();
+    match key.code {
+        KeyCode::Esc => app.highlight_view = None,
+        _ if app.keymap.is(Action::Highlights, key) => app.highlight_view = None,
+        KeyCode::Up => app.highlight_view = Some(selected.saturating_sub(1)),
+        KeyCode::Down => app.highlight_view = Some((selected + 1).min(count.saturating_sub(1))),
+        KeyCode::Char(' ') => {
+            app.highlighter.toggle_rule(selected);
+            save_highlights(app);
+        }
+        KeyCode::Char('a') => {
+            app.prompt = Some(Prompt {
+                kind: PromptKind::EditHighlight(None),
+                buffer: String::new(),
+            });
+        }
+        KeyCode::Enter => {
+            if let Some(rule) = app.highlighter.rules.get(selected) {
+                app.prompt = Some(Prompt {
+                    kind: PromptKind::EditHighlight(Some(selected)),
+                    buffer: rule.spec(),
+                });
+            }
+        }
+        KeyCode::Delete if selected < count => {
+            app.highlighter.remove_rule(selected);
+            app.highlight_view = Some(selected.min(count.saturating_sub(2)));
+            save_highlights(app);
+        }
+        _ => {}
+    }
+}
+
+fn submit_highlight(app: &mut AppState, idx: Option<usize>, spec: &str) {
+    let result = highlight::parse_spec(spec).and_then(|c| app.highlighter.set_rule(idx, c));
+    if let Err(e) = result {
+        app.report_error(format!("{e:#}"));
+        return;
+    }
+    if idx.is_none() {
+        app.highlight_view = Some(app.highlighter.rules.len() - 1);
+    }
+    save_highlights(app);
+}
+
+fn save_highlights(app: &mut AppState) {
+    if let Err(e) = config::save_highlights(&app.highlighter.configs()) {
+        app.report_error(format!("saving highlights: {e:#}"));
+    }
+}
+
+fn handle_prompt_key(app: &mut AppState, key: KeyEvent) -> Result<()> {
+    let Some(prompt) = app.prompt.as_mut() else {
+        return Ok(());
+    };
+    match key.code {
+        KeyCode::Esc => {
+            app.prompt = None;
+        }
+        KeyCode::Tab => {
+            prompt.kind = match prompt.kind {
+                PromptKind::SendFile(p) => PromptKind::SendFile(p.next()),
+                PromptKind::ReceiveFile(p) => PromptKind::ReceiveFile(p.next()),
+                PromptKind::Replay(speed) => PromptKind::Replay(speed.next()),
+                PromptKind::Gcode(flavor) => PromptKind::Gcode(flavor.next()),
+                PromptKind::Reset => {
+                    let names = config::reset_names(&app.reset_sequences);
+                    let next = names
+                        .iter()
+                        .position(|n| *n == prompt.buffer)
+                        .map_or(0, |i| (i + 1) % names.len());
+                    prompt.buffer = names[next].clone();
+                    PromptKind::Reset
+                }
+                other => other,
+            };
+        }
+        KeyCode::Backspace => {
+            prompt.buffer.pop();
+        }
+        KeyCode::Char(c) => {
+            prompt.buffer.push(c);
+        }
+        KeyCode::Enter => {
+            if let Some(prompt) = app.prompt.take() {
+                match overwrite_check(&prompt) {
+                    Some(modal) => app.modal = Some(modal),
+                    None => submit_prompt(app, prompt)?,
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// For prompts that write a file, a popup asking before an existing one
+/// is replaced.
+fn overwrite_check(prompt: &Prompt) -> Option<Modal> {
+    let writes = matches!(
+        prompt.kind,
+        PromptKind::Record
+            | PromptKind::SaveScrollback
+            | PromptKind::Export
+            | PromptKind::ReceiveFile(_)
+    );
+    let path = std::path::Path::new(prompt.buffer.trim());
+    if !writes || prompt.buffer.trim().is_empty() || !path.exists() {
+        return None;
+    }
+    if path.is_dir() {
+        let text = format!("{} is a directory; give a file name.", path.display());
+        return Some(Modal::message("Can't write there", text));
+    }
+    let text = format!("{} already exists.", path.display());
+    let items = ["Overwrite it", "Choose another name", "Cancel"];
+    let then = Then::Overwrite(Prompt {
+        kind: prompt.kind,
+        buffer: prompt.buffer.clone(),
+    });
+    Some(Modal::select("File exists", text, &items, then))
+}
+
+/// Returns true to quit.
+fn handle_modal_key(app: &mut AppState, key: KeyEvent) -> Result<bool> {
+    let Some(modal) = app.modal.as_mut() else {
+        return Ok(false);
+    };
+    let answer = match (&mut modal.kind, key.code) {
+        (_, KeyCode::Esc) => Some(Answer::Dismissed),
+        (ModalKind::Message(_), KeyCode::Enter) => Some(Answer::Dismissed),
+        (ModalKind::Confirm { .. }, KeyCode::Char('y' | 'Y')) => Some(Answer::Yes),
+        (ModalKind::Confirm { .. }, KeyCode::Char('n' | 'N')) => Some(Answer::Dismissed),
+        (ModalKind::Confirm { yes, .. }, KeyCode::Enter) => {
+            Some(if *yes { Answer::Yes } else { Answer::Dismissed })
+        }
+        (
+            ModalKind::Confirm { yes, .. },
+            KeyCode::Left | KeyCode::Right | KeyCode::Tab | KeyCode::BackTab,
+        ) => {
+            *yes = !*yes;
+            None
+        }
+        (ModalKind::Select { selected, .. }, KeyCode::Up | KeyCode::Char('k')) => {
+            *selected = selected.saturating_sub(1);
+            None
+        }
+        (ModalKind::Select { selected, items, .. }, KeyCode::Down | KeyCode::Char('j')) => {
+            *selected = (*selected + 1).min(items.len().saturating_sub(1));
+            None
+        }
+        (ModalKind::Select { selected, .. }, KeyCode::Enter) => Some(Answer::Picked(*selected)),
+        (ModalKind::Select { items, .. }, KeyCode::Char(c)) => c
+            .to_digit(10)
+            .and_then(|n| (n as usize).checked_sub(1))
+            .filter(|&i| i < items.len())
+            .map(Answer::Picked),
+        _ => None,
+    };
+    let Some(answer) = answer else {
+        return Ok(false);
+    };
+    let Some(modal) = app.modal.take() else {
+        return Ok(false);
+    };
+    match (modal.then, answer) {
+        (Then::Restore(saved), Answer::Picked(0)) => restore_state(app, *saved),
+        (Then::Restore(_), _) => {
+            if let Err(e) = state::discard() {
+                app.report_error(format!("{e:#}"));
+            }
+        }
+        (_, Answer::Dismissed) | (Then::Nothing, _) => {}
+        (Then::Quit, _) => return Ok(true),
+        (Then::ClosePort, _) => close_port(app)?,
+        (Then::Overwrite(prompt), Answer::Picked(0)) => submit_prompt(app, prompt)?,
+        (Then::Overwrite(prompt), Answer::Picked(1)) => app.prompt = Some(prompt),
+        (Then::Overwrite(_), _) => {}
+    }
+    Ok(false)
+}
+
+/// What would be cut short by closing the port: a transfer, a flash or a
+/// G-code stream.
+fn port_busy(app: &AppState) -> Option<&'static str> {
+    if app.transfer.is_some() {
+        Some("A file transfer is running")
+    } else if app.flash.is_some() {
+        Some("The STM32 flash is running")
+    } else if app.gcode.is_some() {
+        Some("A G-code file is streaming")
+    } else {
+        None
+    }
+}
+
+/// Asks first when quitting would stop a recording or cut a transfer
+/// short. Returns true to quit now.
+fn confirm_quit(app: &mut AppState) -> bool {
+    let mut reasons = Vec::new();
+    if let Some(recorder) = &app.recorder {
+        reasons.push(format!("Recording to {} is running", recorder.path.display()));
+    }
+    reasons.extend(port_busy(app).map(str::to_string));
+    if reasons.is_empty() {
+        return true;
+    }
+    let text = format!("{}.\nQuit anyway?", reasons.join(".\n"));
+    app.modal = Some(Modal::confirm("Quit", text, Then::Quit));
+    false
+}
+
+fn submit_prompt(app: &mut AppState, prompt: Prompt) -> Result<()> {
+    if let PromptKind::EditMacro(idx) = prompt.kind {
+        // Macros may legitimately be cleared or carry surrounding spaces.
+        store_macro(app, idx, macros::unescape(&prompt.buffer));
+        return Ok(());
+    }
+    if prompt.kind == PromptKind::Filter {
+        app.set_filter(prompt.buffer.trim());
+        return Ok(());
+    }
+    if let PromptKind::EditHighlight(idx) = prompt.kind {
+        submit_highlight(app, idx, prompt.buffer.trim());
+        return Ok(());
+    }
+    if prompt.kind == PromptKind::RepeatInterval {
+        submit_repeat_interval(app, prompt.buffer.trim());
+        return Ok(());
+    }
+    if let PromptKind::Marker(at) = prompt.kind {
+        insert_marker(app, at, prompt.buffer.trim());
+        return Ok(());
+    }
+    let value = prompt.buffer.trim();
+    if value.is_empty() {
+        return Ok(());
+    }
+    let path = std::path::Path::new(value);
+    match prompt.kind {
+        PromptKind::Gcode(flavor) => start_gcode(app, path, flavor)?,
+        PromptKind::SendFile(protocol) => match Transfer::send(protocol, path) {
+            Ok(transfer) => start_transfer(app, transfer, "sending"),
+            Err(e) => app.report_error(format!("{e:#}")),
+        },
+        PromptKind::ReceiveFile(protocol) => {
+            start_transfer(app, Transfer::receive(protocol, path), "receiving into");
+        }
+        PromptKind::RunScript => match script::spawn(path) {
+            Ok((handle, rx)) => {
+                app.add_output_line(format!("[script] running {}", handle.name));
+                app.script = Some(handle);
+                app.script_event_rx = Some(rx);
+            }
+            Err(e) => app.report_error(format!("{e:#}")),
+        },
+        PromptKind::Connect | PromptKind::OpenPath => open_target(app, value.to_string())?,
+        PromptKind::Bridge => match bridge::spawn(value) {
+            Ok((handle, rx)) => {
+                app.add_output_line(format!("[bridge] listening on {}", handle.url()));
+                app.bridge = Some(handle);
+                app.bridge_event_rx = Some(rx);
+            }
+            Err(e) => app.report_error(format!("{e:#}")),
+        },
+        PromptKind::SaveProfile => save_profile(app, value),
+        PromptKind::DefmtElf => start_defmt(app, path),
+        PromptKind::ModbusRead => send_modbus_read(app, value)?,
+        PromptKind::EspElf => start_esp(app, path),
+        PromptKind::Export => {
+            let format = export::Format::from_path(path);
+            let written = match &app.plot {
+                Some(plot) => export::write(path, plot, format),
+                None => Ok(0),
             };
-        }
-        KeyCode::Char('r') => {
-            app.ports = serial::list_ports()?;
-            if app.ports.is_empty() {
-                app.selected_port = None;
-            } else {
-                app.selected_port = Some(0);
+            match written {
+                Ok(rows) => app.add_output_line(format!(
+                    "[export] {rows} rows as {} to {value}",
+                    format.name()
+                )),
+                Err(e) => app.report_error(format!("{e:#}")),
             }
         }
-        KeyCode::Char('b') => {
-            const BAUDS: &[u32] = &[9600, 19200, 38400, 57600, 115200, 230400];
-            let idx = BAUDS.iter().position(|b| *b == app.baud_rate).unwrap_or(0);
-            let next = (idx + 1) % BAUDS.len();
-            app.baud_rate = BAUDS[next];
+        PromptKind::Record => {
+            let port = app
+                .session
+                .as_ref()
+                .map(Session::target)
+                .or_else(|| app.selected_port.map(|i| app.ports[i].port_name.as_str()))
+                .unwrap_or("serial");
+            match Recorder::create(path, port) {
+                Ok(recorder) => {
+                    app.add_output_line(format!("[record] recording to {value}"));
+                    app.recorder = Some(recorder);
+                }
+                Err(e) => app.report_error(format!("{e:#}")),
+            }
         }
-        KeyCode::Char('B') => {
-            const BAUDS: &[u32] = &[9600, 19200, 38400, 57600, 115200, 230400];
-            let idx = BAUDS.iter().position(|b| *b == app.baud_rate).unwrap_or(0);
-            let prev = (idx + BAUDS.len() - 1) % BAUDS.len();
-            app.baud_rate = BAUDS[prev];
+        PromptKind::Replay(speed) => start_replay(app, path, speed),
+        PromptKind::Sniff => start_sniff(app, value)?,
+        PromptKind::Compare => start_compare(app, value)?,
+        PromptKind::Broadcast => start_broadcast(app, value),
+        PromptKind::Ping => match Ping::new(&app.ping_config, &macros::unescape(value)) {
+            Ok(ping) => app.ping = Some(ping),
+            Err(e) => app.add_output_line(format!("[ping] {e:#}")),
+        },
+        PromptKind::Pipe => start_pipe(app, value),
+        PromptKind::BreakDuration => send_break(app, value)?,
+        PromptKind::Reset => run_reset(app, value)?,
+        PromptKind::Stm32Flash => start_flash(app, value),
+        PromptKind::SaveScrollback => match save_scrollback(app, path) {
+            Ok(count) => app.add_output_line(format!("[saved {count} lines to {value}]")),
+            Err(e) => app.report_error(format!("{e:#}")),
+        },
+        PromptKind::EditMacro(_)
+        | PromptKind::RepeatInterval
+        | PromptKind::Filter
+        | PromptKind::EditHighlight(_)
+        | PromptKind::Marker(_) => {}
+    }
+    Ok(())
+}
+
+/// Writes the whole output buffer, including lines held back while paused,
+/// as plain text.
+fn save_scrollback(app: &AppState, path: &std::path::Path) -> Result<usize> {
+    let write = || -> io::Result<usize> {
+        let mut out = io::BufWriter::new(std::fs::File::create(path)?);
+        let pending = app.paused_lines.iter().flatten().map(|l| Cow::Borrowed(l.as_str()));
+        let mut count = 0;
+        for line in app.output_lines.iter().chain(pending) {
+            writeln!(out, "{}", ansi::strip(&line))?;
+            count += 1;
         }
-        _ => {
-            match app.focus {
-                Focus::Ports => match key.code {
-                    KeyCode::Up => move_selection(app, -1),
-                    KeyCode::Down => move_selection(app, 1),
-                    KeyCode::Enter => toggle_port(app)?,
-                    _ => {}
-                },
-                Focus::Output => match key.code {
-                    KeyCode::PageUp => {
-                        app.output_scroll = app.output_scroll.saturating_add(5);
-                    }
-                    KeyCode::PageDown => {
-                        app.output_scroll = app.output_scroll.saturating_sub(5);
-                    }
-                    KeyCode::Home => {
-                        app.output_scroll = app.output_lines.len() as u16;
-                    }
-                    KeyCode::End => {
-                        app.output_scroll = 0;
-                    }
-                    _ => {}
-                },
-                Focus::Input => match key.code {
-                    KeyCode::Enter => {
-                        send_input(app)?;
-                    }
-                    KeyCode::Backspace => {
-                        app.input_buffer.pop();
-                    }
-                    KeyCode::Char(c) => {
-                        app.input_buffer.push(c);
-                    }
-                    KeyCode::Tab => {}
-                    _ => {}
-                },
+        out.flush()?;
+        Ok(count)
+    };
+    write().with_context(|| format!("write {}", path.display()))
+}
+
+fn submit_repeat_interval(app: &mut AppState, value: &str) {
+    let ms = match value {
+        "" | "off" => 0,
+        v => match v.parse::<u64>() {
+            Ok(ms) => ms,
+            Err(_) => {
+                app.add_output_line(format!("[invalid interval: {v}]"));
+                return;
             }
+        },
+    };
+    if ms == 0 {
+        stop_repeat(app);
+        return;
+    }
+    app.repeat_interval = Duration::from_millis(ms);
+    if let Some(repeat) = app.repeat.as_mut() {
+        repeat.interval = app.repeat_interval;
+        repeat.next = Instant::now() + repeat.interval;
+        app.add_output_line(format!("[repeat interval {} ms]", ms));
+    } else if app.input_buffer.is_empty() {
+        app.add_output_line("[repeat: input is empty]");
+    } else {
+        let mut payload = app.input_buffer.clone().into_bytes();
+        let note = append_checksum(app, &mut payload);
+        payload.extend_from_slice(app.line_ending.bytes());
+        start_repeat(app, format!("{}{note}", app.input_buffer), payload);
+    }
+}
+
+fn store_macro(app: &mut AppState, idx: usize, text: String) {
+    let key = macros::key_name(idx);
+    if let Err(e) = config::save_macro(&key, &text) {
+        app.report_error(format!("saving {key}: {e:#}"));
+    }
+    app.macros.set(idx, text);
+}
+
+fn send_macro(app: &mut AppState, idx: usize) -> Result<()> {
+    let Some(text) = app.macros.get(idx).map(str::to_string) else {
+        app.add_output_line(format!("[{} not bound]", macros::key_name(idx)));
+        return Ok(());
+    };
+    if tx_busy(app) {
+        return Ok(());
+    }
+    if let Some(handle) = app.serial() {
+        let mut data = text.clone().into_bytes();
+        let note = append_checksum(app, &mut data);
+        handle.write(data.clone())?;
+        note_sent(app, &data);
+        app.add_sent_line(format!(">> {}{note}", macros::escape(&text)));
+    } else {
+        app.add_output_line("[not open]");
+    }
+    Ok(())
+}
+
+fn start_transfer(app: &mut AppState, transfer: Transfer, verb: &str) {
+    if app.session.is_none() {
+        app.add_output_line("[not open]");
+        return;
+    }
+    app.add_output_line(format!(
+        "[transfer] {} {} {}",
+        transfer.protocol.name(),
+        verb,
+        transfer.path.display()
+    ));
+    app.transfer = Some(transfer);
+}
+
+fn start_flash(app: &mut AppState, spec: &str) {
+    if app.session.is_none() {
+        app.add_output_line("[not open]");
+        return;
+    }
+    let flash = stm32::parse_target(spec).and_then(|(path, addr)| Flasher::new(&path, addr));
+    let flash = match flash {
+        Ok(flash) => flash,
+        Err(e) => {
+            app.report_error(format!("{e:#}"));
+            return;
         }
+    };
+    if app.parity != Parity::Even {
+        app.add_output_line(
+            "[stm32] the bootloader expects 8E1; set parity = \"even\" in this port's profile",
+        );
     }
-    Ok(false)
+    app.add_output_line(format!(
+        "[stm32] flashing {} at 0x{:08x}",
+        flash.path.display(),
+        flash.address
+    ));
+    app.flash = Some(flash);
 }
 
+/// Moves through the ports the filter shows.
 fn move_selection(app: &mut AppState, delta: isize) {
-    if app.ports.is_empty() {
+    let visible = app.visible_ports();
+    if visible.is_empty() {
         app.selected_port = None;
         return;
     }
-    let len = app.ports.len() as isize;
-    let current = app.selected_port.map(|i| i as isize).unwrap_or(0);
+    let len = visible.len() as isize;
+    let current = app
+        .selected_port
+        .and_then(|i| visible.iter().position(|&v| v == i))
+        .map(|i| i as isize)
+        .unwrap_or(0);
     let mut next = current + delta;
     if next < 0 {
         next = 0;
@@ -257,18 +4519,17 @@ fn move_selection(app: &mut AppState, delta: isize) {
     if next >= len {
         next = len - 1;
     }
-    app.selected_port = Some(next as usize);
+    app.selected_port = Some(visible[next as usize]);
 }
 
 fn toggle_port(app: &mut AppState) -> Result<()> {
     if app.is_open {
-        if let Some(handle) = app.serial_handle.take() {
-            handle.close()?;
+        if let Some(busy) = port_busy(app) {
+            let text = format!("{busy}.\nClose the port and stop it?");
+            app.modal = Some(Modal::confirm("Close port", text, Then::ClosePort));
+            return Ok(());
         }
-        app.is_open = false;
-        app.serial_event_rx = None;
-        app.add_output_line("[closing...]");
-        return Ok(());
+        return close_port(app);
     }
 
     let idx = app
@@ -278,9 +4539,314 @@ fn toggle_port(app: &mut AppState) -> Result<()> {
         .ports
         .get(idx)
         .ok_or_else(|| anyhow!("invalid port index"))?;
-    let (handle, rx) = serial::open_port(&port.port_name, app.baud_rate)?;
-    app.serial_handle = Some(handle);
-    app.serial_event_rx = Some(rx);
+    open_target(app, port.port_name.clone())
+}
+
+fn close_port(app: &mut AppState) -> Result<()> {
+    if app.is_open {
+        remember_session(app);
+    }
+    if let Some(session) = app.session.take() {
+        session.close()?;
+    }
+    stop_sniff(app);
+    stop_compare(app);
+    stop_broadcast(app);
+    clear_profile(app);
+    if let Some(running) = app.shell.take() {
+        running.kill();
+    }
+    app.is_open = false;
+    app.modem_lines = None;
+    app.add_output_line("[closing...]");
+    Ok(())
+}
+
+/// Opens a local device path or a remote `rfc2217://` / `telnet://` target.
+fn open_target(app: &mut AppState, target: String) -> Result<()> {
+    open_with(app, target, None)
+}
+
+/// Opens the port used last, with the settings it had then.
+fn reopen_last(app: &mut AppState) -> Result<()> {
+    let Some(last) = app.recent.first().cloned() else {
+        app.add_output_line("[reopen] no port opened yet");
+        return Ok(());
+    };
+    open_with(app, last.target.clone(), Some(&last))
+}
+
+/// Opens `target`, with its profile applied and then, when reopening, the
+/// settings it was last used with.
+fn open_with(app: &mut AppState, target: String, last: Option<&RecentSession>) -> Result<()> {
+    if app.session.is_some() {
+        app.add_output_line("[already open]");
+        return Ok(());
+    }
+    let keys = match profile::attached(&app.ports, &target) {
+        Some(port) => profile::port_keys(port),
+        None => vec![target.clone()],
+    };
+    if let Some(found) = profile::find(&app.profiles, &keys).cloned() {
+        apply_profile(app, &found);
+    }
+    if let Some(last) = last {
+        app.baud_rate = last.baud;
+        app.parity = last.parity;
+        app.line_ending = last.line_ending;
+        app.checksum = last.checksum;
+        app.add_output_line(format!("[reopen] {}: {}", last.target, last.summary()));
+    }
+    // Held by its persistent name, so a reopen finds the adapter again
+    // even if it re-enumerates as another ttyUSB number.
+    let target = serial::stable_path(&target).unwrap_or(target);
+    let settings = PortSettings {
+        baud_rate: app.baud_rate,
+        parity: app.parity,
+        rs485: app.rs485,
+        exclusive: app.serial.exclusive,
+    };
+    let session = Session::open_with(&target, settings)?;
+    app.wakeups.watch(&session);
+    app.serial.apply(&session);
+    app.session = Some(session);
+    app.add_output_line(format!("[opening {target}]"));
+    Ok(())
+}
+
+/// The port and its current settings.
+fn current_session(app: &AppState) -> Option<RecentSession> {
+    Some(RecentSession {
+        target: app.session.as_ref()?.target().to_string(),
+        baud: app.baud_rate,
+        parity: app.parity,
+        line_ending: app.line_ending,
+        checksum: app.checksum,
+    })
+}
+
+/// Puts the open port and its current settings first in the recent list.
+fn remember_session(app: &mut AppState) {
+    let Some(session) = current_session(app) else {
+        return;
+    };
+    if app.recent.first() == Some(&session) {
+        return;
+    }
+    recent::remember(&mut app.recent, session);
+    if let Err(e) = recent::save(&app.recent) {
+        app.report_error(format!("saving recent sessions: {e:#}"));
+    }
+}
+
+/// Port settings as they were before a profile was applied.
+struct BeforeProfile {
+    baud: u32,
+    parity: Parity,
+    line_ending: LineEnding,
+    checksum: Option<Algorithm>,
+}
+
+fn apply_profile(app: &mut AppState, found: &config::ProfileConfig) {
+    if app.profile.is_none() {
+        app.before_profile = Some(BeforeProfile {
+            baud: app.baud_rate,
+            parity: app.parity,
+            line_ending: app.line_ending,
+            checksum: app.checksum,
+        });
+    }
+    app.baud_rate = found.baud.unwrap_or(app.baud_rate);
+    app.parity = found.parity.unwrap_or(app.parity);
+    app.rs485 = found.rs485.map(|r| r.settings());
+    app.profile_reset = found.reset.clone();
+    app.line_ending = found.line_ending.unwrap_or(app.line_ending);
+    if found.checksum.is_some() {
+        app.checksum = found.checksum;
+    }
+    if let Some(framing) = found.framing {
+        set_framing(app, framing);
+    }
+    let result = app
+        .macros
+        .extend(&found.macros)
+        .and_then(|_| app.highlighter.set_profile_rules(&found.highlight));
+    if let Err(e) = result {
+        app.report_error(format!("profile {}: {e:#}", found.name));
+    }
+    app.profile = Some(found.name.clone());
+    app.add_output_line(format!(
+        "[profile {}: {} {}, line ending {}{}{}]",
+        found.name,
+        app.baud_rate,
+        app.parity.frame(),
+        app.line_ending.name(),
+        if app.rs485.is_some() { ", RS-485" } else { "" },
+        found.checksum.map(|c| format!(", {}", c.name())).unwrap_or_default()
+    ));
+}
+
+/// Drops the closed port's profile macros, highlights and checksum, and
+/// puts back the baud rate, parity and line ending it replaced, so the next
+/// port doesn't open with them. Macros come back from the config file,
+/// since edits made meanwhile were saved there.
+fn clear_profile(app: &mut AppState) {
+    if app.profile.take().is_none() {
+        return;
+    }
+    if let Some(before) = app.before_profile.take() {
+        app.baud_rate = before.baud;
+        app.parity = before.parity;
+        app.line_ending = before.line_ending;
+        app.checksum = before.checksum;
+    }
+    app.rs485 = None;
+    app.profile_reset = None;
+    app.highlighter.profile_rules.clear();
+    match config::load().and_then(|c| Macros::from_config(&c.macros)) {
+        Ok(macros) => app.macros = macros,
+        Err(e) => app.add_output_line(format!("[config] {e:#}")),
+    }
+}
+
+fn save_profile(app: &mut AppState, name: &str) {
+    let Some(port) = app.selected_port.and_then(|i| app.ports.get(i)) else {
+        return;
+    };
+    let keys = profile::port_keys(port);
+    let mut saved = match profile::find(&app.profiles, &keys) {
+        Some(existing) => existing.clone(),
+        None => config::ProfileConfig {
+            name: String::new(),
+            pattern: keys[0].clone(),
+            baud: None,
+            parity: None,
+            line_ending: None,
+            framing: None,
+            rs485: None,
+            reset: None,
+            checksum: None,
+            macros: Default::default(),
+            highlight: Vec::new(),
+        },
+    };
+    saved.name = name.to_string();
+    saved.baud = Some(app.baud_rate);
+    // Neither can be changed here, so a profile's own parity and line
+    // ending are kept. The current ones are only saved when no profile set
+    // them and they aren't the defaults, e.g. after reopening a port.
+    let own = app.profile.is_none();
+    if saved.parity.is_none() && own && app.parity != Parity::default() {
+        saved.parity = Some(app.parity);
+    }
+    if saved.line_ending.is_none() && own && app.line_ending != LineEnding::default() {
+        saved.line_ending = Some(app.line_ending);
+    }
+    if let Err(e) = config::save_profile(&saved) {
+        app.report_error(format!("saving profile: {e:#}"));
+        return;
+    }
+    app.add_output_line(format!("[profile {} saved for {}]", saved.name, saved.pattern));
+    match app.profiles.iter_mut().find(|p| p.pattern == saved.pattern) {
+        Some(existing) => *existing = saved,
+        None => app.profiles.push(saved),
+    }
+}
+
+/// Zooms the Output pane to the whole terminal, giving it focus while it
+/// is the only pane, or back.
+fn toggle_zoom(app: &mut AppState) {
+    app.zoomed = !app.zoomed;
+    if app.zoomed {
+        app.unzoom_focus = app.focus;
+        app.focus = Focus::Output;
+    } else {
+        app.focus = app.unzoom_focus;
+    }
+}
+
+fn save_layout(app: &mut AppState) {
+    if let Err(e) = config::save_layout(&app.layout) {
+        app.report_error(format!("saving layout: {e:#}"));
+    }
+}
+
+fn toggle_favorite(app: &mut AppState) {
+    let Some(port) = app.selected_port.and_then(|i| app.ports.get(i)) else {
+        return;
+    };
+    let name = port.port_name.clone();
+    if profile::is_favorite(&app.favorites, port) {
+        let keys = profile::port_keys(port);
+        app.favorites.retain(|f| !keys.iter().any(|k| k.eq_ignore_ascii_case(f)));
+    } else {
+        app.favorites.push(profile::port_keys(port).remove(0));
+    }
+    if let Err(e) = config::save_favorites(&app.favorites) {
+        app.report_error(format!("saving favorites: {e:#}"));
+    }
+    profile::sort_ports(&mut app.ports, &app.favorites);
+    app.selected_port = app.ports.iter().position(|p| p.port_name == name);
+}
+
+fn toggle_dtr(app: &mut AppState) -> Result<()> {
+    let Some(handle) = app.serial() else {
+        app.add_output_line("[not open]");
+        return Ok(());
+    };
+    handle.set_dtr(!app.dtr)?;
+    app.dtr = !app.dtr;
+    app.add_output_line(format!("[DTR {}]", if app.dtr { "on" } else { "off" }));
+    Ok(())
+}
+
+fn toggle_rts(app: &mut AppState) -> Result<()> {
+    let Some(handle) = app.serial() else {
+        app.add_output_line("[not open]");
+        return Ok(());
+    };
+    handle.set_rts(!app.rts)?;
+    app.rts = !app.rts;
+    app.add_output_line(format!("[RTS {}]", if app.rts { "on" } else { "off" }));
+    Ok(())
+}
+
+fn send_break(app: &mut AppState, value: &str) -> Result<()> {
+    let ms = match value.parse::<u64>() {
+        Ok(ms) if ms > 0 => ms,
+        _ => {
+            app.add_output_line(format!("[invalid break duration: {value}]"));
+            return Ok(());
+        }
+    };
+    let Some(handle) = app.serial() else {
+        app.add_output_line("[not open]");
+        return Ok(());
+    };
+    handle.send_break(Duration::from_millis(ms))?;
+    app.break_duration = Duration::from_millis(ms);
+    app.add_output_line(format!("[break {ms} ms]"));
+    Ok(())
+}
+
+fn run_reset(app: &mut AppState, name: &str) -> Result<()> {
+    let Some(steps) = config::reset_sequence(&app.reset_sequences, name) else {
+        let names = config::reset_names(&app.reset_sequences).join(", ");
+        app.add_output_line(format!("[unknown reset sequence {name}; have {names}]"));
+        return Ok(());
+    };
+    let Some(handle) = app.serial() else {
+        app.add_output_line("[not open]");
+        return Ok(());
+    };
+    handle.run_sequence(steps.clone())?;
+    // The header shows where the lines end up.
+    for step in &steps {
+        app.dtr = step.dtr.unwrap_or(app.dtr);
+        app.rts = step.rts.unwrap_or(app.rts);
+    }
+    app.last_reset = name.to_string();
+    app.add_output_line(format!("[reset {name}]"));
     Ok(())
 }
 
@@ -288,16 +4854,351 @@ fn send_input(app: &mut AppState) -> Result<()> {
     if app.input_buffer.is_empty() {
         return Ok(());
     }
-    if let Some(handle) = &app.serial_handle {
-        let mut data = app.input_buffer.clone().into_bytes();
-        data.push(b'\n');
-        handle.write(data)?;
-        app.add_output_line(format!(">> {}", app.input_buffer));
-        app.input_buffer.clear();
+    if tx_busy(app) {
+        return Ok(());
+    }
+    if app.serial().is_some() {
+        let line = std::mem::take(&mut app.input_buffer);
+        app.input_cursor = 0;
+        match line.strip_prefix('!') {
+            // `!!` sends a line that starts with `!`.
+            Some(rest) if rest.starts_with('!') => send_line(app, rest)?,
+            Some(command) => run_shell(app, command.trim()),
+            None => send_line(app, &line)?,
+        }
     } else {
         app.add_output_line("[not open]");
     }
     Ok(())
 }
 
+/// Starts `command`, its output to be sent to the port; a bare `!` stops
+/// the one running.
+fn run_shell(app: &mut AppState, command: &str) {
+    if command.is_empty() {
+        match app.shell.take() {
+            Some(running) => {
+                running.kill();
+                app.add_output_line(format!("[shell] stopped {}", running.command));
+            }
+            None => app.add_output_line("[shell] nothing running"),
+        }
+        return;
+    }
+    if let Some(running) = &app.shell {
+        let line = format!("[shell] still running {} (! alone stops it)", running.command);
+        app.add_output_line(line);
+        return;
+    }
+    match shell::spawn(command) {
+        Ok(running) => {
+            app.add_output_line(format!("[shell] $ {command}"));
+            app.shell = Some(running);
+        }
+        Err(e) => app.add_output_line(format!("[shell] {command}: {e}")),
+    }
+}
+
+fn drain_shell_events(app: &mut AppState) -> Result<()> {
+    let mut drained: Vec<ShellEvent> = Vec::new();
+    if let Some(running) = app.shell.as_ref() {
+        while let Ok(ev) = running.rx.try_recv() {
+            drained.push(ev);
+        }
+    }
+
+    for ev in drained {
+        match ev {
+            ShellEvent::Stdout(line) if app.shell_line_by_line => {
+                queue_lines(app, std::iter::once(line))?;
+            }
+            ShellEvent::Stdout(line) => {
+                if let Some(running) = app.shell.as_mut() {
+                    running.output.push(line);
+                }
+            }
+            ShellEvent::Stderr(line) => app.add_output_line(format!("[shell] {line}")),
+            ShellEvent::Exited(status) => {
+                let Some(running) = app.shell.take() else {
+                    continue;
+                };
+                match status {
+                    Ok(status) if !status.success() => {
+                        app.add_output_line(format!("[shell] {}: {status}", running.command));
+                    }
+                    Ok(_) => {}
+                    Err(e) => app.add_output_line(format!("[shell] {}: {e}", running.command)),
+                }
+                if !running.output.is_empty() {
+                    queue_lines(app, std::iter::once(running.output.join("\n")))?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn start_pipe(app: &mut AppState, command: &str) {
+    if command.is_empty() {
+        return;
+    }
+    app.pipe_command = command.to_string();
+    match shell::spawn_filter(command) {
+        Ok(pipe) => {
+            app.add_output_line(format!("[pipe] received data goes through {command}"));
+            app.pipe = Some(pipe);
+        }
+        Err(e) => app.add_output_line(format!("[pipe] {command}: {e}")),
+    }
+}
+
+/// The filter's output is shown as if it had been received; its errors
+/// are shown as notes.
+fn drain_pipe_events(app: &mut AppState) {
+    let mut drained: Vec<ShellEvent> = Vec::new();
+    if let Some(pipe) = app.pipe.as_ref() {
+        while let Ok(ev) = pipe.rx.try_recv() {
+            drained.push(ev);
+        }
+    }
+
+    for ev in drained {
+        match ev {
+            ShellEvent::Stdout(line) => add_received_line(app, line),
+            ShellEvent::Stderr(line) => app.add_output_line(format!("[pipe] {line}")),
+            ShellEvent::Exited(status) => {
+                let Some(pipe) = app.pipe.take() else {
+                    continue;
+                };
+                let mut line = match status {
+                    Ok(status) => format!("[pipe] {} ended: {status}", pipe.command),
+                    Err(e) => format!("[pipe] {}: {e}", pipe.command),
+                };
+                if pipe.dropped > 0 {
+                    line.push_str(&format!(", {} bytes not piped", pipe.dropped));
+                }
+                app.add_output_line(line);
+                app.add_output_line("[pipe] showing received data as is");
+            }
+        }
+    }
+}
+
+/// With `wait_for_tx`, the bytes of earlier sends not written yet, which
+/// hold a new send back.
+fn tx_backlog(app: &AppState) -> Option<usize> {
+    if !app.wait_for_tx {
+        return None;
+    }
+    let queued = app.serial()?.queued();
+    (queued > 0).then_some(queued)
+}
+
+/// Turns a send down while `tx_backlog` holds it back, saying why.
+fn tx_busy(app: &mut AppState) -> bool {
+    let Some(queued) = tx_backlog(app) else {
+        return false;
+    };
+    app.add_output_line(format!("[tx busy: {queued} bytes still to send]"));
+    true
+}
+
+/// Sends one line of text with the line ending, as if typed and entered.
+fn send_line(app: &mut AppState, line: &str) -> Result<()> {
+    let Some(handle) = app.serial() else {
+        app.add_output_line("[not open]");
+        return Ok(());
+    };
+    let mut data = line.as_bytes().to_vec();
+    let mut note = append_checksum(app, &mut data);
+    data.extend_from_slice(input_ending(app));
+    handle.write(data.clone())?;
+    if app.broadcast.is_some() {
+        let targets = app.broadcast_targets();
+        for session in &targets {
+            session.handle().write(data.clone())?;
+        }
+        note.push_str(&format!("  [+{} ports]", targets.len()));
+    }
+    if app.at_mode {
+        app.at.sent(line);
+    }
+    note_sent(app, &data);
+    app.add_sent_line(format!(">> {}{note}", caret_text(line)));
+    Ok(())
+}
+
+/// Appends the session checksum to `data`, if one is set, and returns a
+/// note for the echoed line.
+fn append_checksum(app: &AppState, data: &mut Vec<u8>) -> String {
+    let Some(algorithm) = app.checksum else {
+        return String::new();
+    };
+    let trailer = algorithm.bytes(data);
+    data.extend_from_slice(&trailer);
+    format!("  [{} {}]", algorithm.name(), hexdump::hex(&trailer))
+}
+
+/// What Enter appends to a typed line: CRLF in AT mode, otherwise the
+/// port's line ending.
+fn input_ending(app: &AppState) -> &'static [u8] {
+    if app.at_mode {
+        b"\r\n"
+    } else {
+        app.line_ending.bytes()
+    }
+}
+
+/// Bracketed paste: text goes in at the input cursor, and each line it
+/// completes is queued to be sent, `paste_delay` apart. Prompts and the
+/// palette take the text as a single line.
+fn handle_paste(app: &mut AppState, text: &str) -> Result<()> {
+    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+    if app.passthrough.is_some() {
+        // A terminal sends Enter as CR, so pasted lines should too.
+        return write_raw(app, text.replace('\n', "\r").into_bytes());
+    }
+    if let Some(prompt) = app.prompt.as_mut() {
+        prompt.buffer.push_str(text.trim_end_matches('\n').replace('\n', " ").as_str());
+        return Ok(());
+    }
+    if let Some(compose) = app.compose.as_mut() {
+        compose.insert(&text);
+        return Ok(());
+    }
+    if let Some(input) = app.checksum_view.as_mut() {
+        input.push_str(&text.replace('\n', " "));
+        return Ok(());
+    }
+    if let Some(palette) = app.palette.as_mut() {
+        palette.query.push_str(text.lines().next().unwrap_or_default());
+        palette.selected = 0;
+        return Ok(());
+    }
+    if app.transfer.is_some()
+        || app.flash.is_some()
+        || app.auto_response_view.is_some()
+        || app.macro_view.is_some()
+        || app.highlight_view.is_some()
+        || app.help_view.is_some()
+        || app.error_view.is_some()
+        || app.inspector.is_some()
+    {
+        return Ok(());
+    }
+    app.focus = Focus::Input;
+    app.input_buffer.insert_str(app.input_cursor, &text);
+    app.input_cursor += text.len();
+    let Some(last) = app.input_buffer.rfind('\n') else {
+        return Ok(());
+    };
+    let rest = app.input_buffer.split_off(last + 1);
+    app.input_cursor -= last + 1;
+    let done = std::mem::replace(&mut app.input_buffer, rest);
+    queue_lines(app, done.lines().map(str::to_string))
+}
+
+/// Queues lines to be sent `paste_delay` apart, after any still pending.
+fn queue_lines(app: &mut AppState, lines: impl Iterator<Item = String>) -> Result<()> {
+    match app.paste.as_mut() {
+        Some(paste) => paste.lines.extend(lines),
+        None => {
+            app.paste = Some(Paste {
+                lines: lines.collect(),
+                sent: 0,
+                next: Instant::now(),
+            });
+        }
+    }
+    tick_paste(app)
+}
+
+fn tick_paste(app: &mut AppState) -> Result<()> {
+    if tx_backlog(app).is_some() {
+        return Ok(());
+    }
+    let Some(paste) = app.paste.as_mut() else {
+        return Ok(());
+    };
+    if Instant::now() < paste.next {
+        return Ok(());
+    }
+    if app.session.is_none() {
+        let left = paste.lines.len();
+        app.paste = None;
+        app.add_output_line(format!("[paste stopped: not open, {left} lines not sent]"));
+        return Ok(());
+    }
+    let Some(line) = paste.lines.pop_front() else {
+        app.paste = None;
+        return Ok(());
+    };
+    paste.sent += 1;
+    paste.next = Instant::now() + app.paste_delay;
+    if paste.lines.is_empty() {
+        app.paste = None;
+    }
+    send_line(app, &line)
+}
+
+fn handle_compose_key(app: &mut AppState, key: KeyEvent) -> Result<()> {
+    let Some(editor) = app.compose.as_mut() else {
+        return Ok(());
+    };
+    let outcome = editor.on_key(key);
+    if matches!(outcome, compose::Outcome::None) {
+        return Ok(());
+    }
+    let editor = app.compose.take().unwrap_or_else(|| Compose::new(""));
+    let text = editor.text();
+    match outcome {
+        compose::Outcome::SendWhole | compose::Outcome::SendLines if editor.is_empty() => {}
+        compose::Outcome::SendWhole | compose::Outcome::SendLines if app.session.is_none() => {
+            app.add_output_line("[not open]");
+            app.compose_draft = text;
+            return Ok(());
+        }
+        compose::Outcome::SendWhole if tx_busy(app) => {
+            app.compose_draft = text;
+            return Ok(());
+        }
+        compose::Outcome::SendWhole => {
+            let ending = input_ending(app);
+            let mut data = Vec::with_capacity(text.len() + editor.lines.len() * ending.len());
+            let mut echo = Vec::with_capacity(editor.lines.len());
+            for line in &editor.lines {
+                let mut payload = line.as_bytes().to_vec();
+                let note = append_checksum(app, &mut payload);
+                data.extend_from_slice(&payload);
+                data.extend_from_slice(ending);
+                echo.push(format!(">> {}{note}", caret_text(line)));
+            }
+            if let Some(handle) = app.serial() {
+                handle.write(data.clone())?;
+                note_sent(app, &data);
+            }
+            for line in echo {
+                app.add_sent_line(line);
+            }
+        }
+        compose::Outcome::SendLines => queue_lines(app, editor.lines.into_iter())?,
+        compose::Outcome::Close | compose::Outcome::None => {
+            app.compose_draft = text;
+            return Ok(());
+        }
+    }
+    app.compose_draft.clear();
+    Ok(())
+}
+
+fn stop_paste(app: &mut AppState) {
+    if let Some(paste) = app.paste.take() {
+        app.add_output_line(format!(
+            "[paste stopped after {} lines, {} not sent]",
+            paste.sent,
+            paste.lines.len()
+        ));
+    }
+}
+
 
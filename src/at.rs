@@ -0,0 +1,235 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// Suggestions listed under the input at most.
+pub const MAX_SUGGESTIONS: usize = 6;
+const MAX_HISTORY: usize = 200;
+
+/// Commands offered out of the box; `[at_commands]` in the config adds more.
+const CATALOG: &[(&str, &str)] = &[
+    ("AT", "Attention, check the module answers"),
+    ("ATI", "Module identification"),
+    ("ATE0", "Echo off"),
+    ("ATE1", "Echo on"),
+    ("ATZ", "Reset to the stored profile"),
+    ("AT&F", "Factory defaults"),
+    ("AT&W", "Store the current profile"),
+    ("AT+CMEE=2", "Verbose +CME ERROR messages"),
+    ("AT+CGMI", "Manufacturer"),
+    ("AT+CGMM", "Model"),
+    ("AT+CGMR", "Firmware revision"),
+    ("AT+CGSN", "IMEI"),
+    ("AT+CIMI", "IMSI"),
+    ("AT+CCID", "SIM ICCID"),
+    ("AT+CPIN?", "SIM PIN state"),
+    ("AT+CFUN?", "Functionality level"),
+    ("AT+CFUN=1", "Full functionality"),
+    ("AT+CFUN=0", "Minimum functionality (radio off)"),
+    ("AT+CSQ", "Signal quality (RSSI, BER)"),
+    ("AT+CESQ", "Extended signal quality"),
+    ("AT+CREG?", "Circuit network registration"),
+    ("AT+CGREG?", "GPRS network registration"),
+    ("AT+CEREG?", "LTE network registration"),
+    ("AT+COPS?", "Current operator"),
+    ("AT+COPS=?", "Scan operators"),
+    ("AT+CGATT?", "Packet service attach state"),
+    ("AT+CGDCONT?", "PDP contexts"),
+    ("AT+CGPADDR", "PDP addresses"),
+    ("AT+CMGF=1", "SMS text mode"),
+    ("AT+CMGL=\"ALL\"", "List SMS"),
+    ("AT+CMGS=", "Send SMS to number"),
+    ("AT+CCLK?", "Real-time clock"),
+    ("AT+CBC", "Battery charge"),
+    ("AT+IPR?", "Baud rate"),
+    ("AT+GMR", "Firmware version"),
+    ("AT+RST", "Restart (ESP AT)"),
+    ("AT+CWMODE?", "Wi-Fi mode (ESP AT)"),
+    ("AT+CWLAP", "List access points (ESP AT)"),
+    ("AT+CIFSR", "Local IP address (ESP AT)"),
+    ("AT+NAME?", "BLE device name (HM-10)"),
+    ("AT+ADDR?", "BLE address (HM-10)"),
+    ("AT+ROLE?", "BLE role (HM-10)"),
+];
+
+/// Final result codes that end a command.
+const OK_CODES: &[&str] = &["OK", "CONNECT", "SEND OK", "READY"];
+const ERROR_CODES: &[&str] = &[
+    "ERROR",
+    "+CME ERROR",
+    "+CMS ERROR",
+    "NO CARRIER",
+    "BUSY",
+    "NO ANSWER",
+    "NO DIALTONE",
+    "SEND FAIL",
+];
+
+pub struct Suggestion {
+    pub command: String,
+    pub help: String,
+}
+
+/// Where the last command stands.
+pub enum CommandState {
+    Waiting {
+        command: String,
+        since: Instant,
+    },
+    Done {
+        command: String,
+        result: String,
+        ok: bool,
+        took: Duration,
+    },
+}
+
+/// Completion, history and result tracking for the AT command mode.
+#[derive(Default)]
+pub struct AtAssistant {
+    catalog: Vec<Suggestion>,
+    history: Vec<String>,
+    /// Entry being shown while walking the history with Up/Down.
+    history_pos: Option<usize>,
+    /// What was typed before walking the history.
+    draft: String,
+    pub state: Option<CommandState>,
+}
+
+impl AtAssistant {
+    /// The bundled catalog plus the config's `[at_commands]` (`"AT+X" =
+    /// "help"`), which replace bundled entries with the same command.
+    pub fn new(extra: &BTreeMap<String, String>) -> Self {
+        let mut catalog: Vec<Suggestion> = CATALOG
+            .iter()
+            .filter(|(command, _)| !extra.contains_key(*command))
+            .map(|(command, help)| Suggestion {
+                command: command.to_string(),
+                help: help.to_string(),
+            })
+            .collect();
+        catalog.extend(extra.iter().map(|(command, help)| Suggestion {
+            command: command.clone(),
+            help: help.clone(),
+        }));
+        Self {
+            catalog,
+            ..Self::default()
+        }
+    }
+
+    /// Commands starting with `input` (case-insensitively): recent history
+    /// first, then the catalog.
+    pub fn suggestions(&self, input: &str) -> Vec<Suggestion> {
+        if input.is_empty() {
+            return Vec::new();
+        }
+        let upper = input.to_ascii_uppercase();
+        let matches = |command: &str| {
+            command.to_ascii_uppercase().starts_with(&upper) && command.len() > input.len()
+        };
+        let mut out: Vec<Suggestion> = Vec::new();
+        for command in self.history.iter().rev() {
+            if matches(command) && !out.iter().any(|s| s.command == *command) {
+                out.push(Suggestion {
+                    command: command.clone(),
+                    help: "history".to_string(),
+                });
+            }
+        }
+        for entry in &self.catalog {
+            if matches(&entry.command) && !out.iter().any(|s| s.command == entry.command) {
+                out.push(Suggestion {
+                    command: entry.command.clone(),
+                    help: entry.help.clone(),
+                });
+            }
+        }
+        out.truncate(MAX_SUGGESTIONS);
+        out
+    }
+
+    /// The rest of the first suggestion, shown after the cursor and taken
+    /// with Right.
+    pub fn completion(&self, input: &str) -> Option<String> {
+        let first = self.suggestions(input).into_iter().next()?;
+        Some(first.command[input.len()..].to_string())
+    }
+
+    /// Records a command as sent and starts waiting for its result.
+    pub fn sent(&mut self, command: &str) {
+        if self.history.last().map(String::as_str) != Some(command) {
+            self.history.push(command.to_string());
+            if self.history.len() > MAX_HISTORY {
+                self.history.remove(0);
+            }
+        }
+        self.history_pos = None;
+        self.state = Some(CommandState::Waiting {
+            command: command.to_string(),
+            since: Instant::now(),
+        });
+    }
+
+    /// Sent commands, oldest first.
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    /// Takes the history of an earlier run.
+    pub fn restore_history(&mut self, mut history: Vec<String>) {
+        let excess = history.len().saturating_sub(MAX_HISTORY);
+        history.drain(..excess);
+        self.history = history;
+        self.history_pos = None;
+    }
+
+    /// Steps back through sent commands; `current` is what the input holds.
+    pub fn history_prev(&mut self, current: &str) -> Option<String> {
+        let pos = match self.history_pos {
+            None => {
+                self.draft = current.to_string();
+                self.history.len().checked_sub(1)?
+            }
+            Some(pos) => pos.checked_sub(1)?,
+        };
+        self.history_pos = Some(pos);
+        self.history.get(pos).cloned()
+    }
+
+    /// Steps forward again, ending at what was typed before.
+    pub fn history_next(&mut self) -> Option<String> {
+        let pos = self.history_pos? + 1;
+        if pos < self.history.len() {
+            self.history_pos = Some(pos);
+            self.history.get(pos).cloned()
+        } else {
+            self.history_pos = None;
+            Some(std::mem::take(&mut self.draft))
+        }
+    }
+
+    /// Looks at a received line for a final result code. Returns whether it
+    /// reported success, or `None` if the line isn't one.
+    pub fn on_line(&mut self, line: &str) -> Option<bool> {
+        let line = line.trim();
+        let ok = if OK_CODES.contains(&line) {
+            true
+        } else if ERROR_CODES
+            .iter()
+            .any(|code| line == *code || line.starts_with(&format!("{code}:")))
+        {
+            false
+        } else {
+            return None;
+        };
+        if let Some(CommandState::Waiting { command, since }) = &self.state {
+            self.state = Some(CommandState::Done {
+                command: command.clone(),
+                result: line.to_string(),
+                ok,
+                took: since.elapsed(),
+            });
+        }
+        Some(ok)
+    }
+}
@@ -0,0 +1,137 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use regex::Regex;
+
+use crate::config::AutoResponseConfig;
+
+pub enum Matcher {
+    Substring(String),
+    Regex(Regex),
+}
+
+pub struct Rule {
+    pub name: String,
+    pub matcher: Matcher,
+    pub response: String,
+    pub delay: Duration,
+    pub enabled: bool,
+}
+
+impl Rule {
+    /// Returns the response to send if `line` triggers this rule.
+    /// Regex responses may reference capture groups (`$1`, `${name}`).
+    fn respond(&self, line: &str) -> Option<String> {
+        match &self.matcher {
+            Matcher::Substring(s) => line.contains(s.as_str()).then(|| self.response.clone()),
+            Matcher::Regex(re) => {
+                let caps = re.captures(line)?;
+                let mut out = String::new();
+                caps.expand(&self.response, &mut out);
+                Some(out)
+            }
+        }
+    }
+}
+
+struct Pending {
+    due: Instant,
+    rule: String,
+    response: String,
+}
+
+pub struct AutoResponder {
+    pub enabled: bool,
+    pub rules: Vec<Rule>,
+    pending: Vec<Pending>,
+}
+
+impl Default for AutoResponder {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            rules: Vec::new(),
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl AutoResponder {
+    pub fn from_config(configs: &[AutoResponseConfig]) -> Result<Self> {
+        let rules = configs
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                let name = c.name.clone().unwrap_or_else(|| format!("rule{}", i + 1));
+                let matcher = if c.regex {
+                    Matcher::Regex(
+                        Regex::new(&c.pattern)
+                            .with_context(|| format!("auto_response {name}: bad regex"))?,
+                    )
+                } else {
+                    Matcher::Substring(c.pattern.clone())
+                };
+                Ok(Rule {
+                    name,
+                    matcher,
+                    response: c.response.clone(),
+                    delay: Duration::from_millis(c.delay_ms),
+                    enabled: c.enabled,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            rules,
+            ..Self::default()
+        })
+    }
+
+    /// Checks a received line against every enabled rule, queueing responses.
+    pub fn on_line(&mut self, line: &str) {
+        if !self.enabled {
+            return;
+        }
+        let now = Instant::now();
+        for rule in self.rules.iter().filter(|r| r.enabled) {
+            if let Some(response) = rule.respond(line) {
+                self.pending.push(Pending {
+                    due: now + rule.delay,
+                    rule: rule.name.clone(),
+                    response,
+                });
+            }
+        }
+    }
+
+    /// Removes and returns `(rule name, response)` for every response now due.
+    pub fn take_due(&mut self) -> Vec<(String, String)> {
+        let now = Instant::now();
+        let mut due = Vec::new();
+        self.pending.retain(|p| {
+            if p.due <= now {
+                due.push((p.rule.clone(), p.response.clone()));
+                false
+            } else {
+                true
+            }
+        });
+        due
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+        if !self.enabled {
+            self.pending.clear();
+        }
+    }
+
+    pub fn toggle_rule(&mut self, idx: usize) {
+        if let Some(rule) = self.rules.get_mut(idx) {
+            rule.enabled = !rule.enabled;
+            if !rule.enabled {
+                let name = rule.name.clone();
+                self.pending.retain(|p| p.rule != name);
+            }
+        }
+    }
+}
@@ -0,0 +1,385 @@
+//! `run <script.toml>`: opens a port, works through send/expect steps and
+//! reports pass or fail through the exit code, for firmware smoke tests in
+//! CI.
+//!
+//! ```toml
+//! port = "/dev/ttyUSB0"
+//! baud = 115200
+//! line_ending = "crlf"
+//! timeout_ms = 5000
+//! fail_on = ["panicked at", "Guru Meditation"]
+//!
+//! [[step]]
+//! send = "version"
+//!
+//! [[step]]
+//! expect_regex = 'v\d+\.\d+\.\d+'
+//! timeout_ms = 2000
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use lazyserial::encoding::TextDecoder;
+use lazyserial::{LineStep, Parity, PortSettings, SerialEvent, Session};
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::cli::Args;
+use crate::config::{self, ResetStepConfig, Rs485Config};
+use crate::profile::{self, LineEnding};
+
+const POLL: Duration = Duration::from_millis(20);
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Script {
+    /// `--port` overrides it.
+    port: Option<String>,
+    baud: Option<u32>,
+    parity: Option<Parity>,
+    /// RS-485 direction control, as in a port profile.
+    rs485: Option<Rs485Config>,
+    /// Appended to `send` steps; defaults to the port profile's, else `lf`.
+    line_ending: Option<LineEnding>,
+    /// For steps without their own.
+    #[serde(default = "default_timeout_ms")]
+    timeout_ms: u64,
+    /// Received text that fails the run whenever it shows up.
+    #[serde(default)]
+    fail_on: Vec<String>,
+    /// Copy received data to stdout.
+    #[serde(default = "default_true")]
+    echo: bool,
+    #[serde(default)]
+    step: Vec<Step>,
+}
+
+fn default_timeout_ms() -> u64 {
+    5000
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// One of `send`, `send_raw`, `send_hex`, `break_ms`, `reset`, `expect`,
+/// `expect_regex` or `sleep_ms`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Step {
+    name: Option<String>,
+    send: Option<String>,
+    /// Sent without a line ending.
+    send_raw: Option<String>,
+    /// Bytes as hex, spaces allowed.
+    send_hex: Option<String>,
+    /// Holds the line in break for this long.
+    break_ms: Option<u64>,
+    /// A reset sequence by name, built in or from `[reset]` in the config.
+    reset: Option<String>,
+    expect: Option<String>,
+    expect_regex: Option<String>,
+    sleep_ms: Option<u64>,
+    timeout_ms: Option<u64>,
+}
+
+enum Action {
+    Send(Vec<u8>),
+    Break(Duration),
+    Lines(Vec<LineStep>),
+    Expect(Regex),
+    Sleep(Duration),
+}
+
+impl Step {
+    fn action(
+        &self,
+        line_ending: LineEnding,
+        resets: &BTreeMap<String, Vec<ResetStepConfig>>,
+    ) -> Result<Action> {
+        let mut actions = Vec::new();
+        if let Some(text) = &self.send {
+            let mut data = text.as_bytes().to_vec();
+            data.extend_from_slice(line_ending.bytes());
+            actions.push(Action::Send(data));
+        }
+        if let Some(text) = &self.send_raw {
+            actions.push(Action::Send(text.as_bytes().to_vec()));
+        }
+        if let Some(hex) = &self.send_hex {
+            actions.push(Action::Send(parse_hex(hex)?));
+        }
+        if let Some(ms) = self.break_ms {
+            actions.push(Action::Break(Duration::from_millis(ms)));
+        }
+        if let Some(name) = &self.reset {
+            let steps = config::reset_sequence(resets, name)
+                .with_context(|| format!("unknown reset sequence {name:?}"))?;
+            actions.push(Action::Lines(steps));
+        }
+        if let Some(text) = &self.expect {
+            actions.push(Action::Expect(Regex::new(&regex::escape(text))?));
+        }
+        if let Some(pattern) = &self.expect_regex {
+            let re = Regex::new(pattern).with_context(|| format!("bad regex {pattern:?}"))?;
+            actions.push(Action::Expect(re));
+        }
+        if let Some(ms) = self.sleep_ms {
+            actions.push(Action::Sleep(Duration::from_millis(ms)));
+        }
+        match actions.len() {
+            1 => Ok(actions.remove(0)),
+            0 => bail!(
+                "no action (send, send_raw, send_hex, break_ms, reset, expect, expect_regex \
+                 or sleep_ms)"
+            ),
+            _ => bail!("more than one action; split it into separate steps"),
+        }
+    }
+
+    fn describe(&self) -> String {
+        if let Some(name) = &self.name {
+            return name.clone();
+        }
+        let fields = [
+            ("send", &self.send),
+            ("send_raw", &self.send_raw),
+            ("send_hex", &self.send_hex),
+            ("reset", &self.reset),
+            ("expect", &self.expect),
+            ("expect_regex", &self.expect_regex),
+        ];
+        match fields.into_iter().find_map(|(k, v)| v.as_ref().map(|v| (k, v))) {
+            Some((kind, value)) => format!("{kind} {value:?}"),
+            None => match self.break_ms {
+                Some(ms) => format!("break {ms} ms"),
+                None => format!("sleep {} ms", self.sleep_ms.unwrap_or(0)),
+            },
+        }
+    }
+}
+
+fn parse_hex(hex: &str) -> Result<Vec<u8>> {
+    let digits: String = hex.chars().filter(|c| !c.is_whitespace()).collect();
+    if !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        bail!("bad hex {hex:?}");
+    }
+    if digits.len() & 1 != 0 {
+        bail!("odd number of hex digits in {hex:?}");
+    }
+    Ok((0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).unwrap_or_default())
+        .collect())
+}
+
+/// Received text and how far expectations have consumed it.
+struct Received {
+    decoder: TextDecoder,
+    text: String,
+    /// Expectations only match after this offset.
+    cursor: usize,
+    /// `fail_on` is checked from here.
+    checked: usize,
+    closed: bool,
+}
+
+impl Received {
+    /// Takes in pending events, echoing data to stdout if asked.
+    fn poll(&mut self, session: &Session, echo: bool) -> Result<()> {
+        while let Some(event) = session.try_event() {
+            match event {
+                SerialEvent::Data(bytes) => {
+                    if echo {
+                        let mut stdout = io::stdout().lock();
+                        stdout.write_all(&bytes)?;
+                        stdout.flush()?;
+                    }
+                    let text = self.decoder.decode(&bytes);
+                    self.text.push_str(&text);
+                }
+                SerialEvent::Error(e) if e.starts_with("modem status") => {}
+                SerialEvent::Error(e) => eprintln!("[error] {e}"),
+                SerialEvent::Closed => self.closed = true,
+                SerialEvent::Opened
+                | SerialEvent::ModemStatus(_)
+                | SerialEvent::OpenFailed(..)
+                | SerialEvent::Stats(_)
+                | SerialEvent::TxProgress(_)
+                | SerialEvent::TxDone => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Keeps taking in events for `duration`, or until the port closes.
+    fn wait(&mut self, session: &Session, echo: bool, duration: Duration) -> Result<()> {
+        let end = Instant::now() + duration;
+        while Instant::now() < end && !self.closed {
+            self.poll(session, echo)?;
+            thread::sleep(POLL);
+        }
+        Ok(())
+    }
+
+    /// The first `fail_on` entry that appeared since the last check.
+    fn failure<'a>(&mut self, fail_on: &'a [String]) -> Option<&'a str> {
+        // Keep a tail so a marker split across reads is still found.
+        let longest = fail_on.iter().map(String::len).max().unwrap_or(0);
+        let mut from = self.checked.saturating_sub(longest);
+        while !self.text.is_char_boundary(from) {
+            from -= 1;
+        }
+        self.checked = self.text.len();
+        let window = &self.text[from..];
+        fail_on.iter().find(|f| window.contains(f.as_str())).map(String::as_str)
+    }
+
+    /// The tail of what came in, for failure messages.
+    fn tail(&self) -> String {
+        let rest = &self.text[self.cursor..];
+        let mut start = rest.len().saturating_sub(200);
+        while !rest.is_char_boundary(start) {
+            start += 1;
+        }
+        rest[start..].escape_debug().to_string()
+    }
+}
+
+/// Runs a script. `Ok(false)` means a step failed; errors are problems
+/// with the script or the port.
+pub fn run(path: &Path, args: &Args) -> Result<bool> {
+    let text = fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
+    let script: Script =
+        toml::from_str(&text).with_context(|| format!("parse {}", path.display()))?;
+    let Some(target) = args.port.as_deref().or(script.port.as_deref()) else {
+        bail!("{}: no port (set `port` or pass --port)", path.display());
+    };
+    let config = config::load().unwrap_or_else(|e| {
+        eprintln!("[config] {e:#}");
+        config::Config::default()
+    });
+    let found = profile::for_target(&config.profile, target);
+    let settings = PortSettings {
+        baud_rate: args
+            .baud
+            .or(script.baud)
+            .or(found.and_then(|p| p.baud))
+            .unwrap_or(115_200),
+        parity: script
+            .parity
+            .or(found.and_then(|p| p.parity))
+            .unwrap_or_default(),
+        rs485: script
+            .rs485
+            .or(found.and_then(|p| p.rs485))
+            .map(|r| r.settings()),
+        exclusive: config.serial.exclusive,
+    };
+    let line_ending = args
+        .line_ending
+        .or(script.line_ending)
+        .or(found.and_then(|p| p.line_ending))
+        .unwrap_or_default();
+    let steps = script
+        .step
+        .iter()
+        .enumerate()
+        .map(|(i, step)| {
+            step.action(line_ending, &config.reset)
+                .with_context(|| format!("step {}", i + 1))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let session = Session::open_with(target, settings)?;
+    config.serial.apply(&session);
+    let deadline = Instant::now() + Duration::from_millis(script.timeout_ms);
+    loop {
+        match session.recv_timeout(POLL) {
+            Some(SerialEvent::Opened) => break,
+            Some(SerialEvent::Error(e)) => bail!(e),
+            Some(SerialEvent::Closed) => bail!("{target} closed"),
+            _ if Instant::now() >= deadline => bail!("timed out opening {target}"),
+            _ => {}
+        }
+    }
+    eprintln!(
+        "[run] {} on {target} at {} {}",
+        path.display(),
+        settings.baud_rate,
+        settings.parity.frame()
+    );
+
+    let mut received = Received {
+        decoder: TextDecoder::new(config.display.encoding),
+        text: String::new(),
+        cursor: 0,
+        checked: 0,
+        closed: false,
+    };
+    let total = steps.len();
+    let started = Instant::now();
+    for (i, (step, action)) in script.step.iter().zip(steps).enumerate() {
+        let label = format!("[{}/{total}] {}", i + 1, step.describe());
+        let timeout = Duration::from_millis(step.timeout_ms.unwrap_or(script.timeout_ms));
+        let step_started = Instant::now();
+        let result = match action {
+            Action::Send(data) => session.handle().write(data).map(|()| Ok(())),
+            Action::Break(duration) => session.handle().send_break(duration).map(|()| Ok(())),
+            Action::Lines(steps) => {
+                let duration = steps.iter().map(|s| s.hold).sum();
+                session.handle().run_sequence(steps)?;
+                // Let the sequence finish before the next step.
+                received.wait(&session, script.echo, duration)?;
+                Ok(Ok(()))
+            }
+            Action::Sleep(duration) => {
+                received.wait(&session, script.echo, duration)?;
+                Ok(Ok(()))
+            }
+            Action::Expect(re) => loop {
+                received.poll(&session, script.echo)?;
+                if let Some(m) = re.find(&received.text[received.cursor..]) {
+                    received.cursor += m.end();
+                    break Ok(Ok(()));
+                }
+                if received.closed {
+                    break Ok(Err("port closed".to_string()));
+                }
+                if step_started.elapsed() >= timeout {
+                    let waited = timeout.as_millis();
+                    let got = received.tail();
+                    break Ok(Err(format!("timed out after {waited} ms; last received: \"{got}\"")));
+                }
+                if let Some(marker) = received.failure(&script.fail_on) {
+                    break Ok(Err(format!("saw fail_on {marker:?}")));
+                }
+                thread::sleep(POLL);
+            },
+        };
+        let mut result = result?;
+        received.poll(&session, script.echo)?;
+        if result.is_ok() {
+            if let Some(marker) = received.failure(&script.fail_on) {
+                result = Err(format!("saw fail_on {marker:?}"));
+            }
+        }
+        match result {
+            Ok(()) => eprintln!("{label} ok ({} ms)", step_started.elapsed().as_millis()),
+            Err(why) => {
+                eprintln!("{label} FAILED: {why}");
+                session.close().ok();
+                return Ok(false);
+            }
+        }
+    }
+    session.close().ok();
+    eprintln!("[run] passed {total} steps in {} ms", started.elapsed().as_millis());
+    Ok(true)
+}
@@ -0,0 +1,288 @@
+//! Bluetooth LE devices with the Nordic UART Service (NUS), the usual
+//! serial port of BLE dev kits (nRF52 boards, Adafruit Bluefruit, ESP32
+//! UART examples): discovery, and [`NusPort`], the service's RX and TX
+//! characteristics used as a serial transport. Targets are `ble://` and the
+//! device's address, or on macOS, which hides addresses, its CoreBluetooth
+//! identifier.
+//!
+//! Needs the `ble` cargo feature; without it scans find nothing and opening
+//! a `ble://` target fails saying so.
+
+use std::io::{self, ErrorKind};
+
+pub const PREFIX: &str = "ble://";
+
+#[cfg(feature = "ble")]
+pub use nus::{scan, NusPort};
+#[cfg(not(feature = "ble"))]
+pub use stub::{scan, NusPort};
+
+fn unsupported() -> io::Error {
+    io::Error::new(ErrorKind::Unsupported, "not available over BLE")
+}
+
+#[cfg(feature = "ble")]
+mod nus {
+    use std::collections::VecDeque;
+    use std::io::{self, ErrorKind};
+    use std::pin::Pin;
+    use std::time::{Duration, Instant};
+
+    use btleplug::api::{
+        BDAddr, Central, CharPropFlags, Characteristic, Manager as _, Peripheral as _, ScanFilter,
+        ValueNotification, WriteType,
+    };
+    use btleplug::platform::{Adapter, Manager, Peripheral};
+    use futures::stream::{Stream, StreamExt};
+    use uuid::Uuid;
+
+    use super::{unsupported, PREFIX};
+    use crate::bluetooth::Device;
+    use crate::serial::{ModemLines, SerialTransport};
+
+    const SERVICE: Uuid = Uuid::from_u128(0x6e400001_b5a3_f393_e0a9_e50e24dcca9e);
+    /// Written by us: data to the device.
+    const RX: Uuid = Uuid::from_u128(0x6e400002_b5a3_f393_e0a9_e50e24dcca9e);
+    /// Notified by the device: data from it.
+    const TX: Uuid = Uuid::from_u128(0x6e400003_b5a3_f393_e0a9_e50e24dcca9e);
+    /// What fits one write at the default ATT MTU of 23.
+    const WRITE_CHUNK: usize = 20;
+    /// How long opening a device looks for it before giving up.
+    const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+    fn ble_error(e: btleplug::Error) -> io::Error {
+        io::Error::other(format!("BLE: {e}"))
+    }
+
+    async fn adapter() -> io::Result<Adapter> {
+        let manager = Manager::new().await.map_err(ble_error)?;
+        let adapters = manager.adapters().await.map_err(ble_error)?;
+        adapters
+            .into_iter()
+            .next()
+            .ok_or_else(|| io::Error::new(ErrorKind::NotFound, "no Bluetooth adapter"))
+    }
+
+    /// The address, or where that's hidden (macOS), the platform's id.
+    async fn identify(peripheral: &Peripheral) -> (String, Option<String>, bool) {
+        let properties = peripheral.properties().await.ok().flatten();
+        let name = properties.as_ref().and_then(|p| p.local_name.clone());
+        let nus = properties.as_ref().is_some_and(|p| p.services.contains(&SERVICE));
+        let address = peripheral.address();
+        let id = if address == BDAddr::default() {
+            peripheral.id().to_string()
+        } else {
+            address.to_string()
+        };
+        (id, name, nus)
+    }
+
+    /// Listens for `duration` for devices advertising the Nordic UART
+    /// Service. Blocks; run it off the UI thread.
+    pub fn scan(duration: Duration) -> io::Result<Vec<Device>> {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+        runtime.block_on(async {
+            let adapter = adapter().await?;
+            let filter = ScanFilter {
+                services: vec![SERVICE],
+            };
+            adapter.start_scan(filter).await.map_err(ble_error)?;
+            tokio::time::sleep(duration).await;
+            let _ = adapter.stop_scan().await;
+            let mut found = Vec::new();
+            for peripheral in adapter.peripherals().await.map_err(ble_error)? {
+                let (address, name, nus) = identify(&peripheral).await;
+                if nus {
+                    found.push(Device {
+                        address,
+                        name: name.unwrap_or_default(),
+                        le: true,
+                    });
+                }
+            }
+            Ok(found)
+        })
+    }
+
+    /// A connected device's UART service. Notifications that arrive faster
+    /// than they are read queue up here.
+    pub struct NusPort {
+        peripheral: Peripheral,
+        rx: Characteristic,
+        write_type: WriteType,
+        notifications: Pin<Box<dyn Stream<Item = ValueNotification> + Send>>,
+        pending: VecDeque<u8>,
+    }
+
+    impl NusPort {
+        /// Finds the device `id` (an address, or on macOS an identifier)
+        /// among those in range and connects to its UART service.
+        pub async fn connect(id: &str) -> io::Result<Self> {
+            let adapter = adapter().await?;
+            let filter = ScanFilter {
+                services: vec![SERVICE],
+            };
+            adapter.start_scan(filter).await.map_err(ble_error)?;
+            let found = find(&adapter, id).await;
+            let _ = adapter.stop_scan().await;
+            let peripheral = found?;
+            peripheral.connect().await.map_err(ble_error)?;
+            peripheral.discover_services().await.map_err(ble_error)?;
+            let characteristics = peripheral.characteristics();
+            let characteristic = |uuid: Uuid| {
+                characteristics.iter().find(|c| c.uuid == uuid).cloned().ok_or_else(|| {
+                    io::Error::new(ErrorKind::NotFound, format!("{PREFIX}{id}: no UART service"))
+                })
+            };
+            let rx = characteristic(RX)?;
+            let tx = characteristic(TX)?;
+            let notifications = peripheral.notifications().await.map_err(ble_error)?;
+            peripheral.subscribe(&tx).await.map_err(ble_error)?;
+            let write_type = if rx.properties.contains(CharPropFlags::WRITE_WITHOUT_RESPONSE) {
+                WriteType::WithoutResponse
+            } else {
+                WriteType::WithResponse
+            };
+            Ok(Self {
+                peripheral,
+                rx,
+                write_type,
+                notifications,
+                pending: VecDeque::new(),
+            })
+        }
+    }
+
+    async fn find(adapter: &Adapter, id: &str) -> io::Result<Peripheral> {
+        let deadline = Instant::now() + CONNECT_TIMEOUT;
+        loop {
+            for peripheral in adapter.peripherals().await.map_err(ble_error)? {
+                if identify(&peripheral).await.0.eq_ignore_ascii_case(id) {
+                    return Ok(peripheral);
+                }
+            }
+            if Instant::now() >= deadline {
+                return Err(io::Error::new(ErrorKind::NotFound, "device not in range"));
+            }
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    }
+
+    impl Drop for NusPort {
+        fn drop(&mut self) {
+            // Disconnecting is async; the worker's runtime is still up
+            // when the port goes, so hand it over.
+            let peripheral = self.peripheral.clone();
+            if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+                runtime.spawn(async move { peripheral.disconnect().await });
+            }
+        }
+    }
+
+    impl SerialTransport for NusPort {
+        /// Returns 0 once the device disconnects.
+        async fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            while self.pending.is_empty() {
+                match self.notifications.next().await {
+                    Some(n) if n.uuid == TX => self.pending.extend(n.value),
+                    Some(_) => {}
+                    None => return Ok(0),
+                }
+            }
+            let len = buf.len().min(self.pending.len());
+            for (slot, byte) in buf.iter_mut().zip(self.pending.drain(..len)) {
+                *slot = byte;
+            }
+            Ok(len)
+        }
+
+        async fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+            for chunk in data.chunks(WRITE_CHUNK) {
+                self.peripheral
+                    .write(&self.rx, chunk, self.write_type)
+                    .await
+                    .map_err(ble_error)?;
+            }
+            Ok(())
+        }
+
+        async fn set_dtr(&mut self, _level: bool) -> io::Result<()> {
+            Err(unsupported())
+        }
+
+        async fn set_rts(&mut self, _level: bool) -> io::Result<()> {
+            Err(unsupported())
+        }
+
+        async fn modem_lines(&mut self) -> io::Result<ModemLines> {
+            Err(unsupported())
+        }
+
+        async fn drain(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+
+        async fn send_break(&mut self, _duration: Duration) -> io::Result<()> {
+            Err(unsupported())
+        }
+    }
+}
+
+#[cfg(not(feature = "ble"))]
+mod stub {
+    use std::io::{self, ErrorKind};
+    use std::time::Duration;
+
+    use super::unsupported;
+    use crate::bluetooth::Device;
+    use crate::serial::{ModemLines, SerialTransport};
+
+    /// Finds nothing: built without BLE support.
+    pub fn scan(_duration: Duration) -> io::Result<Vec<Device>> {
+        Ok(Vec::new())
+    }
+
+    /// Stands in without the `ble` feature: connecting fails.
+    pub struct NusPort {
+        never: std::convert::Infallible,
+    }
+
+    impl NusPort {
+        pub async fn connect(_id: &str) -> io::Result<Self> {
+            Err(io::Error::new(
+                ErrorKind::Unsupported,
+                "built without BLE support (cargo build --features ble)",
+            ))
+        }
+    }
+
+    impl SerialTransport for NusPort {
+        async fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            match self.never {}
+        }
+
+        async fn write_all(&mut self, _data: &[u8]) -> io::Result<()> {
+            match self.never {}
+        }
+
+        async fn set_dtr(&mut self, _level: bool) -> io::Result<()> {
+            Err(unsupported())
+        }
+
+        async fn set_rts(&mut self, _level: bool) -> io::Result<()> {
+            Err(unsupported())
+        }
+
+        async fn modem_lines(&mut self) -> io::Result<ModemLines> {
+            Err(unsupported())
+        }
+
+        async fn drain(&mut self) -> io::Result<()> {
+            match self.never {}
+        }
+
+        async fn send_break(&mut self, _duration: Duration) -> io::Result<()> {
+            Err(unsupported())
+        }
+    }
+}
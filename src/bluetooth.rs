@@ -0,0 +1,373 @@
+//! Bluetooth classic serial (SPP) devices such as HC-05 modules and OBD
+//! dongles: discovery through BlueZ's `bluetoothctl`, and [`RfcommPort`],
+//! an RFCOMM socket used as a serial transport (Linux only). Targets are
+//! `bt://AA:BB:CC:DD:EE:FF`, with `/channel` appended when the device's
+//! serial port is not on RFCOMM channel 1.
+
+use std::io::{self, ErrorKind, Read};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const PREFIX: &str = "bt://";
+/// Where SPP sits on HC-05/HC-06 modules and most other serial devices.
+const DEFAULT_CHANNEL: u8 = 1;
+/// Paging a device that is off or out of range takes a while to give up.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+/// Longest a `bluetoothctl` query may take; it waits for bluetoothd
+/// forever when the daemon isn't running.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long a scan listens for nearby devices.
+pub const SCAN_TIME: Duration = Duration::from_secs(8);
+/// The Serial Port Profile's service class.
+const SPP_UUID: &str = "00001101-0000-1000-8000-00805f9b34fb";
+
+/// A device that offers a serial port: over RFCOMM, or with `le`, as a
+/// BLE UART ([`crate::ble`]).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Device {
+    pub address: String,
+    pub name: String,
+    pub le: bool,
+}
+
+impl Device {
+    /// What to open it by.
+    pub fn target(&self) -> String {
+        let prefix = if self.le { crate::ble::PREFIX } else { PREFIX };
+        format!("{prefix}{}", self.address)
+    }
+}
+
+/// Devices BlueZ knows (paired, or seen in a scan) that offer a serial
+/// port, or whose services aren't known yet. With `scan`, first listens for
+/// nearby devices for [`SCAN_TIME`]. Blocks; run it off the UI thread.
+pub fn devices(scan: bool) -> io::Result<Vec<Device>> {
+    if scan {
+        let secs = SCAN_TIME.as_secs().to_string();
+        bluetoothctl(&["--timeout", &secs, "scan", "on"], SCAN_TIME + COMMAND_TIMEOUT)?;
+    }
+    let listing = bluetoothctl(&["devices"], COMMAND_TIMEOUT)?;
+    let mut found = Vec::new();
+    for (address, name) in listing.lines().filter_map(parse_device) {
+        let info = bluetoothctl(&["info", address], COMMAND_TIMEOUT).unwrap_or_default();
+        if offers_serial(&info) {
+            found.push(Device {
+                address: address.to_string(),
+                name: name.to_string(),
+                le: false,
+            });
+        }
+    }
+    Ok(found)
+}
+
+/// Address and name from a `Device AA:BB:CC:DD:EE:FF name` line of
+/// `bluetoothctl devices`.
+pub fn parse_device(line: &str) -> Option<(&str, &str)> {
+    let rest = line.trim().strip_prefix("Device ")?;
+    let (address, name) = rest.split_once(' ').unwrap_or((rest, ""));
+    parse_address(address)?;
+    Some((address, name.trim()))
+}
+
+/// Whether `bluetoothctl info` lists the serial port service, or no
+/// services at all (devices not yet queried, which HC-05s often are).
+fn offers_serial(info: &str) -> bool {
+    let mut uuids = info.lines().filter(|l| l.trim_start().starts_with("UUID:")).peekable();
+    uuids.peek().is_none() || uuids.any(|l| l.to_ascii_lowercase().contains(SPP_UUID))
+}
+
+/// Runs `bluetoothctl`, returning its output, or an error if it can't be
+/// run or doesn't finish within `timeout`.
+fn bluetoothctl(args: &[&str], timeout: Duration) -> io::Result<String> {
+    let mut child = Command::new("bluetoothctl")
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| io::Error::new(e.kind(), format!("bluetoothctl: {e}")))?;
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let reader = thread::spawn(move || {
+        let mut out = String::new();
+        let _ = stdout.read_to_string(&mut out);
+        out
+    });
+    let deadline = Instant::now() + timeout;
+    while child.try_wait()?.is_none() {
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(io::Error::new(
+                ErrorKind::TimedOut,
+                "bluetoothctl did not answer (is bluetoothd running?)",
+            ));
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    Ok(reader.join().unwrap_or_default())
+}
+
+/// `AA:BB:CC:DD:EE:FF` as bytes, most significant first.
+fn parse_address(text: &str) -> Option<[u8; 6]> {
+    let mut bytes = [0; 6];
+    let mut parts = text.split(':');
+    for byte in &mut bytes {
+        let part = parts.next().filter(|p| p.len() == 2)?;
+        *byte = u8::from_str_radix(part, 16).ok()?;
+    }
+    parts.next().is_none().then_some(bytes)
+}
+
+/// Address and RFCOMM channel from `AA:BB:CC:DD:EE:FF[/channel]`.
+pub fn parse_target(target: &str) -> io::Result<([u8; 6], u8)> {
+    let invalid = || {
+        io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("{target}: expected AA:BB:CC:DD:EE:FF or AA:BB:CC:DD:EE:FF/channel"),
+        )
+    };
+    let (address, channel) = match target.split_once('/') {
+        Some((address, channel)) => (address, channel.parse().map_err(|_| invalid())?),
+        None => (target, DEFAULT_CHANNEL),
+    };
+    if !(1..=30).contains(&channel) {
+        return Err(invalid());
+    }
+    Ok((parse_address(address).ok_or_else(invalid)?, channel))
+}
+
+fn unsupported() -> io::Error {
+    io::Error::new(ErrorKind::Unsupported, "not available over Bluetooth")
+}
+
+#[cfg(target_os = "linux")]
+pub use rfcomm::RfcommPort;
+
+#[cfg(target_os = "linux")]
+mod rfcomm {
+    use std::io::{self, ErrorKind};
+    use std::mem::size_of;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::time::Duration;
+
+    use tokio::io::unix::AsyncFd;
+
+    use super::{parse_target, unsupported, CONNECT_TIMEOUT};
+    use crate::serial::{ModemLines, SerialTransport};
+
+    const BTPROTO_RFCOMM: libc::c_int = 3;
+
+    /// `struct sockaddr_rc`.
+    #[repr(C)]
+    struct SockaddrRc {
+        family: libc::sa_family_t,
+        /// Least significant byte first, as BlueZ stores addresses.
+        bdaddr: [u8; 6],
+        channel: u8,
+    }
+
+    /// A connected RFCOMM socket. The link carries data only: there are no
+    /// modem lines to set or read and no break.
+    pub struct RfcommPort {
+        fd: AsyncFd<OwnedFd>,
+    }
+
+    impl RfcommPort {
+        /// Connects to `AA:BB:CC:DD:EE:FF[/channel]`. The device must be
+        /// paired already if it asks for a PIN.
+        pub async fn connect(target: &str) -> io::Result<Self> {
+            let (address, channel) = parse_target(target)?;
+            let kind = libc::SOCK_STREAM | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC;
+            // SAFETY: creates a socket; the fd is owned right after.
+            let raw = unsafe { libc::socket(libc::AF_BLUETOOTH, kind, BTPROTO_RFCOMM) };
+            if raw < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // SAFETY: `raw` is a fresh fd nothing else owns.
+            let fd = unsafe { OwnedFd::from_raw_fd(raw) };
+            let mut bdaddr = address;
+            bdaddr.reverse();
+            let addr = SockaddrRc {
+                family: libc::AF_BLUETOOTH as libc::sa_family_t,
+                bdaddr,
+                channel,
+            };
+            // SAFETY: `addr` is a valid `sockaddr_rc` of the size passed.
+            let result = unsafe {
+                libc::connect(
+                    fd.as_raw_fd(),
+                    (&addr as *const SockaddrRc).cast(),
+                    size_of::<SockaddrRc>() as libc::socklen_t,
+                )
+            };
+            if result < 0 {
+                let e = io::Error::last_os_error();
+                if e.raw_os_error() != Some(libc::EINPROGRESS) {
+                    return Err(e);
+                }
+            }
+            let fd = AsyncFd::new(fd)?;
+            // Writable once the connection is made or has failed.
+            tokio::time::timeout(CONNECT_TIMEOUT, fd.writable())
+                .await
+                .map_err(|_| io::Error::new(ErrorKind::TimedOut, "connect timed out"))??
+                .retain_ready();
+            match socket_error(fd.get_ref())? {
+                0 => Ok(Self { fd }),
+                code => Err(io::Error::from_raw_os_error(code)),
+            }
+        }
+    }
+
+    fn socket_error(fd: &OwnedFd) -> io::Result<libc::c_int> {
+        let mut error: libc::c_int = 0;
+        let mut len = size_of::<libc::c_int>() as libc::socklen_t;
+        // SAFETY: `error` and `len` describe a buffer of the right size.
+        let result = unsafe {
+            libc::getsockopt(
+                fd.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_ERROR,
+                (&mut error as *mut libc::c_int).cast(),
+                &mut len,
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(error)
+    }
+
+    fn check(n: isize) -> io::Result<usize> {
+        usize::try_from(n).map_err(|_| io::Error::last_os_error())
+    }
+
+    impl SerialTransport for RfcommPort {
+        async fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            loop {
+                let mut guard = self.fd.readable().await?;
+                // SAFETY: reads into `buf`, at most its length.
+                let result = guard.try_io(|fd| {
+                    check(unsafe { libc::read(fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) })
+                });
+                match result {
+                    // The device going away resets the link.
+                    Ok(Err(e)) if e.kind() == ErrorKind::ConnectionReset => return Ok(0),
+                    Ok(result) => return result,
+                    Err(_would_block) => continue,
+                }
+            }
+        }
+
+        async fn write_all(&mut self, mut data: &[u8]) -> io::Result<()> {
+            while !data.is_empty() {
+                let mut guard = self.fd.writable().await?;
+                // SAFETY: writes from `data`, at most its length.
+                let result = guard.try_io(|fd| {
+                    check(unsafe { libc::write(fd.as_raw_fd(), data.as_ptr().cast(), data.len()) })
+                });
+                if let Ok(written) = result {
+                    data = &data[written?..];
+                }
+            }
+            Ok(())
+        }
+
+        async fn set_dtr(&mut self, _level: bool) -> io::Result<()> {
+            Err(unsupported())
+        }
+
+        async fn set_rts(&mut self, _level: bool) -> io::Result<()> {
+            Err(unsupported())
+        }
+
+        async fn modem_lines(&mut self) -> io::Result<ModemLines> {
+            Err(unsupported())
+        }
+
+        async fn drain(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+
+        async fn send_break(&mut self, _duration: Duration) -> io::Result<()> {
+            Err(unsupported())
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub use stub::RfcommPort;
+
+#[cfg(not(target_os = "linux"))]
+mod stub {
+    use std::io::{self, ErrorKind};
+    use std::time::Duration;
+
+    use super::{parse_target, unsupported};
+    use crate::serial::{ModemLines, SerialTransport};
+
+    /// Stands in where there is no RFCOMM socket support: connecting fails.
+    pub struct RfcommPort {
+        never: std::convert::Infallible,
+    }
+
+    impl RfcommPort {
+        pub async fn connect(target: &str) -> io::Result<Self> {
+            parse_target(target)?;
+            Err(io::Error::new(
+                ErrorKind::Unsupported,
+                "Bluetooth serial needs Linux; elsewhere paired devices appear as serial ports",
+            ))
+        }
+    }
+
+    impl SerialTransport for RfcommPort {
+        async fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            match self.never {}
+        }
+
+        async fn write_all(&mut self, _data: &[u8]) -> io::Result<()> {
+            match self.never {}
+        }
+
+        async fn set_dtr(&mut self, _level: bool) -> io::Result<()> {
+            Err(unsupported())
+        }
+
+        async fn set_rts(&mut self, _level: bool) -> io::Result<()> {
+            Err(unsupported())
+        }
+
+        async fn modem_lines(&mut self) -> io::Result<ModemLines> {
+            Err(unsupported())
+        }
+
+        async fn drain(&mut self) -> io::Result<()> {
+            match self.never {}
+        }
+
+        async fn send_break(&mut self, _duration: Duration) -> io::Result<()> {
+            Err(unsupported())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_bluetooth_targets_and_listings() {
+        let (address, channel) = parse_target("98:D3:31:F5:12:34").unwrap();
+        assert_eq!((address, channel), ([0x98, 0xd3, 0x31, 0xf5, 0x12, 0x34], 1));
+        assert_eq!(parse_target("98:D3:31:F5:12:34/3").unwrap().1, 3);
+        assert!(parse_target("98:D3:31:F5:12").is_err());
+        assert!(parse_target("98:D3:31:F5:12:34/0").is_err());
+
+        let device = parse_device("Device 98:D3:31:F5:12:34 HC-05 module");
+        assert_eq!(device, Some(("98:D3:31:F5:12:34", "HC-05 module")));
+        assert_eq!(parse_device("Controller 00:1A:7D:DA:71:13 host"), None);
+    }
+}
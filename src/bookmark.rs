@@ -0,0 +1,84 @@
+use std::collections::BTreeSet;
+
+use anyhow::{Context, Result};
+use regex::Regex;
+
+use crate::ansi;
+
+/// Bookmarked output lines, by absolute line number (see
+/// `Scrollback::evicted`), and the patterns that bookmark received lines
+/// on their own.
+#[derive(Default)]
+pub struct Bookmarks {
+    lines: BTreeSet<usize>,
+    patterns: Vec<Regex>,
+}
+
+impl Bookmarks {
+    pub fn from_config(patterns: &[String]) -> Result<Self> {
+        let patterns = patterns
+            .iter()
+            .map(|p| Regex::new(p).with_context(|| format!("auto_bookmark: bad regex {p:?}")))
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            lines: BTreeSet::new(),
+            patterns,
+        })
+    }
+
+    /// Whether a received line should be bookmarked automatically.
+    pub fn matches(&self, line: &str) -> bool {
+        if self.patterns.is_empty() {
+            return false;
+        }
+        let line = ansi::strip(line);
+        self.patterns.iter().any(|p| p.is_match(&line))
+    }
+
+    pub fn contains(&self, line: usize) -> bool {
+        self.lines.contains(&line)
+    }
+
+    pub fn insert(&mut self, line: usize) {
+        self.lines.insert(line);
+    }
+
+    /// Adds or removes a bookmark; returns whether the line is now marked.
+    pub fn toggle(&mut self, line: usize) -> bool {
+        if self.lines.remove(&line) {
+            return false;
+        }
+        self.lines.insert(line);
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// Forgets bookmarks on lines trimmed from the scrollback.
+    pub fn prune(&mut self, evicted: usize) {
+        if self.lines.first().is_some_and(|&n| n < evicted) {
+            self.lines = self.lines.split_off(&evicted);
+        }
+    }
+
+    /// Marked lines after `from` (newer first) then from the oldest round,
+    /// or before it (older first) then from the newest round.
+    pub fn cycle(&self, from: usize, forward: bool) -> Vec<usize> {
+        let (before, after): (Vec<usize>, Vec<usize>) = self
+            .lines
+            .iter()
+            .filter(|&&n| n != from)
+            .partition(|&&n| n < from);
+        if forward {
+            after.into_iter().chain(before).collect()
+        } else {
+            before.into_iter().rev().chain(after.into_iter().rev()).collect()
+        }
+    }
+}
@@ -0,0 +1,286 @@
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use tungstenite::{Message, WebSocket};
+
+/// Granularity at which the listener and client threads notice `stop`.
+const STOP_POLL: Duration = Duration::from_millis(100);
+/// Listen addresses starting with this serve WebSocket clients.
+pub const WS_PREFIX: &str = "ws://";
+/// How long a WebSocket client waits for input before sending what the
+/// port received meanwhile; it owns its socket, so this bounds the delay.
+const WS_POLL: Duration = Duration::from_millis(10);
+/// How long a connecting client gets to finish the WebSocket handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Reads waiting to be sent to one client; a client that lets this many
+/// pile up has stopped reading and is dropped.
+const CLIENT_QUEUE: usize = 1024;
+/// How long a write to a TCP client may block before the client is
+/// dropped.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+pub enum BridgeEvent {
+    Connected(SocketAddr),
+    /// Bytes from a network client, to be written to the serial port.
+    Data(SocketAddr, Vec<u8>),
+    Disconnected(SocketAddr),
+    Error(String),
+}
+
+enum Client {
+    /// Data for the client's writer thread, and the socket to shut down.
+    Tcp(SyncSender<Vec<u8>>, TcpStream),
+    /// Data for the client's thread to send as a binary message.
+    WebSocket(SyncSender<Vec<u8>>),
+}
+
+type Clients = Arc<Mutex<Vec<(SocketAddr, Client)>>>;
+
+/// A TCP listener whose clients share the open serial port: their input is
+/// written to the port and everything the port receives goes to all of them.
+/// With `websocket` clients speak WebSocket, one binary message per read,
+/// so a browser page can follow the port.
+pub struct BridgeHandle {
+    pub addr: SocketAddr,
+    pub websocket: bool,
+    clients: Clients,
+    stop: Arc<AtomicBool>,
+}
+
+impl BridgeHandle {
+    /// Where clients connect, `ws://` included for WebSocket.
+    pub fn url(&self) -> String {
+        if self.websocket {
+            format!("{WS_PREFIX}{}", self.addr)
+        } else {
+            self.addr.to_string()
+        }
+    }
+
+    /// Queues received serial data for every client, dropping any that
+    /// are gone or too far behind. Never blocks: the clients' own threads
+    /// do the writing.
+    pub fn broadcast(&self, data: &[u8]) {
+        let mut clients = self.clients.lock().unwrap();
+        clients.retain(|(_, client)| {
+            let (Client::Tcp(tx, _) | Client::WebSocket(tx)) = client;
+            match tx.try_send(data.to_vec()) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_) | TrySendError::Disconnected(_)) => {
+                    if let Client::Tcp(_, stream) = client {
+                        let _ = stream.shutdown(Shutdown::Both);
+                    }
+                    false
+                }
+            }
+        });
+    }
+
+    pub fn client_count(&self) -> usize {
+        self.clients.lock().unwrap().len()
+    }
+
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+        for (_, client) in self.clients.lock().unwrap().drain(..) {
+            if let Client::Tcp(_, stream) = client {
+                let _ = stream.shutdown(Shutdown::Both);
+            }
+        }
+    }
+}
+
+/// Starts listening on `addr` (e.g. `0.0.0.0:7000`, or
+/// `ws://0.0.0.0:7001` for WebSocket clients).
+pub fn spawn(addr: &str) -> Result<(BridgeHandle, Receiver<BridgeEvent>)> {
+    let (addr, websocket) = match addr.strip_prefix(WS_PREFIX) {
+        Some(addr) => (addr.trim_end_matches('/'), true),
+        None => (addr, false),
+    };
+    let listener = TcpListener::bind(addr).with_context(|| format!("listen on {addr}"))?;
+    let local = listener.local_addr()?;
+    listener.set_nonblocking(true)?;
+
+    let (event_tx, event_rx) = mpsc::channel::<BridgeEvent>();
+    let clients: Clients = Arc::default();
+    let stop = Arc::new(AtomicBool::new(false));
+
+    let thread_clients = clients.clone();
+    let thread_stop = stop.clone();
+    thread::spawn(move || {
+        while !thread_stop.load(Ordering::Relaxed) {
+            match listener.accept() {
+                Ok((stream, peer)) => {
+                    let added = if websocket {
+                        add_websocket_client(stream, peer, &thread_clients, &event_tx, &thread_stop)
+                    } else {
+                        add_client(stream, peer, &thread_clients, &event_tx, &thread_stop)
+                    };
+                    if let Err(e) = added {
+                        let _ = event_tx.send(BridgeEvent::Error(format!("{peer}: {e}")));
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(STOP_POLL),
+                Err(e) => {
+                    let _ = event_tx.send(BridgeEvent::Error(format!("accept failed: {e}")));
+                    break;
+                }
+            }
+        }
+    });
+
+    Ok((
+        BridgeHandle {
+            addr: local,
+            websocket,
+            clients,
+            stop,
+        },
+        event_rx,
+    ))
+}
+
+fn add_client(
+    stream: TcpStream,
+    peer: SocketAddr,
+    clients: &Clients,
+    event_tx: &Sender<BridgeEvent>,
+    stop: &Arc<AtomicBool>,
+) -> std::io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_nodelay(true)?;
+    stream.set_read_timeout(Some(STOP_POLL))?;
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    let mut reader = stream.try_clone()?;
+    let mut writer = stream.try_clone()?;
+    let (tx, rx) = mpsc::sync_channel::<Vec<u8>>(CLIENT_QUEUE);
+    clients.lock().unwrap().push((peer, Client::Tcp(tx, stream)));
+    let _ = event_tx.send(BridgeEvent::Connected(peer));
+
+    thread::spawn(move || {
+        // Ends once the client is dropped, or with the first failed write;
+        // the shutdown then ends the reader too.
+        for data in rx {
+            if writer.write_all(&data).is_err() {
+                let _ = writer.shutdown(Shutdown::Both);
+                return;
+            }
+        }
+    });
+
+    let clients = clients.clone();
+    let event_tx = event_tx.clone();
+    let stop = stop.clone();
+    thread::spawn(move || {
+        let mut buf = [0u8; 4096];
+        while !stop.load(Ordering::Relaxed) {
+            match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => {
+                    let _ = event_tx.send(BridgeEvent::Data(peer, buf[..n].to_vec()));
+                }
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(_) => break,
+            }
+        }
+        clients.lock().unwrap().retain(|(addr, _)| *addr != peer);
+        let _ = event_tx.send(BridgeEvent::Disconnected(peer));
+    });
+    Ok(())
+}
+
+/// Registers a WebSocket client once its handshake is done, on the client's
+/// own thread so a slow one can't hold up the listener.
+fn add_websocket_client(
+    stream: TcpStream,
+    peer: SocketAddr,
+    clients: &Clients,
+    event_tx: &Sender<BridgeEvent>,
+    stop: &Arc<AtomicBool>,
+) -> std::io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_nodelay(true)?;
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+
+    let clients = clients.clone();
+    let event_tx = event_tx.clone();
+    let stop = stop.clone();
+    thread::spawn(move || {
+        let mut socket = match tungstenite::accept(stream) {
+            Ok(socket) => socket,
+            Err(e) => {
+                let _ = event_tx.send(BridgeEvent::Error(format!("{peer}: handshake: {e}")));
+                return;
+            }
+        };
+        if let Err(e) = socket.get_ref().set_read_timeout(Some(WS_POLL)) {
+            let _ = event_tx.send(BridgeEvent::Error(format!("{peer}: {e}")));
+            return;
+        }
+        let (tx, rx) = mpsc::sync_channel(CLIENT_QUEUE);
+        clients.lock().unwrap().push((peer, Client::WebSocket(tx)));
+        let _ = event_tx.send(BridgeEvent::Connected(peer));
+
+        serve_websocket(&mut socket, peer, &rx, &event_tx, &stop);
+        let _ = socket.close(None);
+        let _ = socket.flush();
+        clients.lock().unwrap().retain(|(addr, _)| *addr != peer);
+        let _ = event_tx.send(BridgeEvent::Disconnected(peer));
+    });
+    Ok(())
+}
+
+/// Relays between the client and the port until either side goes away.
+fn serve_websocket(
+    socket: &mut WebSocket<TcpStream>,
+    peer: SocketAddr,
+    outgoing: &Receiver<Vec<u8>>,
+    event_tx: &Sender<BridgeEvent>,
+    stop: &AtomicBool,
+) {
+    while !stop.load(Ordering::Relaxed) {
+        loop {
+            match outgoing.try_recv() {
+                Ok(data) => {
+                    if socket.write(Message::Binary(data)).is_err() {
+                        return;
+                    }
+                }
+                Err(mpsc::TryRecvError::Empty) => break,
+                // Dropped by `stop`, or for falling behind.
+                Err(mpsc::TryRecvError::Disconnected) => return,
+            }
+        }
+        // Also sends any pong queued by the last read.
+        if let Err(e) = socket.flush() {
+            if !is_timeout(&e) {
+                return;
+            }
+        }
+        let data = match socket.read() {
+            Ok(Message::Binary(data)) => data,
+            Ok(Message::Text(text)) => text.into_bytes(),
+            Ok(Message::Close(_)) => return,
+            Ok(_) => continue,
+            Err(e) if is_timeout(&e) => continue,
+            Err(_) => return,
+        };
+        let _ = event_tx.send(BridgeEvent::Data(peer, data));
+    }
+}
+
+fn is_timeout(e: &tungstenite::Error) -> bool {
+    match e {
+        tungstenite::Error::Io(e) => {
+            matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,311 @@
+//! Session capture files: timestamped RX/TX bytes, recorded live and
+//! played back later.
+//!
+//! The format is line-based text so captures can be read and trimmed by
+//! hand:
+//!
+//! ```text
+//! # lazyserial capture v1 start=1714566896123456
+//! 0 tx 41540d0a
+//! 15230 rx 4f4b0d0a
+//! ```
+//!
+//! `start` is the wall-clock time recording began, in microseconds since
+//! the Unix epoch; each record gives its offset from there in
+//! microseconds, the direction and the bytes in hex. Markers are comment
+//! lines, `# <micros> mark <note>`, and are skipped on playback.
+//!
+//! Paths ending in `.pcapng` are written and read as pcapng instead (see
+//! [`crate::pcapng`]), for Wireshark.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+
+use crate::pcapng;
+use crate::Direction;
+
+const MAGIC: &str = "# lazyserial capture v1";
+
+fn is_pcapng(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e.eq_ignore_ascii_case("pcapng"))
+}
+
+enum Sink {
+    Text(BufWriter<File>),
+    Pcapng(pcapng::Writer<BufWriter<File>>),
+}
+
+/// Appends records to a capture file as they happen.
+pub struct Recorder {
+    pub path: PathBuf,
+    sink: Sink,
+    start: SystemTime,
+    started: Instant,
+    pub records: u64,
+}
+
+impl Recorder {
+    /// Starts a capture file; `port` names the interface in pcapng files.
+    pub fn create(path: &Path, port: &str) -> Result<Self> {
+        let file = File::create(path).with_context(|| format!("create {}", path.display()))?;
+        let mut out = BufWriter::new(file);
+        let start = SystemTime::now();
+        let sink = if is_pcapng(path) {
+            let mut writer = pcapng::Writer::new(out, port)?;
+            writer.flush()?;
+            Sink::Pcapng(writer)
+        } else {
+            let micros = start.duration_since(UNIX_EPOCH).unwrap_or_default().as_micros();
+            writeln!(out, "{MAGIC} start={micros}")?;
+            out.flush()?;
+            Sink::Text(out)
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            sink,
+            start,
+            started: Instant::now(),
+            records: 0,
+        })
+    }
+
+    pub fn record(&mut self, direction: Direction, data: &[u8]) -> Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        let at = self.started.elapsed();
+        // Flushed per record so a crash or a killed session keeps the data.
+        match &mut self.sink {
+            Sink::Text(out) => {
+                let dir = match direction {
+                    Direction::Rx => "rx",
+                    Direction::Tx => "tx",
+                };
+                let hex: String = data.iter().map(|b| format!("{b:02x}")).collect();
+                writeln!(out, "{} {dir} {hex}", at.as_micros())?;
+                out.flush()?;
+            }
+            Sink::Pcapng(writer) => {
+                writer.packet(self.start + at, direction, data)?;
+                writer.flush()?;
+            }
+        }
+        self.records += 1;
+        Ok(())
+    }
+
+    /// Notes a moment in the capture: a comment line in text captures, a
+    /// commented empty packet in pcapng.
+    pub fn mark(&mut self, at: SystemTime, note: &str) -> Result<()> {
+        let offset = at.duration_since(self.start).unwrap_or_default();
+        match &mut self.sink {
+            Sink::Text(out) => {
+                let note = note.replace(['\r', '\n'], " ");
+                let line = format!("# {} mark {note}", offset.as_micros());
+                writeln!(out, "{}", line.trim_end())?;
+                out.flush()?;
+            }
+            Sink::Pcapng(writer) => {
+                writer.comment(at, format!("mark {note}").trim_end())?;
+                writer.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Record {
+    /// Offset from the start of the capture.
+    pub at: Duration,
+    pub direction: Direction,
+    pub data: Vec<u8>,
+}
+
+/// A whole capture file.
+#[derive(Debug, Default)]
+pub struct Capture {
+    /// Wall-clock time recording began.
+    pub start: Option<SystemTime>,
+    pub records: Vec<Record>,
+}
+
+impl Capture {
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("open {}", path.display()))?;
+        let capture = if is_pcapng(path) {
+            pcapng::read(BufReader::new(file)).map(Self::from_packets)
+        } else {
+            Self::parse(BufReader::new(file))
+        };
+        capture.with_context(|| format!("capture: {}", path.display()))
+    }
+
+    /// Times become offsets from the first packet.
+    fn from_packets(packets: Vec<pcapng::Packet>) -> Self {
+        let start = packets.first().map(|p| p.time);
+        let records = packets
+            .into_iter()
+            .map(|p| Record {
+                at: start
+                    .and_then(|s| p.time.duration_since(s).ok())
+                    .unwrap_or_default(),
+                direction: p.direction,
+                data: p.data,
+            })
+            .collect();
+        Self { start, records }
+    }
+
+    pub fn parse(input: impl BufRead) -> Result<Self> {
+        let mut capture = Capture::default();
+        for (n, line) in input.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if let Some(header) = line.strip_prefix(MAGIC) {
+                let start = header.trim().strip_prefix("start=").and_then(|s| s.parse().ok());
+                capture.start = start.map(|us| UNIX_EPOCH + Duration::from_micros(us));
+                continue;
+            }
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if n == 0 {
+                bail!("not a lazyserial capture (no {MAGIC:?} header)");
+            }
+            let record = parse_record(line).with_context(|| format!("line {}", n + 1))?;
+            capture.records.push(record);
+        }
+        Ok(capture)
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.records.last().map_or(Duration::ZERO, |r| r.at)
+    }
+}
+
+fn parse_record(line: &str) -> Result<Record> {
+    let mut fields = line.split_whitespace();
+    let (Some(at), Some(dir), hex) = (fields.next(), fields.next(), fields.next()) else {
+        bail!("expected `<micros> <rx|tx> <hex>`");
+    };
+    let at: u64 = at.parse().context("bad offset")?;
+    let direction = match dir {
+        "rx" => Direction::Rx,
+        "tx" => Direction::Tx,
+        other => bail!("bad direction {other:?}"),
+    };
+    let hex = hex.unwrap_or("");
+    if hex.len() % 2 != 0 {
+        bail!("odd number of hex digits");
+    }
+    let data = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .context("bad hex")?;
+    Ok(Record {
+        at: Duration::from_micros(at),
+        direction,
+        data,
+    })
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Speed {
+    #[default]
+    Original,
+    X2,
+    X5,
+    X10,
+    /// Everything at once.
+    Instant,
+}
+
+impl Speed {
+    pub fn name(self) -> &'static str {
+        match self {
+            Speed::Original => "1x",
+            Speed::X2 => "2x",
+            Speed::X5 => "5x",
+            Speed::X10 => "10x",
+            Speed::Instant => "instant",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Speed::Original => Speed::X2,
+            Speed::X2 => Speed::X5,
+            Speed::X5 => Speed::X10,
+            Speed::X10 => Speed::Instant,
+            Speed::Instant => Speed::Original,
+        }
+    }
+
+    fn factor(self) -> Option<u32> {
+        match self {
+            Speed::Original => Some(1),
+            Speed::X2 => Some(2),
+            Speed::X5 => Some(5),
+            Speed::X10 => Some(10),
+            Speed::Instant => None,
+        }
+    }
+}
+
+/// Hands out a capture's records as their (scaled) time comes.
+pub struct Replay {
+    capture: Capture,
+    next: usize,
+    started: Instant,
+    pub speed: Speed,
+}
+
+impl Replay {
+    pub fn new(capture: Capture, speed: Speed) -> Self {
+        Self {
+            capture,
+            next: 0,
+            started: Instant::now(),
+            speed,
+        }
+    }
+
+    /// Records whose time has come since the last call.
+    pub fn due(&mut self) -> Vec<Record> {
+        let elapsed = self.started.elapsed();
+        let records = &self.capture.records[self.next..];
+        let count = match self.speed.factor() {
+            Some(factor) => records.iter().take_while(|r| r.at / factor <= elapsed).count(),
+            None => records.len(),
+        };
+        self.next += count;
+        records[..count].to_vec()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.capture.records.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.capture.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.capture.records.is_empty()
+    }
+
+    /// Records played so far.
+    pub fn position(&self) -> usize {
+        self.next
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.capture.duration()
+    }
+}
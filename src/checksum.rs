@@ -0,0 +1,143 @@
+//! Checksums and CRCs common in serial protocols, for appending to sent
+//! payloads and for the calculator.
+
+use serde::Deserialize;
+
+use crate::modbus;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Algorithm {
+    /// Sum of the bytes, modulo 256.
+    Sum8,
+    /// XOR of the bytes, as in NMEA and many vendor protocols.
+    Xor8,
+    /// CRC-8/SMBUS: polynomial 0x07, initial value 0.
+    Crc8,
+    /// CRC-16/CCITT-FALSE: polynomial 0x1021, initial value 0xffff; sent
+    /// high byte first.
+    #[serde(rename = "crc16-ccitt")]
+    Crc16Ccitt,
+    /// CRC-16/MODBUS; sent low byte first, as on the wire.
+    #[serde(rename = "crc16-modbus")]
+    Crc16Modbus,
+    /// CRC-32 (IEEE 802.3, zlib); sent high byte first.
+    Crc32,
+}
+
+impl Algorithm {
+    pub const ALL: [Algorithm; 6] = [
+        Algorithm::Sum8,
+        Algorithm::Xor8,
+        Algorithm::Crc8,
+        Algorithm::Crc16Ccitt,
+        Algorithm::Crc16Modbus,
+        Algorithm::Crc32,
+    ];
+
+    /// Also the name used in the config file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::Sum8 => "sum8",
+            Algorithm::Xor8 => "xor8",
+            Algorithm::Crc8 => "crc8",
+            Algorithm::Crc16Ccitt => "crc16-ccitt",
+            Algorithm::Crc16Modbus => "crc16-modbus",
+            Algorithm::Crc32 => "crc32",
+        }
+    }
+
+    /// Appended bytes.
+    pub fn width(self) -> usize {
+        match self {
+            Algorithm::Sum8 | Algorithm::Xor8 | Algorithm::Crc8 => 1,
+            Algorithm::Crc16Ccitt | Algorithm::Crc16Modbus => 2,
+            Algorithm::Crc32 => 4,
+        }
+    }
+
+    pub fn compute(self, data: &[u8]) -> u32 {
+        match self {
+            Algorithm::Sum8 => data.iter().fold(0u8, |acc, &b| acc.wrapping_add(b)) as u32,
+            Algorithm::Xor8 => data.iter().fold(0u8, |acc, &b| acc ^ b) as u32,
+            Algorithm::Crc8 => crc8(data) as u32,
+            Algorithm::Crc16Ccitt => crc16_ccitt(data) as u32,
+            Algorithm::Crc16Modbus => modbus::crc16(data) as u32,
+            Algorithm::Crc32 => crc32(data),
+        }
+    }
+
+    /// The checksum of `data` as it goes on the wire.
+    pub fn bytes(self, data: &[u8]) -> Vec<u8> {
+        let value = self.compute(data);
+        match self {
+            Algorithm::Crc16Modbus => (value as u16).to_le_bytes().to_vec(),
+            _ => value.to_be_bytes()[4 - self.width()..].to_vec(),
+        }
+    }
+
+    /// Appends the checksum of everything in `data`.
+    pub fn append(self, data: &mut Vec<u8>) {
+        let trailer = self.bytes(data);
+        data.extend_from_slice(&trailer);
+    }
+}
+
+fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    for &byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+fn crc16_ccitt(data: &[u8]) -> u16 {
+    let mut crc = 0xffffu16;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// Parses calculator input: hex bytes, optionally separated by spaces,
+/// commas or `0x` prefixes (`01 03 0x00,0A`), or text in double quotes.
+pub fn parse_input(input: &str) -> Result<Vec<u8>, String> {
+    let input = input.trim();
+    if let Some(text) = input.strip_prefix('"') {
+        return Ok(text.strip_suffix('"').unwrap_or(text).as_bytes().to_vec());
+    }
+    let mut bytes = Vec::new();
+    for token in input.split(|c: char| c.is_whitespace() || c == ',') {
+        let digits = token
+            .strip_prefix("0x")
+            .or_else(|| token.strip_prefix("0X"))
+            .unwrap_or(token);
+        if !digits.is_ascii() || digits.len() & 1 != 0 {
+            return Err(format!("`{token}` is not whole hex bytes"));
+        }
+        for pair in digits.as_bytes().chunks(2) {
+            let pair = std::str::from_utf8(pair).unwrap_or_default();
+            let byte = u8::from_str_radix(pair, 16)
+                .map_err(|_| format!("`{token}` is not hex"))?;
+            bytes.push(byte);
+        }
+    }
+    Ok(bytes)
+}
@@ -0,0 +1,97 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+
+use crate::config::LineLimit;
+use crate::profile::LineEnding;
+
+pub const USAGE: &str = "\
+Usage: setial_tui [OPTIONS]
+       setial_tui run <SCRIPT> [--port <PORT>] [--baud <RATE>] [--line-ending <EOL>]
+
+Commands:
+  run <SCRIPT>           Run a TOML script of send/expect steps and exit nonzero on failure
+
+Options:
+  --defmt <ELF>          Decode received bytes as defmt logs using this firmware ELF
+  --no-tui               Bridge stdin and stdout to --port instead of starting the TUI
+  -p, --port <PORT>      Port for --no-tui or run: a device path, rfc2217://, telnet://, bt://
+                         or ble:// target
+  -b, --baud <RATE>      Baud rate for --no-tui or run (default: the port's profile, else 115200)
+  --line-ending <EOL>    Ending stdin lines are sent with: lf, cr, crlf or none
+                         (default: the port's profile, else lf)
+  --log <FILE>           Record --no-tui traffic to a capture file (.pcapng for Wireshark)
+  --api <ADDR>           Serve an HTTP API for driving the TUI on ADDR, e.g. 127.0.0.1:8080
+  --scrollback <LINES>   Output lines the TUI keeps, or unlimited (default: scrollback_lines
+                         in the config, else unlimited)
+  -h, --help             Print this help
+";
+
+/// Command-line options.
+#[derive(Debug, Default)]
+pub struct Args {
+    pub help: bool,
+    pub defmt_elf: Option<PathBuf>,
+    pub no_tui: bool,
+    pub port: Option<String>,
+    pub baud: Option<u32>,
+    pub line_ending: Option<LineEnding>,
+    pub log: Option<PathBuf>,
+    /// Listen address of the HTTP API.
+    pub api: Option<String>,
+    /// Script for the `run` command.
+    pub run: Option<PathBuf>,
+    /// Overrides `scrollback_lines` for the TUI.
+    pub scrollback: Option<LineLimit>,
+}
+
+impl Args {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut parsed = Args::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            // `--name=value` and `--name value` are both accepted.
+            let (name, inline) = match arg.split_once('=') {
+                Some((name, value)) if name.starts_with("--") => (name, Some(value.to_string())),
+                _ => (arg.as_str(), None),
+            };
+            let mut value = |what: &str| match inline.clone() {
+                Some(value) => Ok(value),
+                None => args.next().with_context(|| format!("{name} needs {what}")),
+            };
+            match name {
+                "-h" | "--help" => parsed.help = true,
+                "--no-tui" => parsed.no_tui = true,
+                "--defmt" => parsed.defmt_elf = Some(value("an ELF path")?.into()),
+                "-p" | "--port" => parsed.port = Some(value("a port")?),
+                "-b" | "--baud" => {
+                    let baud = value("a baud rate")?;
+                    parsed.baud = Some(baud.parse().with_context(|| format!("bad baud {baud:?}"))?);
+                }
+                "--line-ending" => {
+                    let eol = value("lf, cr, crlf or none")?;
+                    parsed.line_ending = Some(match eol.as_str() {
+                        "lf" => LineEnding::Lf,
+                        "cr" => LineEnding::Cr,
+                        "crlf" => LineEnding::Crlf,
+                        "none" => LineEnding::None,
+                        _ => bail!("bad line ending {eol:?}: expected lf, cr, crlf or none"),
+                    });
+                }
+                "--log" => parsed.log = Some(value("a file")?.into()),
+                "--api" => parsed.api = Some(value("a listen address")?),
+                "--scrollback" => {
+                    let limit = LineLimit::parse(&value("a number of lines or unlimited")?);
+                    limit.max().context("--scrollback")?;
+                    parsed.scrollback = Some(limit);
+                }
+                "run" if parsed.run.is_none() => parsed.run = Some(value("a script")?.into()),
+                _ => bail!("unknown argument {arg:?}\n\n{USAGE}"),
+            }
+        }
+        if parsed.no_tui && parsed.port.is_none() {
+            bail!("--no-tui needs --port");
+        }
+        Ok(parsed)
+    }
+}
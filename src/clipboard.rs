@@ -0,0 +1,53 @@
+use std::io::Write;
+
+use anyhow::{Context, Result};
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Copies text to the system clipboard.
+///
+/// The text is always sent to the terminal as an OSC 52 sequence, which
+/// also works over SSH and inside tmux with `set-clipboard on`. When a local
+/// clipboard is reachable it is set directly as well, since not every
+/// terminal honours OSC 52.
+#[derive(Default)]
+pub struct Clipboard {
+    // Kept alive: on X11 the clipboard contents belong to the owning process.
+    system: Option<arboard::Clipboard>,
+}
+
+impl Clipboard {
+    /// Returns a short description of where the text went.
+    pub fn copy(&mut self, text: &str) -> Result<&'static str> {
+        write_osc52(text)?;
+        if self.system.is_none() {
+            self.system = arboard::Clipboard::new().ok();
+        }
+        match self.system.as_mut().map(|c| c.set_text(text)) {
+            Some(Ok(())) => Ok("clipboard + OSC 52"),
+            _ => Ok("OSC 52"),
+        }
+    }
+}
+
+fn write_osc52(text: &str) -> Result<()> {
+    let mut out = std::io::stdout();
+    write!(out, "\x1b]52;c;{}\x07", base64(text.as_bytes())).context("write OSC 52")?;
+    out.flush().context("write OSC 52")
+}
+
+fn base64(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,86 @@
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use lazyserial::encoding::TextDecoder;
+use lazyserial::linebuf::LineAssembler;
+use lazyserial::Session;
+
+/// Lines kept per port; older ones are dropped.
+const MAX_LINES: usize = 10_000;
+
+/// Which port a compared line came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    /// The main port.
+    Left,
+    /// The port opened to compare with it.
+    Right,
+}
+
+pub struct Stamped {
+    pub at: Instant,
+    pub side: Side,
+    pub text: String,
+}
+
+/// A second port opened beside the main one, with both ports' received
+/// lines kept stamped with their arrival so they can be lined up in time.
+pub struct Compare {
+    pub session: Session,
+    pub decoder: TextDecoder,
+    pub lines: LineAssembler,
+    pub started: Instant,
+    left: VecDeque<Stamped>,
+    right: VecDeque<Stamped>,
+    /// Show both ports in one pane in arrival order rather than side by side.
+    pub interleave: bool,
+    /// Lines (of both ports, in arrival order) scrolled back from the bottom.
+    pub scroll: usize,
+}
+
+impl Compare {
+    pub fn new(session: Session, decoder: TextDecoder, lines: LineAssembler) -> Self {
+        Self {
+            session,
+            decoder,
+            lines,
+            started: Instant::now(),
+            left: VecDeque::new(),
+            right: VecDeque::new(),
+            interleave: false,
+            scroll: 0,
+        }
+    }
+
+    pub fn push(&mut self, side: Side, text: String) {
+        let lines = match side {
+            Side::Left => &mut self.left,
+            Side::Right => &mut self.right,
+        };
+        lines.push_back(Stamped {
+            at: Instant::now(),
+            side,
+            text,
+        });
+        if lines.len() > MAX_LINES {
+            lines.pop_front();
+        }
+        // Keep what's on screen in place while scrolled back.
+        if self.scroll > 0 {
+            self.scroll += 1;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.left.len() + self.right.len()
+    }
+
+    /// Both ports' lines in arrival order, up to the scroll position, so
+    /// side by side panes built from it end at the same moment.
+    pub fn shown(&self) -> Vec<&Stamped> {
+        let mut merged: Vec<&Stamped> = self.left.iter().chain(&self.right).collect();
+        merged.sort_by_key(|line| line.at);
+        merged.truncate(merged.len().saturating_sub(self.scroll));
+        merged
+    }
+}
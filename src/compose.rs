@@ -0,0 +1,117 @@
+//! Multi-line compose popup, for payloads too long for the input line such
+//! as a JSON blob or a G-code snippet.
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::app::{next_char, prev_char};
+
+pub enum Outcome {
+    None,
+    /// Closed without sending; the text is kept as a draft.
+    Close,
+    /// Send the text in one write, newlines turned into the line ending.
+    SendWhole,
+    /// Send each line on its own, paced like a paste.
+    SendLines,
+}
+
+pub struct Compose {
+    pub lines: Vec<String>,
+    pub row: usize,
+    /// Byte offset of the cursor in `lines[row]`, on a char boundary.
+    pub col: usize,
+}
+
+impl Compose {
+    /// Opens on `text` with the cursor at its end.
+    pub fn new(text: &str) -> Self {
+        let mut compose = Self {
+            lines: vec![String::new()],
+            row: 0,
+            col: 0,
+        };
+        compose.insert(text);
+        compose
+    }
+
+    pub fn text(&self) -> String {
+        self.lines.join("\n")
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.iter().all(String::is_empty)
+    }
+
+    /// Inserts `text` at the cursor; newlines in it start new lines.
+    pub fn insert(&mut self, text: &str) {
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        let tail = self.lines[self.row].split_off(self.col);
+        let mut pieces = text.split('\n');
+        if let Some(first) = pieces.next() {
+            self.lines[self.row].push_str(first);
+        }
+        for piece in pieces {
+            self.row += 1;
+            self.lines.insert(self.row, piece.to_string());
+        }
+        self.col = self.lines[self.row].len();
+        self.lines[self.row].push_str(&tail);
+    }
+
+    pub fn on_key(&mut self, key: KeyEvent) -> Outcome {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let line = &self.lines[self.row];
+        match key.code {
+            KeyCode::Esc => return Outcome::Close,
+            KeyCode::Char('s') if ctrl => return Outcome::SendWhole,
+            KeyCode::Char('l') if ctrl => return Outcome::SendLines,
+            KeyCode::Enter => self.insert("\n"),
+            KeyCode::Tab => self.insert("  "),
+            KeyCode::Char(c) if !ctrl => self.insert(c.encode_utf8(&mut [0; 4])),
+            KeyCode::Left if self.col > 0 => self.col = prev_char(line, self.col),
+            KeyCode::Left if self.row > 0 => {
+                self.row -= 1;
+                self.col = self.lines[self.row].len();
+            }
+            KeyCode::Right if self.col < line.len() => self.col = next_char(line, self.col),
+            KeyCode::Right if self.row + 1 < self.lines.len() => {
+                self.row += 1;
+                self.col = 0;
+            }
+            KeyCode::Up if self.row > 0 => self.move_row(self.row - 1),
+            KeyCode::Down if self.row + 1 < self.lines.len() => self.move_row(self.row + 1),
+            KeyCode::Home => self.col = 0,
+            KeyCode::End => self.col = line.len(),
+            KeyCode::Backspace if self.col > 0 => {
+                let start = prev_char(line, self.col);
+                self.lines[self.row].replace_range(start..self.col, "");
+                self.col = start;
+            }
+            KeyCode::Backspace if self.row > 0 => {
+                let line = self.lines.remove(self.row);
+                self.row -= 1;
+                self.col = self.lines[self.row].len();
+                self.lines[self.row].push_str(&line);
+            }
+            KeyCode::Delete if self.col < line.len() => {
+                let end = next_char(line, self.col);
+                self.lines[self.row].replace_range(self.col..end, "");
+            }
+            KeyCode::Delete if self.row + 1 < self.lines.len() => {
+                let next = self.lines.remove(self.row + 1);
+                self.lines[self.row].push_str(&next);
+            }
+            _ => {}
+        }
+        Outcome::None
+    }
+
+    /// Moves to `row`, keeping the cursor's character column where the
+    /// line is long enough.
+    fn move_row(&mut self, row: usize) {
+        let column = self.lines[self.row][..self.col].chars().count();
+        self.row = row;
+        let line = &self.lines[row];
+        self.col = line.char_indices().nth(column).map_or(line.len(), |(i, _)| i);
+    }
+}
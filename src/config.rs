@@ -0,0 +1,711 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use lazyserial::checksum::Algorithm;
+use lazyserial::encoding::Encoding;
+use lazyserial::framing::Framing;
+use lazyserial::linktest::Pattern;
+use lazyserial::{reset, LineStep, Overflow, Parity, Rs485, Session};
+use serde::Deserialize;
+use toml_edit::DocumentMut;
+
+use crate::ansi::AnsiMode;
+use crate::profile::LineEnding;
+
+/// Contents of `config.toml`. Every section is optional.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub display: DisplayConfig,
+    pub input: InputConfig,
+    pub serial: SerialConfig,
+    pub layout: LayoutConfig,
+    /// `F1 = "text"` .. `F12 = "text"`.
+    pub macros: BTreeMap<String, String>,
+    pub auto_response: Vec<AutoResponseConfig>,
+    pub highlight: Vec<HighlightConfig>,
+    pub alert: Vec<AlertConfig>,
+    /// Ports listed first and starred, by `vid:pid` or path.
+    pub favorites: Vec<String>,
+    pub profile: Vec<ProfileConfig>,
+    /// Action name to key(s), e.g. `quit = "ctrl-q"`.
+    pub keymap: BTreeMap<String, KeySpec>,
+    pub theme: ThemeConfig,
+    /// Extra AT-mode completions, command to help text.
+    pub at_commands: BTreeMap<String, String>,
+    /// Named DTR/RTS reset sequences, added to (or replacing) the built-in
+    /// ones.
+    pub reset: BTreeMap<String, Vec<ResetStepConfig>>,
+    pub mqtt: MqttConfig,
+    pub ping: PingConfig,
+    pub link_test: LinkTestConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisplayConfig {
+    pub ansi: AnsiMode,
+    /// Keep received data from controlling the terminal: only an allowed
+    /// set of SGR attributes is rendered and other control characters are
+    /// dropped.
+    pub sanitize: bool,
+    pub encoding: Encoding,
+    /// How long an unterminated line may sit before it is shown anyway.
+    pub line_timeout_ms: u64,
+    /// Silence after which the data that ends it gets a separator line
+    /// saying how long it was; 0 turns them off.
+    pub idle_gap_ms: u64,
+    /// Cap on screen redraws per second; data arriving in between is
+    /// batched into the next frame.
+    pub max_fps: u32,
+    /// Capture the mouse for scrolling, focus and selection. Turn off to
+    /// keep the terminal's own text selection.
+    pub mouse: bool,
+    /// Wrap long output lines; off cuts them at the pane edge and Left /
+    /// Right scroll sideways.
+    pub wrap: bool,
+    /// Show a received line repeated back to back once, with a count.
+    pub collapse_repeats: bool,
+    /// Show control characters and line endings as symbols, escape
+    /// sequences unrendered.
+    pub control_chars: bool,
+    /// Lay output out like a chat: sent lines right-aligned, received ones
+    /// left.
+    pub chat: bool,
+    /// Output lines kept in all, or `"unlimited"` for as many as memory
+    /// and disk allow.
+    pub scrollback_lines: LineLimit,
+    /// Memory for the most recent output lines; beyond it older lines
+    /// spill to disk.
+    pub scrollback_memory_mb: u64,
+    /// Disk space for scrollback beyond the most recent lines; 0 keeps
+    /// history in memory only.
+    pub scrollback_disk_mb: u64,
+    /// Regexes that bookmark the received lines they match; empty turns
+    /// automatic bookmarks off.
+    pub auto_bookmark: Vec<String>,
+    /// Shell command received data is piped through from startup; its
+    /// output is shown instead.
+    pub pipe: Option<String>,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        Self {
+            ansi: AnsiMode::default(),
+            sanitize: true,
+            encoding: Encoding::default(),
+            line_timeout_ms: 200,
+            idle_gap_ms: 2000,
+            max_fps: 30,
+            mouse: true,
+            wrap: true,
+            collapse_repeats: false,
+            control_chars: false,
+            chat: false,
+            scrollback_lines: LineLimit::default(),
+            scrollback_memory_mb: 64,
+            scrollback_disk_mb: 1024,
+            auto_bookmark: vec![
+                r"(?i)\b(error|fatal|panic|exception|assert(ion)? failed|guru meditation)\b"
+                    .to_string(),
+            ],
+            pipe: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InputConfig {
+    /// Gap between the lines of a multi-line paste, for devices that can't
+    /// take a whole block at once.
+    pub paste_line_delay_ms: u64,
+    /// Hold new sends (typed lines, macros, pastes) until everything sent
+    /// before has been written.
+    pub wait_for_tx: bool,
+    /// Send each line a `!command` prints as it comes, rather than all of
+    /// its output once it exits.
+    pub shell_line_by_line: bool,
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        Self {
+            paste_line_delay_ms: 20,
+            wait_for_tx: false,
+            shell_line_by_line: false,
+        }
+    }
+}
+
+/// Arrangement of the Ports and Output panes; changed from the keyboard or
+/// by dragging the border, and saved back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LayoutConfig {
+    /// Share of the screen the Ports pane takes: its width side by side,
+    /// its height stacked.
+    pub ports_width: u16,
+    pub direction: Split,
+    /// Hide the Ports pane while a port is open.
+    pub collapse_ports: bool,
+}
+
+impl LayoutConfig {
+    pub const MIN_PORTS_WIDTH: u16 = 10;
+    pub const MAX_PORTS_WIDTH: u16 = 70;
+}
+
+impl Default for LayoutConfig {
+    fn default() -> Self {
+        Self {
+            ports_width: 30,
+            direction: Split::default(),
+            collapse_ports: false,
+        }
+    }
+}
+
+/// How the Ports and Output panes share the screen.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Split {
+    /// Side by side, Ports on the left.
+    #[default]
+    Horizontal,
+    /// Stacked, Ports on top.
+    Vertical,
+}
+
+impl Split {
+    pub fn name(self) -> &'static str {
+        match self {
+            Split::Horizontal => "horizontal",
+            Split::Vertical => "vertical",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SerialConfig {
+    /// Received data allowed to wait for the screen before `overflow`
+    /// kicks in.
+    pub rx_buffer_kb: usize,
+    /// `drop-oldest`, `drop-newest` or `pause` (stop reading and deassert
+    /// RTS until caught up).
+    pub overflow: Overflow,
+    /// Lock local ports against other programs while open; off lets
+    /// another program open the port alongside.
+    pub exclusive: bool,
+    /// On macOS, list each adapter once as its `/dev/cu.*` device; off
+    /// lists the `/dev/tty.*` one instead.
+    pub prefer_callout: bool,
+}
+
+impl Default for SerialConfig {
+    fn default() -> Self {
+        Self {
+            rx_buffer_kb: lazyserial::serial::DEFAULT_RX_BUFFER / 1024,
+            overflow: Overflow::default(),
+            exclusive: true,
+            prefer_callout: true,
+        }
+    }
+}
+
+impl SerialConfig {
+    pub fn apply(&self, session: &Session) {
+        session.set_overflow(self.rx_buffer_kb.saturating_mul(1024).max(1), self.overflow);
+    }
+}
+
+/// Publishing received lines to an MQTT broker, started with lazyserial
+/// when `broker` is set.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MqttConfig {
+    /// `host` or `host:port`.
+    pub broker: Option<String>,
+    pub topic: String,
+    /// Topic whose messages are written to the port.
+    pub subscribe: Option<String>,
+    /// Regex picking what to publish from each line: its first group, or
+    /// the whole match; lines it doesn't match aren't published.
+    pub field: Option<String>,
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            broker: None,
+            topic: "lazyserial/rx".to_string(),
+            subscribe: None,
+            field: None,
+            client_id: "lazyserial".to_string(),
+            username: None,
+            password: None,
+        }
+    }
+}
+
+/// Round trip measurement; the probe can be changed each run.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PingConfig {
+    pub probe: String,
+    /// Regex for the answer; by default the probe echoed back.
+    pub expect: Option<String>,
+    pub count: u32,
+    /// How long to wait for an answer before counting the probe lost.
+    pub timeout_ms: u64,
+    /// Pause after an answer before the next probe.
+    pub interval_ms: u64,
+}
+
+impl Default for PingConfig {
+    fn default() -> Self {
+        Self {
+            probe: "ping\n".to_string(),
+            expect: None,
+            count: 10,
+            timeout_ms: 1000,
+            interval_ms: 100,
+        }
+    }
+}
+
+/// Loopback bit error test.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LinkTestConfig {
+    pub pattern: Pattern,
+    /// Length of the run.
+    pub bytes: usize,
+}
+
+impl Default for LinkTestConfig {
+    fn default() -> Self {
+        Self {
+            pattern: Pattern::default(),
+            bytes: 64 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AutoResponseConfig {
+    pub name: Option<String>,
+    /// Substring to look for in received lines, or a regex when `regex = true`.
+    #[serde(rename = "match")]
+    pub pattern: String,
+    #[serde(default)]
+    pub regex: bool,
+    /// Sent verbatim; include `\n` or `\r\n` if the device expects one.
+    pub response: String,
+    #[serde(default)]
+    pub delay_ms: u64,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HighlightConfig {
+    /// Regex searched for in each displayed line.
+    #[serde(rename = "match")]
+    pub pattern: String,
+    /// Color name (`red`, `lightblue`), index (`208`) or `#rrggbb`.
+    pub fg: Option<String>,
+    pub bg: Option<String>,
+    #[serde(default)]
+    pub bold: bool,
+    /// Style the whole line rather than just the matched text.
+    #[serde(default)]
+    pub line: bool,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlertConfig {
+    pub name: Option<String>,
+    /// Regex searched for in received lines.
+    #[serde(rename = "match")]
+    pub pattern: String,
+    /// Ring the terminal bell.
+    #[serde(default = "default_true")]
+    pub bell: bool,
+    /// Show a desktop notification.
+    #[serde(default = "default_true")]
+    pub notify: bool,
+    /// Bookmark the matching line.
+    #[serde(default)]
+    pub bookmark: bool,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+/// Settings applied when a matching port is opened.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileConfig {
+    pub name: String,
+    /// USB `vid:pid` in hex (e.g. `10c4:ea60`), a port path or a remote
+    /// target.
+    #[serde(rename = "match")]
+    pub pattern: String,
+    pub baud: Option<u32>,
+    pub parity: Option<Parity>,
+    pub line_ending: Option<LineEnding>,
+    pub framing: Option<Framing>,
+    /// Drive RTS for an RS-485 transceiver; `rs485 = {}` for the defaults.
+    pub rs485: Option<Rs485Config>,
+    /// Reset sequence the reset key runs without asking.
+    pub reset: Option<String>,
+    /// Appended to every line and macro sent, e.g. `crc16-modbus`.
+    pub checksum: Option<Algorithm>,
+    /// Overrides the matching `[macros]` keys while the port is open.
+    #[serde(default)]
+    pub macros: BTreeMap<String, String>,
+    /// Added to the global rules while the port is open.
+    #[serde(default)]
+    pub highlight: Vec<HighlightConfig>,
+}
+
+/// RS-485 direction control on RTS.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Rs485Config {
+    /// RTS level while transmitting.
+    pub rts_on_send: bool,
+    pub delay_before_ms: u64,
+    pub delay_after_ms: u64,
+}
+
+impl Default for Rs485Config {
+    fn default() -> Self {
+        Self {
+            rts_on_send: true,
+            delay_before_ms: 0,
+            delay_after_ms: 0,
+        }
+    }
+}
+
+impl Rs485Config {
+    pub fn settings(&self) -> Rs485 {
+        Rs485 {
+            rts_on_send: self.rts_on_send,
+            delay_before: Duration::from_millis(self.delay_before_ms),
+            delay_after: Duration::from_millis(self.delay_after_ms),
+        }
+    }
+}
+
+/// One step of a reset sequence: `{ dtr = true, rts = false, ms = 100 }`.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ResetStepConfig {
+    pub dtr: Option<bool>,
+    pub rts: Option<bool>,
+    /// How long to hold the lines before the next step.
+    #[serde(default)]
+    pub ms: u64,
+}
+
+/// The reset sequence called `name`: from `[reset]` if defined there,
+/// else a built-in one.
+pub fn reset_sequence(
+    custom: &BTreeMap<String, Vec<ResetStepConfig>>,
+    name: &str,
+) -> Option<Vec<LineStep>> {
+    let Some(steps) = custom.get(name) else {
+        return reset::builtin(name);
+    };
+    let steps = steps
+        .iter()
+        .map(|s| LineStep {
+            dtr: s.dtr,
+            rts: s.rts,
+            hold: Duration::from_millis(s.ms),
+        })
+        .collect();
+    Some(steps)
+}
+
+/// Built-in sequence names followed by the ones from `[reset]`.
+pub fn reset_names(custom: &BTreeMap<String, Vec<ResetStepConfig>>) -> Vec<String> {
+    let mut names: Vec<String> = reset::BUILTIN.iter().map(|n| n.to_string()).collect();
+    for name in custom.keys() {
+        if !names.contains(name) {
+            names.push(name.clone());
+        }
+    }
+    names
+}
+
+/// A named color scheme plus per-color overrides (`accent = "#ff8800"`).
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ThemeConfig {
+    pub name: String,
+    #[serde(flatten)]
+    pub colors: BTreeMap<String, String>,
+}
+
+impl Default for ThemeConfig {
+    fn default() -> Self {
+        Self {
+            name: "default".to_string(),
+            colors: BTreeMap::new(),
+        }
+    }
+}
+
+/// A number of lines, or `"unlimited"`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum LineLimit {
+    Lines(usize),
+    Word(String),
+}
+
+impl Default for LineLimit {
+    fn default() -> Self {
+        LineLimit::Word("unlimited".to_string())
+    }
+}
+
+impl LineLimit {
+    /// Takes a number of lines, or anything else as a word to check.
+    pub fn parse(text: &str) -> Self {
+        text.parse().map_or_else(|_| LineLimit::Word(text.to_string()), LineLimit::Lines)
+    }
+
+    /// The limit, `None` for unlimited.
+    pub fn max(&self) -> Result<Option<usize>> {
+        match self {
+            LineLimit::Lines(0) => bail!("keep at least 1 line"),
+            LineLimit::Lines(n) => Ok(Some(*n)),
+            LineLimit::Word(w) if w.eq_ignore_ascii_case("unlimited") => Ok(None),
+            LineLimit::Word(w) => bail!("expected a number of lines or \"unlimited\", not {w:?}"),
+        }
+    }
+}
+
+/// One key or a list of keys for a `[keymap]` action.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum KeySpec {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl KeySpec {
+    /// The keys, minus empty strings (used to unbind).
+    pub fn keys(&self) -> Vec<&str> {
+        let keys: Vec<&str> = match self {
+            KeySpec::One(key) => vec![key.as_str()],
+            KeySpec::Many(keys) => keys.iter().map(String::as_str).collect(),
+        };
+        keys.into_iter().filter(|k| !k.is_empty()).collect()
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+pub fn config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|d| d.join("lazyserial").join("config.toml"))
+}
+
+/// Where decoder plugins are loaded from, next to the config file.
+pub fn plugin_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|d| d.join("lazyserial").join("plugins"))
+}
+
+/// Loads the user config, falling back to defaults when no file exists.
+pub fn load() -> Result<Config> {
+    let Some(path) = config_path() else {
+        return Ok(Config::default());
+    };
+    if !path.exists() {
+        return Ok(Config::default());
+    }
+    let text = fs::read_to_string(&path).with_context(|| format!("read {}", path.display()))?;
+    toml::from_str(&text).with_context(|| format!("parse {}", path.display()))
+}
+
+/// Stores one `[macros]` entry back into `config.toml`, keeping the rest of
+/// the file (comments, ordering) intact. An empty `text` removes the entry.
+pub fn save_macro(key: &str, text: &str) -> Result<()> {
+    edit(|doc, path| {
+        let table = doc
+            .entry("macros")
+            .or_insert(toml_edit::table())
+            .as_table_mut()
+            .ok_or_else(|| anyhow!("[macros] in {} is not a table", path.display()))?;
+        let existing: Vec<String> = table
+            .iter()
+            .map(|(k, _)| k.to_string())
+            .filter(|k| k.eq_ignore_ascii_case(key))
+            .collect();
+        for k in existing {
+            table.remove(&k);
+        }
+        if !text.is_empty() {
+            table.insert(key, toml_edit::Item::Value(basic_string(text)?));
+        }
+        Ok(())
+    })
+}
+
+/// Replaces every `[[highlight]]` table in `config.toml` with `rules`.
+pub fn save_highlights(rules: &[HighlightConfig]) -> Result<()> {
+    edit(|doc, _| {
+        let mut tables = toml_edit::ArrayOfTables::new();
+        for rule in rules {
+            let mut table = toml_edit::Table::new();
+            table.insert("match", toml_edit::Item::Value(basic_string(&rule.pattern)?));
+            if let Some(fg) = &rule.fg {
+                table.insert("fg", toml_edit::value(fg.as_str()));
+            }
+            if let Some(bg) = &rule.bg {
+                table.insert("bg", toml_edit::value(bg.as_str()));
+            }
+            if rule.bold {
+                table.insert("bold", toml_edit::value(true));
+            }
+            if rule.line {
+                table.insert("line", toml_edit::value(true));
+            }
+            if !rule.enabled {
+                table.insert("enabled", toml_edit::value(false));
+            }
+            tables.push(table);
+        }
+        if tables.is_empty() {
+            doc.remove("highlight");
+        } else {
+            doc.insert("highlight", toml_edit::Item::ArrayOfTables(tables));
+        }
+        Ok(())
+    })
+}
+
+/// Stores the pane layout in `[layout]`, keeping anything else there.
+pub fn save_layout(layout: &LayoutConfig) -> Result<()> {
+    edit(|doc, path| {
+        let table = doc
+            .entry("layout")
+            .or_insert(toml_edit::table())
+            .as_table_mut()
+            .ok_or_else(|| anyhow!("[layout] in {} is not a table", path.display()))?;
+        table.insert("ports_width", toml_edit::value(i64::from(layout.ports_width)));
+        table.insert("direction", toml_edit::value(layout.direction.name()));
+        table.insert("collapse_ports", toml_edit::value(layout.collapse_ports));
+        Ok(())
+    })
+}
+
+pub fn save_favorites(favorites: &[String]) -> Result<()> {
+    edit(|doc, _| {
+        let mut array = toml_edit::Array::new();
+        for key in favorites {
+            array.push(basic_string(key)?);
+        }
+        doc["favorites"] = toml_edit::value(array);
+        Ok(())
+    })
+}
+
+/// Writes the line settings of `profile` to the `[[profile]]` with the same
+/// `match`, or appends one. Macros and highlights already in the file stay.
+pub fn save_profile(profile: &ProfileConfig) -> Result<()> {
+    edit(|doc, path| {
+        let tables = doc
+            .entry("profile")
+            .or_insert(toml_edit::Item::ArrayOfTables(toml_edit::ArrayOfTables::new()))
+            .as_array_of_tables_mut()
+            .ok_or_else(|| anyhow!("profile in {} is not [[profile]]", path.display()))?;
+        let existing = tables.iter().position(|t| {
+            t.get("match")
+                .and_then(|m| m.as_str())
+                .is_some_and(|m| m.eq_ignore_ascii_case(&profile.pattern))
+        });
+        let table = match existing.and_then(|i| tables.get_mut(i)) {
+            Some(table) => table,
+            None => {
+                tables.push(toml_edit::Table::new());
+                let last = tables.len() - 1;
+                tables.get_mut(last).ok_or_else(|| anyhow!("append [[profile]]"))?
+            }
+        };
+        // Indexing keeps the keys, and so any comments attached to them.
+        table["name"] = toml_edit::Item::Value(basic_string(&profile.name)?);
+        table["match"] = toml_edit::Item::Value(basic_string(&profile.pattern)?);
+        if let Some(baud) = profile.baud {
+            table["baud"] = toml_edit::value(i64::from(baud));
+        }
+        if let Some(parity) = profile.parity {
+            table["parity"] = toml_edit::value(parity.name());
+        }
+        if let Some(ending) = profile.line_ending {
+            table["line_ending"] = toml_edit::value(ending.name());
+        }
+        Ok(())
+    })
+}
+
+/// Applies `change` to `config.toml` in place, creating the file if needed.
+fn edit(change: impl FnOnce(&mut DocumentMut, &Path) -> Result<()>) -> Result<()> {
+    let path = config_path().ok_or_else(|| anyhow!("no config directory"))?;
+    let mut doc = if path.exists() {
+        fs::read_to_string(&path)
+            .with_context(|| format!("read {}", path.display()))?
+            .parse::<DocumentMut>()
+            .with_context(|| format!("parse {}", path.display()))?
+    } else {
+        DocumentMut::new()
+    };
+    change(&mut doc, &path)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("create {}", dir.display()))?;
+    }
+    fs::write(&path, doc.to_string()).with_context(|| format!("write {}", path.display()))
+}
+
+fn basic_string(text: &str) -> Result<toml_edit::Value> {
+    toml_basic_string(text)
+        .parse()
+        .map_err(|e| anyhow!("encode {text:?}: {e}"))
+}
+
+/// Quotes `text` as a single-line TOML basic string.
+fn toml_basic_string(text: &str) -> String {
+    let mut out = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04X}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -0,0 +1,183 @@
+//! Structured protocol decoders behind the packet list.
+//!
+//! A [`Decoder`] is fed the bytes of both directions and turns them into
+//! [`Record`]s, one per packet or sentence, laid out in the columns it
+//! declares. Decoders live with their protocol (Modbus RTU in
+//! [`modbus`](crate::modbus), NMEA in [`nmea`](crate::nmea), COBS/SLIP in
+//! [`framing`](crate::framing)) and are found by name in a [`Registry`]; a
+//! program embedding this crate can register its own next to the built-in
+//! ones, and shared libraries are loaded into it by
+//! [`plugin`](crate::plugin). [`Monitor`] runs one decoder and keeps its
+//! recent records.
+
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use crate::framing::{FramedDecoder, Framing};
+use crate::modbus::RtuDecoder;
+use crate::nmea::SentenceDecoder;
+use crate::Direction;
+
+/// Records kept for the packet list.
+const MAX_RECORDS: usize = 1000;
+
+/// A column of the packet list, between the time/direction columns and the
+/// free-form details.
+#[derive(Clone, Copy, Debug)]
+pub struct Column {
+    pub name: &'static str,
+    pub width: usize,
+}
+
+/// One decoded packet.
+#[derive(Clone, Debug)]
+pub struct Record {
+    pub direction: Direction,
+    /// When its first byte arrived.
+    pub at: Instant,
+    /// One value per [`Decoder::columns`] entry.
+    pub fields: Vec<String>,
+    /// Whether its checksum or CRC matched; `None` for protocols without one.
+    pub valid: Option<bool>,
+    /// Decoded contents, or why it didn't decode.
+    pub details: String,
+    pub raw: Vec<u8>,
+}
+
+pub trait Decoder: Send {
+    /// Heading of the packet list, e.g. `Modbus RTU`.
+    fn title(&self) -> &'static str;
+
+    fn columns(&self) -> &'static [Column];
+
+    /// Takes bytes seen in `direction` at `now` and returns the records
+    /// they completed.
+    fn push(&mut self, direction: Direction, bytes: &[u8], now: Instant) -> Vec<Record>;
+
+    /// Records completed by the line going quiet; called on every tick.
+    fn flush_due(&mut self, _now: Instant) -> Vec<Record> {
+        Vec::new()
+    }
+}
+
+type Make = Box<dyn Fn() -> Box<dyn Decoder> + Send + Sync>;
+
+pub struct Entry {
+    /// What the decoder is selected by, e.g. `modbus`.
+    pub name: &'static str,
+    pub description: &'static str,
+    make: Make,
+}
+
+/// The decoders available by name, in the order they are offered.
+#[derive(Default)]
+pub struct Registry {
+    entries: Vec<Entry>,
+}
+
+impl Registry {
+    /// Modbus RTU, NMEA 0183, COBS and SLIP.
+    pub fn builtin() -> Self {
+        let mut registry = Self::default();
+        registry.register("modbus", "Modbus RTU frames", || Box::new(RtuDecoder::new()));
+        registry.register("nmea", "NMEA 0183 sentences", || Box::<SentenceDecoder>::default());
+        registry.register("cobs", "COBS frames", || Box::new(FramedDecoder::new(Framing::Cobs)));
+        registry.register("slip", "SLIP frames", || Box::new(FramedDecoder::new(Framing::Slip)));
+        registry
+    }
+
+    /// Adds a decoder, replacing any registered under the same name.
+    pub fn register<F>(&mut self, name: &'static str, description: &'static str, make: F)
+    where
+        F: Fn() -> Box<dyn Decoder> + Send + Sync + 'static,
+    {
+        let entry = Entry {
+            name,
+            description,
+            make: Box::new(make),
+        };
+        match self.entries.iter_mut().find(|e| e.name == name) {
+            Some(existing) => *existing = entry,
+            None => self.entries.push(entry),
+        }
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// A fresh monitor running the decoder called `name`.
+    pub fn monitor(&self, name: &str) -> Option<Monitor> {
+        let entry = self.entries.iter().find(|e| e.name == name)?;
+        Some(Monitor::new(entry.name, (entry.make)()))
+    }
+
+    /// The name after `current` in registration order; the first one when
+    /// `current` is `None` and `None` after the last.
+    pub fn next_name(&self, current: Option<&str>) -> Option<&'static str> {
+        let mut names = self.entries.iter().map(|e| e.name);
+        match current {
+            None => names.next(),
+            Some(current) => names.skip_while(|&n| n != current).nth(1),
+        }
+    }
+}
+
+/// Runs a decoder over the traffic and keeps its most recent records.
+pub struct Monitor {
+    /// The registry name it was created from.
+    pub name: &'static str,
+    pub started: Instant,
+    pub records: VecDeque<Record>,
+    decoder: Box<dyn Decoder>,
+}
+
+impl Monitor {
+    pub fn new(name: &'static str, decoder: Box<dyn Decoder>) -> Self {
+        Self {
+            name,
+            started: Instant::now(),
+            records: VecDeque::new(),
+            decoder,
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        self.decoder.title()
+    }
+
+    pub fn columns(&self) -> &'static [Column] {
+        self.decoder.columns()
+    }
+
+    /// Records whose checksum didn't match.
+    pub fn invalid(&self) -> usize {
+        self.records.iter().filter(|r| r.valid == Some(false)).count()
+    }
+
+    pub fn push(&mut self, direction: Direction, bytes: &[u8]) {
+        let records = self.decoder.push(direction, bytes, Instant::now());
+        self.add(records);
+    }
+
+    /// Closes packets that have gone quiet; call on every tick.
+    pub fn tick(&mut self) {
+        let records = self.decoder.flush_due(Instant::now());
+        self.add(records);
+    }
+
+    fn add(&mut self, mut records: Vec<Record>) {
+        records.sort_by_key(|r| r.at);
+        for record in records {
+            if self.records.len() == MAX_RECORDS {
+                self.records.pop_front();
+            }
+            self.records.push_back(record);
+        }
+    }
+}
+
+/// Bytes as space-separated hex pairs, for records' details.
+pub fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{b:02x}")).collect::<Vec<_>>().join(" ")
+}
@@ -0,0 +1,636 @@
+//! Decoding of [defmt](https://defmt.ferrous-systems.com) log streams.
+//!
+//! defmt firmware sends compact binary frames that reference format strings
+//! interned in the firmware's ELF file. [`Table`] reads those strings from
+//! the `.defmt` section and [`Decoder`] turns received bytes back into log
+//! messages. Both the default rzCOBS framing and the `encoding-raw`
+//! feature are understood (the ELF records which one the firmware uses).
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+use defmt_parser::{DisplayHint, Fragment, Parameter, ParserMode, TimePrecision, Type};
+use object::{Object, ObjectSection, ObjectSymbol};
+use serde::Deserialize;
+
+/// The only wire format version this decoder speaks (defmt 0.3 and 1.x).
+const WIRE_VERSION: &str = "4";
+
+/// Bound on a raw-encoded buffer that never yields a frame, so a wrong ELF
+/// or baud rate can't grow it forever.
+const MAX_PENDING: usize = 64 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Trace => "TRACE",
+            Level::Debug => "DEBUG",
+            Level::Info => "INFO",
+            Level::Warn => "WARN",
+            Level::Error => "ERROR",
+        }
+    }
+
+    fn from_tag(tag: &str) -> Option<Self> {
+        Some(match tag {
+            "defmt_trace" => Level::Trace,
+            "defmt_debug" => Level::Debug,
+            "defmt_info" => Level::Info,
+            "defmt_warn" => Level::Warn,
+            "defmt_error" => Level::Error,
+            _ => return None,
+        })
+    }
+}
+
+/// One decoded log statement.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Message {
+    /// `None` for `println!`.
+    pub level: Option<Level>,
+    /// Formatted with the firmware's `timestamp!` format, if it has one.
+    pub timestamp: Option<String>,
+    pub text: String,
+}
+
+/// How frames are delimited on the wire.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Framing {
+    /// Zero-terminated rzCOBS frames (the defmt default).
+    Rzcobs,
+    /// Back-to-back frames with no delimiters.
+    Raw,
+}
+
+struct Entry {
+    tag: String,
+    format: String,
+}
+
+/// The interned strings of one firmware image.
+pub struct Table {
+    entries: HashMap<u16, Entry>,
+    timestamp: Option<String>,
+    framing: Framing,
+}
+
+/// Name of a `.defmt` symbol, a JSON object.
+#[derive(Deserialize)]
+struct SymbolName {
+    tag: String,
+    data: String,
+}
+
+impl Table {
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = fs::read(path).with_context(|| format!("read {}", path.display()))?;
+        Self::parse(&data).with_context(|| format!("defmt: {}", path.display()))
+    }
+
+    /// Reads the table from the bytes of an ELF file.
+    pub fn parse(elf: &[u8]) -> Result<Self> {
+        let file = object::File::parse(elf).context("not an ELF file")?;
+        let section = file
+            .section_by_name(".defmt")
+            .ok_or_else(|| anyhow!("no .defmt section (is the firmware built with defmt?)"))?
+            .index();
+
+        let mut entries = HashMap::new();
+        let mut timestamp = None;
+        let mut version = None;
+        let mut framing = Framing::Rzcobs;
+        for symbol in file.symbols() {
+            let Ok(name) = symbol.name() else {
+                continue;
+            };
+            if let Some(v) = name.strip_prefix("_defmt_version_ = ") {
+                version = Some(v.to_string());
+                continue;
+            }
+            if name == "_defmt_encoding_ = raw" {
+                framing = Framing::Raw;
+                continue;
+            }
+            if symbol.section_index() != Some(section) {
+                continue;
+            }
+            let Ok(parsed) = serde_json::from_str::<SymbolName>(name) else {
+                continue;
+            };
+            if parsed.tag == "defmt_timestamp" {
+                timestamp = Some(parsed.data);
+                continue;
+            }
+            let entry = Entry {
+                tag: parsed.tag,
+                format: parsed.data,
+            };
+            entries.insert(symbol.address() as u16, entry);
+        }
+        match version.as_deref() {
+            Some(WIRE_VERSION) => {}
+            Some(v) => bail!("unsupported defmt wire version {v} (expected {WIRE_VERSION})"),
+            None => bail!("no defmt version symbol"),
+        }
+        Ok(Self {
+            entries,
+            timestamp,
+            framing,
+        })
+    }
+
+    pub fn framing(&self) -> Framing {
+        self.framing
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn entry(&self, index: u16) -> Result<&Entry, Error> {
+        self.entries
+            .get(&index)
+            .ok_or_else(|| Error::Malformed(format!("unknown string index {index:#06x}")))
+    }
+}
+
+enum Error {
+    /// The frame ended early; with raw framing more bytes may complete it.
+    Incomplete,
+    Malformed(String),
+}
+
+impl From<defmt_parser::Error> for Error {
+    fn from(e: defmt_parser::Error) -> Self {
+        Error::Malformed(format!("bad format string: {e}"))
+    }
+}
+
+/// Turns a received byte stream into log messages.
+pub struct Decoder {
+    table: Table,
+    pending: Vec<u8>,
+}
+
+impl Decoder {
+    pub fn new(table: Table) -> Self {
+        Self {
+            table,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn table(&self) -> &Table {
+        &self.table
+    }
+
+    /// Appends received bytes and returns every frame they completed; a
+    /// frame that can't be decoded yields an error and decoding carries on
+    /// with the next one.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<Result<Message>> {
+        match self.table.framing {
+            Framing::Rzcobs => self.push_rzcobs(bytes),
+            Framing::Raw => self.push_raw(bytes),
+        }
+    }
+
+    fn push_rzcobs(&mut self, bytes: &[u8]) -> Vec<Result<Message>> {
+        let mut out = Vec::new();
+        for &byte in bytes {
+            if byte != 0 {
+                self.pending.push(byte);
+                continue;
+            }
+            let encoded = std::mem::take(&mut self.pending);
+            if encoded.is_empty() {
+                continue;
+            }
+            let result = match rzcobs_decode(&encoded) {
+                Some(frame) => self.decode_frame(&frame).map(|(msg, _)| msg),
+                None => Err(Error::Malformed("bad rzCOBS framing".to_string())),
+            };
+            out.push(result.map_err(|e| match e {
+                Error::Incomplete => anyhow!("truncated frame"),
+                Error::Malformed(msg) => anyhow!(msg),
+            }));
+        }
+        out
+    }
+
+    fn push_raw(&mut self, bytes: &[u8]) -> Vec<Result<Message>> {
+        self.pending.extend_from_slice(bytes);
+        let mut out = Vec::new();
+        while !self.pending.is_empty() {
+            match self.decode_frame(&self.pending) {
+                Ok((msg, used)) => {
+                    self.pending.drain(..used);
+                    out.push(Ok(msg));
+                }
+                Err(Error::Incomplete) => {
+                    if self.pending.len() > MAX_PENDING {
+                        self.pending.clear();
+                        out.push(Err(anyhow!("lost sync, dropped {MAX_PENDING} bytes")));
+                    }
+                    break;
+                }
+                Err(Error::Malformed(msg)) => {
+                    // Without delimiters there is no way to find the next
+                    // frame, so start over with the next read.
+                    self.pending.clear();
+                    out.push(Err(anyhow!("{msg}; lost sync")));
+                    break;
+                }
+            }
+        }
+        out
+    }
+
+    /// Decodes one frame, returning it with the number of bytes it used.
+    fn decode_frame(&self, frame: &[u8]) -> Result<(Message, usize), Error> {
+        let mut r = Reader { data: frame, pos: 0 };
+        let entry = self.table.entry(r.u16()?)?;
+        let level = Level::from_tag(&entry.tag);
+        if level.is_none() && entry.tag != "defmt_println" {
+            return Err(Error::Malformed(format!("{:?} is not a log statement", entry.tag)));
+        }
+        let timestamp = match &self.table.timestamp {
+            Some(format) => Some(self.format(&mut r, format)?),
+            None => None,
+        };
+        let text = self.format(&mut r, &entry.format)?;
+        Ok((
+            Message {
+                level,
+                timestamp,
+                text,
+            },
+            r.pos,
+        ))
+    }
+
+    /// Reads the arguments of `format` and renders it.
+    fn format(&self, r: &mut Reader, format: &str) -> Result<String, Error> {
+        let fragments = defmt_parser::parse(format, ParserMode::ForwardsCompatible)?;
+        let mut params: Vec<&Parameter> = fragments
+            .iter()
+            .filter_map(|f| match f {
+                Fragment::Parameter(p) => Some(p),
+                Fragment::Literal(_) => None,
+            })
+            .collect();
+        params.sort_by_key(|p| p.index);
+
+        // Arguments are sent once each, in index order. Bitfields share an
+        // argument; its width is set by the highest bit any of them uses.
+        let mut args: HashMap<usize, Arg> = HashMap::new();
+        for (i, param) in params.iter().enumerate() {
+            if args.contains_key(&param.index) {
+                continue;
+            }
+            let arg = match &param.ty {
+                Type::BitField(_) => {
+                    let high = params[i..]
+                        .iter()
+                        .take_while(|p| p.index == param.index)
+                        .filter_map(|p| match &p.ty {
+                            Type::BitField(range) => Some(range.end),
+                            _ => None,
+                        })
+                        .max()
+                        .unwrap_or(0);
+                    match high {
+                        0..=8 => Arg::Uint(r.u8()?.into()),
+                        9..=16 => Arg::Uint(r.u16()?.into()),
+                        17..=32 => Arg::Uint(r.u32()?.into()),
+                        33..=64 => Arg::Uint(r.u64()?.into()),
+                        _ => Arg::Uint(r.u128()?),
+                    }
+                }
+                ty => self.read_arg(r, ty)?,
+            };
+            args.insert(param.index, arg);
+        }
+
+        let mut out = String::new();
+        for fragment in &fragments {
+            match fragment {
+                Fragment::Literal(text) => out.push_str(text),
+                Fragment::Parameter(p) => {
+                    let arg = &args[&p.index];
+                    if let (Type::BitField(range), Arg::Uint(v)) = (&p.ty, arg) {
+                        let width = u32::from(range.end - range.start);
+                        let bits = (v >> range.start) & mask(width);
+                        render(&mut out, &Arg::Uint(bits), p.hint.as_ref());
+                    } else {
+                        render(&mut out, arg, p.hint.as_ref());
+                    }
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    fn read_arg(&self, r: &mut Reader, ty: &Type) -> Result<Arg, Error> {
+        Ok(match ty {
+            Type::U8 => Arg::Uint(r.u8()?.into()),
+            Type::U16 => Arg::Uint(r.u16()?.into()),
+            Type::U32 | Type::Usize => Arg::Uint(r.u32()?.into()),
+            Type::U64 => Arg::Uint(r.u64()?.into()),
+            Type::U128 => Arg::Uint(r.u128()?),
+            Type::I8 => Arg::Int((r.u8()? as i8).into()),
+            Type::I16 => Arg::Int((r.u16()? as i16).into()),
+            Type::I32 | Type::Isize => Arg::Int((r.u32()? as i32).into()),
+            Type::I64 => Arg::Int((r.u64()? as i64).into()),
+            Type::I128 => Arg::Int(r.u128()? as i128),
+            Type::F32 => Arg::Float(f32::from_bits(r.u32()?).into()),
+            Type::F64 => Arg::Float(f64::from_bits(r.u64()?)),
+            Type::Bool => Arg::Bool(r.u8()? != 0),
+            Type::Char => {
+                let c = r.u32()?;
+                Arg::Char(char::from_u32(c).unwrap_or(char::REPLACEMENT_CHARACTER))
+            }
+            Type::Str => {
+                let len = r.u32()? as usize;
+                Arg::Str(String::from_utf8_lossy(r.bytes(len)?).into_owned())
+            }
+            Type::IStr => Arg::Str(self.table.entry(r.u16()?)?.format.clone()),
+            Type::U8Slice => {
+                let len = r.u32()? as usize;
+                Arg::Bytes(r.bytes(len)?.to_vec())
+            }
+            Type::U8Array(len) => Arg::Bytes(r.bytes(*len)?.to_vec()),
+            Type::Debug | Type::Display => Arg::Text(r.until_ff()?),
+            Type::Format => {
+                let index = r.u16()?;
+                Arg::Text(self.format_value(r, index)?)
+            }
+            Type::FormatArray(len) => {
+                let index = r.u16()?;
+                let items = (0..*len)
+                    .map(|_| self.format_value(r, index))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Arg::Text(format!("[{}]", items.join(", ")))
+            }
+            Type::FormatSlice => {
+                let len = r.u32()?;
+                let index = r.u16()?;
+                let items = (0..len)
+                    .map(|_| self.format_value(r, index))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Arg::Text(format!("[{}]", items.join(", ")))
+            }
+            Type::FormatSequence => {
+                // Each `write!` of a hand-written `Format` impl, up to a 0.
+                let mut text = String::new();
+                loop {
+                    let index = r.u16()?;
+                    if index == 0 {
+                        break;
+                    }
+                    let entry = self.table.entry(index)?;
+                    text.push_str(&self.format(r, &entry.format)?);
+                }
+                Arg::Text(text)
+            }
+            Type::BitField(_) => unreachable!("bitfields are read by `format`"),
+        })
+    }
+
+    /// Renders a `Format` value whose type is interned at `index`. Derived
+    /// enums list their variants as `A|B(..)` and send the variant number
+    /// first.
+    fn format_value(&self, r: &mut Reader, index: u16) -> Result<String, Error> {
+        let entry = self.table.entry(index)?;
+        if entry.tag != "defmt_derived" || !entry.format.contains('|') {
+            return self.format(r, &entry.format);
+        }
+        let variants: Vec<&str> = entry.format.split('|').collect();
+        let variant = if variants.len() <= u8::MAX as usize {
+            r.u8()? as usize
+        } else if variants.len() <= u16::MAX as usize {
+            r.u16()? as usize
+        } else {
+            r.u32()? as usize
+        };
+        let format = variants
+            .get(variant)
+            .ok_or_else(|| Error::Malformed(format!("bad enum variant {variant}")))?;
+        self.format(r, format)
+    }
+}
+
+enum Arg {
+    Uint(u128),
+    Int(i128),
+    Float(f64),
+    Bool(bool),
+    Char(char),
+    Str(String),
+    Bytes(Vec<u8>),
+    /// Already formatted (nested `Format` values, `Debug2Format`).
+    Text(String),
+}
+
+fn mask(width: u32) -> u128 {
+    if width >= 128 {
+        u128::MAX
+    } else {
+        (1 << width) - 1
+    }
+}
+
+fn render(out: &mut String, arg: &Arg, hint: Option<&DisplayHint>) {
+    match arg {
+        Arg::Uint(v) => render_int(out, *v, false, hint),
+        Arg::Int(v) => match hint {
+            Some(DisplayHint::Hexadecimal { .. } | DisplayHint::Binary { .. }) => {
+                render_int(out, *v as u128, false, hint)
+            }
+            _ => render_int(out, v.unsigned_abs(), *v < 0, hint),
+        },
+        Arg::Float(v) => {
+            let _ = write!(out, "{v}");
+        }
+        Arg::Bool(v) => {
+            let _ = write!(out, "{v}");
+        }
+        Arg::Char(c) => match hint {
+            Some(DisplayHint::Debug) => {
+                let _ = write!(out, "{c:?}");
+            }
+            _ => out.push(*c),
+        },
+        Arg::Str(s) => match hint {
+            Some(DisplayHint::Debug) => {
+                let _ = write!(out, "{s:?}");
+            }
+            _ => out.push_str(s),
+        },
+        Arg::Bytes(bytes) => render_bytes(out, bytes, hint),
+        Arg::Text(s) => out.push_str(s),
+    }
+}
+
+fn render_int(out: &mut String, v: u128, negative: bool, hint: Option<&DisplayHint>) {
+    if negative {
+        out.push('-');
+    }
+    let _ = match hint {
+        Some(DisplayHint::Hexadecimal {
+            alternate,
+            uppercase,
+            zero_pad,
+        }) => {
+            let prefix = if *alternate { "0x" } else { "" };
+            let width = zero_pad.saturating_sub(prefix.len());
+            if *uppercase {
+                write!(out, "{prefix}{v:0width$X}")
+            } else {
+                write!(out, "{prefix}{v:0width$x}")
+            }
+        }
+        Some(DisplayHint::Binary {
+            alternate,
+            zero_pad,
+        }) => {
+            let prefix = if *alternate { "0b" } else { "" };
+            let width = zero_pad.saturating_sub(prefix.len());
+            write!(out, "{prefix}{v:0width$b}")
+        }
+        Some(DisplayHint::NoHint { zero_pad }) => write!(out, "{v:0zero_pad$}"),
+        Some(
+            DisplayHint::Seconds(precision)
+            | DisplayHint::Time(precision)
+            | DisplayHint::ISO8601(precision),
+        ) => {
+            let (per_second, digits) = match precision {
+                TimePrecision::Micros => (1_000_000, 6),
+                TimePrecision::Millis => (1_000, 3),
+                TimePrecision::Seconds => (1, 0),
+            };
+            if digits == 0 {
+                write!(out, "{v}")
+            } else {
+                write!(out, "{}.{:0digits$}", v / per_second, v % per_second)
+            }
+        }
+        _ => write!(out, "{v}"),
+    };
+}
+
+fn render_bytes(out: &mut String, bytes: &[u8], hint: Option<&DisplayHint>) {
+    if let Some(DisplayHint::Ascii) = hint {
+        out.push_str("b\"");
+        for &b in bytes {
+            out.extend(std::ascii::escape_default(b).map(char::from));
+        }
+        out.push('"');
+        return;
+    }
+    out.push('[');
+    for (i, &b) in bytes.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        render_int(out, b.into(), false, hint);
+    }
+    out.push(']');
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let end = self.pos.checked_add(len).ok_or(Error::Incomplete)?;
+        let bytes = self.data.get(self.pos..end).ok_or(Error::Incomplete)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        Ok(self.bytes(N)?.try_into().expect("slice of length N"))
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    fn u128(&mut self) -> Result<u128, Error> {
+        Ok(u128::from_le_bytes(self.array()?))
+    }
+
+    /// Text up to the 0xff terminator of `Debug2Format`/`Display2Format`.
+    fn until_ff(&mut self) -> Result<String, Error> {
+        let rest = &self.data[self.pos..];
+        let len = rest.iter().position(|&b| b == 0xff).ok_or(Error::Incomplete)?;
+        self.pos += len + 1;
+        Ok(String::from_utf8_lossy(&rest[..len]).into_owned())
+    }
+}
+
+/// Decodes one rzCOBS frame (without its 0x00 terminator). The encoding
+/// runs backwards from the end of the frame; the result may carry trailing
+/// zeros, which frame decoding never reads.
+fn rzcobs_decode(encoded: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(encoded.len() * 2);
+    let mut input = encoded.iter().rev().copied();
+    while let Some(code) = input.next() {
+        match code {
+            0x00 => return None,
+            0x01..=0x7f => {
+                // One bit per byte, MSB first: 1 is a zero, 0 a literal.
+                for bit in (0..7).rev() {
+                    if code & (1 << bit) != 0 {
+                        out.push(0);
+                    } else {
+                        out.push(input.next()?);
+                    }
+                }
+            }
+            0x80..=0xfe => {
+                out.push(0);
+                for _ in 0..(code & 0x7f) + 7 {
+                    out.push(input.next()?);
+                }
+            }
+            0xff => {
+                for _ in 0..134 {
+                    out.push(input.next()?);
+                }
+            }
+        }
+    }
+    out.reverse();
+    Some(out)
+}
@@ -0,0 +1,45 @@
+use std::collections::VecDeque;
+
+use lazyserial::Direction;
+
+/// Which way each output line went over the port, by absolute line number
+/// (see `Scrollback::evicted`), recorded as lines are added so coloring and
+/// the chat layout don't have to guess from the text. Kept as runs, since
+/// lines mostly come in stretches going the same way.
+#[derive(Clone, Default)]
+pub struct LineDirections {
+    /// The first line of each run and the direction of its lines; `None`
+    /// for notes, which didn't cross the port.
+    runs: VecDeque<(usize, Option<Direction>)>,
+}
+
+impl LineDirections {
+    /// Records line `number`, newer than any recorded so far.
+    pub fn push(&mut self, number: usize, direction: Option<Direction>) {
+        if self.runs.back().map(|&(_, d)| d) != Some(direction) {
+            self.runs.push_back((number, direction));
+        }
+    }
+
+    pub fn get(&self, number: usize) -> Option<Direction> {
+        let runs = self.runs.partition_point(|&(start, _)| start <= number);
+        runs.checked_sub(1).and_then(|i| self.runs[i].1)
+    }
+
+    /// Forgets runs that ended before the lines trimmed from the
+    /// scrollback.
+    pub fn prune(&mut self, evicted: usize) {
+        while self.runs.get(1).is_some_and(|&(start, _)| start <= evicted) {
+            self.runs.pop_front();
+        }
+    }
+
+    /// Puts back what was recorded before a clear, keeping what has been
+    /// recorded since for the lines from `from` on.
+    pub fn restore(&mut self, old: LineDirections, from: usize) {
+        let newer = std::mem::replace(self, old);
+        for (start, direction) in newer.runs {
+            self.push(start.max(from), direction);
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use serde::Deserialize;
+
+/// Upper half (0x80..=0xFF) of IBM code page 437.
+const CP437_HIGH: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å',
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ',
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»',
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐',
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧',
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀',
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩',
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{a0}',
+];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Encoding {
+    #[default]
+    #[serde(alias = "utf-8")]
+    Utf8,
+    #[serde(alias = "latin-1", alias = "iso-8859-1")]
+    Latin1,
+    ShiftJis,
+    Cp437,
+}
+
+impl Encoding {
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Latin1 => "Latin-1",
+            Encoding::ShiftJis => "Shift-JIS",
+            Encoding::Cp437 => "CP437",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Encoding::Utf8 => Encoding::Latin1,
+            Encoding::Latin1 => Encoding::ShiftJis,
+            Encoding::ShiftJis => Encoding::Cp437,
+            Encoding::Cp437 => Encoding::Utf8,
+        }
+    }
+
+    /// The bytes decoding gave `text` from, barring undecodable input:
+    /// U+FFFD stays as itself in UTF-8 and becomes `?` elsewhere.
+    pub fn encode(self, text: &str) -> Vec<u8> {
+        match self {
+            Encoding::Utf8 => text.as_bytes().to_vec(),
+            Encoding::Latin1 => text.chars().map(|c| u8::try_from(c).unwrap_or(b'?')).collect(),
+            Encoding::ShiftJis => encoding_rs::SHIFT_JIS.encode(text).0.into_owned(),
+            Encoding::Cp437 => text
+                .chars()
+                .map(|c| match u8::try_from(c) {
+                    Ok(b) if b < 0x80 => b,
+                    _ => CP437_HIGH.iter().position(|&h| h == c).map_or(b'?', |i| 0x80 + i as u8),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Streaming byte-to-text decoder. Multi-byte sequences split across reads
+/// are carried over; undecodable bytes become U+FFFD.
+pub struct TextDecoder {
+    encoding: Encoding,
+    inner: Option<encoding_rs::Decoder>,
+}
+
+impl TextDecoder {
+    pub fn new(encoding: Encoding) -> Self {
+        let inner = match encoding {
+            Encoding::Utf8 => Some(encoding_rs::UTF_8.new_decoder_without_bom_handling()),
+            Encoding::ShiftJis => Some(encoding_rs::SHIFT_JIS.new_decoder_without_bom_handling()),
+            Encoding::Latin1 | Encoding::Cp437 => None,
+        };
+        Self { encoding, inner }
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    pub fn decode(&mut self, bytes: &[u8]) -> String {
+        match (&mut self.inner, self.encoding) {
+            (Some(decoder), _) => {
+                let mut out = String::with_capacity(
+                    decoder
+                        .max_utf8_buffer_length(bytes.len())
+                        .unwrap_or(bytes.len() * 3),
+                );
+                let _ = decoder.decode_to_string(bytes, &mut out, false);
+                out
+            }
+            (None, Encoding::Cp437) => bytes
+                .iter()
+                .map(|&b| {
+                    if b < 0x80 {
+                        b as char
+                    } else {
+                        CP437_HIGH[(b - 0x80) as usize]
+                    }
+                })
+                .collect(),
+            (None, _) => bytes.iter().map(|&b| b as char).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_decoded_text_back_to_its_bytes() {
+        let bytes = [b'A', 0xb0, 0xc4, 0x82, 0xa0, b'\n'];
+        for encoding in [Encoding::Latin1, Encoding::Cp437] {
+            let text = TextDecoder::new(encoding).decode(&bytes);
+            assert_eq!(encoding.encode(&text), bytes, "{encoding:?}");
+        }
+        assert_eq!(Encoding::ShiftJis.encode("Aあ"), [b'A', 0x82, 0xa0]);
+        assert_eq!(Encoding::Utf8.encode("é"), "é".as_bytes());
+    }
+}
@@ -0,0 +1,140 @@
+//! Symbolization of ESP32/ESP8266 panic output.
+//!
+//! ESP-IDF and the Arduino cores print a panic as a register dump and a
+//! `Backtrace:` line of `PC:SP` pairs. [`crash_addresses`] picks the code
+//! addresses out of those lines and [`Symbolizer`] resolves them against
+//! the firmware ELF to function, file and line, the way `idf_monitor`
+//! does with `addr2line -pfiaC`.
+
+use std::borrow::Cow;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use addr2line::gimli::{EndianRcSlice, RunTimeEndian};
+use anyhow::{Context, Result};
+use object::{Object, ObjectSymbol, SymbolKind};
+use regex::Regex;
+
+/// One resolved frame. Inlined calls come first, followed by the function
+/// they were inlined into.
+#[derive(Clone, Debug)]
+pub struct Frame {
+    pub function: String,
+    /// `file:line`, when the ELF has line info for the address.
+    pub location: Option<String>,
+    pub inlined: bool,
+}
+
+struct Symbol {
+    addr: u64,
+    size: u64,
+    name: String,
+}
+
+/// Debug info and function symbols of one firmware image.
+pub struct Symbolizer {
+    context: addr2line::Context<EndianRcSlice<RunTimeEndian>>,
+    /// Function symbols by address, for ELFs without line info.
+    symbols: Vec<Symbol>,
+}
+
+impl Symbolizer {
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = fs::read(path).with_context(|| format!("read {}", path.display()))?;
+        Self::parse(&data).with_context(|| format!("esp: {}", path.display()))
+    }
+
+    pub fn parse(elf: &[u8]) -> Result<Self> {
+        let file = object::File::parse(elf).context("not an ELF file")?;
+        let context = addr2line::Context::new(&file).context("reading debug info")?;
+        let mut symbols: Vec<Symbol> = file
+            .symbols()
+            .filter(|s| s.kind() == SymbolKind::Text && s.size() > 0)
+            .filter_map(|s| {
+                Some(Symbol {
+                    addr: s.address(),
+                    size: s.size(),
+                    name: addr2line::demangle_auto(Cow::from(s.name().ok()?), None).into_owned(),
+                })
+            })
+            .collect();
+        symbols.sort_by_key(|s| s.addr);
+        Ok(Self { context, symbols })
+    }
+
+    /// Number of function symbols, as a sanity check that the ELF fits.
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    /// Frames for a code address; empty when it isn't in the image.
+    pub fn lookup(&self, addr: u64) -> Vec<Frame> {
+        let mut frames = Vec::new();
+        if let Ok(mut iter) = self.context.find_frames(addr).skip_all_loads() {
+            while let Ok(Some(frame)) = iter.next() {
+                let function = frame
+                    .function
+                    .as_ref()
+                    .and_then(|f| f.demangle().ok())
+                    .map_or_else(|| "??".to_string(), Cow::into_owned);
+                let location = frame.location.as_ref().and_then(|l| {
+                    let file = l.file?;
+                    Some(match l.line {
+                        Some(line) => format!("{file}:{line}"),
+                        None => file.to_string(),
+                    })
+                });
+                frames.push(Frame {
+                    function,
+                    location,
+                    inlined: true,
+                });
+            }
+        }
+        if let Some(last) = frames.last_mut() {
+            last.inlined = false;
+        }
+        if frames.iter().all(|f| f.function == "??") {
+            let idx = self.symbols.partition_point(|s| s.addr <= addr);
+            let symbol = idx.checked_sub(1).map(|i| &self.symbols[i]);
+            if let Some(symbol) = symbol.filter(|s| addr < s.addr + s.size) {
+                let location = frames.pop().and_then(|f| f.location);
+                frames = vec![Frame {
+                    function: symbol.name.clone(),
+                    location,
+                    inlined: false,
+                }];
+            }
+        }
+        frames
+    }
+}
+
+/// Code addresses in a line of panic output: the PCs of a `Backtrace:`
+/// line, or the program counter of a register dump (`PC : 0x…`,
+/// `MEPC : 0x…` on RISC-V chips, `epc1=0x…` on the ESP8266).
+pub fn crash_addresses(line: &str) -> Vec<u64> {
+    static REGISTER: OnceLock<Regex> = OnceLock::new();
+    let hex = |s: &str| u64::from_str_radix(s.trim_start_matches("0x"), 16).ok();
+    if let Some((_, frames)) = line.split_once("Backtrace:") {
+        return frames
+            .split_whitespace()
+            .filter_map(|pair| hex(pair.split(':').next()?))
+            .filter(|&addr| addr != 0)
+            .collect();
+    }
+    let register = REGISTER.get_or_init(|| {
+        Regex::new(r"\b(?:PC|MEPC|RA|epc[1-3])\s*[:=]\s*0x([0-9a-fA-F]{8})\b")
+            .expect("register regex is valid")
+    });
+    register
+        .captures_iter(line)
+        .filter_map(|c| hex(&c[1]))
+        .filter(|&addr| addr != 0)
+        .collect()
+}
@@ -0,0 +1,115 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde_json::{Map, Number, Value};
+
+use crate::plot::Plotter;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    /// One JSON object per line.
+    JsonLines,
+}
+
+impl Format {
+    /// JSON Lines for `.json`, `.jsonl` and `.ndjson`, CSV otherwise.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json" | "jsonl" | "ndjson") => Format::JsonLines,
+            _ => Format::Csv,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Csv => "CSV",
+            Format::JsonLines => "JSON Lines",
+        }
+    }
+}
+
+/// Writes the plotter's samples with their receive times, one row per
+/// line, a column per value name. Returns the number of rows.
+pub fn write(path: &Path, plot: &Plotter, format: Format) -> Result<usize> {
+    let write = || -> io::Result<usize> {
+        let mut out = BufWriter::new(File::create(path)?);
+        let mut columns: Vec<&str> = Vec::new();
+        for row in &plot.rows {
+            for (name, _) in &row.values {
+                if !columns.contains(&name.as_str()) {
+                    columns.push(name);
+                }
+            }
+        }
+        if format == Format::Csv {
+            let header: Vec<String> = ["time", "sample"]
+                .into_iter()
+                .chain(columns.iter().copied())
+                .map(csv_field)
+                .collect();
+            writeln!(out, "{}", header.join(","))?;
+        }
+        for row in &plot.rows {
+            let value = |column: &str| row.values.iter().find(|(n, _)| n == column).map(|v| v.1);
+            match format {
+                Format::Csv => {
+                    let mut fields = vec![iso8601(row.time), row.sample.to_string()];
+                    for column in &columns {
+                        fields.push(value(column).map_or(String::new(), |v| v.to_string()));
+                    }
+                    writeln!(out, "{}", fields.join(","))?;
+                }
+                Format::JsonLines => {
+                    let mut object = Map::new();
+                    object.insert("time".to_string(), Value::from(iso8601(row.time)));
+                    object.insert("sample".to_string(), Value::from(row.sample));
+                    for (name, v) in &row.values {
+                        let v = Number::from_f64(*v).map_or(Value::Null, Value::Number);
+                        object.insert(name.clone(), v);
+                    }
+                    writeln!(out, "{}", Value::Object(object))?;
+                }
+            }
+        }
+        out.flush()?;
+        Ok(plot.rows.len())
+    };
+    write().with_context(|| format!("write {}", path.display()))
+}
+
+/// Quotes a CSV field if it needs it.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// `2024-05-01T12:34:56.789Z`.
+pub fn iso8601(time: SystemTime) -> String {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since.as_secs();
+    let (days, rem) = ((secs / 86_400) as i64, secs % 86_400);
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm).
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60,
+        since.subsec_millis()
+    )
+}
@@ -0,0 +1,92 @@
+use std::collections::VecDeque;
+
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+
+use crate::ansi;
+use crate::scrollback::Scrollback;
+
+/// Live output filter. Only the rendered view is narrowed; the scrollback
+/// keeps every line, so turning the filter off restores the full output.
+pub struct Filter {
+    expr: String,
+    regex: Regex,
+    /// Hide matching lines instead of showing only them.
+    invert: bool,
+    pub active: bool,
+    /// Absolute line numbers (see `Scrollback::evicted`) of shown lines.
+    shown: VecDeque<usize>,
+}
+
+impl Filter {
+    /// Parses a regex, optionally prefixed with `!` to hide matching lines.
+    pub fn new(expr: &str) -> Result<Self> {
+        let (invert, pattern) = match expr.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, expr),
+        };
+        if pattern.is_empty() {
+            bail!("empty filter pattern");
+        }
+        let regex = Regex::new(pattern).with_context(|| format!("filter: bad regex {pattern:?}"))?;
+        Ok(Self {
+            expr: expr.to_string(),
+            regex,
+            invert,
+            active: true,
+            shown: VecDeque::new(),
+        })
+    }
+
+    pub fn expr(&self) -> &str {
+        &self.expr
+    }
+
+    pub fn shows(&self, line: &str) -> bool {
+        self.regex.is_match(&ansi::strip(line)) != self.invert
+    }
+
+    /// Rescans the whole buffer, e.g. after the expression changed.
+    pub fn rebuild(&mut self, lines: &Scrollback) {
+        let evicted = lines.evicted();
+        self.shown = (0..lines.len())
+            .filter(|&i| lines.get(i).is_some_and(|l| self.shows(&l)))
+            .map(|i| i + evicted)
+            .collect();
+    }
+
+    /// Records a line just pushed to `lines`. Returns whether it is shown.
+    pub fn on_push(&mut self, lines: &Scrollback) -> bool {
+        while self.shown.front().is_some_and(|&n| n < lines.evicted()) {
+            self.shown.pop_front();
+        }
+        let Some(idx) = lines.len().checked_sub(1) else {
+            return false;
+        };
+        let shown = lines.get(idx).is_some_and(|l| self.shows(&l));
+        if shown {
+            self.shown.push_back(idx + lines.evicted());
+        }
+        shown
+    }
+
+    /// Number of lines that pass the filter.
+    pub fn len(&self) -> usize {
+        self.shown.len()
+    }
+
+    /// Position among the shown lines of absolute line `number`.
+    pub fn position(&self, number: usize) -> Option<usize> {
+        self.shown.binary_search(&number).ok()
+    }
+
+    /// Shown lines before absolute line `number`.
+    pub fn count_before(&self, number: usize) -> usize {
+        self.shown.partition_point(|&n| n < number)
+    }
+
+    /// Index into `lines` of the `pos`th shown line.
+    pub fn line(&self, lines: &Scrollback, pos: usize) -> Option<usize> {
+        self.shown.get(pos)?.checked_sub(lines.evicted())
+    }
+}
@@ -0,0 +1,216 @@
+//! Reassembly of binary frames from a byte stream.
+//!
+//! Devices speaking a packet protocol over UART usually delimit packets
+//! with COBS (zero-terminated) or SLIP (RFC 1055, `0xC0`-terminated).
+//! [`FrameDecoder`] splits the stream at the delimiters and undoes the
+//! byte stuffing, so each packet can be shown whole rather than as text;
+//! [`FramedDecoder`] does the same for the packet list.
+
+use std::time::Instant;
+
+use anyhow::{anyhow, bail, Result};
+use serde::Deserialize;
+
+use crate::decoder::{hex, Column, Decoder, Record};
+use crate::Direction;
+
+const SLIP_END: u8 = 0xc0;
+const SLIP_ESC: u8 = 0xdb;
+const SLIP_ESC_END: u8 = 0xdc;
+const SLIP_ESC_ESC: u8 = 0xdd;
+
+/// Longest frame kept while waiting for a delimiter.
+const MAX_FRAME: usize = 64 * 1024;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Framing {
+    /// Plain text lines.
+    #[default]
+    None,
+    Cobs,
+    Slip,
+}
+
+impl Framing {
+    pub fn name(self) -> &'static str {
+        match self {
+            Framing::None => "none",
+            Framing::Cobs => "cobs",
+            Framing::Slip => "slip",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Framing::None => Framing::Cobs,
+            Framing::Cobs => Framing::Slip,
+            Framing::Slip => Framing::None,
+        }
+    }
+}
+
+/// Splits received bytes into frames.
+pub struct FrameDecoder {
+    framing: Framing,
+    pending: Vec<u8>,
+    overflowed: bool,
+}
+
+impl FrameDecoder {
+    pub fn new(framing: Framing) -> Self {
+        Self {
+            framing,
+            pending: Vec::new(),
+            overflowed: false,
+        }
+    }
+
+    pub fn framing(&self) -> Framing {
+        self.framing
+    }
+
+    /// Appends received bytes and returns every frame they completed, as
+    /// decoded payloads. A frame that doesn't decode comes back as an error
+    /// and the stream carries on with the next one. Empty frames (SLIP
+    /// senders often start with a delimiter) are skipped.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<Result<Vec<u8>>> {
+        let delimiter = match self.framing {
+            Framing::None => return vec![Ok(bytes.to_vec())],
+            Framing::Cobs => 0x00,
+            Framing::Slip => SLIP_END,
+        };
+        let mut frames = Vec::new();
+        for &byte in bytes {
+            if byte != delimiter {
+                if self.pending.len() < MAX_FRAME {
+                    self.pending.push(byte);
+                } else {
+                    self.overflowed = true;
+                }
+                continue;
+            }
+            let raw = std::mem::take(&mut self.pending);
+            if std::mem::take(&mut self.overflowed) {
+                frames.push(Err(anyhow!("frame longer than {MAX_FRAME} bytes, dropped")));
+                continue;
+            }
+            if raw.is_empty() {
+                continue;
+            }
+            frames.push(match self.framing {
+                Framing::Cobs => cobs_decode(&raw),
+                Framing::Slip => slip_decode(&raw),
+                Framing::None => unreachable!(),
+            });
+        }
+        frames
+    }
+}
+
+/// Splits both directions into frames for the packet list.
+pub struct FramedDecoder {
+    rx: FrameDecoder,
+    tx: FrameDecoder,
+    rx_at: Option<Instant>,
+    tx_at: Option<Instant>,
+}
+
+impl FramedDecoder {
+    pub fn new(framing: Framing) -> Self {
+        Self {
+            rx: FrameDecoder::new(framing),
+            tx: FrameDecoder::new(framing),
+            rx_at: None,
+            tx_at: None,
+        }
+    }
+}
+
+const COLUMNS: &[Column] = &[Column {
+    name: "bytes",
+    width: 5,
+}];
+
+impl Decoder for FramedDecoder {
+    fn title(&self) -> &'static str {
+        match self.rx.framing {
+            Framing::Cobs => "COBS frames",
+            Framing::Slip => "SLIP frames",
+            Framing::None => "raw",
+        }
+    }
+
+    fn columns(&self) -> &'static [Column] {
+        COLUMNS
+    }
+
+    fn push(&mut self, direction: Direction, bytes: &[u8], now: Instant) -> Vec<Record> {
+        let (decoder, started) = match direction {
+            Direction::Rx => (&mut self.rx, &mut self.rx_at),
+            Direction::Tx => (&mut self.tx, &mut self.tx_at),
+        };
+        let at = *started.get_or_insert(now);
+        let frames = decoder.push(bytes);
+        if !frames.is_empty() {
+            // Close enough: the next frame starts with the next push.
+            *started = None;
+        }
+        frames
+            .into_iter()
+            .map(|frame| {
+                let (fields, details, raw) = match frame {
+                    Ok(data) => (vec![data.len().to_string()], hex(&data), data),
+                    Err(e) => (vec!["-".to_string()], format!("{e:#}"), Vec::new()),
+                };
+                Record {
+                    direction,
+                    at,
+                    fields,
+                    valid: None,
+                    details,
+                    raw,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Decodes one COBS frame without its zero terminator.
+pub fn cobs_decode(encoded: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(encoded.len());
+    let mut pos = 0;
+    while pos < encoded.len() {
+        let code = encoded[pos] as usize;
+        let end = pos + code;
+        if end > encoded.len() {
+            bail!("COBS code {code:#04x} at offset {pos} runs past the frame end");
+        }
+        out.extend_from_slice(&encoded[pos + 1..end]);
+        pos = end;
+        // Every block but the last, or a full 254-byte one, ends in a zero.
+        if code < 0xff && pos < encoded.len() {
+            out.push(0);
+        }
+    }
+    Ok(out)
+}
+
+/// Decodes one SLIP frame without its END delimiter.
+pub fn slip_decode(encoded: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(encoded.len());
+    let mut bytes = encoded.iter().enumerate();
+    while let Some((pos, &byte)) = bytes.next() {
+        if byte != SLIP_ESC {
+            out.push(byte);
+            continue;
+        }
+        match bytes.next() {
+            Some((_, &SLIP_ESC_END)) => out.push(SLIP_END),
+            Some((_, &SLIP_ESC_ESC)) => out.push(SLIP_ESC),
+            Some((_, &other)) => bail!("SLIP escape followed by {other:#04x} at offset {pos}"),
+            None => bail!("SLIP escape at the end of the frame"),
+        }
+    }
+    Ok(out)
+}
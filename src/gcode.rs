@@ -0,0 +1,307 @@
+//! G-code streaming to 3D printers and CNC controllers: one line in flight
+//! at a time, each released by the firmware's `ok`.
+//!
+//! Marlin gets line numbers and checksums (`N12 G1 X5*93`) and is answered
+//! when it asks for a `Resend`; GRBL gets the bare lines and stops the job
+//! on `error:` or `ALARM:`. Like [`Transfer`](crate::transfer::Transfer), a
+//! [`Streamer`] is fed received bytes and returns what to write.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+
+use crate::transfer::TransferStatus;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Flavor {
+    Marlin,
+    Grbl,
+}
+
+impl Flavor {
+    pub fn name(self) -> &'static str {
+        match self {
+            Flavor::Marlin => "Marlin",
+            Flavor::Grbl => "GRBL",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Flavor::Marlin => Flavor::Grbl,
+            Flavor::Grbl => Flavor::Marlin,
+        }
+    }
+}
+
+/// What the line in flight is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum InFlight {
+    /// `M110 N0`, resetting Marlin's line counter before the file.
+    Reset,
+    Line(usize),
+}
+
+pub struct Streamer {
+    pub path: PathBuf,
+    pub file_name: String,
+    pub flavor: Flavor,
+    pub status: TransferStatus,
+    /// No new lines go out while set; the one in flight still completes.
+    pub paused: bool,
+    /// Lines the firmware asked to have sent again.
+    pub resends: u32,
+    /// Commands with comments and blank lines removed.
+    lines: Vec<String>,
+    /// Index of the next line to send.
+    next: usize,
+    acked: usize,
+    in_flight: Option<InFlight>,
+    /// A `Resend` came in; the `ok` that follows doesn't acknowledge the
+    /// line in flight.
+    resend_pending: bool,
+    rx: Vec<u8>,
+    started: Instant,
+    sent_at: Instant,
+}
+
+impl Streamer {
+    pub fn new(path: &Path, flavor: Flavor) -> Result<Self> {
+        let text = fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
+        let lines: Vec<String> = text.lines().filter_map(clean).collect();
+        if lines.is_empty() {
+            bail!("{} has no G-code", path.display());
+        }
+        let now = Instant::now();
+        Ok(Self {
+            path: path.to_path_buf(),
+            file_name: path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            flavor,
+            status: TransferStatus::Running,
+            paused: false,
+            resends: 0,
+            lines,
+            next: 0,
+            acked: 0,
+            in_flight: None,
+            resend_pending: false,
+            rx: Vec::new(),
+            started: now,
+            sent_at: now,
+        })
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.status != TransferStatus::Running
+    }
+
+    pub fn total(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Lines the firmware has acknowledged.
+    pub fn acked(&self) -> usize {
+        self.acked
+    }
+
+    pub fn progress(&self) -> f64 {
+        self.acked as f64 / self.lines.len() as f64
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// How long the line in flight has waited for its `ok`.
+    pub fn waiting(&self) -> Option<Duration> {
+        self.in_flight.map(|_| self.sent_at.elapsed())
+    }
+
+    /// The first line to write: Marlin's counter reset, or the first
+    /// command.
+    pub fn start(&mut self) -> Vec<u8> {
+        if self.flavor == Flavor::Marlin {
+            self.in_flight = Some(InFlight::Reset);
+            self.sent_at = Instant::now();
+            return b"M110 N0\n".to_vec();
+        }
+        self.send_next()
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) -> Vec<u8> {
+        self.paused = false;
+        if self.in_flight.is_some() {
+            return Vec::new();
+        }
+        self.send_next()
+    }
+
+    pub fn cancel(&mut self) {
+        if !self.is_finished() {
+            self.status = TransferStatus::Cancelled;
+        }
+    }
+
+    pub fn fail<S: Into<String>>(&mut self, reason: S) {
+        if !self.is_finished() {
+            self.status = TransferStatus::Failed(reason.into());
+        }
+    }
+
+    /// One-line outcome for the output log once streaming ends.
+    pub fn summary(&self) -> String {
+        let resends = match self.resends {
+            0 => String::new(),
+            n => format!(", {n} resent"),
+        };
+        match &self.status {
+            TransferStatus::Running => format!("{}/{} lines", self.acked, self.lines.len()),
+            TransferStatus::Done => format!(
+                "streamed {} ({} lines{resends}) in {:.1}s",
+                self.path.display(),
+                self.lines.len(),
+                self.elapsed().as_secs_f64()
+            ),
+            TransferStatus::Failed(reason) => format!(
+                "stopped after {}/{} lines: {reason}",
+                self.acked,
+                self.lines.len()
+            ),
+            TransferStatus::Cancelled => {
+                format!("aborted after {}/{} lines", self.acked, self.lines.len())
+            }
+        }
+    }
+
+    pub fn on_data(&mut self, bytes: &[u8]) -> Vec<u8> {
+        if self.is_finished() {
+            return Vec::new();
+        }
+        self.rx.extend_from_slice(bytes);
+        let mut out = Vec::new();
+        while let Some(end) = self.rx.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.rx.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            out.extend(self.on_line(line.trim()));
+            if self.is_finished() {
+                break;
+            }
+        }
+        out
+    }
+
+    fn on_line(&mut self, line: &str) -> Vec<u8> {
+        let lower = line.to_ascii_lowercase();
+        if lower.starts_with("ok") {
+            return self.on_ok();
+        }
+        if let Some(n) = resend_line(&lower) {
+            if self.flavor == Flavor::Marlin {
+                // Marlin counts from 1 after `M110 N0`.
+                match n.checked_sub(1).filter(|&i| i < self.lines.len()) {
+                    Some(index) => {
+                        self.next = index;
+                        self.acked = self.acked.min(index);
+                        self.resend_pending = true;
+                        self.resends += 1;
+                    }
+                    None => self.fail(format!("firmware asked to resend unknown line {n}")),
+                }
+            }
+            return Vec::new();
+        }
+        let fatal = match self.flavor {
+            Flavor::Grbl => lower.starts_with("error:") || lower.starts_with("alarm:"),
+            Flavor::Marlin => {
+                lower.starts_with("!!")
+                    || (lower.starts_with("error:")
+                        && (lower.contains("halted") || lower.contains("kill")))
+            }
+        };
+        if fatal {
+            let at = match self.in_flight {
+                Some(InFlight::Line(i)) => format!("line {} `{}`", i + 1, self.lines[i]),
+                _ => "start".to_string(),
+            };
+            self.fail(format!("{line} at {at}"));
+        }
+        Vec::new()
+    }
+
+    fn on_ok(&mut self) -> Vec<u8> {
+        let Some(in_flight) = self.in_flight.take() else {
+            return Vec::new();
+        };
+        if let InFlight::Line(index) = in_flight {
+            if !std::mem::take(&mut self.resend_pending) {
+                self.acked = self.acked.max(index + 1);
+            }
+        }
+        if self.acked == self.lines.len() {
+            self.status = TransferStatus::Done;
+            return Vec::new();
+        }
+        if self.paused {
+            return Vec::new();
+        }
+        self.send_next()
+    }
+
+    fn send_next(&mut self) -> Vec<u8> {
+        let Some(command) = self.lines.get(self.next) else {
+            return Vec::new();
+        };
+        let mut out = match self.flavor {
+            Flavor::Marlin => numbered(self.next + 1, command),
+            Flavor::Grbl => command.clone(),
+        };
+        out.push('\n');
+        self.in_flight = Some(InFlight::Line(self.next));
+        self.next += 1;
+        self.sent_at = Instant::now();
+        out.into_bytes()
+    }
+}
+
+/// The command part of a line: comments (`; ...` and `( ... )`), `%` tape
+/// markers and surrounding blanks removed. `None` when nothing is left.
+fn clean(line: &str) -> Option<String> {
+    let line = line.split(';').next().unwrap_or_default();
+    let mut command = String::with_capacity(line.len());
+    let mut in_comment = false;
+    for c in line.chars() {
+        match c {
+            '(' => in_comment = true,
+            ')' if in_comment => in_comment = false,
+            c if !in_comment => command.push(c),
+            _ => {}
+        }
+    }
+    let command = command.trim();
+    (!command.is_empty() && command != "%").then(|| command.to_string())
+}
+
+/// `N<n> <command>*<checksum>`, the checksum being the XOR of every byte
+/// before the `*`.
+fn numbered(n: usize, command: &str) -> String {
+    let line = format!("N{n} {command}");
+    let checksum = line.bytes().fold(0u8, |acc, b| acc ^ b);
+    format!("{line}*{checksum}")
+}
+
+/// The line number in Marlin's `Resend: 12` (or `rs 12`).
+fn resend_line(lower: &str) -> Option<usize> {
+    let rest = lower
+        .strip_prefix("resend:")
+        .or_else(|| lower.strip_prefix("rs "))?;
+    rest.trim().trim_start_matches('n').trim().parse().ok()
+}
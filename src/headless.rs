@@ -0,0 +1,148 @@
+//! `--no-tui`: stdin goes to the port and what the port sends goes to
+//! stdout, for shell pipelines and scripts.
+
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use lazyserial::capture::Recorder;
+use lazyserial::{Direction, PortSettings, SerialEvent, Session};
+
+use crate::cli::Args;
+use crate::config;
+use crate::profile::{self, LineEnding};
+
+/// After stdin ends, how long the port must stay quiet before exiting, so
+/// the reply to the last line still comes through.
+const LINGER: Duration = Duration::from_millis(500);
+const POLL: Duration = Duration::from_millis(20);
+
+pub fn run(args: &Args) -> Result<()> {
+    let Some(target) = args.port.as_deref() else {
+        bail!("--no-tui needs --port");
+    };
+    let config = config::load().unwrap_or_else(|e| {
+        eprintln!("[config] {e:#}");
+        config::Config::default()
+    });
+    let found = profile::for_target(&config.profile, target);
+    let mut settings = PortSettings::new(115_200);
+    if let Some(found) = found {
+        settings.baud_rate = found.baud.unwrap_or(settings.baud_rate);
+        settings.parity = found.parity.unwrap_or(settings.parity);
+        settings.rs485 = found.rs485.map(|r| r.settings());
+    }
+    settings.baud_rate = args.baud.unwrap_or(settings.baud_rate);
+    settings.exclusive = config.serial.exclusive;
+    let line_ending = args
+        .line_ending
+        .or(found.and_then(|p| p.line_ending))
+        .unwrap_or_default();
+    let mut recorder = match &args.log {
+        Some(path) => Some(Recorder::create(path, target)?),
+        None => None,
+    };
+
+    let session = Session::open_with(target, settings)?;
+    config.serial.apply(&session);
+    let stdin = read_stdin(line_ending);
+    let mut stdout = io::stdout().lock();
+    let mut stdin_done = false;
+    let mut last_data = Instant::now();
+    // Bytes the worker discarded while stdout was behind, as last reported.
+    let mut dropped = 0;
+    // Driver receive errors, likewise.
+    let mut line_errors = 0;
+    loop {
+        while let Some(event) = session.try_event() {
+            match event {
+                SerialEvent::Data(bytes) => {
+                    match stdout.write_all(&bytes).and_then(|()| stdout.flush()) {
+                        // The reader went away, e.g. `| head`.
+                        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+                        result => result?,
+                    }
+                    if let Some(recorder) = recorder.as_mut() {
+                        recorder.record(Direction::Rx, &bytes)?;
+                    }
+                    last_data = Instant::now();
+                }
+                // Nothing here shows modem lines, so their absence is noise.
+                SerialEvent::Error(e) if e.starts_with("modem status") => {}
+                SerialEvent::Error(e) => eprintln!("[error] {e}"),
+                SerialEvent::Stats(stats) => {
+                    if stats.dropped > dropped {
+                        eprintln!("[overflow] {} bytes dropped", stats.dropped - dropped);
+                        dropped = stats.dropped;
+                    }
+                    if let Some(e) = stats.line_errors.filter(|e| e.total() > line_errors) {
+                        eprintln!(
+                            "[line errors] framing {}, parity {}, overrun {}, break {}, \
+                             buffer overrun {}",
+                            e.framing, e.parity, e.overrun, e.breaks, e.buffer_overrun
+                        );
+                        line_errors = e.total();
+                    }
+                }
+                SerialEvent::Closed if stdin_done => return Ok(()),
+                SerialEvent::Closed => bail!("{target} closed"),
+                SerialEvent::Opened
+                | SerialEvent::ModemStatus(_)
+                | SerialEvent::OpenFailed(..)
+                | SerialEvent::TxProgress(_)
+                | SerialEvent::TxDone => {}
+            }
+        }
+        if stdin_done {
+            if last_data.elapsed() >= LINGER {
+                session.close()?;
+                return Ok(());
+            }
+            thread::sleep(POLL);
+            continue;
+        }
+        match stdin.recv_timeout(POLL) {
+            Ok(data) => {
+                session.handle().write(data.clone())?;
+                if let Some(recorder) = recorder.as_mut() {
+                    recorder.record(Direction::Tx, &data)?;
+                }
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                stdin_done = true;
+                last_data = Instant::now();
+            }
+        }
+    }
+}
+
+/// Lines from stdin with their terminator swapped for `line_ending`; the
+/// channel closes at end of input.
+fn read_stdin(line_ending: LineEnding) -> mpsc::Receiver<Vec<u8>> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut stdin = io::stdin().lock();
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            match stdin.read_until(b'\n', &mut line) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {}
+            }
+            if line.ends_with(b"\n") {
+                line.pop();
+                if line.ends_with(b"\r") {
+                    line.pop();
+                }
+                line.extend_from_slice(line_ending.bytes());
+            }
+            if tx.send(line.clone()).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
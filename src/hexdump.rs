@@ -0,0 +1,51 @@
+/// Bytes shown per hex dump row.
+pub const ROW_BYTES: usize = 16;
+
+/// Bytes as space-separated hex pairs, e.g. `01 03 ff`.
+pub fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{b:02x}")).collect::<Vec<_>>().join(" ")
+}
+
+/// Formats `data` as `hexdump -C` style rows: offset, hex bytes in two
+/// groups of eight, and the printable ASCII with `.` for everything else.
+pub fn rows(data: &[u8]) -> Vec<String> {
+    data.chunks(ROW_BYTES)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let mut hex = String::with_capacity(ROW_BYTES * 3 + 1);
+            for col in 0..ROW_BYTES {
+                if col == ROW_BYTES / 2 {
+                    hex.push(' ');
+                }
+                match chunk.get(col) {
+                    Some(b) => hex.push_str(&format!("{b:02x} ")),
+                    None => hex.push_str("   "),
+                }
+            }
+            let ascii: String = chunk
+                .iter()
+                .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+                .collect();
+            format!("{:04x}  {hex} |{ascii}|", i * ROW_BYTES)
+        })
+        .collect()
+}
+
+/// The bytes of a row made by [`rows`], ignoring any indent. `None` for
+/// anything else.
+pub fn parse_row(line: &str) -> Option<Vec<u8>> {
+    let (offset, rest) = line.trim_start().split_once("  ")?;
+    if offset.len() < 4 || !offset.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let (hex, _ascii) = rest.split_once(" |")?;
+    let bytes = hex
+        .split_whitespace()
+        .map(|pair| {
+            (pair.len() == 2)
+                .then(|| u8::from_str_radix(pair, 16).ok())
+                .flatten()
+        })
+        .collect::<Option<Vec<u8>>>()?;
+    (!bytes.is_empty()).then_some(bytes)
+}
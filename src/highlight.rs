@@ -0,0 +1,207 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context, Result};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use regex::Regex;
+
+use crate::config::HighlightConfig;
+
+pub struct HighlightRule {
+    pub config: HighlightConfig,
+    regex: Regex,
+    pub style: Style,
+}
+
+impl HighlightRule {
+    pub fn new(config: HighlightConfig) -> Result<Self> {
+        let regex = Regex::new(&config.pattern)
+            .with_context(|| format!("highlight: bad regex {:?}", config.pattern))?;
+        let mut style = Style::default();
+        if let Some(fg) = &config.fg {
+            style = style.fg(parse_color(fg)?);
+        }
+        if let Some(bg) = &config.bg {
+            style = style.bg(parse_color(bg)?);
+        }
+        if config.bold {
+            style = style.add_modifier(Modifier::BOLD);
+        }
+        Ok(Self {
+            config,
+            regex,
+            style,
+        })
+    }
+
+    /// Editable one-line form, e.g. `ERROR => red bold line`.
+    pub fn spec(&self) -> String {
+        let c = &self.config;
+        let mut words = Vec::new();
+        if let Some(fg) = &c.fg {
+            words.push(fg.clone());
+        }
+        if let Some(bg) = &c.bg {
+            words.push(format!("on {bg}"));
+        }
+        if c.bold {
+            words.push("bold".to_string());
+        }
+        if c.line {
+            words.push("line".to_string());
+        }
+        format!("{} => {}", c.pattern, words.join(" "))
+    }
+}
+
+/// Colors output lines whose text matches configured regexes. Later rules
+/// win where they overlap.
+#[derive(Default)]
+pub struct Highlighter {
+    pub rules: Vec<HighlightRule>,
+    /// Rules from the open port's profile, applied after `rules` and never
+    /// saved back to the config.
+    pub profile_rules: Vec<HighlightRule>,
+}
+
+impl Highlighter {
+    pub fn from_config(configs: &[HighlightConfig]) -> Result<Self> {
+        let rules = configs
+            .iter()
+            .cloned()
+            .map(HighlightRule::new)
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            rules,
+            profile_rules: Vec::new(),
+        })
+    }
+
+    pub fn set_profile_rules(&mut self, configs: &[HighlightConfig]) -> Result<()> {
+        self.profile_rules = Self::from_config(configs)?.rules;
+        Ok(())
+    }
+
+    pub fn configs(&self) -> Vec<HighlightConfig> {
+        self.rules.iter().map(|r| r.config.clone()).collect()
+    }
+
+    pub fn toggle_rule(&mut self, idx: usize) {
+        if let Some(rule) = self.rules.get_mut(idx) {
+            rule.config.enabled = !rule.config.enabled;
+        }
+    }
+
+    /// Replaces rule `idx`, or appends when `idx` is `None`. An edited rule
+    /// keeps its enabled state.
+    pub fn set_rule(&mut self, idx: Option<usize>, mut config: HighlightConfig) -> Result<()> {
+        match idx.and_then(|i| self.rules.get_mut(i)) {
+            Some(rule) => {
+                config.enabled = rule.config.enabled;
+                *rule = HighlightRule::new(config)?;
+            }
+            None => self.rules.push(HighlightRule::new(config)?),
+        }
+        Ok(())
+    }
+
+    pub fn remove_rule(&mut self, idx: usize) {
+        if idx < self.rules.len() {
+            self.rules.remove(idx);
+        }
+    }
+
+    pub fn apply(&self, mut line: Line<'static>) -> Line<'static> {
+        let mut rules = self
+            .rules
+            .iter()
+            .chain(&self.profile_rules)
+            .filter(|r| r.config.enabled)
+            .peekable();
+        if rules.peek().is_none() {
+            return line;
+        }
+        let text: String = line.spans.iter().map(|s| s.content.as_ref()).collect();
+        for rule in rules {
+            if rule.config.line {
+                if rule.regex.is_match(&text) {
+                    line.patch_style(rule.style);
+                }
+                continue;
+            }
+            for m in rule.regex.find_iter(&text).filter(|m| !m.is_empty()) {
+                line = patch_range(line, m.start(), m.end(), rule.style);
+            }
+        }
+        line
+    }
+}
+
+/// Parses the popup's `regex => style words` form. Words are a foreground
+/// color, `on <color>` for the background, `bold`, and `line` to color the
+/// whole line instead of just the match.
+pub fn parse_spec(spec: &str) -> Result<HighlightConfig> {
+    let (pattern, style) = spec
+        .rsplit_once("=>")
+        .ok_or_else(|| anyhow!("expected `regex => style`, e.g. `ERROR => red bold`"))?;
+    let mut config = HighlightConfig {
+        pattern: pattern.trim().to_string(),
+        fg: None,
+        bg: None,
+        bold: false,
+        line: false,
+        enabled: true,
+    };
+    let mut words = style.split_whitespace();
+    while let Some(word) = words.next() {
+        match word {
+            "bold" => config.bold = true,
+            "line" => config.line = true,
+            "on" => {
+                let color = words.next().ok_or_else(|| anyhow!("`on` needs a color"))?;
+                parse_color(color)?;
+                config.bg = Some(color.to_string());
+            }
+            color => {
+                parse_color(color)?;
+                config.fg = Some(color.to_string());
+            }
+        }
+    }
+    if config.pattern.is_empty() {
+        bail!("empty pattern");
+    }
+    HighlightRule::new(config.clone())?;
+    Ok(config)
+}
+
+pub fn parse_color(name: &str) -> Result<Color> {
+    Color::from_str(name).map_err(|_| anyhow!("unknown color {name:?}"))
+}
+
+/// Applies `style` to bytes `start..end` of the line's text, splitting spans
+/// at the boundaries.
+fn patch_range(line: Line<'static>, start: usize, end: usize, style: Style) -> Line<'static> {
+    let mut spans = Vec::with_capacity(line.spans.len() + 2);
+    let mut pos = 0;
+    for span in line.spans {
+        let len = span.content.len();
+        let (s, e) = (start.clamp(pos, pos + len) - pos, end.clamp(pos, pos + len) - pos);
+        pos += len;
+        if s == e {
+            spans.push(span);
+            continue;
+        }
+        let content = span.content.as_ref();
+        if s > 0 {
+            spans.push(Span::styled(content[..s].to_string(), span.style));
+        }
+        spans.push(Span::styled(content[s..e].to_string(), span.style.patch(style)));
+        if e < len {
+            spans.push(Span::styled(content[e..].to_string(), span.style));
+        }
+    }
+    let mut out = Line::from(spans);
+    out.alignment = line.alignment;
+    out
+}
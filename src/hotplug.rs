@@ -0,0 +1,46 @@
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+use lazyserial::serial;
+use serialport::SerialPortInfo;
+
+/// How often the watcher re-enumerates ports.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Polls the system port list on a background thread (enumeration can be
+/// slow on some platforms) and sends the full list whenever the set of port
+/// names changes. The thread ends once the receiver is dropped.
+pub fn watch(initial: &[SerialPortInfo]) -> Receiver<Vec<SerialPortInfo>> {
+    let (tx, rx) = mpsc::channel();
+    let mut known = names(initial);
+    thread::spawn(move || loop {
+        thread::sleep(POLL_INTERVAL);
+        let Ok(ports) = serial::list_ports() else {
+            continue;
+        };
+        let current = names(&ports);
+        if current == known {
+            continue;
+        }
+        known = current;
+        if tx.send(ports).is_err() {
+            break;
+        }
+    });
+    rx
+}
+
+/// Port names in `new` but not in `old`.
+pub fn added<'a>(old: &[SerialPortInfo], new: &'a [SerialPortInfo]) -> Vec<&'a str> {
+    new.iter()
+        .map(|p| p.port_name.as_str())
+        .filter(|name| !old.iter().any(|p| p.port_name == *name))
+        .collect()
+}
+
+fn names(ports: &[SerialPortInfo]) -> Vec<String> {
+    let mut names: Vec<String> = ports.iter().map(|p| p.port_name.clone()).collect();
+    names.sort();
+    names
+}
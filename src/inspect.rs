@@ -0,0 +1,90 @@
+//! Byte inspector: the bytes of selected hex dump rows read as integers and
+//! floats of either byte order, like a debugger's memory view.
+
+use std::fmt::{Display, LowerExp};
+
+use crossterm::event::{KeyCode, KeyEvent};
+
+use crate::hexdump;
+
+pub struct Inspector {
+    pub bytes: Vec<u8>,
+    /// Where the values are read from.
+    pub offset: usize,
+}
+
+/// A type's name and its value read little-endian and big-endian; the
+/// single-byte types have no big-endian reading.
+pub type Value = (&'static str, String, Option<String>);
+
+impl Inspector {
+    /// Collects the bytes of the hex dump rows among `lines`; `None` when
+    /// there are none.
+    pub fn new<S: AsRef<str>>(lines: &[S]) -> Option<Self> {
+        let bytes: Vec<u8> = lines
+            .iter()
+            .filter_map(|line| hexdump::parse_row(line.as_ref()))
+            .flatten()
+            .collect();
+        (!bytes.is_empty()).then_some(Self { bytes, offset: 0 })
+    }
+
+    /// Moves the offset; returns whether the inspector should close.
+    pub fn on_key(&mut self, key: KeyEvent) -> bool {
+        let last = self.bytes.len() - 1;
+        match key.code {
+            KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q') => return true,
+            KeyCode::Left => self.offset = self.offset.saturating_sub(1),
+            KeyCode::Right => self.offset = (self.offset + 1).min(last),
+            KeyCode::Up => self.offset = self.offset.saturating_sub(hexdump::ROW_BYTES),
+            KeyCode::Down => self.offset = (self.offset + hexdump::ROW_BYTES).min(last),
+            KeyCode::Home => self.offset = 0,
+            KeyCode::End => self.offset = last,
+            _ => {}
+        }
+        false
+    }
+
+    /// Every type wide enough to fit in the bytes from the offset on.
+    pub fn values(&self) -> Vec<Value> {
+        let at = &self.bytes[self.offset..];
+        let mut values = Vec::new();
+        if let Some(&b) = at.first() {
+            values.push(("u8", b.to_string(), None));
+            values.push(("i8", (b as i8).to_string(), None));
+        }
+        macro_rules! both {
+            ($name:literal, $ty:ty, $show:expr) => {
+                if let Some(b) = field(at) {
+                    let show = $show;
+                    let (le, be) = (<$ty>::from_le_bytes(b), <$ty>::from_be_bytes(b));
+                    values.push(($name, show(le), Some(show(be))));
+                }
+            };
+        }
+        both!("u16", u16, |v: u16| v.to_string());
+        both!("i16", i16, |v: i16| v.to_string());
+        both!("u32", u32, |v: u32| v.to_string());
+        both!("i32", i32, |v: i32| v.to_string());
+        both!("f32", f32, float::<f32>);
+        both!("u64", u64, |v: u64| v.to_string());
+        both!("i64", i64, |v: i64| v.to_string());
+        both!("f64", f64, float::<f64>);
+        values
+    }
+}
+
+fn field<const N: usize>(data: &[u8]) -> Option<[u8; N]> {
+    data.get(..N)?.try_into().ok()
+}
+
+/// Plain notation for everyday magnitudes, exponent notation for the tiny
+/// and huge values reinterpreted integers tend to give.
+fn float<T: Into<f64> + Display + LowerExp + Copy>(v: T) -> String {
+    let magnitude = v.into().abs();
+    if magnitude == 0.0 || !magnitude.is_finite() || (1e-4..1e9).contains(&magnitude) {
+        format!("{v}")
+    } else {
+        format!("{v:e}")
+    }
+}
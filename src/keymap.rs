@@ -0,0 +1,704 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use anyhow::{anyhow, bail, Result};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::app::Focus;
+use crate::config::KeySpec;
+
+/// Where a binding applies. Global bindings work in every pane.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scope {
+    Global,
+    Ports,
+    Output,
+    Input,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 4] = [Scope::Global, Scope::Ports, Scope::Output, Scope::Input];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Scope::Global => "global",
+            Scope::Ports => "ports",
+            Scope::Output => "output",
+            Scope::Input => "input",
+        }
+    }
+
+    fn of(focus: Focus) -> Self {
+        match focus {
+            Focus::Ports => Scope::Ports,
+            Focus::Output => Scope::Output,
+            Focus::Input => Scope::Input,
+        }
+    }
+}
+
+/// Everything a key can be bound to on the main screen. Popups and prompts
+/// keep their own fixed keys.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Help,
+    Palette,
+    Quit,
+    Refresh,
+    FocusNext,
+    FocusPrev,
+    BaudUp,
+    BaudDown,
+    Cancel,
+    SendFile,
+    ReceiveFile,
+    ToggleDtr,
+    ToggleRts,
+    Break,
+    Reset,
+    SendCtrlC,
+    SendCtrlD,
+    Compose,
+    Marker,
+    Errors,
+    Retry,
+    Reopen,
+    Clear,
+    ClearScrollback,
+    UndoClear,
+    Buffer,
+    Checksum,
+    ChecksumCalc,
+    AutoResponses,
+    Macros,
+    Pause,
+    Repeat,
+    Bridge,
+    Mqtt,
+    PortsWider,
+    PortsNarrower,
+    CollapsePorts,
+    Layout,
+    Zoom,
+    Passthrough,
+    SaveScrollback,
+    CycleEncoding,
+    ToggleAnsi,
+    Script,
+    Defmt,
+    EspCrash,
+    Framing,
+    Modbus,
+    Decoder,
+    Alerts,
+    ModbusRead,
+    Nmea,
+    AtMode,
+    Plot,
+    Export,
+    Record,
+    Replay,
+    Sniff,
+    Compare,
+    Broadcast,
+    Ping,
+    LinkTest,
+    Pipe,
+    Stm32Flash,
+    Gcode,
+    PrevPort,
+    NextPort,
+    Open,
+    Connect,
+    OpenPath,
+    Favorite,
+    PortFilter,
+    Bluetooth,
+    SaveProfile,
+    ScrollUp,
+    ScrollDown,
+    PageUp,
+    PageDown,
+    ScrollTop,
+    Follow,
+    Yank,
+    Inspect,
+    ToggleWrap,
+    Collapse,
+    ControlChars,
+    Chat,
+    HexSplit,
+    Interleave,
+    ScrollLeft,
+    ScrollRight,
+    Bookmark,
+    NextBookmark,
+    PrevBookmark,
+    YankAll,
+    Highlights,
+    Filter,
+    ToggleFilter,
+    Send,
+    Literal,
+}
+
+pub struct ActionInfo {
+    pub action: Action,
+    /// Name used in the `[keymap]` config section.
+    pub name: &'static str,
+    pub scope: Scope,
+    default_keys: &'static [&'static str],
+    /// One-line description for the help overlay.
+    pub help: &'static str,
+    /// Label in the header's key summary; `None` leaves it out.
+    pub short: Option<&'static str>,
+}
+
+const fn info(
+    action: Action,
+    name: &'static str,
+    scope: Scope,
+    default_keys: &'static [&'static str],
+    help: &'static str,
+    short: Option<&'static str>,
+) -> ActionInfo {
+    ActionInfo {
+        action,
+        name,
+        scope,
+        default_keys,
+        help,
+        short,
+    }
+}
+
+use Action as A;
+use Scope::{Global, Input, Output, Ports};
+
+pub const ACTIONS: &[ActionInfo] = &[
+    info(A::Help, "help", Global, &["?"], "This help", Some("help")),
+    info(A::Palette, "palette", Global, &["ctrl-p"], "Command palette", Some("commands")),
+    info(A::Quit, "quit", Global, &["q"], "Quit", Some("quit")),
+    info(A::FocusNext, "focus-next", Global, &["tab"], "Focus the next pane", Some("focus")),
+    info(A::FocusPrev, "focus-prev", Global, &["backtab"], "Focus the previous pane", None),
+    info(A::Refresh, "refresh", Global, &["r"], "Refresh the port list", Some("refresh")),
+    info(A::BaudUp, "baud-up", Global, &["b"], "Next baud rate", Some("baud")),
+    info(A::BaudDown, "baud-down", Global, &["B"], "Previous baud rate", None),
+    info(
+        A::Cancel,
+        "cancel",
+        Global,
+        &["esc"],
+        "Clear the selection, or stop a running repeat or paste",
+        None,
+    ),
+    info(
+        A::SendFile,
+        "send-file",
+        Global,
+        &["ctrl-u"],
+        "Send a file (XMODEM/YMODEM)",
+        Some("send file"),
+    ),
+    info(
+        A::ReceiveFile,
+        "receive-file",
+        Global,
+        &["ctrl-g"],
+        "Receive a file (XMODEM/YMODEM)",
+        Some("recv file"),
+    ),
+    info(A::ToggleDtr, "toggle-dtr", Global, &["ctrl-t"], "Toggle DTR", Some("DTR")),
+    info(A::ToggleRts, "toggle-rts", Global, &["ctrl-r"], "Toggle RTS", Some("RTS")),
+    info(A::Break, "break", Global, &["alt-b"], "Send a break", None),
+    info(A::Reset, "reset", Global, &["alt-r"], "Run a DTR/RTS reset sequence", None),
+    info(A::SendCtrlC, "send-ctrl-c", Global, &["alt-c"], "Send a bare Ctrl-C (0x03)", None),
+    info(A::SendCtrlD, "send-ctrl-d", Global, &["ctrl-d"], "Send a bare Ctrl-D (0x04)", None),
+    info(A::Compose, "compose", Global, &["alt-e"], "Compose a multi-line payload", None),
+    info(A::Marker, "marker", Global, &["alt-m"], "Insert a timestamped marker line", None),
+    info(A::Errors, "errors", Global, &["alt-n"], "Error history", None),
+    info(A::Clear, "clear", Global, &["ctrl-l"], "Clear the output view", None),
+    info(
+        A::ClearScrollback,
+        "clear-scrollback",
+        Global,
+        &["alt-k"],
+        "Clear the whole scrollback",
+        None,
+    ),
+    info(A::UndoClear, "undo-clear", Global, &["alt-u"], "Undo a clear (for 10 seconds)", None),
+    info(A::Buffer, "buffer", Global, &["alt-i"], "Scrollback usage: lines and bytes", None),
+    info(A::Retry, "retry", Global, &["alt-o"], "Retry opening the port that failed", None),
+    info(
+        A::Reopen,
+        "reopen",
+        Global,
+        &["alt-s"],
+        "Reopen the last port with its previous settings",
+        None,
+    ),
+    info(
+        A::Checksum,
+        "checksum",
+        Global,
+        &[],
+        "Cycle the checksum appended to sent lines and macros",
+        None,
+    ),
+    info(A::ChecksumCalc, "checksum-calc", Global, &[], "Checksum / CRC calculator", None),
+    info(
+        A::AutoResponses,
+        "auto-responses",
+        Global,
+        &["ctrl-o"],
+        "Auto-response rules",
+        Some("auto"),
+    ),
+    info(A::Macros, "macros", Global, &["ctrl-f"], "Macro list", Some("macros")),
+    info(
+        A::Repeat,
+        "repeat",
+        Global,
+        &["ctrl-y"],
+        "Repeat the input line every N ms",
+        Some("repeat"),
+    ),
+    info(A::Pause, "pause", Global, &["ctrl-s"], "Pause / resume the output view", Some("pause")),
+    info(
+        A::SaveScrollback,
+        "save",
+        Global,
+        &["ctrl-w"],
+        "Save the scrollback to a file",
+        Some("save"),
+    ),
+    info(
+        A::Bridge,
+        "bridge",
+        Global,
+        &["ctrl-b"],
+        "Share the port over TCP or WebSocket",
+        Some("bridge"),
+    ),
+    info(A::Mqtt, "mqtt", Global, &[], "Publish received lines over MQTT on / off", None),
+    info(A::PortsWider, "ports-wider", Global, &["alt-right"], "Widen the Ports pane", None),
+    info(A::PortsNarrower, "ports-narrower", Global, &["alt-left"], "Narrow the Ports pane", None),
+    info(
+        A::CollapsePorts,
+        "collapse-ports",
+        Global,
+        &["alt-p"],
+        "Hide the Ports pane while a port is open",
+        None,
+    ),
+    info(A::Layout, "layout", Global, &["alt-l"], "Ports beside / above the output", None),
+    info(A::Zoom, "zoom", Global, &["alt-z"], "Zoom the output to the whole screen / back", None),
+    info(
+        A::Passthrough,
+        "terminal",
+        Global,
+        &["ctrl-a"],
+        "Raw terminal passthrough",
+        Some("terminal"),
+    ),
+    info(A::Script, "script", Global, &["ctrl-x"], "Run / stop a Rhai script", Some("script")),
+    info(A::Defmt, "defmt", Global, &[], "Decode defmt logs / back to text", None),
+    info(
+        A::EspCrash,
+        "esp-crash",
+        Global,
+        &[],
+        "Decode ESP panic backtraces / stop",
+        None,
+    ),
+    info(A::Framing, "framing", Global, &[], "Cycle frame decoding: none, COBS, SLIP", None),
+    info(A::Modbus, "modbus", Global, &[], "Modbus RTU packet monitor on / off", None),
+    info(
+        A::Decoder,
+        "decoder",
+        Global,
+        &[],
+        "Cycle the packet list decoder: off, Modbus, NMEA, COBS, SLIP, plugins",
+        None,
+    ),
+    info(
+        A::ModbusRead,
+        "modbus-read",
+        Global,
+        &[],
+        "Send a Modbus read-holding-registers query",
+        None,
+    ),
+    info(A::Alerts, "alerts", Global, &[], "Alert rules on / off", None),
+    info(A::Nmea, "nmea", Global, &[], "NMEA GPS summary pane on / off", None),
+    info(A::AtMode, "at-mode", Global, &[], "AT command mode on / off", None),
+    info(A::Plot, "plot", Global, &[], "Plot numbers from received lines / hide", None),
+    info(A::Export, "export", Global, &[], "Export plotted data as CSV or JSON Lines", None),
+    info(A::Record, "record", Global, &[], "Record RX/TX traffic to a capture file", None),
+    info(A::Replay, "replay", Global, &[], "Replay a capture file into the output", None),
+    info(A::Sniff, "sniff", Global, &[], "Forward between the port and a second one", None),
+    info(A::Compare, "compare", Global, &[], "Compare a second port's output with this one", None),
+    info(A::Broadcast, "broadcast", Global, &[], "Broadcast typed lines to more ports too", None),
+    info(A::Ping, "ping", Global, &[], "Ping the device: round trip time statistics", None),
+    info(
+        A::LinkTest,
+        "link-test",
+        Global,
+        &[],
+        "Link test: loop a pattern back, count bit errors and drops",
+        None,
+    ),
+    info(A::Pipe, "pipe", Global, &[], "Pipe received data through a filter command", None),
+    info(
+        A::Stm32Flash,
+        "stm32-flash",
+        Global,
+        &[],
+        "Flash a binary through the STM32 UART bootloader",
+        None,
+    ),
+    info(
+        A::Gcode,
+        "gcode",
+        Global,
+        &[],
+        "Stream a G-code file, or pause / resume the stream",
+        None,
+    ),
+    info(A::ToggleAnsi, "ansi", Global, &["ctrl-e"], "Render / strip ANSI escapes", Some("ansi")),
+    info(
+        A::CycleEncoding,
+        "encoding",
+        Global,
+        &["ctrl-n"],
+        "Next character encoding",
+        Some("encoding"),
+    ),
+    info(A::PrevPort, "prev-port", Ports, &["up"], "Select the previous port", None),
+    info(A::NextPort, "next-port", Ports, &["down"], "Select the next port", None),
+    info(A::Open, "open", Ports, &["enter"], "Open / close the selected port", Some("open/close")),
+    info(A::Connect, "connect", Ports, &["c"], "Connect to an RFC 2217 or telnet server", None),
+    info(A::OpenPath, "open-path", Ports, &["o"], "Open a device path by hand", None),
+    info(A::Favorite, "favorite", Ports, &["*"], "Star / unstar the port", None),
+    info(A::PortFilter, "filter-ports", Ports, &["/"], "Fuzzy-filter the port list", None),
+    info(A::Bluetooth, "bluetooth", Ports, &["s"], "Scan for Bluetooth serial devices", None),
+    info(A::SaveProfile, "save-profile", Ports, &["p"], "Save the baud rate as a profile", None),
+    info(A::ScrollUp, "scroll-up", Output, &["up"], "Scroll up a line", None),
+    info(A::ScrollDown, "scroll-down", Output, &["down"], "Scroll down a line", None),
+    info(A::PageUp, "page-up", Output, &["pageup"], "Scroll up a page", None),
+    info(A::PageDown, "page-down", Output, &["pagedown"], "Scroll down a page", None),
+    info(A::ScrollTop, "scroll-top", Output, &["home"], "Jump to the oldest line", None),
+    info(A::Follow, "follow", Output, &["end", "f"], "Jump to the bottom and follow", None),
+    info(A::ToggleWrap, "wrap", Output, &["w"], "Wrap long lines / cut them off", None),
+    info(A::Collapse, "collapse", Output, &["z"], "Collapse repeated lines / show each", None),
+    info(
+        A::ControlChars,
+        "control-chars",
+        Output,
+        &["v"],
+        "Show control characters and line endings as symbols / not",
+        None,
+    ),
+    info(A::Chat, "chat", Output, &["t"], "Sent lines on the right, like a chat / all left", None),
+    info(A::HexSplit, "hex-split", Output, &["x"], "Show the bytes in hex alongside / hide", None),
+    info(
+        A::Interleave,
+        "interleave",
+        Output,
+        &["c"],
+        "Interleave the compared ports' lines / side by side",
+        None,
+    ),
+    info(A::ScrollLeft, "scroll-left", Output, &["left"], "Scroll unwrapped lines left", None),
+    info(A::ScrollRight, "scroll-right", Output, &["right"], "Scroll unwrapped lines right", None),
+    info(A::Yank, "yank", Output, &["y"], "Copy the selection (or screen)", None),
+    info(A::YankAll, "yank-all", Output, &["Y"], "Copy the whole buffer", None),
+    info(A::Inspect, "inspect", Output, &["i"], "Inspect the selected hex dump bytes", None),
+    info(A::Bookmark, "bookmark", Output, &["m"], "Bookmark the bottom line, or unmark it", None),
+    info(A::NextBookmark, "next-bookmark", Output, &["n"], "Jump to the next bookmark", None),
+    info(A::PrevBookmark, "prev-bookmark", Output, &["N"], "Jump to the previous bookmark", None),
+    info(A::Highlights, "highlights", Output, &["h"], "Highlight rules", None),
+    info(A::Filter, "filter", Output, &["/"], "Filter the view by regex", None),
+    info(A::ToggleFilter, "toggle-filter", Output, &["F"], "Turn the filter off / on", None),
+    info(A::Send, "send", Input, &["enter"], "Send the input line", None),
+    info(
+        A::Literal,
+        "literal",
+        Input,
+        &["ctrl-v"],
+        "Type the next key as a control character (^V ^C, ^V Esc)",
+        None,
+    ),
+];
+
+impl Action {
+    pub fn info(self) -> &'static ActionInfo {
+        ACTIONS
+            .iter()
+            .find(|i| i.action == self)
+            .expect("every action is listed in ACTIONS")
+    }
+}
+
+/// A key press as bound in the keymap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Key {
+    code: KeyCode,
+    mods: KeyModifiers,
+}
+
+impl Key {
+    /// Normalizes an event so it compares equal to the parsed binding:
+    /// Shift is implied by the character for printable keys, and Ctrl
+    /// letters are lower case.
+    pub fn from_event(event: KeyEvent) -> Self {
+        let mut mods = event.modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT);
+        let code = match event.code {
+            KeyCode::Char(c) if mods.contains(KeyModifiers::CONTROL) => {
+                KeyCode::Char(c.to_ascii_lowercase())
+            }
+            KeyCode::Char(c) => KeyCode::Char(c),
+            KeyCode::BackTab => KeyCode::BackTab,
+            code => {
+                mods |= event.modifiers & KeyModifiers::SHIFT;
+                code
+            }
+        };
+        Self { code, mods }
+    }
+
+    /// Parses `q`, `B`, `ctrl-t`, `alt-x`, `shift-up`, `f5`, `pageup`, ...
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut mods = KeyModifiers::NONE;
+        let mut rest = spec;
+        loop {
+            let lower = rest.to_ascii_lowercase();
+            let (prefix, m) = if lower.starts_with("ctrl-") {
+                ("ctrl-", KeyModifiers::CONTROL)
+            } else if lower.starts_with("alt-") {
+                ("alt-", KeyModifiers::ALT)
+            } else if lower.starts_with("shift-") {
+                ("shift-", KeyModifiers::SHIFT)
+            } else {
+                break;
+            };
+            if rest.len() == prefix.len() {
+                break;
+            }
+            mods |= m;
+            rest = &rest[prefix.len()..];
+        }
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match rest.to_ascii_lowercase().as_str() {
+                "enter" | "return" => KeyCode::Enter,
+                "esc" | "escape" => KeyCode::Esc,
+                "tab" => KeyCode::Tab,
+                "backtab" => KeyCode::BackTab,
+                "backspace" => KeyCode::Backspace,
+                "delete" | "del" => KeyCode::Delete,
+                "insert" | "ins" => KeyCode::Insert,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" | "pgup" => KeyCode::PageUp,
+                "pagedown" | "pgdn" => KeyCode::PageDown,
+                "space" => KeyCode::Char(' '),
+                name => match name.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                    Some(n) if (1..=24).contains(&n) => KeyCode::F(n),
+                    _ => bail!("unknown key {spec:?}"),
+                },
+            },
+        };
+        let code = match code {
+            KeyCode::Tab if mods.contains(KeyModifiers::SHIFT) => KeyCode::BackTab,
+            KeyCode::Char(c) if mods.contains(KeyModifiers::SHIFT) => {
+                KeyCode::Char(c.to_ascii_uppercase())
+            }
+            code => code,
+        };
+        Ok(Self::from_event(KeyEvent::new(code, mods)))
+    }
+}
+
+impl Key {
+    /// A printable character without Ctrl or Alt, i.e. something typed.
+    pub fn is_text(&self) -> bool {
+        matches!(self.code, KeyCode::Char(_)) && self.mods.is_empty()
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.mods.contains(KeyModifiers::ALT) {
+            f.write_str("M-")?;
+        }
+        if self.mods.contains(KeyModifiers::SHIFT) {
+            f.write_str("S-")?;
+        }
+        if self.mods.contains(KeyModifiers::CONTROL) {
+            if let KeyCode::Char(c) = self.code {
+                return write!(f, "^{}", c.to_ascii_uppercase());
+            }
+            f.write_str("C-")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => f.write_str("Space"),
+            KeyCode::Char(c) => write!(f, "{c}"),
+            KeyCode::F(n) => write!(f, "F{n}"),
+            KeyCode::Enter => f.write_str("Enter"),
+            KeyCode::Esc => f.write_str("Esc"),
+            KeyCode::Tab => f.write_str("Tab"),
+            KeyCode::BackTab => f.write_str("S-Tab"),
+            KeyCode::Backspace => f.write_str("Bksp"),
+            KeyCode::Delete => f.write_str("Del"),
+            KeyCode::Insert => f.write_str("Ins"),
+            KeyCode::Up => f.write_str("Up"),
+            KeyCode::Down => f.write_str("Down"),
+            KeyCode::Left => f.write_str("Left"),
+            KeyCode::Right => f.write_str("Right"),
+            KeyCode::Home => f.write_str("Home"),
+            KeyCode::End => f.write_str("End"),
+            KeyCode::PageUp => f.write_str("PgUp"),
+            KeyCode::PageDown => f.write_str("PgDn"),
+            code => write!(f, "{code:?}"),
+        }
+    }
+}
+
+/// Key bindings for the main screen: the defaults from [`ACTIONS`] with the
+/// config's `[keymap]` overrides applied.
+pub struct Keymap {
+    bindings: Vec<(Key, Action)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let bindings = ACTIONS
+            .iter()
+            .flat_map(|i| {
+                i.default_keys
+                    .iter()
+                    .map(|k| (Key::parse(k).expect("default keys parse"), i.action))
+            })
+            .collect();
+        Self { bindings }
+    }
+}
+
+impl Keymap {
+    /// Each entry replaces every default key of that action; an empty
+    /// string or list unbinds it. Bindings that would shadow each other are
+    /// rejected.
+    pub fn from_config(overrides: &BTreeMap<String, KeySpec>) -> Result<Self> {
+        let mut keymap = Self::default();
+        for (name, spec) in overrides {
+            let info = ACTIONS
+                .iter()
+                .find(|i| i.name == name.as_str())
+                .ok_or_else(|| anyhow!("keymap: unknown action {name:?}"))?;
+            let keys = spec
+                .keys()
+                .into_iter()
+                .map(Key::parse)
+                .collect::<Result<Vec<_>>>()?;
+            keymap.bindings.retain(|(_, a)| *a != info.action);
+            keymap.bindings.extend(keys.into_iter().map(|k| (k, info.action)));
+        }
+        keymap.check_conflicts()?;
+        Ok(keymap)
+    }
+
+    fn check_conflicts(&self) -> Result<()> {
+        let mut conflicts = Vec::new();
+        for (i, (key, a)) in self.bindings.iter().enumerate() {
+            for (other, b) in &self.bindings[i + 1..] {
+                let (sa, sb) = (a.info().scope, b.info().scope);
+                let overlap = sa == sb || sa == Scope::Global || sb == Scope::Global;
+                if key == other && a != b && overlap {
+                    conflicts.push(format!(
+                        "{key} is bound to {} ({}) and {} ({})",
+                        a.info().name,
+                        sa.name(),
+                        b.info().name,
+                        sb.name()
+                    ));
+                }
+            }
+        }
+        if !conflicts.is_empty() {
+            bail!("keymap: {}", conflicts.join("; "));
+        }
+        Ok(())
+    }
+
+    /// The action for `event` in the focused pane. While the Input pane has
+    /// focus, plain characters are text: global bindings on them are only
+    /// reachable with Alt held (`Alt-q` quits), so typing `q` types `q`.
+    pub fn lookup(&self, focus: Focus, event: KeyEvent) -> Option<Action> {
+        let key = Key::from_event(event);
+        let scope = Scope::of(focus);
+        if let Some(action) = self.find(scope, key) {
+            return Some(action);
+        }
+        if scope != Scope::Input || !key.is_text() {
+            if let Some(action) = self.find(Scope::Global, key) {
+                return Some(action);
+            }
+        }
+        if scope == Scope::Input && key.mods == KeyModifiers::ALT {
+            let plain = Key {
+                code: key.code,
+                mods: KeyModifiers::NONE,
+            };
+            if plain.is_text() {
+                return self.find(Scope::Global, plain);
+            }
+        }
+        None
+    }
+
+    fn find(&self, scope: Scope, key: Key) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(k, a)| *k == key && a.info().scope == scope)
+            .map(|(_, a)| *a)
+    }
+
+    /// Whether `event` is one of the keys bound to `action`.
+    pub fn is(&self, action: Action, event: KeyEvent) -> bool {
+        let key = Key::from_event(event);
+        self.bindings.iter().any(|(k, a)| *k == key && *a == action)
+    }
+
+    pub fn keys(&self, action: Action) -> Vec<Key> {
+        self.bindings
+            .iter()
+            .filter(|(_, a)| *a == action)
+            .map(|(k, _)| *k)
+            .collect()
+    }
+
+    /// Keys bound to `action` for display, e.g. `End/f`; empty if unbound.
+    pub fn label(&self, action: Action) -> String {
+        let keys: Vec<String> = self.keys(action).iter().map(Key::to_string).collect();
+        keys.join("/")
+    }
+
+    /// Compact `key:label` summary of the global actions for the header.
+    pub fn summary(&self) -> String {
+        ACTIONS
+            .iter()
+            .filter_map(|i| {
+                let short = i.short?;
+                let keys = self.label(i.action);
+                (!keys.is_empty()).then(|| format!("{keys}:{short}"))
+            })
+            .collect::<Vec<_>>()
+            .join("  ")
+    }
+}
@@ -0,0 +1,54 @@
+//! Serial session layer behind the lazyserial TUI.
+//!
+//! [`Session`] opens a local port, a network serial server or a Bluetooth
+//! serial device ([`bluetooth`], [`ble`]) on a worker thread and reports
+//! everything that happens as [`SerialEvent`]s. The remaining modules are the
+//! building blocks the TUI layers on top: session recording and replay ([`capture`]), text decoding
+//! ([`encoding`]), line reassembly ([`linebuf`]), a pty loopback device
+//! for testing without hardware (`loopback`, Unix only), an in-memory
+//! transport for driving the worker in tests ([`mock`]), port lock files
+//! ([`portlock`]), COBS/SLIP frame
+//! reassembly ([`framing`]), board reset sequences ([`reset`]), checksums
+//! and CRCs ([`checksum`]), loopback bit error testing ([`linktest`]),
+//! XMODEM/YMODEM file transfer ([`transfer`]),
+//! STM32 UART bootloader flashing ([`stm32`]), G-code streaming
+//! ([`gcode`]), defmt log decoding ([`defmt`]), the packet list's
+//! protocol decoders ([`decoder`]) and their runtime-loaded plugins
+//! ([`plugin`]), Modbus RTU frame decoding
+//! ([`modbus`]), NMEA GPS sentence parsing ([`nmea`]), ESP panic backtrace
+//! symbolization ([`espcrash`]) and pcapng files ([`pcapng`]).
+
+pub mod ble;
+pub mod bluetooth;
+pub mod capture;
+pub mod checksum;
+pub mod decoder;
+pub mod defmt;
+pub mod encoding;
+pub mod espcrash;
+pub mod framing;
+pub mod gcode;
+pub mod linebuf;
+pub mod linktest;
+#[cfg(unix)]
+pub mod loopback;
+pub mod mock;
+pub mod modbus;
+pub mod nmea;
+pub mod pcapng;
+pub mod plugin;
+pub mod portlock;
+pub mod reset;
+pub mod rfc2217;
+pub mod serial;
+pub mod session;
+pub mod stm32;
+pub mod transfer;
+#[cfg(windows)]
+mod winports;
+
+pub use serial::{
+    Direction, IoStats, LineErrors, LineStep, ModemLines, OpenFailure, Overflow, Parity,
+    PortSettings, Rs485, SerialEvent, SerialHandle, SerialTransport, TxProgress,
+};
+pub use session::Session;
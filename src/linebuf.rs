@@ -0,0 +1,105 @@
+use std::time::{Duration, Instant};
+
+/// Reassembles decoded text into display lines across read boundaries.
+///
+/// `\n`, `\r` and `\r\n` all end a line (a CRLF split across two reads
+/// still counts once). Text without a terminator is held until more data
+/// arrives or `flush_after` passes, so prompts like `login: ` still show up.
+pub struct LineAssembler {
+    partial: String,
+    last_was_cr: bool,
+    last_data: Instant,
+    flush_after: Duration,
+    /// Leave each line's terminator on it, for showing it.
+    keep_terminators: bool,
+}
+
+impl LineAssembler {
+    pub fn new(flush_after: Duration) -> Self {
+        Self {
+            partial: String::new(),
+            last_was_cr: false,
+            last_data: Instant::now(),
+            flush_after,
+            keep_terminators: false,
+        }
+    }
+
+    /// Leaves `\r`, `\n` or `\r\n` on the end of each line from now on. A
+    /// CR at the end of the data then holds its line back until the next
+    /// data or `flush_after` shows whether an LF follows.
+    pub fn set_keep_terminators(&mut self, keep: bool) {
+        self.keep_terminators = keep;
+    }
+
+    pub fn flush_after(&self) -> Duration {
+        self.flush_after
+    }
+
+    /// Appends text and returns every line it completed, without terminators.
+    pub fn push(&mut self, text: &str) -> Vec<String> {
+        let mut lines = Vec::new();
+        if !text.is_empty() {
+            self.last_data = Instant::now();
+        }
+        let mut rest = text;
+        if self.last_was_cr && !self.partial.is_empty() && !text.is_empty() {
+            // A kept CR held back for the LF that may follow it.
+            if let Some(after) = rest.strip_prefix('\n') {
+                self.partial.push('\n');
+                rest = after;
+            }
+            lines.push(std::mem::take(&mut self.partial));
+            self.last_was_cr = false;
+        }
+        // Whole runs up to each terminator are copied at once.
+        while let Some(end) = rest.find(['\r', '\n']) {
+            let terminator = rest.as_bytes()[end];
+            if self.keep_terminators {
+                self.partial.push_str(&rest[..=end]);
+                rest = &rest[end + 1..];
+                if terminator == b'\r' {
+                    if let Some(after) = rest.strip_prefix('\n') {
+                        self.partial.push('\n');
+                        rest = after;
+                    } else if rest.is_empty() {
+                        self.last_was_cr = true;
+                        return lines;
+                    }
+                }
+                lines.push(std::mem::take(&mut self.partial));
+                self.last_was_cr = false;
+                continue;
+            }
+            let skip = terminator == b'\n' && end == 0 && self.last_was_cr;
+            if !skip {
+                self.partial.push_str(&rest[..end]);
+                lines.push(std::mem::take(&mut self.partial));
+            }
+            self.last_was_cr = terminator == b'\r';
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() {
+            self.partial.push_str(rest);
+            self.last_was_cr = false;
+        }
+        lines
+    }
+
+    /// Returns the pending partial line once it has sat idle long enough.
+    pub fn flush_due(&mut self) -> Option<String> {
+        if self.partial.is_empty() || self.last_data.elapsed() < self.flush_after {
+            return None;
+        }
+        self.flush()
+    }
+
+    /// Returns the pending partial line unconditionally.
+    pub fn flush(&mut self) -> Option<String> {
+        if self.partial.is_empty() {
+            return None;
+        }
+        self.last_was_cr = false;
+        Some(std::mem::take(&mut self.partial))
+    }
+}
@@ -0,0 +1,263 @@
+//! Link testing: a known byte pattern is sent through a loopback and what
+//! comes back is checked against it, counting corrupted bits and dropped
+//! bytes.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+/// Bytes that must line up again before a mismatch is taken to be bytes
+/// lost rather than corrupted.
+const SYNC: usize = 8;
+/// Furthest ahead a lost stretch is looked for.
+const MAX_SKIP: usize = 4096;
+/// Bytes sent ahead of what has come back; beyond this sending waits, so a
+/// dead loopback doesn't run the whole test into the void.
+const MAX_IN_FLIGHT: usize = 64 * 1024;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Pattern {
+    /// PRBS-15 (x^15 + x^14 + 1), the usual bit error test sequence.
+    #[default]
+    Prbs15,
+    /// 0x00 to 0xff over and over, easy to read in a hex dump.
+    Counting,
+}
+
+impl Pattern {
+    /// Also the name used in the config file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Pattern::Prbs15 => "prbs15",
+            Pattern::Counting => "counting",
+        }
+    }
+}
+
+/// Produces the bytes of a [`Pattern`].
+#[derive(Clone, Debug)]
+pub struct Generator {
+    pattern: Pattern,
+    state: u16,
+    count: u8,
+}
+
+impl Generator {
+    pub fn new(pattern: Pattern) -> Self {
+        Self {
+            pattern,
+            state: 0x7fff,
+            count: 0,
+        }
+    }
+
+    pub fn next_byte(&mut self) -> u8 {
+        match self.pattern {
+            Pattern::Counting => {
+                let b = self.count;
+                self.count = self.count.wrapping_add(1);
+                b
+            }
+            Pattern::Prbs15 => {
+                let mut b = 0;
+                for _ in 0..8 {
+                    let bit = ((self.state >> 14) ^ (self.state >> 13)) & 1;
+                    self.state = ((self.state << 1) | bit) & 0x7fff;
+                    b = (b << 1) | bit as u8;
+                }
+                b
+            }
+        }
+    }
+}
+
+/// Counts so far; see [`LinkTest::report`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    pub sent: usize,
+    /// Bytes that came back, right or not.
+    pub received: usize,
+    pub bit_errors: u64,
+    /// Bytes that came back with bits flipped.
+    pub bad_bytes: usize,
+    /// Sent bytes that never came back.
+    pub dropped: usize,
+    /// Bytes that came back that were never sent.
+    pub extra: usize,
+}
+
+impl Report {
+    /// Bit errors per bit received, once something has been.
+    pub fn bit_error_rate(&self) -> Option<f64> {
+        (self.received > 0).then(|| self.bit_errors as f64 / (self.received as f64 * 8.0))
+    }
+}
+
+/// A test run over `total` bytes.
+pub struct LinkTest {
+    pub pattern: Pattern,
+    pub total: usize,
+    generator: Generator,
+    /// Sent bytes not yet matched with what came back.
+    in_flight: VecDeque<u8>,
+    /// Received bytes waiting on enough more to tell a lost stretch from
+    /// corruption.
+    pending: Vec<u8>,
+    report: Report,
+    pub started: Instant,
+    /// When data last came back, or the test started.
+    last_rx: Instant,
+    finished: Option<Instant>,
+}
+
+impl LinkTest {
+    pub fn new(pattern: Pattern, total: usize) -> Self {
+        let now = Instant::now();
+        Self {
+            pattern,
+            total,
+            generator: Generator::new(pattern),
+            in_flight: VecDeque::new(),
+            pending: Vec::new(),
+            report: Report::default(),
+            started: now,
+            last_rx: now,
+            finished: None,
+        }
+    }
+
+    /// Up to `max` more bytes of the pattern to send, fewer while too much
+    /// is still out.
+    pub fn next_chunk(&mut self, max: usize) -> Vec<u8> {
+        let room = MAX_IN_FLIGHT.saturating_sub(self.in_flight.len());
+        let len = max.min(room).min(self.total - self.report.sent);
+        let chunk: Vec<u8> = (0..len).map(|_| self.generator.next_byte()).collect();
+        self.in_flight.extend(&chunk);
+        self.report.sent += len;
+        chunk
+    }
+
+    pub fn on_data(&mut self, data: &[u8]) {
+        self.last_rx = Instant::now();
+        self.report.received += data.len();
+        self.pending.extend_from_slice(data);
+        self.verify(false);
+    }
+
+    /// Ends the run once everything has been sent and either all of it came
+    /// back or nothing has for `idle`; whatever is still out is counted as
+    /// dropped. Returns whether the run is over.
+    pub fn finish_if_idle(&mut self, idle: Duration) -> bool {
+        if self.finished.is_some() {
+            return true;
+        }
+        let all_sent = self.report.sent == self.total;
+        if all_sent && (self.in_flight.is_empty() || self.last_rx.elapsed() >= idle) {
+            self.finish();
+        }
+        self.finished.is_some()
+    }
+
+    /// Stops the run, counting bytes still out as dropped.
+    pub fn finish(&mut self) {
+        self.verify(true);
+        self.report.dropped += self.in_flight.len();
+        self.in_flight.clear();
+        self.finished.get_or_insert_with(Instant::now);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished.is_some()
+    }
+
+    pub fn report(&self) -> &Report {
+        &self.report
+    }
+
+    /// Received bytes per second, up to the end of the run.
+    pub fn throughput(&self) -> f64 {
+        let end = self.finished.unwrap_or_else(Instant::now);
+        let secs = end.duration_since(self.started).as_secs_f64();
+        if secs > 0.0 {
+            self.report.received as f64 / secs
+        } else {
+            0.0
+        }
+    }
+
+    /// Matches received bytes against what was sent. A mismatch followed
+    /// by `SYNC` bytes found further on in the sent data means the bytes in
+    /// between were lost; otherwise the byte was corrupted. With `flush`,
+    /// bytes too few to decide are taken as corrupted.
+    fn verify(&mut self, flush: bool) {
+        let mut at = 0;
+        while at < self.pending.len() {
+            let Some(&expected) = self.in_flight.front() else {
+                self.report.extra += self.pending.len() - at;
+                at = self.pending.len();
+                break;
+            };
+            let got = self.pending[at];
+            if got == expected {
+                self.in_flight.pop_front();
+                at += 1;
+                continue;
+            }
+            let window = &self.pending[at..];
+            if window.len() < SYNC && !flush {
+                break;
+            }
+            if let Some(skip) = self.find_skip(window) {
+                self.report.dropped += skip;
+                self.in_flight.drain(..skip);
+                continue;
+            }
+            self.report.bit_errors += u64::from((got ^ expected).count_ones());
+            self.report.bad_bytes += 1;
+            self.in_flight.pop_front();
+            at += 1;
+        }
+        self.pending.drain(..at);
+    }
+
+    /// How many sent bytes to skip for `window` to line up with the rest.
+    fn find_skip(&self, window: &[u8]) -> Option<usize> {
+        if window.len() < SYNC {
+            return None;
+        }
+        let sync = &window[..SYNC];
+        let last = self.in_flight.len().checked_sub(SYNC)?.min(MAX_SKIP);
+        (1..=last).find(|&skip| self.in_flight.range(skip..skip + SYNC).eq(sync.iter()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn link_test_counts_bit_errors_and_dropped_bytes() {
+        let mut test = LinkTest::new(Pattern::Prbs15, 4000);
+        let mut sent = Vec::new();
+        while sent.len() < 4000 {
+            sent.extend(test.next_chunk(1024));
+        }
+        let mut echoed = sent.clone();
+        echoed[100] ^= 0b0000_0101;
+        echoed.drain(2000..2010);
+        echoed.truncate(3980);
+        for chunk in echoed.chunks(7) {
+            test.on_data(chunk);
+        }
+        test.finish();
+
+        let report = test.report();
+        assert_eq!(report.sent, 4000);
+        assert_eq!(report.received, 3980);
+        assert_eq!((report.bit_errors, report.bad_bytes), (2, 1));
+        assert_eq!(report.dropped, 20);
+        assert_eq!(report.extra, 0);
+    }
+}
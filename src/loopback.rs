@@ -0,0 +1,98 @@
+//! A virtual serial device backed by pseudo terminals, for trying the TUI
+//! and exercising the serial worker without hardware.
+//!
+//! Two pty pairs are joined by a thread. The device end
+//! ([`VirtualDevice::path`]) is opened like any port. Whatever is written
+//! to it comes back (a loopback) until another program opens the peer end
+//! ([`VirtualDevice::peer_path`]); from then on bytes are passed between
+//! the two ends instead, so a script or terminal on the peer plays the
+//! device.
+
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serialport::{SerialPort, TTYPort};
+
+/// How long each end is polled per pass.
+const POLL: Duration = Duration::from_millis(10);
+
+pub struct VirtualDevice {
+    path: String,
+    peer_path: String,
+    stop: Arc<AtomicBool>,
+}
+
+impl VirtualDevice {
+    pub fn create() -> Result<Self> {
+        let (device, device_path) = open_pair()?;
+        let (peer, peer_path) = open_pair()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&stop);
+        thread::spawn(move || run(device, peer, &flag));
+        Ok(Self {
+            path: device_path,
+            peer_path,
+            stop,
+        })
+    }
+
+    /// The end to open as a port.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The end another program opens to play the device.
+    pub fn peer_path(&self) -> &str {
+        &self.peer_path
+    }
+}
+
+impl Drop for VirtualDevice {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// A pty master and the path of its slave. The slave is closed again so
+/// the master sees a hangup whenever nobody has it open.
+fn open_pair() -> Result<(TTYPort, String)> {
+    let (mut master, slave) = TTYPort::pair().context("create pty pair")?;
+    let path = slave.name().context("pty has no path")?;
+    drop(slave);
+    master.set_timeout(POLL)?;
+    Ok((master, path))
+}
+
+fn run(mut device: TTYPort, mut peer: TTYPort, stop: &AtomicBool) {
+    let mut buf = [0u8; 4096];
+    let mut peer_open = false;
+    while !stop.load(Ordering::Relaxed) {
+        match device.read(&mut buf) {
+            // Write errors mean the other end isn't open; the bytes are
+            // dropped like on a disconnected line.
+            Ok(n) if peer_open => {
+                peer.write_all(&buf[..n]).ok();
+            }
+            Ok(n) => {
+                device.write_all(&buf[..n]).ok();
+            }
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => {}
+            Err(_) => thread::sleep(POLL),
+        }
+        match peer.read(&mut buf) {
+            Ok(n) => {
+                peer_open = true;
+                device.write_all(&buf[..n]).ok();
+            }
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => peer_open = true,
+            Err(_) => {
+                peer_open = false;
+                thread::sleep(POLL);
+            }
+        }
+    }
+}
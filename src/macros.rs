@@ -0,0 +1,109 @@
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+
+pub const MACRO_COUNT: usize = 12;
+
+/// Canned strings bound to F1..F12, sent verbatim when the key is pressed.
+#[derive(Default)]
+pub struct Macros {
+    slots: [String; MACRO_COUNT],
+}
+
+impl Macros {
+    /// Builds the table from the config's `[macros]` section (`F1 = "..."`).
+    pub fn from_config(map: &BTreeMap<String, String>) -> Result<Self> {
+        let mut macros = Self::default();
+        macros.extend(map)?;
+        Ok(macros)
+    }
+
+    /// Binds every key in `map`, leaving the other slots as they are.
+    pub fn extend(&mut self, map: &BTreeMap<String, String>) -> Result<()> {
+        for (key, text) in map {
+            let Some(idx) = slot_index(key) else {
+                bail!("macros: unknown key {key:?} (expected F1..F12)");
+            };
+            self.slots[idx] = text.clone();
+        }
+        Ok(())
+    }
+
+    /// `idx` is zero-based: 0 is F1.
+    pub fn get(&self, idx: usize) -> Option<&str> {
+        self.slots
+            .get(idx)
+            .map(String::as_str)
+            .filter(|s| !s.is_empty())
+    }
+
+    pub fn set(&mut self, idx: usize, text: String) {
+        if let Some(slot) = self.slots.get_mut(idx) {
+            *slot = text;
+        }
+    }
+}
+
+pub fn key_name(idx: usize) -> String {
+    format!("F{}", idx + 1)
+}
+
+fn slot_index(key: &str) -> Option<usize> {
+    let n: usize = key.strip_prefix(['F', 'f'])?.parse().ok()?;
+    (1..=MACRO_COUNT).contains(&n).then(|| n - 1)
+}
+
+/// Renders control characters as `\r`, `\n`, `\t`, `\xNN` for editing.
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\r' => out.push_str("\\r"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 || c as u32 == 0x7f => {
+                out.push_str(&format!("\\x{:02x}", c as u32));
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Inverse of [`escape`]. Unknown escapes are kept literally.
+pub fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('r') => out.push('\r'),
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('0') => out.push('\0'),
+            Some('e') => out.push('\x1b'),
+            Some('\\') => out.push('\\'),
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                match u8::from_str_radix(&hex, 16) {
+                    // Macros are sent as UTF-8, so only ASCII bytes round-trip.
+                    Ok(b) if hex.len() == 2 && b < 0x80 => out.push(b as char),
+                    _ => {
+                        out.push_str("\\x");
+                        out.push_str(&hex);
+                    }
+                }
+            }
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            }
+            None => out.push('\\'),
+        }
+    }
+    out
+}
@@ -1,9 +1,63 @@
+mod alert;
+mod ansi;
+mod api;
 mod app;
+mod at;
+mod autoresponse;
+mod batch;
+mod bookmark;
+mod bridge;
+mod cli;
+mod clipboard;
+mod compare;
+mod compose;
+mod config;
+mod direction;
+mod export;
+mod filter;
+mod headless;
+mod hexdump;
+mod highlight;
+mod hotplug;
+mod inspect;
+mod keymap;
+mod macros;
+mod mqtt;
+mod palette;
+mod plot;
+mod passthrough;
+mod ping;
+mod profile;
+mod recent;
 mod ui;
-mod serial;
+mod script;
+mod state;
+mod theme;
+mod toast;
+mod scrollback;
+mod shell;
 
 use anyhow::Result;
 
 fn main() -> Result<()> {
-    app::run()
+    let args = cli::Args::parse(std::env::args().skip(1))?;
+    if args.help {
+        print!("{}", cli::USAGE);
+        return Ok(());
+    }
+    if let Some(script) = &args.run {
+        // 1 is a failed step, 2 a script or port that couldn't be used.
+        match batch::run(script, &args) {
+            Ok(true) => return Ok(()),
+            Ok(false) => std::process::exit(1),
+            Err(e) => {
+                eprintln!("Error: {e:#}");
+                std::process::exit(2);
+            }
+        }
+    }
+    if args.no_tui {
+        return headless::run(&args);
+    }
+    app::run(args)
 }
@@ -0,0 +1,169 @@
+//! An in-memory [`SerialTransport`] for driving the serial worker in tests.
+//!
+//! [`MockTransport::pair`] returns the transport, handed to
+//! [`Session::with_transport`](crate::Session::with_transport), and a
+//! [`MockDevice`] that plays the other end: it feeds bytes to the worker,
+//! collects what the worker writes and the breaks it sends, sets the modem
+//! lines and can fail or disconnect the line.
+
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+
+use crate::serial::{ModemLines, SerialTransport};
+
+/// State both halves see.
+#[derive(Default)]
+struct Shared {
+    dtr: AtomicBool,
+    rts: AtomicBool,
+    fail_writes: AtomicBool,
+    /// `None` reports modem lines as unsupported, like a pty.
+    modem_lines: Mutex<Option<ModemLines>>,
+    breaks: Mutex<Vec<Duration>>,
+}
+
+/// The worker's end.
+pub struct MockTransport {
+    incoming: UnboundedReceiver<io::Result<Vec<u8>>>,
+    /// Rest of a chunk that didn't fit the last read.
+    pending: Vec<u8>,
+    written: Sender<Vec<u8>>,
+    shared: Arc<Shared>,
+}
+
+/// The device's end.
+pub struct MockDevice {
+    outgoing: UnboundedSender<io::Result<Vec<u8>>>,
+    written: Receiver<Vec<u8>>,
+    shared: Arc<Shared>,
+}
+
+impl MockTransport {
+    pub fn pair() -> (MockTransport, MockDevice) {
+        let (outgoing, incoming) = tokio::sync::mpsc::unbounded_channel();
+        let (written_tx, written_rx) = mpsc::channel();
+        let shared = Arc::new(Shared::default());
+        let transport = MockTransport {
+            incoming,
+            pending: Vec::new(),
+            written: written_tx,
+            shared: Arc::clone(&shared),
+        };
+        let device = MockDevice {
+            outgoing,
+            written: written_rx,
+            shared,
+        };
+        (transport, device)
+    }
+}
+
+impl SerialTransport for MockTransport {
+    async fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.is_empty() {
+            // `recv` is cancel-safe and nothing else is touched before it
+            // completes, so neither is this.
+            match self.incoming.recv().await {
+                Some(chunk) => self.pending = chunk?,
+                None => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.pending.len());
+        buf[..n].copy_from_slice(&self.pending[..n]);
+        self.pending.drain(..n);
+        Ok(n)
+    }
+
+    async fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+        if self.shared.fail_writes.load(Ordering::Relaxed) {
+            return Err(io::Error::other("mock write failure"));
+        }
+        self.written
+            .send(data.to_vec())
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "mock device dropped"))
+    }
+
+    async fn set_dtr(&mut self, level: bool) -> io::Result<()> {
+        self.shared.dtr.store(level, Ordering::Relaxed);
+        Ok(())
+    }
+
+    async fn set_rts(&mut self, level: bool) -> io::Result<()> {
+        self.shared.rts.store(level, Ordering::Relaxed);
+        Ok(())
+    }
+
+    async fn modem_lines(&mut self) -> io::Result<ModemLines> {
+        let lines = *self.shared.modem_lines.lock().unwrap_or_else(|e| e.into_inner());
+        lines.ok_or_else(|| io::Error::new(io::ErrorKind::Unsupported, "no modem lines"))
+    }
+
+    async fn drain(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    async fn send_break(&mut self, duration: Duration) -> io::Result<()> {
+        self.shared.breaks.lock().unwrap_or_else(|e| e.into_inner()).push(duration);
+        Ok(())
+    }
+}
+
+impl MockDevice {
+    /// Bytes for the worker to read, as one chunk.
+    pub fn send(&self, data: &[u8]) {
+        self.outgoing.send(Ok(data.to_vec())).ok();
+    }
+
+    /// Makes the worker's next read fail with `kind`.
+    pub fn fail_read(&self, kind: io::ErrorKind) {
+        self.outgoing
+            .send(Err(io::Error::new(kind, "mock read failure")))
+            .ok();
+    }
+
+    /// Makes writes fail until turned off again.
+    pub fn fail_writes(&self, fail: bool) {
+        self.shared.fail_writes.store(fail, Ordering::Relaxed);
+    }
+
+    pub fn set_modem_lines(&self, lines: Option<ModemLines>) {
+        *self.shared.modem_lines.lock().unwrap_or_else(|e| e.into_inner()) = lines;
+    }
+
+    pub fn dtr(&self) -> bool {
+        self.shared.dtr.load(Ordering::Relaxed)
+    }
+
+    pub fn rts(&self) -> bool {
+        self.shared.rts.load(Ordering::Relaxed)
+    }
+
+    /// Durations of the breaks sent so far.
+    pub fn breaks(&self) -> Vec<Duration> {
+        self.shared.breaks.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Everything the worker wrote within `timeout`, stopping early once
+    /// `len` bytes have arrived.
+    pub fn received(&self, len: usize, timeout: Duration) -> Vec<u8> {
+        let deadline = Instant::now() + timeout;
+        let mut out = Vec::new();
+        while out.len() < len {
+            let left = deadline.saturating_duration_since(Instant::now());
+            match self.written.recv_timeout(left) {
+                Ok(chunk) => out.extend(chunk),
+                Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        out
+    }
+
+    /// Hangs up: the worker's next read sees end of stream, as when a USB
+    /// adapter is unplugged or a server closes the connection.
+    pub fn disconnect(self) {}
+}
@@ -0,0 +1,416 @@
+//! Modbus RTU frame splitting and decoding.
+//!
+//! RTU frames carry no delimiter: they are separated by at least 3.5
+//! character times of silence and end in a CRC-16. [`Splitter`] cuts a
+//! byte stream at the first length the function code allows whose CRC
+//! checks out, falling back to the silence gap for anything it can't size.
+
+use std::time::{Duration, Instant};
+
+use crate::decoder::{hex, Column, Decoder, Record};
+use crate::Direction;
+
+/// Silence after which pending bytes count as a whole frame. Longer than
+/// 3.5 characters at any common baud rate, since bytes reach us in chunks.
+const FRAME_GAP: Duration = Duration::from_millis(20);
+/// Longest RTU frame the spec allows.
+const MAX_FRAME: usize = 256;
+/// Register values shown before the rest are elided.
+const MAX_VALUES: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Request,
+    Response,
+    Exception,
+    /// Function code we can't size, or bytes that didn't form a frame.
+    Unknown,
+}
+
+impl Kind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Request => "req",
+            Kind::Response => "resp",
+            Kind::Exception => "exc",
+            Kind::Unknown => "?",
+        }
+    }
+}
+
+/// One RTU frame, CRC included.
+#[derive(Clone, Debug)]
+pub struct Frame {
+    pub direction: Direction,
+    pub at: Instant,
+    pub raw: Vec<u8>,
+    pub kind: Kind,
+    pub crc_ok: bool,
+}
+
+impl Frame {
+    fn new(direction: Direction, at: Instant, raw: Vec<u8>) -> Self {
+        let crc_ok = raw.len() >= 4 && check_crc(&raw);
+        // A short coil read fits both shapes; what we send is the request.
+        let preferred = match direction {
+            Direction::Tx => Kind::Request,
+            Direction::Rx => Kind::Response,
+        };
+        let kinds: Vec<Kind> = candidates(&raw, direction)
+            .into_iter()
+            .filter(|&(len, _)| crc_ok && len == raw.len())
+            .map(|(_, kind)| kind)
+            .collect();
+        let kind = if kinds.contains(&preferred) {
+            preferred
+        } else {
+            kinds.first().copied().unwrap_or(Kind::Unknown)
+        };
+        Self {
+            direction,
+            at,
+            raw,
+            kind,
+            crc_ok,
+        }
+    }
+
+    pub fn unit(&self) -> Option<u8> {
+        self.raw.first().copied()
+    }
+
+    pub fn function(&self) -> Option<u8> {
+        self.raw.get(1).copied()
+    }
+
+    /// Function code and name, e.g. `03 Read Holding Registers`. Exceptions
+    /// show the function they answer.
+    pub fn function_name(&self) -> String {
+        match self.function() {
+            Some(fc) => format!("{:02} {}", fc & 0x7f, function_name(fc & 0x7f)),
+            None => "-".to_string(),
+        }
+    }
+
+    fn into_record(self) -> Record {
+        let unit = self.unit().map_or("-".to_string(), |u| u.to_string());
+        Record {
+            direction: self.direction,
+            at: self.at,
+            fields: vec![unit, self.kind.name().to_string(), self.function_name()],
+            valid: Some(self.crc_ok),
+            details: self.details(),
+            raw: self.raw,
+        }
+    }
+
+    /// Addresses and values carried by the frame, or a hex dump of it when
+    /// it didn't decode.
+    pub fn details(&self) -> String {
+        if self.crc_ok {
+            if let Some(details) = self.decode() {
+                return details;
+            }
+        }
+        format!("{} bytes: {}", self.raw.len(), hex(&self.raw))
+    }
+
+    fn decode(&self) -> Option<String> {
+        let body = &self.raw[2..self.raw.len() - 2];
+        let fc = self.function()?;
+        let word = |i: usize| -> Option<u16> {
+            Some(u16::from_be_bytes([*body.get(i)?, *body.get(i + 1)?]))
+        };
+        Some(match (self.kind, fc) {
+            (Kind::Exception, _) => {
+                let code = *body.first()?;
+                format!("exception {code:#04x}: {}", exception_name(code))
+            }
+            (Kind::Request, 1..=4) => format!("addr {:#06x} count {}", word(0)?, word(2)?),
+            (Kind::Response, 1 | 2) => format!("bits {}", bits(body.get(1..)?)),
+            (Kind::Response, 3 | 4) => format!("regs {}", registers(body.get(1..)?)),
+            (_, 5) => {
+                let state = match word(2)? {
+                    0xff00 => "on".to_string(),
+                    0x0000 => "off".to_string(),
+                    other => format!("{other:#06x}"),
+                };
+                format!("addr {:#06x} {state}", word(0)?)
+            }
+            (_, 6) => format!("addr {:#06x} value {}", word(0)?, word(2)?),
+            (Kind::Request, 15) => {
+                format!("addr {:#06x} count {} bits {}", word(0)?, word(2)?, bits(body.get(5..)?))
+            }
+            (Kind::Request, 16) => format!(
+                "addr {:#06x} count {} regs {}",
+                word(0)?,
+                word(2)?,
+                registers(body.get(5..)?)
+            ),
+            (Kind::Response, 15 | 16) => format!("addr {:#06x} count {}", word(0)?, word(2)?),
+            (_, 22) => format!(
+                "addr {:#06x} and {:#06x} or {:#06x}",
+                word(0)?,
+                word(2)?,
+                word(4)?
+            ),
+            (Kind::Request, 23) => format!(
+                "read {:#06x} count {}, write {:#06x} count {} regs {}",
+                word(0)?,
+                word(2)?,
+                word(4)?,
+                word(6)?,
+                registers(body.get(9..)?)
+            ),
+            (Kind::Response, 23) => format!("regs {}", registers(body.get(1..)?)),
+            _ => return None,
+        })
+    }
+}
+
+/// Cuts one direction's byte stream into frames.
+pub struct Splitter {
+    direction: Direction,
+    pending: Vec<u8>,
+    started: Instant,
+    last: Instant,
+}
+
+impl Splitter {
+    pub fn new(direction: Direction) -> Self {
+        let now = Instant::now();
+        Self {
+            direction,
+            pending: Vec::new(),
+            started: now,
+            last: now,
+        }
+    }
+
+    /// Appends bytes seen at `now` and returns the frames they completed.
+    pub fn push(&mut self, bytes: &[u8], now: Instant) -> Vec<Frame> {
+        let mut frames: Vec<Frame> = self.flush_due(now).into_iter().collect();
+        if self.pending.is_empty() {
+            self.started = now;
+        }
+        self.pending.extend_from_slice(bytes);
+        self.last = now;
+        loop {
+            let found = candidates(&self.pending, self.direction)
+                .into_iter()
+                .filter(|&(len, _)| len <= self.pending.len())
+                .find(|&(len, _)| check_crc(&self.pending[..len]));
+            let len = match found {
+                Some((len, _)) => len,
+                None if self.pending.len() > MAX_FRAME => MAX_FRAME,
+                None => break,
+            };
+            let raw: Vec<u8> = self.pending.drain(..len).collect();
+            frames.push(Frame::new(self.direction, self.started, raw));
+        }
+        frames
+    }
+
+    /// Takes whatever is pending once the line has been quiet for a frame
+    /// gap: a frame the function code couldn't size, or a damaged one.
+    pub fn flush_due(&mut self, now: Instant) -> Option<Frame> {
+        if self.pending.is_empty() || now.duration_since(self.last) < FRAME_GAP {
+            return None;
+        }
+        let raw = std::mem::take(&mut self.pending);
+        Some(Frame::new(self.direction, self.started, raw))
+    }
+}
+
+/// Splits both directions into frames for the packet list.
+pub struct RtuDecoder {
+    rx: Splitter,
+    tx: Splitter,
+}
+
+impl Default for RtuDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RtuDecoder {
+    pub fn new() -> Self {
+        Self {
+            rx: Splitter::new(Direction::Rx),
+            tx: Splitter::new(Direction::Tx),
+        }
+    }
+}
+
+const COLUMNS: &[Column] = &[
+    Column {
+        name: "unit",
+        width: 3,
+    },
+    Column {
+        name: "kind",
+        width: 4,
+    },
+    Column {
+        name: "function",
+        width: 33,
+    },
+];
+
+impl Decoder for RtuDecoder {
+    fn title(&self) -> &'static str {
+        "Modbus RTU"
+    }
+
+    fn columns(&self) -> &'static [Column] {
+        COLUMNS
+    }
+
+    fn push(&mut self, direction: Direction, bytes: &[u8], now: Instant) -> Vec<Record> {
+        let frames = match direction {
+            Direction::Rx => self.rx.push(bytes, now),
+            Direction::Tx => self.tx.push(bytes, now),
+        };
+        frames.into_iter().map(Frame::into_record).collect()
+    }
+
+    fn flush_due(&mut self, now: Instant) -> Vec<Record> {
+        [self.rx.flush_due(now), self.tx.flush_due(now)]
+            .into_iter()
+            .flatten()
+            .map(Frame::into_record)
+            .collect()
+    }
+}
+
+/// Builds a read request (function 1 to 4) with its CRC.
+pub fn read_request(unit: u8, function: u8, addr: u16, count: u16) -> Vec<u8> {
+    let mut frame = vec![unit, function];
+    frame.extend_from_slice(&addr.to_be_bytes());
+    frame.extend_from_slice(&count.to_be_bytes());
+    frame.extend_from_slice(&crc16(&frame).to_le_bytes());
+    frame
+}
+
+/// CRC-16/MODBUS; sent low byte first.
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0xffffu16;
+    for &byte in data {
+        crc ^= byte as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xa001 } else { crc >> 1 };
+        }
+    }
+    crc
+}
+
+fn check_crc(frame: &[u8]) -> bool {
+    let Some(split) = frame.len().checked_sub(2) else {
+        return false;
+    };
+    let (body, crc) = frame.split_at(split);
+    crc16(body) == u16::from_le_bytes([crc[0], crc[1]])
+}
+
+/// Frame lengths the function code at `buf[1]` allows, shortest first,
+/// with what the frame would be at that length. Functions whose response
+/// echoes the request count as requests when sent and responses when
+/// received.
+fn candidates(buf: &[u8], direction: Direction) -> Vec<(usize, Kind)> {
+    let Some(&fc) = buf.get(1) else {
+        return Vec::new();
+    };
+    let echo = match direction {
+        Direction::Tx => Kind::Request,
+        Direction::Rx => Kind::Response,
+    };
+    let counted = |at: usize, extra: usize| buf.get(at).map(|&n| n as usize + extra);
+    let mut out = Vec::new();
+    if fc & 0x80 != 0 {
+        out.push((5, Kind::Exception));
+        return out;
+    }
+    match fc {
+        1..=4 => {
+            out.push((8, Kind::Request));
+            out.extend(counted(2, 5).map(|len| (len, Kind::Response)));
+        }
+        5 | 6 | 8 => out.push((8, echo)),
+        7 => out.extend([(4, Kind::Request), (5, Kind::Response)]),
+        11 => out.extend([(4, Kind::Request), (8, Kind::Response)]),
+        15 | 16 => {
+            out.push((8, Kind::Response));
+            out.extend(counted(6, 9).map(|len| (len, Kind::Request)));
+        }
+        17 => {
+            out.push((4, Kind::Request));
+            out.extend(counted(2, 5).map(|len| (len, Kind::Response)));
+        }
+        22 => out.push((10, echo)),
+        23 => {
+            out.extend(counted(2, 5).map(|len| (len, Kind::Response)));
+            out.extend(counted(10, 13).map(|len| (len, Kind::Request)));
+        }
+        _ => {}
+    }
+    out.sort_by_key(|&(len, _)| len);
+    out
+}
+
+fn function_name(fc: u8) -> &'static str {
+    match fc {
+        1 => "Read Coils",
+        2 => "Read Discrete Inputs",
+        3 => "Read Holding Registers",
+        4 => "Read Input Registers",
+        5 => "Write Single Coil",
+        6 => "Write Single Register",
+        7 => "Read Exception Status",
+        8 => "Diagnostics",
+        11 => "Get Comm Event Counter",
+        15 => "Write Multiple Coils",
+        16 => "Write Multiple Registers",
+        17 => "Report Server ID",
+        22 => "Mask Write Register",
+        23 => "Read/Write Multiple Registers",
+        43 => "Encapsulated Interface",
+        _ => "Unknown",
+    }
+}
+
+fn exception_name(code: u8) -> &'static str {
+    match code {
+        1 => "illegal function",
+        2 => "illegal data address",
+        3 => "illegal data value",
+        4 => "server device failure",
+        5 => "acknowledge",
+        6 => "server device busy",
+        8 => "memory parity error",
+        0x0a => "gateway path unavailable",
+        0x0b => "gateway target failed to respond",
+        _ => "unknown",
+    }
+}
+
+/// Coil bytes as bits, lowest address first.
+fn bits(data: &[u8]) -> String {
+    let bits: Vec<String> = data
+        .iter()
+        .take(MAX_VALUES / 2)
+        .map(|b| (0..8).map(|i| if b >> i & 1 == 1 { '1' } else { '0' }).collect())
+        .collect();
+    let more = if data.len() > MAX_VALUES / 2 { " …" } else { "" };
+    format!("{}{more}", bits.join(" "))
+}
+
+fn registers(data: &[u8]) -> String {
+    let values: Vec<String> = data
+        .chunks_exact(2)
+        .take(MAX_VALUES)
+        .map(|w| u16::from_be_bytes([w[0], w[1]]).to_string())
+        .collect();
+    let more = if data.len() / 2 > MAX_VALUES { ", …" } else { "" };
+    format!("[{}{more}]", values.join(", "))
+}
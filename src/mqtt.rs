@@ -0,0 +1,152 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use regex::Regex;
+use rumqttc::{Client, Connection, Event, MqttOptions, Packet, QoS, RecvTimeoutError};
+
+use crate::ansi;
+use crate::config::MqttConfig;
+
+const DEFAULT_PORT: u16 = 1883;
+/// Granularity at which the connection thread notices `stop`.
+const STOP_POLL: Duration = Duration::from_millis(100);
+/// Wait between attempts to reach the broker.
+const RETRY: Duration = Duration::from_secs(2);
+/// Publishes queued for the connection thread; beyond this lines are
+/// dropped rather than holding up the UI.
+const QUEUE: usize = 1024;
+
+#[derive(Debug)]
+pub enum MqttEvent {
+    Connected,
+    /// A message on the subscribed topic, to be written to the port.
+    Message(Vec<u8>),
+    /// The broker couldn't be reached or the connection dropped; retried
+    /// until stopped.
+    Error(String),
+}
+
+/// A connection to an MQTT broker that received lines are published to.
+pub struct MqttHandle {
+    /// `host:port`, for messages.
+    pub broker: String,
+    pub topic: String,
+    /// Publish only this part of matching lines (see [`MqttConfig::field`]).
+    field: Option<Regex>,
+    client: Client,
+    stop: Arc<AtomicBool>,
+    /// Lines not published because the queue was full.
+    pub dropped: u64,
+}
+
+impl MqttHandle {
+    /// Publishes a received line, or its field, if it has one.
+    pub fn publish_line(&mut self, line: &str) {
+        let line = ansi::strip(line);
+        let payload = match &self.field {
+            Some(field) => {
+                let Some(caps) = field.captures(&line) else {
+                    return;
+                };
+                let Some(m) = caps.get(1).or_else(|| caps.get(0)) else {
+                    return;
+                };
+                m.as_str().to_string()
+            }
+            None => line,
+        };
+        if self.client.try_publish(&self.topic, QoS::AtMostOnce, false, payload).is_err() {
+            self.dropped += 1;
+        }
+    }
+
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+        let _ = self.client.try_disconnect();
+    }
+}
+
+/// Connects to the broker in `config` in the background; the connection is
+/// kept up (and the subscription renewed) until stopped.
+pub fn spawn(config: &MqttConfig) -> Result<(MqttHandle, Receiver<MqttEvent>)> {
+    let broker = config.broker.as_deref().ok_or_else(|| anyhow!("no broker in [mqtt]"))?;
+    let broker = broker.strip_prefix("mqtt://").unwrap_or(broker).trim_end_matches('/');
+    let (host, port) = match broker.rsplit_once(':') {
+        Some((host, port)) => {
+            (host, port.parse().with_context(|| format!("bad broker port: {port}"))?)
+        }
+        None => (broker, DEFAULT_PORT),
+    };
+    let field = match &config.field {
+        Some(pattern) => Some(Regex::new(pattern).context("[mqtt] field: bad regex")?),
+        None => None,
+    };
+    let mut options = MqttOptions::new(&config.client_id, host, port);
+    options.set_keep_alive(Duration::from_secs(30));
+    if let Some(user) = &config.username {
+        options.set_credentials(user, config.password.as_deref().unwrap_or_default());
+    }
+    let (client, connection) = Client::new(options, QUEUE);
+
+    let (event_tx, event_rx) = mpsc::channel();
+    let stop = Arc::new(AtomicBool::new(false));
+    let subscribe = config.subscribe.clone();
+    let thread_client = client.clone();
+    let thread_stop = stop.clone();
+    thread::spawn(move || {
+        run(connection, &thread_client, subscribe.as_deref(), &event_tx, &thread_stop)
+    });
+
+    Ok((
+        MqttHandle {
+            broker: format!("{host}:{port}"),
+            topic: config.topic.clone(),
+            field,
+            client,
+            stop,
+            dropped: 0,
+        },
+        event_rx,
+    ))
+}
+
+fn run(
+    mut connection: Connection,
+    client: &Client,
+    subscribe: Option<&str>,
+    event_tx: &Sender<MqttEvent>,
+    stop: &AtomicBool,
+) {
+    // Reported once per outage rather than on every retry.
+    let mut failing = false;
+    while !stop.load(Ordering::Relaxed) {
+        match connection.recv_timeout(STOP_POLL) {
+            Ok(Ok(Event::Incoming(Packet::ConnAck(_)))) => {
+                failing = false;
+                if let Some(topic) = subscribe {
+                    let _ = client.try_subscribe(topic, QoS::AtMostOnce);
+                }
+                let _ = event_tx.send(MqttEvent::Connected);
+            }
+            Ok(Ok(Event::Incoming(Packet::Publish(publish)))) => {
+                let _ = event_tx.send(MqttEvent::Message(publish.payload.to_vec()));
+            }
+            Ok(Ok(_)) | Err(RecvTimeoutError::Timeout) => {}
+            Ok(Err(e)) => {
+                if !failing {
+                    failing = true;
+                    let _ = event_tx.send(MqttEvent::Error(e.to_string()));
+                }
+                let retry_at = Instant::now() + RETRY;
+                while Instant::now() < retry_at && !stop.load(Ordering::Relaxed) {
+                    thread::sleep(STOP_POLL);
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+}
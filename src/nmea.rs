@@ -0,0 +1,275 @@
+//! NMEA 0183 sentence parsing for GPS receivers.
+//!
+//! [`Tracker`] is fed received text lines, checks each sentence's checksum
+//! and keeps the latest fix, position, motion and satellite counts from
+//! the GGA, RMC, GSA, GSV, VTG and GLL sentences receivers send.
+//! [`SentenceDecoder`] lists the sentences themselves in the packet list.
+
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+use anyhow::{bail, Context, Result};
+
+use crate::decoder::{Column, Decoder, Record};
+use crate::Direction;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fix {
+    None,
+    /// GGA reports a plain fix without saying whether it is 2D or 3D.
+    Gps,
+    Fix2d,
+    Fix3d,
+    Dgps,
+    Rtk,
+    Estimated,
+}
+
+impl Fix {
+    pub fn name(self) -> &'static str {
+        match self {
+            Fix::None => "no fix",
+            Fix::Gps => "GPS",
+            Fix::Fix2d => "2D",
+            Fix::Fix3d => "3D",
+            Fix::Dgps => "DGPS",
+            Fix::Rtk => "RTK",
+            Fix::Estimated => "estimated",
+        }
+    }
+}
+
+/// Latest values seen across all sentences.
+#[derive(Debug, Default)]
+pub struct Tracker {
+    pub fix: Option<Fix>,
+    /// `hh:mm:ss` UTC.
+    pub time: Option<String>,
+    /// `yyyy-mm-dd` from RMC.
+    pub date: Option<String>,
+    /// Decimal degrees, north and east positive.
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// Meters above mean sea level.
+    pub altitude: Option<f64>,
+    pub satellites_used: Option<u32>,
+    /// Satellites in view per talker (`GP`, `GL`, `GA`, `BD`...).
+    pub satellites_in_view: BTreeMap<String, u32>,
+    pub speed_knots: Option<f64>,
+    /// Degrees true.
+    pub course: Option<f64>,
+    pub hdop: Option<f64>,
+    pub pdop: Option<f64>,
+    pub vdop: Option<f64>,
+    pub sentences: u64,
+    pub bad_checksums: u64,
+}
+
+impl Tracker {
+    /// Takes one received line. Returns `None` when it isn't an NMEA
+    /// sentence, an error when its checksum doesn't match.
+    pub fn feed(&mut self, line: &str) -> Option<Result<()>> {
+        let start = line.find(['$', '!'])?;
+        let sentence = line[start + 1..].trim_end();
+        let head = sentence.split(',').next()?;
+        if head.len() < 5 || !head.bytes().all(|b| b.is_ascii_alphanumeric()) {
+            return None;
+        }
+        let result = self.parse(sentence);
+        match &result {
+            Ok(()) => self.sentences += 1,
+            Err(_) => self.bad_checksums += 1,
+        }
+        Some(result)
+    }
+
+    fn parse(&mut self, sentence: &str) -> Result<()> {
+        let body = verify(sentence)?;
+        let fields: Vec<&str> = body.split(',').collect();
+        let head = fields[0];
+        let (talker, kind) = head.split_at(head.len() - 3);
+        let field = |i: usize| fields.get(i).copied().filter(|f| !f.is_empty());
+        let number = |i: usize| field(i).and_then(|f| f.parse::<f64>().ok());
+        match kind {
+            "GGA" => {
+                self.set_time(field(1));
+                self.set_position(field(2), field(3), field(4), field(5));
+                self.fix = field(6).map(|q| match q {
+                    "0" => Fix::None,
+                    "1" => self
+                        .fix
+                        .filter(|f| matches!(f, Fix::Fix2d | Fix::Fix3d))
+                        .unwrap_or(Fix::Gps),
+                    "2" | "3" => Fix::Dgps,
+                    "4" | "5" => Fix::Rtk,
+                    "6" => Fix::Estimated,
+                    _ => Fix::None,
+                });
+                self.satellites_used = field(7).and_then(|n| n.parse().ok());
+                self.hdop = number(8);
+                self.altitude = number(9);
+            }
+            "RMC" => {
+                self.set_time(field(1));
+                if field(2) == Some("V") {
+                    self.fix = Some(Fix::None);
+                }
+                self.set_position(field(3), field(4), field(5), field(6));
+                self.speed_knots = number(7);
+                self.course = number(8);
+                if let Some(d) = field(9).filter(|d| d.len() == 6) {
+                    let century = if &d[4..6] < "80" { "20" } else { "19" };
+                    self.date = Some(format!("{century}{}-{}-{}", &d[4..6], &d[2..4], &d[0..2]));
+                }
+            }
+            "GSA" => {
+                let fix = match field(2) {
+                    Some("2") => Fix::Fix2d,
+                    Some("3") => Fix::Fix3d,
+                    _ => Fix::None,
+                };
+                // GGA knows about differential fixes, GSA only the dimension.
+                if !matches!(self.fix, Some(Fix::Dgps | Fix::Rtk)) || fix == Fix::None {
+                    self.fix = Some(fix);
+                }
+                self.pdop = number(15);
+                self.hdop = number(16);
+                self.vdop = number(17);
+            }
+            "GSV" => {
+                if let Some(n) = field(3).and_then(|n| n.parse().ok()) {
+                    self.satellites_in_view.insert(talker.to_string(), n);
+                }
+            }
+            "VTG" => {
+                self.course = number(1).or(self.course);
+                self.speed_knots = number(5).or(self.speed_knots);
+            }
+            "GLL" => {
+                self.set_position(field(1), field(2), field(3), field(4));
+                self.set_time(field(5));
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn set_time(&mut self, field: Option<&str>) {
+        if let Some(t) = field.filter(|t| t.len() >= 6) {
+            self.time = Some(format!("{}:{}:{}", &t[0..2], &t[2..4], &t[4..6]));
+        }
+    }
+
+    fn set_position(
+        &mut self,
+        lat: Option<&str>,
+        ns: Option<&str>,
+        lon: Option<&str>,
+        ew: Option<&str>,
+    ) {
+        let (Some(lat), Some(lon)) = (degrees(lat, 2), degrees(lon, 3)) else {
+            return;
+        };
+        self.latitude = Some(if ns == Some("S") { -lat } else { lat });
+        self.longitude = Some(if ew == Some("W") { -lon } else { lon });
+    }
+
+    /// Total satellites in view over all constellations.
+    pub fn satellites_in_view_total(&self) -> u32 {
+        self.satellites_in_view.values().sum()
+    }
+}
+
+/// The sentence up to its `*XX` checksum, once the checksum has been
+/// checked. Sentences without one pass as they are.
+fn verify(sentence: &str) -> Result<&str> {
+    let Some((body, sum)) = sentence.rsplit_once('*') else {
+        return Ok(sentence);
+    };
+    let expected =
+        u8::from_str_radix(sum, 16).with_context(|| format!("bad checksum field {sum:?}"))?;
+    let actual = body.bytes().fold(0, |acc, b| acc ^ b);
+    if actual != expected {
+        bail!("checksum is {actual:02X}, sentence says {expected:02X}");
+    }
+    Ok(body)
+}
+
+/// Splits both directions into sentences for the packet list.
+#[derive(Default)]
+pub struct SentenceDecoder {
+    rx: Vec<u8>,
+    tx: Vec<u8>,
+    rx_at: Option<Instant>,
+    tx_at: Option<Instant>,
+}
+
+const COLUMNS: &[Column] = &[Column {
+    name: "sentence",
+    width: 8,
+}];
+
+impl Decoder for SentenceDecoder {
+    fn title(&self) -> &'static str {
+        "NMEA 0183"
+    }
+
+    fn columns(&self) -> &'static [Column] {
+        COLUMNS
+    }
+
+    fn push(&mut self, direction: Direction, bytes: &[u8], now: Instant) -> Vec<Record> {
+        let (pending, started) = match direction {
+            Direction::Rx => (&mut self.rx, &mut self.rx_at),
+            Direction::Tx => (&mut self.tx, &mut self.tx_at),
+        };
+        let mut records = Vec::new();
+        for &byte in bytes {
+            let at = *started.get_or_insert(now);
+            if byte != b'\n' {
+                pending.push(byte);
+                continue;
+            }
+            let raw = std::mem::take(pending);
+            *started = None;
+            let line = String::from_utf8_lossy(&raw).trim().to_string();
+            if !line.is_empty() {
+                records.push(sentence_record(direction, at, &line, raw));
+            }
+        }
+        records
+    }
+}
+
+fn sentence_record(direction: Direction, at: Instant, line: &str, raw: Vec<u8>) -> Record {
+    let (head, valid, details) = match line.strip_prefix(['$', '!']) {
+        // Not a sentence; listed as it is so nothing goes missing.
+        None => ("-".to_string(), None, line.to_string()),
+        Some(sentence) => match verify(sentence) {
+            Ok(body) => {
+                let (head, rest) = body.split_once(',').unwrap_or((body, ""));
+                (head.to_string(), sentence.contains('*').then_some(true), rest.to_string())
+            }
+            Err(e) => {
+                let head = sentence.split(',').next().unwrap_or_default();
+                (head.to_string(), Some(false), format!("{e:#}"))
+            }
+        },
+    };
+    Record {
+        direction,
+        at,
+        fields: vec![head],
+        valid,
+        details,
+        raw,
+    }
+}
+
+/// `ddmm.mmmm` (or `dddmm.mmmm`) to decimal degrees.
+fn degrees(field: Option<&str>, degree_digits: usize) -> Option<f64> {
+    let field = field?;
+    let deg: f64 = field.get(..degree_digits)?.parse().ok()?;
+    let min: f64 = field.get(degree_digits..)?.parse().ok()?;
+    Some(deg + min / 60.0)
+}
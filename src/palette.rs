@@ -0,0 +1,79 @@
+use crate::keymap::{self, Action, Keymap};
+use crate::macros::{self, Macros};
+
+/// Something the palette can run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    Action(Action),
+    /// Send macro `n` (0 is F1).
+    Macro(usize),
+}
+
+pub struct Entry {
+    pub command: Command,
+    pub label: String,
+    /// Keys bound to the command, for display.
+    pub keys: String,
+}
+
+/// Ctrl-P command list narrowed by a fuzzy query.
+#[derive(Default)]
+pub struct Palette {
+    pub query: String,
+    pub selected: usize,
+}
+
+impl Palette {
+    /// Every runnable command matching the query, best match first.
+    pub fn entries(&self, keymap: &Keymap, macros: &Macros) -> Vec<Entry> {
+        let actions = keymap::ACTIONS
+            .iter()
+            .filter(|i| i.action != Action::Palette)
+            .map(|i| Entry {
+                command: Command::Action(i.action),
+                label: i.help.to_string(),
+                keys: keymap.label(i.action),
+            });
+        let bound = (0..macros::MACRO_COUNT).filter_map(|idx| {
+            let text = macros.get(idx)?;
+            Some(Entry {
+                command: Command::Macro(idx),
+                label: format!("Send macro: {}", macros::escape(text)),
+                keys: macros::key_name(idx),
+            })
+        });
+        let mut scored: Vec<(i64, Entry)> = actions
+            .chain(bound)
+            .filter_map(|e| Some((fuzzy_score(&self.query, &e.label)?, e)))
+            .collect();
+        // Stable, so equal scores keep the keymap's order.
+        scored.sort_by_key(|(score, _)| -score);
+        scored.into_iter().map(|(_, e)| e).collect()
+    }
+}
+
+/// Scores `text` against `query` as a case-insensitive subsequence, or
+/// `None` if some query character is missing. Runs of consecutive matches
+/// and matches at word starts score higher.
+pub fn fuzzy_score(query: &str, text: &str) -> Option<i64> {
+    let text: Vec<char> = text.chars().flat_map(char::to_lowercase).collect();
+    let mut score = 0;
+    let mut pos = 0;
+    let mut last: Option<usize> = None;
+    for q in query.chars().flat_map(char::to_lowercase) {
+        if q.is_whitespace() {
+            continue;
+        }
+        let found = pos + text[pos..].iter().position(|&c| c == q)?;
+        score += 1;
+        if last.is_some_and(|l| l + 1 == found) {
+            score += 5;
+        }
+        if found == 0 || !text[found - 1].is_alphanumeric() {
+            score += 3;
+        }
+        last = Some(found);
+        pos = found + 1;
+    }
+    Some(score)
+}
@@ -0,0 +1,116 @@
+use std::io::{self, Write};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// Prefix key for passthrough commands, as in picocom.
+pub const ESCAPE_KEY: char = 'a';
+
+pub enum Action {
+    Send(Vec<u8>),
+    Exit,
+    None,
+}
+
+/// Raw terminal mode: every key goes to the port as the bytes a terminal
+/// would send, and received data is written to the screen untouched.
+/// `Ctrl-A q` (or `x`) returns to the TUI; `Ctrl-A Ctrl-A` sends a literal
+/// Ctrl-A.
+#[derive(Default)]
+pub struct Passthrough {
+    escape_pending: bool,
+}
+
+impl Passthrough {
+    pub fn on_key(&mut self, key: KeyEvent) -> Action {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        if self.escape_pending {
+            self.escape_pending = false;
+            return match key.code {
+                KeyCode::Char('q' | 'x') => Action::Exit,
+                KeyCode::Char(ESCAPE_KEY) if ctrl => Action::Send(vec![0x01]),
+                _ => Action::None,
+            };
+        }
+        if ctrl && key.code == KeyCode::Char(ESCAPE_KEY) {
+            self.escape_pending = true;
+            return Action::None;
+        }
+        match encode_key(key) {
+            Some(bytes) => Action::Send(bytes),
+            None => Action::None,
+        }
+    }
+}
+
+/// Writes received bytes straight to the terminal.
+pub fn echo(bytes: &[u8]) -> io::Result<()> {
+    let mut out = io::stdout();
+    out.write_all(bytes)?;
+    out.flush()
+}
+
+/// Bytes an xterm-compatible terminal sends for `key`.
+fn encode_key(key: KeyEvent) -> Option<Vec<u8>> {
+    let seq: &[u8] = match key.code {
+        KeyCode::Char(c) => {
+            let mut bytes = Vec::new();
+            if key.modifiers.contains(KeyModifiers::ALT) {
+                bytes.push(0x1b);
+            }
+            if key.modifiers.contains(KeyModifiers::CONTROL) {
+                bytes.push(control_byte(c)?);
+            } else {
+                let mut buf = [0u8; 4];
+                bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            }
+            return Some(bytes);
+        }
+        KeyCode::Enter => b"\r",
+        KeyCode::Backspace => b"\x7f",
+        KeyCode::Tab => b"\t",
+        KeyCode::BackTab => b"\x1b[Z",
+        KeyCode::Esc => b"\x1b",
+        KeyCode::Up => b"\x1b[A",
+        KeyCode::Down => b"\x1b[B",
+        KeyCode::Right => b"\x1b[C",
+        KeyCode::Left => b"\x1b[D",
+        KeyCode::Home => b"\x1b[H",
+        KeyCode::End => b"\x1b[F",
+        KeyCode::Insert => b"\x1b[2~",
+        KeyCode::Delete => b"\x1b[3~",
+        KeyCode::PageUp => b"\x1b[5~",
+        KeyCode::PageDown => b"\x1b[6~",
+        KeyCode::F(n) => match n {
+            1 => b"\x1bOP",
+            2 => b"\x1bOQ",
+            3 => b"\x1bOR",
+            4 => b"\x1bOS",
+            5 => b"\x1b[15~",
+            6 => b"\x1b[17~",
+            7 => b"\x1b[18~",
+            8 => b"\x1b[19~",
+            9 => b"\x1b[20~",
+            10 => b"\x1b[21~",
+            11 => b"\x1b[23~",
+            12 => b"\x1b[24~",
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(seq.to_vec())
+}
+
+fn control_byte(c: char) -> Option<u8> {
+    match c {
+        'a'..='z' => Some(c as u8 - b'a' + 1),
+        'A'..='Z' => Some(c as u8 - b'A' + 1),
+        '@' | ' ' | '2' => Some(0x00),
+        '[' | '3' => Some(0x1b),
+        '\\' | '4' => Some(0x1c),
+        ']' | '5' => Some(0x1d),
+        '^' | '6' => Some(0x1e),
+        '_' | '7' | '/' => Some(0x1f),
+        '8' | '?' => Some(0x7f),
+        _ => None,
+    }
+}
@@ -0,0 +1,231 @@
+//! Minimal pcapng reading and writing for serial captures.
+//!
+//! A file holds one section with one interface (the port) and an Enhanced
+//! Packet Block per chunk of bytes, with the direction in `epb_flags` so
+//! Wireshark can tell RX from TX. The link type is `LINKTYPE_USER0`
+//! (147); mapping it to a dissector such as `modbus_rtu` or `nmea0183` in
+//! Wireshark's DLT_USER preferences decodes the traffic.
+
+use std::io::{self, Read, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+
+use crate::Direction;
+
+pub const LINKTYPE_USER0: u16 = 147;
+
+const SECTION_HEADER: u32 = 0x0A0D_0D0A;
+const INTERFACE_DESCRIPTION: u32 = 1;
+const ENHANCED_PACKET: u32 = 6;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+
+const OPT_END: u16 = 0;
+const SHB_USERAPPL: u16 = 4;
+const IF_NAME: u16 = 2;
+const IF_TSRESOL: u16 = 9;
+const OPT_COMMENT: u16 = 1;
+const EPB_FLAGS: u16 = 2;
+const FLAG_INBOUND: u32 = 1;
+const FLAG_OUTBOUND: u32 = 2;
+
+/// Writes packets after a section header and one interface description.
+pub struct Writer<W: Write> {
+    out: W,
+}
+
+impl<W: Write> Writer<W> {
+    /// Starts the file; `interface` names the port in Wireshark.
+    pub fn new(mut out: W, interface: &str) -> io::Result<Self> {
+        let mut shb = Vec::new();
+        shb.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+        shb.extend_from_slice(&1u16.to_le_bytes());
+        shb.extend_from_slice(&0u16.to_le_bytes());
+        // Section length unknown.
+        shb.extend_from_slice(&(-1i64).to_le_bytes());
+        push_option(&mut shb, SHB_USERAPPL, b"lazyserial");
+        push_option(&mut shb, OPT_END, &[]);
+        write_block(&mut out, SECTION_HEADER, &shb)?;
+
+        let mut idb = Vec::new();
+        idb.extend_from_slice(&LINKTYPE_USER0.to_le_bytes());
+        idb.extend_from_slice(&0u16.to_le_bytes());
+        idb.extend_from_slice(&0u32.to_le_bytes());
+        push_option(&mut idb, IF_NAME, interface.as_bytes());
+        push_option(&mut idb, IF_TSRESOL, &[6]);
+        push_option(&mut idb, OPT_END, &[]);
+        write_block(&mut out, INTERFACE_DESCRIPTION, &idb)?;
+        Ok(Self { out })
+    }
+
+    pub fn packet(
+        &mut self,
+        time: SystemTime,
+        direction: Direction,
+        data: &[u8],
+    ) -> io::Result<()> {
+        self.write_packet(time, direction, data, None)
+    }
+
+    /// An empty packet carrying `comment`, which Wireshark lists like any
+    /// other packet comment.
+    pub fn comment(&mut self, time: SystemTime, comment: &str) -> io::Result<()> {
+        self.write_packet(time, Direction::Rx, &[], Some(comment))
+    }
+
+    fn write_packet(
+        &mut self,
+        time: SystemTime,
+        direction: Direction,
+        data: &[u8],
+        comment: Option<&str>,
+    ) -> io::Result<()> {
+        let micros = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_micros() as u64;
+        let len = data.len() as u32;
+        let mut epb = Vec::with_capacity(data.len() + 32);
+        epb.extend_from_slice(&0u32.to_le_bytes());
+        epb.extend_from_slice(&((micros >> 32) as u32).to_le_bytes());
+        epb.extend_from_slice(&(micros as u32).to_le_bytes());
+        epb.extend_from_slice(&len.to_le_bytes());
+        epb.extend_from_slice(&len.to_le_bytes());
+        epb.extend_from_slice(data);
+        pad(&mut epb);
+        let flags = match direction {
+            Direction::Rx => FLAG_INBOUND,
+            Direction::Tx => FLAG_OUTBOUND,
+        };
+        push_option(&mut epb, EPB_FLAGS, &flags.to_le_bytes());
+        if let Some(comment) = comment {
+            push_option(&mut epb, OPT_COMMENT, comment.as_bytes());
+        }
+        push_option(&mut epb, OPT_END, &[]);
+        write_block(&mut self.out, ENHANCED_PACKET, &epb)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+fn write_block(out: &mut impl Write, kind: u32, body: &[u8]) -> io::Result<()> {
+    let len = (body.len() + 12) as u32;
+    out.write_all(&kind.to_le_bytes())?;
+    out.write_all(&len.to_le_bytes())?;
+    out.write_all(body)?;
+    out.write_all(&len.to_le_bytes())
+}
+
+fn push_option(body: &mut Vec<u8>, code: u16, value: &[u8]) {
+    body.extend_from_slice(&code.to_le_bytes());
+    body.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    body.extend_from_slice(value);
+    pad(body);
+}
+
+fn pad(body: &mut Vec<u8>) {
+    body.resize(body.len().next_multiple_of(4), 0);
+}
+
+/// A packet read back, with its absolute time.
+pub struct Packet {
+    pub time: SystemTime,
+    pub direction: Direction,
+    pub data: Vec<u8>,
+}
+
+/// Enhanced packets of a little-endian pcapng file. Packets without a
+/// direction flag count as received.
+pub fn read(mut input: impl Read) -> Result<Vec<Packet>> {
+    let mut data = Vec::new();
+    input.read_to_end(&mut data)?;
+    let u32_at = |b: &[u8], at: usize| u32::from_le_bytes(b[at..at + 4].try_into().unwrap());
+    let mut packets = Vec::new();
+    // Ticks per second of each interface, in file order.
+    let mut resolutions: Vec<u64> = Vec::new();
+    let mut pos = 0;
+    while pos + 12 <= data.len() {
+        let kind = u32_at(&data, pos);
+        let len = u32_at(&data, pos + 4) as usize;
+        if pos == 0 && kind != SECTION_HEADER {
+            bail!("not a pcapng file");
+        }
+        if len < 12 || len & 3 != 0 || pos + len > data.len() {
+            bail!("truncated or corrupt block at offset {pos}");
+        }
+        let body = &data[pos + 8..pos + len - 4];
+        match kind {
+            SECTION_HEADER => {
+                if body.len() < 16 || u32_at(body, 0) != BYTE_ORDER_MAGIC {
+                    bail!("not a little-endian pcapng file");
+                }
+                resolutions.clear();
+            }
+            INTERFACE_DESCRIPTION if body.len() >= 8 => {
+                let mut resolution = 1_000_000;
+                for (code, value) in options(&body[8..]) {
+                    if code == IF_TSRESOL && !value.is_empty() {
+                        let exp = u32::from(value[0] & 0x7f);
+                        resolution = if value[0] & 0x80 != 0 {
+                            1u64.checked_shl(exp).unwrap_or(u64::MAX)
+                        } else {
+                            10u64.checked_pow(exp).unwrap_or(u64::MAX)
+                        };
+                    }
+                }
+                resolutions.push(resolution);
+            }
+            ENHANCED_PACKET if body.len() >= 20 => {
+                let interface = u32_at(body, 0) as usize;
+                let ticks = (u64::from(u32_at(body, 4)) << 32) | u64::from(u32_at(body, 8));
+                let captured = u32_at(body, 12) as usize;
+                let Some(payload) = body.get(20..20 + captured) else {
+                    bail!("packet at offset {pos} overruns its block");
+                };
+                let resolution = *resolutions
+                    .get(interface)
+                    .context("packet for an undescribed interface")?;
+                let rest = &body[(20 + captured).next_multiple_of(4).min(body.len())..];
+                let flags = options(rest)
+                    .find(|(code, value)| *code == EPB_FLAGS && value.len() == 4)
+                    .map_or(0, |(_, value)| u32_at(value, 0));
+                let secs = ticks / resolution;
+                let nanos = (ticks % resolution) as u128 * 1_000_000_000 / resolution as u128;
+                let Some(time) = UNIX_EPOCH.checked_add(Duration::new(secs, nanos as u32)) else {
+                    bail!("packet at offset {pos} has an impossible timestamp");
+                };
+                packets.push(Packet {
+                    time,
+                    direction: if flags & 3 == FLAG_OUTBOUND {
+                        Direction::Tx
+                    } else {
+                        Direction::Rx
+                    },
+                    data: payload.to_vec(),
+                });
+            }
+            _ => {}
+        }
+        pos += len;
+    }
+    if pos != data.len() {
+        bail!("trailing bytes after the last block");
+    }
+    Ok(packets)
+}
+
+/// `(code, value)` pairs up to `opt_endofopt`.
+fn options(mut body: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    std::iter::from_fn(move || {
+        if body.len() < 4 {
+            return None;
+        }
+        let code = u16::from_le_bytes([body[0], body[1]]);
+        let len = usize::from(u16::from_le_bytes([body[2], body[3]]));
+        if code == OPT_END || body.len() < 4 + len {
+            return None;
+        }
+        let value = &body[4..4 + len];
+        body = &body[(4 + len).next_multiple_of(4).min(body.len())..];
+        Some((code, value))
+    })
+}
@@ -0,0 +1,165 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use regex::Regex;
+
+use crate::config::PingConfig;
+use crate::macros;
+
+/// Received text kept while waiting for an answer; a device that keeps
+/// talking without ever matching shouldn't grow it without bound.
+const MAX_ANSWER: usize = 4096;
+
+/// A ping run: sends a probe, waits for the answer or the timeout, and
+/// records the round trip, `count` times over.
+pub struct Ping {
+    /// The probe with control characters escaped, for display.
+    pub label: String,
+    probe: Vec<u8>,
+    expect: Regex,
+    pub count: u32,
+    timeout: Duration,
+    interval: Duration,
+    /// When the probe still waiting for its answer was sent.
+    sent_at: Option<Instant>,
+    next: Instant,
+    /// Text received since that probe went out.
+    answer: String,
+    pub rtts: Vec<Duration>,
+    pub lost: u32,
+    reported: bool,
+}
+
+pub struct Stats {
+    pub min: Duration,
+    pub avg: Duration,
+    pub max: Duration,
+    /// Mean difference between consecutive round trips.
+    pub jitter: Duration,
+}
+
+impl Ping {
+    /// Pings with `probe`; the answer is `config.expect`, or else the probe
+    /// echoed back.
+    pub fn new(config: &PingConfig, probe: &str) -> Result<Self> {
+        let expect = match &config.expect {
+            Some(pattern) => Regex::new(pattern).context("[ping] expect: bad regex")?,
+            None => Regex::new(&regex::escape(probe.trim()))?,
+        };
+        Ok(Self {
+            label: macros::escape(probe),
+            probe: probe.as_bytes().to_vec(),
+            expect,
+            count: config.count.max(1),
+            timeout: Duration::from_millis(config.timeout_ms),
+            interval: Duration::from_millis(config.interval_ms),
+            sent_at: None,
+            next: Instant::now(),
+            answer: String::new(),
+            rtts: Vec::new(),
+            lost: 0,
+            reported: false,
+        })
+    }
+
+    /// Probes answered or given up on.
+    pub fn finished(&self) -> u32 {
+        self.rtts.len() as u32 + self.lost
+    }
+
+    pub fn done(&self) -> bool {
+        self.finished() >= self.count
+    }
+
+    /// True once, when the last probe has been answered or timed out.
+    pub fn take_done(&mut self) -> bool {
+        let first = self.done() && !self.reported;
+        self.reported |= first;
+        first
+    }
+
+    /// The probe to send, when the next one is due; a probe that went
+    /// unanswered for the timeout counts as lost.
+    pub fn poll(&mut self, now: Instant) -> Option<Vec<u8>> {
+        if let Some(sent) = self.sent_at {
+            if now.duration_since(sent) < self.timeout {
+                return None;
+            }
+            self.sent_at = None;
+            self.lost += 1;
+        }
+        if self.done() || now < self.next {
+            return None;
+        }
+        self.sent_at = Some(now);
+        self.answer.clear();
+        Some(self.probe.clone())
+    }
+
+    pub fn on_text(&mut self, text: &str, now: Instant) {
+        let Some(sent) = self.sent_at else {
+            return;
+        };
+        self.answer.push_str(text);
+        if self.expect.is_match(&self.answer) {
+            self.rtts.push(now.duration_since(sent));
+            self.sent_at = None;
+            self.next = now + self.interval;
+        } else if self.answer.len() > MAX_ANSWER {
+            let cut = self.answer.len() - MAX_ANSWER / 2;
+            let cut = (cut..self.answer.len())
+                .find(|&i| self.answer.is_char_boundary(i))
+                .unwrap_or(self.answer.len());
+            self.answer.drain(..cut);
+        }
+    }
+
+    /// Round trip statistics, once a probe has been answered.
+    pub fn stats(&self) -> Option<Stats> {
+        let min = *self.rtts.iter().min()?;
+        let max = *self.rtts.iter().max()?;
+        let avg = self.rtts.iter().sum::<Duration>() / self.rtts.len() as u32;
+        let steps: Vec<Duration> = self
+            .rtts
+            .windows(2)
+            .map(|w| w[1].checked_sub(w[0]).unwrap_or_else(|| w[0] - w[1]))
+            .collect();
+        let jitter = match steps.len() {
+            0 => Duration::ZERO,
+            n => steps.iter().sum::<Duration>() / n as u32,
+        };
+        Some(Stats {
+            min,
+            avg,
+            max,
+            jitter,
+        })
+    }
+
+    /// One line summing up the run so far.
+    pub fn summary(&self) -> String {
+        let mut line = format!(
+            "{} of {} probes, {} answered, {} lost",
+            self.finished(),
+            self.count,
+            self.rtts.len(),
+            self.lost
+        );
+        if let Some(stats) = self.stats() {
+            line.push_str(&format!(
+                ", rtt min/avg/max/jitter {}/{}/{}/{} ms",
+                millis(stats.min),
+                millis(stats.avg),
+                millis(stats.max),
+                millis(stats.jitter)
+            ));
+        }
+        line
+    }
+}
+
+/// `d` in milliseconds with a fractional digit: round trips on a fast
+/// link are often under one.
+pub fn millis(d: Duration) -> String {
+    format!("{:.1}", d.as_secs_f64() * 1000.0)
+}
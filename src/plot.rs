@@ -0,0 +1,131 @@
+use std::collections::VecDeque;
+use std::time::SystemTime;
+
+/// Samples kept per series.
+const MAX_POINTS: usize = 2000;
+/// Samples shown across the chart.
+pub const WINDOW: f64 = 300.0;
+/// Series told apart by color before they start repeating.
+pub const MAX_SERIES: usize = 8;
+
+pub struct Series {
+    pub name: String,
+    /// `(sample number, value)`.
+    pub points: VecDeque<(f64, f64)>,
+}
+
+/// One parsed line, kept for export.
+pub struct Row {
+    pub time: SystemTime,
+    pub sample: u64,
+    pub values: Vec<(String, f64)>,
+}
+
+/// Numbers pulled from received lines, Arduino serial plotter style: one
+/// sample per line, values separated by commas, tabs or spaces, each
+/// optionally labeled as `name:value`. Unlabeled values are named by
+/// position.
+#[derive(Default)]
+pub struct Plotter {
+    pub series: Vec<Series>,
+    /// Lines plotted so far; the x coordinate of the next sample.
+    samples: u64,
+    /// Sample the view stopped at while paused.
+    frozen: Option<f64>,
+    /// The last `MAX_POINTS` samples with every value, charted or not.
+    pub rows: VecDeque<Row>,
+}
+
+impl Plotter {
+    /// Adds a sample if the line holds only numbers; other lines are
+    /// skipped.
+    pub fn push(&mut self, line: &str) {
+        let Some(values) = parse(line) else {
+            return;
+        };
+        let sample = self.samples;
+        let x = sample as f64;
+        self.samples += 1;
+        let values: Vec<(String, f64)> = values
+            .into_iter()
+            .enumerate()
+            .map(|(i, (label, value))| (label.unwrap_or_else(|| format!("#{}", i + 1)), value))
+            .collect();
+        if self.rows.len() == MAX_POINTS {
+            self.rows.pop_front();
+        }
+        self.rows.push_back(Row {
+            time: SystemTime::now(),
+            sample,
+            values: values.clone(),
+        });
+        for (name, value) in values {
+            let idx = match self.series.iter().position(|s| s.name == name) {
+                Some(idx) => idx,
+                None if self.series.len() < MAX_SERIES => {
+                    self.series.push(Series {
+                        name,
+                        points: VecDeque::new(),
+                    });
+                    self.series.len() - 1
+                }
+                None => continue,
+            };
+            let points = &mut self.series[idx].points;
+            if points.len() == MAX_POINTS {
+                points.pop_front();
+            }
+            points.push_back((x, value));
+        }
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.frozen = paused.then_some(self.samples as f64);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.frozen.is_some()
+    }
+
+    /// The x range on screen: the last `WINDOW` samples, or those before
+    /// the pause.
+    pub fn x_bounds(&self) -> [f64; 2] {
+        let end = self.frozen.unwrap_or(self.samples as f64).max(WINDOW);
+        [end - WINDOW, end]
+    }
+
+    /// Points of each series inside `x_bounds`.
+    pub fn visible(&self) -> Vec<(&str, Vec<(f64, f64)>)> {
+        let [start, end] = self.x_bounds();
+        self.series
+            .iter()
+            .map(|s| {
+                let points = s
+                    .points
+                    .iter()
+                    .copied()
+                    .filter(|&(x, _)| x >= start && x <= end)
+                    .collect();
+                (s.name.as_str(), points)
+            })
+            .collect()
+    }
+}
+
+/// Values on a line, or `None` if anything on it isn't a number.
+fn parse(line: &str) -> Option<Vec<(Option<String>, f64)>> {
+    let mut values = Vec::new();
+    let line = line.replace(": ", ":");
+    for token in line.split([',', '\t', ' ', ';']).filter(|t| !t.is_empty()) {
+        let (label, number) = match token.rsplit_once(':') {
+            Some((label, number)) => (Some(label.to_string()), number),
+            None => (None, token),
+        };
+        let value: f64 = number.parse().ok()?;
+        if !value.is_finite() {
+            return None;
+        }
+        values.push((label, value));
+    }
+    (!values.is_empty()).then_some(values)
+}
@@ -0,0 +1,242 @@
+//! Packet list decoders loaded at runtime from shared libraries (`.so`,
+//! `.dylib`, `.dll`), so a proprietary protocol can be added without
+//! rebuilding lazyserial.
+//!
+//! A plugin exports the C functions declared in
+//! `include/lazyserial_plugin.h`: its ABI version, name, title and columns,
+//! and `new` / `free` / `push` (and optionally `flush`) for decoder
+//! instances. Records come back through a callback, one call each, with
+//! the fields tab-separated. Loading a library runs its code, so only
+//! trusted files belong in the plugin directory.
+
+use std::ffi::{c_char, c_void, CStr, OsStr};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+
+use anyhow::{bail, Context, Result};
+use libloading::Library;
+
+use crate::decoder::{Column, Decoder, Record, Registry};
+use crate::Direction;
+
+/// The `LAZYSERIAL_PLUGIN_ABI` this build speaks.
+pub const ABI_VERSION: u32 = 1;
+
+type AbiFn = unsafe extern "C" fn() -> u32;
+type TextFn = unsafe extern "C" fn() -> *const c_char;
+type NewFn = unsafe extern "C" fn() -> *mut c_void;
+type FreeFn = unsafe extern "C" fn(*mut c_void);
+type EmitFn =
+    unsafe extern "C" fn(*mut c_void, u8, *const c_char, i32, *const c_char, *const u8, usize);
+type PushFn = unsafe extern "C" fn(*mut c_void, u8, *const u8, usize, EmitFn, *mut c_void);
+type FlushFn = unsafe extern "C" fn(*mut c_void, EmitFn, *mut c_void);
+
+/// A loaded library and its entry points, shared by its decoders.
+struct Plugin {
+    title: &'static str,
+    columns: &'static [Column],
+    new: NewFn,
+    free: FreeFn,
+    push: PushFn,
+    flush: Option<FlushFn>,
+    /// Keeps the functions above mapped; dropped last.
+    _library: Library,
+}
+
+/// Loads every shared library in `dir` into `registry`, returning each
+/// file with the decoder name it registered or why it didn't load (the
+/// error names the file). A missing directory loads nothing.
+pub fn load_dir(dir: &Path, registry: &mut Registry) -> Vec<(PathBuf, Result<&'static str>)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension() == Some(OsStr::new(std::env::consts::DLL_EXTENSION)))
+        .collect();
+    paths.sort();
+    paths
+        .into_iter()
+        .map(|path| {
+            let result = load(&path, registry);
+            (path, result)
+        })
+        .collect()
+}
+
+/// Loads one plugin and registers its decoder, replacing a built-in one
+/// of the same name.
+pub fn load(path: &Path, registry: &mut Registry) -> Result<&'static str> {
+    // SAFETY: the library's initializers run here; the plugin directory is
+    // trusted like the config file next to it.
+    // Its errors, like the symbol lookups', name the file.
+    let library = unsafe { Library::new(path) }?;
+    // SAFETY: each symbol is declared with these signatures in the header.
+    let (abi, name, title, columns, new, free, push, flush) = unsafe {
+        (
+            *library.get::<AbiFn>(b"lazyserial_plugin_abi\0")?,
+            *library.get::<TextFn>(b"lazyserial_plugin_name\0")?,
+            *library.get::<TextFn>(b"lazyserial_plugin_title\0")?,
+            *library.get::<TextFn>(b"lazyserial_plugin_columns\0")?,
+            *library.get::<NewFn>(b"lazyserial_decoder_new\0")?,
+            *library.get::<FreeFn>(b"lazyserial_decoder_free\0")?,
+            *library.get::<PushFn>(b"lazyserial_decoder_push\0")?,
+            library.get::<FlushFn>(b"lazyserial_decoder_flush\0").ok().map(|f| *f),
+        )
+    };
+    // SAFETY: plain calls returning static data, per the header.
+    let (abi, name, title, columns) = unsafe { (abi(), text(name()), text(title()), columns()) };
+    let file = path.display();
+    if abi != ABI_VERSION {
+        bail!("{file}: plugin ABI {abi}, this lazyserial speaks {ABI_VERSION}");
+    }
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        bail!("{file}: plugin name {name:?} is empty or has spaces");
+    }
+    // SAFETY: as above.
+    let columns = parse_columns(&unsafe { text(columns) }).with_context(|| file.to_string())?;
+    // Plugins stay loaded for the whole run, so their strings can too.
+    let name: &'static str = Box::leak(name.into_boxed_str());
+    let description: &'static str = Box::leak(format!("plugin {file}").into());
+    let plugin = Arc::new(Plugin {
+        title: Box::leak(title.into_boxed_str()),
+        columns: Box::leak(columns.into_boxed_slice()),
+        new,
+        free,
+        push,
+        flush,
+        _library: library,
+    });
+    registry.register(name, description, move || {
+        // SAFETY: per the header, `new` returns a fresh instance for `free`.
+        let state = unsafe { (plugin.new)() };
+        Box::new(PluginDecoder {
+            plugin: plugin.clone(),
+            state,
+        })
+    });
+    Ok(name)
+}
+
+/// `name:width` pairs separated by commas, e.g. `addr:4,command:12`.
+fn parse_columns(spec: &str) -> Result<Vec<Column>> {
+    spec.split(',')
+        .filter(|c| !c.trim().is_empty())
+        .map(|column| {
+            let (name, width) = column.split_once(':').unwrap_or((column, "8"));
+            let width = width
+                .trim()
+                .parse()
+                .with_context(|| format!("column width in {column:?}"))?;
+            Ok(Column {
+                name: Box::leak(name.trim().to_string().into_boxed_str()),
+                width,
+            })
+        })
+        .collect()
+}
+
+/// # Safety
+/// `ptr` is null or a NUL-terminated string.
+unsafe fn text(ptr: *const c_char) -> String {
+    if ptr.is_null() {
+        return String::new();
+    }
+    CStr::from_ptr(ptr).to_string_lossy().into_owned()
+}
+
+struct PluginDecoder {
+    plugin: Arc<Plugin>,
+    state: *mut c_void,
+}
+
+// SAFETY: the header requires an instance to be usable from any thread as
+// long as calls don't overlap, and `&mut self` keeps them from doing so.
+unsafe impl Send for PluginDecoder {}
+
+impl Drop for PluginDecoder {
+    fn drop(&mut self) {
+        // SAFETY: `state` came from this plugin's `new` and is freed once.
+        unsafe { (self.plugin.free)(self.state) };
+    }
+}
+
+/// Where `emit` collects records during one call into the plugin.
+struct Sink {
+    at: Instant,
+    records: Vec<Record>,
+}
+
+unsafe extern "C" fn emit(
+    ctx: *mut c_void,
+    direction: u8,
+    fields: *const c_char,
+    valid: i32,
+    details: *const c_char,
+    raw: *const u8,
+    raw_len: usize,
+) {
+    // SAFETY: `ctx` is the `Sink` passed to the call that is emitting.
+    let sink = &mut *(ctx as *mut Sink);
+    let raw = if raw.is_null() {
+        Vec::new()
+    } else {
+        std::slice::from_raw_parts(raw, raw_len).to_vec()
+    };
+    sink.records.push(Record {
+        direction: if direction == 0 { Direction::Rx } else { Direction::Tx },
+        at: sink.at,
+        fields: text(fields).split('\t').map(str::to_string).collect(),
+        valid: match valid {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        },
+        details: text(details),
+        raw,
+    });
+}
+
+impl Decoder for PluginDecoder {
+    fn title(&self) -> &'static str {
+        self.plugin.title
+    }
+
+    fn columns(&self) -> &'static [Column] {
+        self.plugin.columns
+    }
+
+    fn push(&mut self, direction: Direction, bytes: &[u8], now: Instant) -> Vec<Record> {
+        let mut sink = Sink {
+            at: now,
+            records: Vec::new(),
+        };
+        let direction = match direction {
+            Direction::Rx => 0,
+            Direction::Tx => 1,
+        };
+        let ctx = &mut sink as *mut Sink as *mut c_void;
+        // SAFETY: `bytes` and `sink` outlive the call, which is the only
+        // time the plugin may use them.
+        unsafe {
+            (self.plugin.push)(self.state, direction, bytes.as_ptr(), bytes.len(), emit, ctx);
+        }
+        sink.records
+    }
+
+    fn flush_due(&mut self, now: Instant) -> Vec<Record> {
+        let Some(flush) = self.plugin.flush else {
+            return Vec::new();
+        };
+        let mut sink = Sink {
+            at: now,
+            records: Vec::new(),
+        };
+        let ctx = &mut sink as *mut Sink as *mut c_void;
+        // SAFETY: as in `push`.
+        unsafe { flush(self.state, emit, ctx) };
+        sink.records
+    }
+}
@@ -0,0 +1,222 @@
+//! Keeping other programs off an open port, and saying who has it when
+//! opening fails.
+//!
+//! Opening a local port exclusively takes the serial crate's `TIOCEXCL`
+//! and `flock` locks on Unix. On top of that, a [`PortLock`] honours and
+//! writes UUCP-style lock files (`/run/lock/LCK..ttyUSB0`), the convention
+//! minicom, picocom and other terminal programs follow.
+
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Where lock files are looked for, and created in the first writable one.
+pub const LOCK_DIRS: &[&str] = &["/run/lock", "/var/lock"];
+
+/// A lock file held for an open port; removed when dropped.
+#[derive(Debug)]
+pub struct PortLock {
+    path: Option<PathBuf>,
+}
+
+impl PortLock {
+    /// Fails if a live process (this one included) holds a lock file for
+    /// `device`, naming it; otherwise takes one. Lock files left by dead
+    /// processes are cleared. Without a writable lock directory the lock is
+    /// empty rather than an error.
+    pub fn acquire(device: &str) -> io::Result<Self> {
+        let dirs: Vec<&Path> = LOCK_DIRS.iter().map(Path::new).collect();
+        Self::acquire_in(&dirs, device)
+    }
+
+    /// Like [`PortLock::acquire`], with lock files in `dirs`.
+    fn acquire_in(dirs: &[&Path], device: &str) -> io::Result<Self> {
+        let name = lock_name(device);
+        for dir in dirs {
+            let path = dir.join(&name);
+            match read_pid(&path) {
+                Some(pid) if alive(pid) => return Err(locked_by(pid, &path)),
+                // Left by a process that has gone.
+                Some(_) => {
+                    let _ = fs::remove_file(&path);
+                }
+                None => {}
+            }
+        }
+        for dir in dirs {
+            let path = dir.join(&name);
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    // The UUCP format: the pid in ten columns and a newline.
+                    writeln!(file, "{:>10}", std::process::id())?;
+                    return Ok(Self { path: Some(path) });
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                    // Someone else got there in between.
+                    return match read_pid(&path) {
+                        Some(pid) => Err(locked_by(pid, &path)),
+                        None => Err(held(format!("locked ({})", path.display()))),
+                    };
+                }
+                Err(_) => continue,
+            }
+        }
+        Ok(Self { path: None })
+    }
+
+    /// The lock file taken, if any.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+}
+
+impl Drop for PortLock {
+    fn drop(&mut self) {
+        if let Some(path) = &self.path {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// `LCK..` and the device's path under `/dev` with `/` as `_`, after
+/// following symlinks such as `/dev/serial/by-id/...`, so every name for a
+/// port maps to the same lock.
+fn lock_name(device: &str) -> String {
+    let path = fs::canonicalize(device).unwrap_or_else(|_| PathBuf::from(device));
+    let path = path.to_string_lossy();
+    let name = path.strip_prefix("/dev/").unwrap_or(&path).trim_start_matches('/');
+    format!("LCK..{}", name.replace('/', "_"))
+}
+
+/// The pid in a lock file: ASCII, or four binary bytes as old Kermit
+/// wrote them.
+fn read_pid(path: &Path) -> Option<u32> {
+    let bytes = fs::read(path).ok()?;
+    if let Ok(pid) = String::from_utf8_lossy(&bytes).trim().parse() {
+        return Some(pid);
+    }
+    let raw: [u8; 4] = bytes.as_slice().try_into().ok()?;
+    Some(u32::from_ne_bytes(raw))
+}
+
+fn locked_by(pid: u32, path: &Path) -> io::Error {
+    if pid == std::process::id() {
+        return held("already open in this program".to_string());
+    }
+    held(format!("locked by {} in {}", describe(pid), path.display()))
+}
+
+#[cfg(unix)]
+fn alive(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    if pid <= 0 {
+        return false;
+    }
+    // SAFETY: signal 0 only checks that the process exists.
+    let result = unsafe { libc::kill(pid, 0) };
+    result == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
+fn alive(_pid: u32) -> bool {
+    true
+}
+
+/// `name (pid N)` where the process name can be found, else `pid N`.
+fn describe(pid: u32) -> String {
+    match fs::read_to_string(format!("/proc/{pid}/comm")) {
+        Ok(name) => format!("{} (pid {pid})", name.trim()),
+        Err(_) => format!("pid {pid}"),
+    }
+}
+
+/// Processes other than this one with `device` open, described as by
+/// `describe`. Linux only, and only processes this user may look into.
+pub fn holders(device: &str) -> Vec<String> {
+    let Ok(device) = fs::canonicalize(device) else {
+        return Vec::new();
+    };
+    let Ok(procs) = fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    let own = std::process::id();
+    procs
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<u32>().ok())
+        .filter(|&pid| pid != own)
+        .filter(|pid| {
+            let Ok(fds) = fs::read_dir(format!("/proc/{pid}/fd")) else {
+                return false;
+            };
+            fds.filter_map(Result::ok)
+                .any(|fd| fs::read_link(fd.path()).is_ok_and(|target| target == device))
+        })
+        .map(describe)
+        .collect()
+}
+
+/// The error for `device` failing to open although it exists, which means
+/// another program has it: named where possible.
+pub fn busy(device: &str) -> io::Error {
+    let holders = holders(device);
+    let message = if holders.is_empty() {
+        "in use by another program".to_string()
+    } else {
+        format!("in use by {}", holders.join(", "))
+    };
+    held(message)
+}
+
+/// What the errors for a port someone else has carry, so [`is_busy`] can
+/// tell them from other failures.
+#[derive(Debug)]
+struct Held(String);
+
+impl fmt::Display for Held {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Held {}
+
+fn held(message: String) -> io::Error {
+    io::Error::other(Held(message))
+}
+
+/// Whether opening failed because another program (or this one) has the
+/// port.
+pub fn is_busy(e: &io::Error) -> bool {
+    #[cfg(unix)]
+    if e.raw_os_error() == Some(libc::EBUSY) {
+        return true;
+    }
+    e.get_ref().is_some_and(|inner| inner.is::<Held>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn lock_files_name_their_holder_and_stale_ones_are_cleared() {
+        let dir = tempfile::tempdir().unwrap();
+        let dirs = [dir.path()];
+        let lock_file = dir.path().join("LCK..ttyTEST0");
+        // pid 1 is always running.
+        std::fs::write(&lock_file, format!("{:>10}\n", 1)).unwrap();
+        let err = PortLock::acquire_in(&dirs, "/dev/ttyTEST0").unwrap_err();
+        assert!(err.to_string().contains("pid 1"), "{err}");
+
+        std::fs::write(&lock_file, format!("{:>10}\n", i32::MAX)).unwrap();
+        let lock = PortLock::acquire_in(&dirs, "/dev/ttyTEST0").unwrap();
+        assert_eq!(lock.path(), Some(lock_file.as_path()));
+        let pid: u32 = std::fs::read_to_string(&lock_file).unwrap().trim().parse().unwrap();
+        assert_eq!(pid, std::process::id());
+        drop(lock);
+        assert!(!lock_file.exists());
+    }
+}
@@ -0,0 +1,96 @@
+use std::fs;
+
+use lazyserial::serial;
+use serde::Deserialize;
+use serialport::{SerialPortInfo, SerialPortType};
+
+use crate::config::ProfileConfig;
+
+/// What the Input pane appends to each line it sends.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    Cr,
+    Crlf,
+    None,
+}
+
+impl LineEnding {
+    pub fn bytes(self) -> &'static [u8] {
+        match self {
+            LineEnding::Lf => b"\n",
+            LineEnding::Cr => b"\r",
+            LineEnding::Crlf => b"\r\n",
+            LineEnding::None => b"",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "lf",
+            LineEnding::Cr => "cr",
+            LineEnding::Crlf => "crlf",
+            LineEnding::None => "none",
+        }
+    }
+}
+
+/// Names a profile or favorite can use for `port`, most specific first:
+/// `vid:pid:serial` and `vid:pid` in hex for USB devices, then the port
+/// path.
+pub fn port_keys(port: &SerialPortInfo) -> Vec<String> {
+    let mut keys = Vec::new();
+    if let SerialPortType::UsbPort(usb) = &port.port_type {
+        let id = format!("{:04x}:{:04x}", usb.vid, usb.pid);
+        if let Some(serial) = &usb.serial_number {
+            keys.push(format!("{id}:{serial}"));
+        }
+        keys.push(id);
+    }
+    keys.push(port.port_name.clone());
+    keys
+}
+
+fn matches(key: &str, keys: &[String]) -> bool {
+    keys.iter().any(|k| k.eq_ignore_ascii_case(key))
+}
+
+/// The first profile whose `match` names one of `keys`.
+pub fn find<'a>(profiles: &'a [ProfileConfig], keys: &[String]) -> Option<&'a ProfileConfig> {
+    profiles.iter().find(|p| matches(&p.pattern, keys))
+}
+
+/// The attached port `target` leads to, by its name or through a link
+/// such as `/dev/serial/by-id/...`.
+pub fn attached<'a>(ports: &'a [SerialPortInfo], target: &str) -> Option<&'a SerialPortInfo> {
+    if let Some(port) = ports.iter().find(|p| p.port_name == target) {
+        return Some(port);
+    }
+    let device = fs::canonicalize(target).ok()?;
+    ports
+        .iter()
+        .find(|p| fs::canonicalize(&p.port_name).is_ok_and(|path| path == device))
+}
+
+/// The profile for a port given by path or remote target, looking the
+/// path up among attached ports so `vid:pid` profiles match too.
+pub fn for_target<'a>(profiles: &'a [ProfileConfig], target: &str) -> Option<&'a ProfileConfig> {
+    let ports = serial::list_ports().unwrap_or_default();
+    let keys = match attached(&ports, target) {
+        Some(port) => port_keys(port),
+        None => vec![target.to_string()],
+    };
+    find(profiles, &keys)
+}
+
+pub fn is_favorite(favorites: &[String], port: &SerialPortInfo) -> bool {
+    let keys = port_keys(port);
+    favorites.iter().any(|f| matches(f, &keys))
+}
+
+/// Moves favorite ports to the top, keeping discovery order otherwise.
+pub fn sort_ports(ports: &mut [SerialPortInfo], favorites: &[String]) {
+    ports.sort_by_key(|p| !is_favorite(favorites, p));
+}
@@ -0,0 +1,102 @@
+//! Recently opened ports and the settings they were last used with, kept in
+//! `recent.toml` next to the config file so the last one can be reopened
+//! with one key, also after a restart.
+
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use lazyserial::checksum::Algorithm;
+use lazyserial::serial::Parity;
+use serde::Deserialize;
+
+use crate::profile::LineEnding;
+
+/// Sessions kept; the oldest are forgotten.
+const MAX_RECENT: usize = 10;
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RecentSession {
+    pub target: String,
+    pub baud: u32,
+    #[serde(default)]
+    pub parity: Parity,
+    #[serde(default)]
+    pub line_ending: LineEnding,
+    pub checksum: Option<Algorithm>,
+}
+
+impl RecentSession {
+    /// The settings in a few words, for notes.
+    pub fn summary(&self) -> String {
+        let mut text = format!(
+            "{} {}, line ending {}",
+            self.baud,
+            self.parity.frame(),
+            self.line_ending.name()
+        );
+        if let Some(checksum) = self.checksum {
+            text.push_str(&format!(", {}", checksum.name()));
+        }
+        text
+    }
+
+    /// As written to the file.
+    pub fn to_table(&self) -> toml_edit::Table {
+        let mut table = toml_edit::Table::new();
+        table.insert("target", toml_edit::value(self.target.as_str()));
+        table.insert("baud", toml_edit::value(i64::from(self.baud)));
+        table.insert("parity", toml_edit::value(self.parity.name()));
+        table.insert("line_ending", toml_edit::value(self.line_ending.name()));
+        if let Some(checksum) = self.checksum {
+            table.insert("checksum", toml_edit::value(checksum.name()));
+        }
+        table
+    }
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RecentFile {
+    session: Vec<RecentSession>,
+}
+
+fn path() -> Option<PathBuf> {
+    dirs::config_dir().map(|d| d.join("lazyserial").join("recent.toml"))
+}
+
+/// Recent sessions, newest first; none before the first port is opened.
+pub fn load() -> Result<Vec<RecentSession>> {
+    let Some(path) = path() else {
+        return Ok(Vec::new());
+    };
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let text = fs::read_to_string(&path).with_context(|| format!("read {}", path.display()))?;
+    let file: RecentFile =
+        toml::from_str(&text).with_context(|| format!("parse {}", path.display()))?;
+    Ok(file.session)
+}
+
+/// Puts `session` first, replacing an older entry for the same target.
+pub fn remember(recent: &mut Vec<RecentSession>, session: RecentSession) {
+    recent.retain(|s| s.target != session.target);
+    recent.insert(0, session);
+    recent.truncate(MAX_RECENT);
+}
+
+pub fn save(recent: &[RecentSession]) -> Result<()> {
+    let path = path().ok_or_else(|| anyhow!("no config directory"))?;
+    let mut tables = toml_edit::ArrayOfTables::new();
+    for session in recent {
+        tables.push(session.to_table());
+    }
+    let mut doc = toml_edit::DocumentMut::new();
+    doc.insert("session", toml_edit::Item::ArrayOfTables(tables));
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("create {}", dir.display()))?;
+    }
+    fs::write(&path, doc.to_string()).with_context(|| format!("write {}", path.display()))
+}
@@ -0,0 +1,43 @@
+//! Built-in DTR/RTS sequences that reset common boards, for
+//! [`SerialHandle::run_sequence`](crate::SerialHandle::run_sequence).
+//!
+//! Levels are the logical line states the port reports: asserting a line
+//! drives the adapter's pin low, which is what the usual auto-reset
+//! circuits expect.
+
+use std::time::Duration;
+
+use crate::serial::LineStep;
+
+/// Names accepted by [`builtin`].
+pub const BUILTIN: &[&str] = &["esp32", "esp32-run", "arduino"];
+
+/// The sequence called `name`, if there is one:
+///
+/// - `esp32`: into the ROM bootloader through the EN/IO0 transistor pair
+///   on most dev boards, as esptool's classic reset does
+/// - `esp32-run`: a plain reset into the application
+/// - `arduino`: the DTR pulse that starts an AVR bootloader
+pub fn builtin(name: &str) -> Option<Vec<LineStep>> {
+    let step = |dtr, rts, ms| LineStep {
+        dtr,
+        rts,
+        hold: Duration::from_millis(ms),
+    };
+    let steps = match name {
+        // RTS holds EN low while IO0 stays high, then IO0 goes low as EN
+        // is released so the chip samples it at boot.
+        "esp32" => vec![
+            step(Some(false), Some(true), 100),
+            step(Some(true), Some(false), 50),
+            step(Some(false), None, 0),
+        ],
+        "esp32-run" => vec![step(Some(false), Some(true), 100), step(None, Some(false), 0)],
+        "arduino" => vec![
+            step(Some(false), Some(false), 250),
+            step(Some(true), Some(true), 50),
+        ],
+        _ => return None,
+    };
+    Some(steps)
+}
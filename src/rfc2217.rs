@@ -0,0 +1,313 @@
+//! Telnet client for network serial servers such as ser2net, optionally
+//! speaking the RFC 2217 COM-PORT-OPTION to set the line and read modem
+//! status on the remote port.
+
+use std::io::{self, ErrorKind};
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::serial::{ModemLines, Parity, PortSettings, SerialTransport};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WONT: u8 = 252;
+const WILL: u8 = 251;
+const SB: u8 = 250;
+const SE: u8 = 240;
+
+const OPT_BINARY: u8 = 0;
+const OPT_SGA: u8 = 3;
+const OPT_COM_PORT: u8 = 44;
+
+// COM-PORT-OPTION commands from the client; the server answers with +100.
+const SET_BAUDRATE: u8 = 1;
+const SET_DATASIZE: u8 = 2;
+const SET_PARITY: u8 = 3;
+const SET_STOPSIZE: u8 = 4;
+const SET_CONTROL: u8 = 5;
+const NOTIFY_MODEMSTATE: u8 = 7;
+const SET_MODEMSTATE_MASK: u8 = 11;
+const SERVER_OFFSET: u8 = 100;
+
+const PARITY_NONE: u8 = 1;
+const PARITY_ODD: u8 = 2;
+const PARITY_EVEN: u8 = 3;
+const STOPSIZE_1: u8 = 1;
+const CONTROL_BREAK_ON: u8 = 5;
+const CONTROL_BREAK_OFF: u8 = 6;
+const CONTROL_DTR_ON: u8 = 8;
+const CONTROL_DTR_OFF: u8 = 9;
+const CONTROL_RTS_ON: u8 = 11;
+const CONTROL_RTS_OFF: u8 = 12;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Raw telnet: data only, no control over the remote port.
+    Telnet,
+    Rfc2217,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum RxState {
+    Data,
+    Iac,
+    Option(u8),
+    Sub,
+    SubIac,
+}
+
+pub struct TelnetPort {
+    stream: TcpStream,
+    mode: Mode,
+    state: RxState,
+    sub: Vec<u8>,
+    /// Options enabled on our side (we sent WILL) and on the server's (DO).
+    local: [bool; 256],
+    remote: [bool; 256],
+    modem: ModemLines,
+    /// Negotiation bytes not yet written to the socket.
+    pending: Vec<u8>,
+    raw: Vec<u8>,
+}
+
+impl TelnetPort {
+    /// `addr` is `host:port`. In RFC 2217 mode the remote port is set up
+    /// from `settings` with DTR and RTS asserted, like a freshly opened
+    /// local port.
+    pub async fn connect(addr: &str, mode: Mode, settings: PortSettings) -> io::Result<Self> {
+        let mut last_err = io::Error::new(ErrorKind::NotFound, format!("{addr}: no addresses"));
+        let mut stream = None;
+        for sock in tokio::net::lookup_host(addr).await? {
+            match tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(sock)).await {
+                Ok(Ok(s)) => {
+                    stream = Some(s);
+                    break;
+                }
+                Ok(Err(e)) => last_err = e,
+                Err(_) => last_err = io::Error::new(ErrorKind::TimedOut, "connect timed out"),
+            }
+        }
+        let stream = stream.ok_or(last_err)?;
+        stream.set_nodelay(true)?;
+
+        let mut port = Self {
+            stream,
+            mode,
+            state: RxState::Data,
+            sub: Vec::new(),
+            local: [false; 256],
+            remote: [false; 256],
+            modem: ModemLines::default(),
+            pending: Vec::new(),
+            raw: Vec::new(),
+        };
+        port.negotiate(WILL, OPT_BINARY);
+        port.negotiate(DO, OPT_BINARY);
+        port.negotiate(DO, OPT_SGA);
+        if mode == Mode::Rfc2217 {
+            port.negotiate(WILL, OPT_COM_PORT);
+            let parity = match settings.parity {
+                Parity::None => PARITY_NONE,
+                Parity::Odd => PARITY_ODD,
+                Parity::Even => PARITY_EVEN,
+            };
+            port.com_port(SET_BAUDRATE, &settings.baud_rate.to_be_bytes());
+            port.com_port(SET_DATASIZE, &[8]);
+            port.com_port(SET_PARITY, &[parity]);
+            port.com_port(SET_STOPSIZE, &[STOPSIZE_1]);
+            port.com_port(SET_MODEMSTATE_MASK, &[0xf0]);
+            port.com_port(SET_CONTROL, &[CONTROL_DTR_ON]);
+            port.com_port(SET_CONTROL, &[CONTROL_RTS_ON]);
+        }
+        port.flush_pending().await?;
+        Ok(port)
+    }
+
+    /// Writes queued negotiation bytes. Cancel-safe: only what was actually
+    /// written is dropped from the queue.
+    async fn flush_pending(&mut self) -> io::Result<()> {
+        while !self.pending.is_empty() {
+            let n = self.stream.write(&self.pending).await?;
+            self.pending.drain(..n);
+        }
+        Ok(())
+    }
+
+    fn negotiate(&mut self, verb: u8, opt: u8) {
+        match verb {
+            WILL => self.local[opt as usize] = true,
+            WONT => self.local[opt as usize] = false,
+            DO => self.remote[opt as usize] = true,
+            DONT => self.remote[opt as usize] = false,
+            _ => {}
+        }
+        self.pending.extend_from_slice(&[IAC, verb, opt]);
+    }
+
+    fn com_port(&mut self, cmd: u8, payload: &[u8]) {
+        self.pending.extend_from_slice(&[IAC, SB, OPT_COM_PORT, cmd]);
+        for &b in payload {
+            self.pending.push(b);
+            if b == IAC {
+                self.pending.push(IAC);
+            }
+        }
+        self.pending.extend_from_slice(&[IAC, SE]);
+    }
+
+    fn require_rfc2217(&self) -> io::Result<()> {
+        if self.mode == Mode::Rfc2217 {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                ErrorKind::Unsupported,
+                "not available over plain telnet (use rfc2217://)",
+            ))
+        }
+    }
+
+    /// Answers a DO/DONT/WILL/WONT from the server, without re-acknowledging
+    /// states we are already in so the two sides cannot loop.
+    fn on_option(&mut self, verb: u8, opt: u8) {
+        let ours = matches!(opt, OPT_BINARY | OPT_SGA)
+            || (opt == OPT_COM_PORT && self.mode == Mode::Rfc2217);
+        let theirs = matches!(opt, OPT_BINARY | OPT_SGA);
+        match verb {
+            DO if ours && !self.local[opt as usize] => self.negotiate(WILL, opt),
+            DO if !ours => self.negotiate(WONT, opt),
+            DONT if self.local[opt as usize] => self.negotiate(WONT, opt),
+            WILL if theirs && !self.remote[opt as usize] => self.negotiate(DO, opt),
+            WILL if !theirs => self.negotiate(DONT, opt),
+            WONT if self.remote[opt as usize] => self.negotiate(DONT, opt),
+            _ => {}
+        }
+    }
+
+    fn on_subnegotiation(&mut self) {
+        if let [OPT_COM_PORT, cmd, state, ..] = self.sub[..] {
+            if cmd == NOTIFY_MODEMSTATE + SERVER_OFFSET {
+                self.modem = ModemLines {
+                    cts: state & 0x10 != 0,
+                    dsr: state & 0x20 != 0,
+                    ri: state & 0x40 != 0,
+                    cd: state & 0x80 != 0,
+                };
+            }
+        }
+    }
+
+    /// Runs received bytes through the telnet parser, copying data bytes to
+    /// `buf` and queueing any negotiation replies.
+    fn decode(&mut self, raw: &[u8], buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        for &b in raw {
+            self.state = match (self.state, b) {
+                (RxState::Data, IAC) => RxState::Iac,
+                (RxState::Data, b) => {
+                    buf[len] = b;
+                    len += 1;
+                    RxState::Data
+                }
+                (RxState::Iac, IAC) => {
+                    buf[len] = IAC;
+                    len += 1;
+                    RxState::Data
+                }
+                (RxState::Iac, DO | DONT | WILL | WONT) => RxState::Option(b),
+                (RxState::Iac, SB) => {
+                    self.sub.clear();
+                    RxState::Sub
+                }
+                (RxState::Iac, _) => RxState::Data,
+                (RxState::Option(verb), opt) => {
+                    self.on_option(verb, opt);
+                    RxState::Data
+                }
+                (RxState::Sub, IAC) => RxState::SubIac,
+                (RxState::Sub, b) => {
+                    self.sub.push(b);
+                    RxState::Sub
+                }
+                (RxState::SubIac, SE) => {
+                    self.on_subnegotiation();
+                    RxState::Data
+                }
+                (RxState::SubIac, b) => {
+                    self.sub.push(b);
+                    RxState::Sub
+                }
+            };
+        }
+        len
+    }
+}
+
+impl SerialTransport for TelnetPort {
+    /// Keeps reading until real data arrives; reads that carry only telnet
+    /// commands are answered and swallowed. Returns 0 once the server hangs up.
+    async fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            self.flush_pending().await?;
+            self.raw.resize(buf.len(), 0);
+            let n = self.stream.read(&mut self.raw).await?;
+            if n == 0 {
+                return Ok(0);
+            }
+            let raw = std::mem::take(&mut self.raw);
+            let len = self.decode(&raw[..n], buf);
+            self.raw = raw;
+            if len > 0 {
+                return Ok(len);
+            }
+        }
+    }
+
+    async fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+        self.flush_pending().await?;
+        let mut escaped = Vec::with_capacity(data.len());
+        for &b in data {
+            escaped.push(b);
+            if b == IAC {
+                escaped.push(IAC);
+            }
+        }
+        self.stream.write_all(&escaped).await
+    }
+
+    async fn set_dtr(&mut self, level: bool) -> io::Result<()> {
+        self.require_rfc2217()?;
+        self.com_port(SET_CONTROL, &[if level { CONTROL_DTR_ON } else { CONTROL_DTR_OFF }]);
+        self.flush_pending().await
+    }
+
+    async fn set_rts(&mut self, level: bool) -> io::Result<()> {
+        self.require_rfc2217()?;
+        self.com_port(SET_CONTROL, &[if level { CONTROL_RTS_ON } else { CONTROL_RTS_OFF }]);
+        self.flush_pending().await
+    }
+
+    async fn modem_lines(&mut self) -> io::Result<ModemLines> {
+        self.require_rfc2217()?;
+        Ok(self.modem)
+    }
+
+    async fn drain(&mut self) -> io::Result<()> {
+        self.flush_pending().await?;
+        self.stream.flush().await
+    }
+
+    /// Timed here, so network jitter stretches or shortens it.
+    async fn send_break(&mut self, duration: Duration) -> io::Result<()> {
+        self.require_rfc2217()?;
+        self.com_port(SET_CONTROL, &[CONTROL_BREAK_ON]);
+        self.flush_pending().await?;
+        tokio::time::sleep(duration).await;
+        self.com_port(SET_CONTROL, &[CONTROL_BREAK_OFF]);
+        self.flush_pending().await
+    }
+}
@@ -0,0 +1,179 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use rhai::{Dynamic, Engine, EvalAltResult, Position};
+
+const DEFAULT_EXPECT_TIMEOUT_MS: i64 = 5000;
+/// Granularity at which blocking script calls notice cancellation.
+const CANCEL_POLL: Duration = Duration::from_millis(50);
+
+#[derive(Debug)]
+pub enum ScriptEvent {
+    Log(String),
+    Send(Vec<u8>),
+    Break(Duration),
+    Finished(Result<(), String>),
+}
+
+pub struct ScriptHandle {
+    pub name: String,
+    line_tx: Sender<String>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl ScriptHandle {
+    /// Hands a received line to the script for `expect()`.
+    pub fn feed_line(&self, line: &str) {
+        let _ = self.line_tx.send(line.to_string());
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Compiles and starts a Rhai script on its own thread.
+///
+/// Scripts get `send(text)`, `send_break(ms)`, `expect(pattern[, timeout_ms])`,
+/// `sleep(ms)` and `log(msg)`. `expect` takes a regex, consumes received lines until one
+/// matches and returns it, or throws on timeout.
+pub fn spawn(path: &Path) -> Result<(ScriptHandle, Receiver<ScriptEvent>)> {
+    let source =
+        std::fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string());
+
+    let (event_tx, event_rx) = mpsc::channel::<ScriptEvent>();
+    let (line_tx, line_rx) = mpsc::channel::<String>();
+    let cancel = Arc::new(AtomicBool::new(false));
+
+    let thread_cancel = cancel.clone();
+    thread::spawn(move || {
+        let engine = build_engine(event_tx.clone(), line_rx, thread_cancel);
+        let result = engine
+            .compile(&source)
+            .map_err(|e| e.to_string())
+            .and_then(|ast| engine.run_ast(&ast).map_err(|e| e.to_string()));
+        let _ = event_tx.send(ScriptEvent::Finished(result));
+    });
+
+    Ok((
+        ScriptHandle {
+            name,
+            line_tx,
+            cancel,
+        },
+        event_rx,
+    ))
+}
+
+fn build_engine(
+    event_tx: Sender<ScriptEvent>,
+    line_rx: Receiver<String>,
+    cancel: Arc<AtomicBool>,
+) -> Engine {
+    let mut engine = Engine::new();
+
+    let progress_cancel = cancel.clone();
+    engine.on_progress(move |_| {
+        progress_cancel
+            .load(Ordering::Relaxed)
+            .then(|| Dynamic::from("cancelled"))
+    });
+
+    let tx = event_tx.clone();
+    engine.on_print(move |s| {
+        let _ = tx.send(ScriptEvent::Log(s.to_string()));
+    });
+    let tx = event_tx.clone();
+    engine.register_fn("log", move |msg: &str| {
+        let _ = tx.send(ScriptEvent::Log(msg.to_string()));
+    });
+
+    let tx = event_tx.clone();
+    engine.register_fn("send", move |text: &str| {
+        let _ = tx.send(ScriptEvent::Send(text.as_bytes().to_vec()));
+    });
+
+    let tx = event_tx;
+    engine.register_fn("send_break", move |ms: i64| {
+        let _ = tx.send(ScriptEvent::Break(Duration::from_millis(ms.max(0) as u64)));
+    });
+
+    let sleep_cancel = cancel.clone();
+    engine.register_fn("sleep", move |ms: i64| -> Result<(), Box<EvalAltResult>> {
+        let deadline = Instant::now() + Duration::from_millis(ms.max(0) as u64);
+        while Instant::now() < deadline {
+            if sleep_cancel.load(Ordering::Relaxed) {
+                return Err(cancelled());
+            }
+            thread::sleep(CANCEL_POLL.min(deadline.saturating_duration_since(Instant::now())));
+        }
+        Ok(())
+    });
+
+    let lines = std::rc::Rc::new(line_rx);
+    let expect_lines = lines.clone();
+    let expect_cancel = cancel.clone();
+    engine.register_fn(
+        "expect",
+        move |pattern: &str, timeout_ms: i64| -> Result<String, Box<EvalAltResult>> {
+            expect(&expect_lines, &expect_cancel, pattern, timeout_ms)
+        },
+    );
+    engine.register_fn(
+        "expect",
+        move |pattern: &str| -> Result<String, Box<EvalAltResult>> {
+            expect(&lines, &cancel, pattern, DEFAULT_EXPECT_TIMEOUT_MS)
+        },
+    );
+
+    engine
+}
+
+fn expect(
+    lines: &Receiver<String>,
+    cancel: &AtomicBool,
+    pattern: &str,
+    timeout_ms: i64,
+) -> Result<String, Box<EvalAltResult>> {
+    let re = Regex::new(pattern).map_err(|e| runtime_error(format!("bad pattern: {e}")))?;
+    let deadline = Instant::now() + Duration::from_millis(timeout_ms.max(0) as u64);
+    loop {
+        if cancel.load(Ordering::Relaxed) {
+            return Err(cancelled());
+        }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(runtime_error(format!(
+                "expect timed out after {timeout_ms} ms waiting for /{pattern}/"
+            )));
+        }
+        match lines.recv_timeout(remaining.min(CANCEL_POLL)) {
+            Ok(line) if re.is_match(&line) => return Ok(line),
+            Ok(_) | Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(runtime_error("session ended".to_string()));
+            }
+        }
+    }
+}
+
+fn runtime_error(msg: String) -> Box<EvalAltResult> {
+    Box::new(EvalAltResult::ErrorRuntime(msg.into(), Position::NONE))
+}
+
+fn cancelled() -> Box<EvalAltResult> {
+    Box::new(EvalAltResult::ErrorTerminated(
+        Dynamic::from("cancelled"),
+        Position::NONE,
+    ))
+}
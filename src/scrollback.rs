@@ -0,0 +1,308 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+/// Lines kept in memory; older ones move to disk.
+const RAM_LINES: usize = 5000;
+/// Lines per spill file, so the oldest can be dropped a file at a time.
+const CHUNK_LINES: usize = 16 * 1024;
+
+/// How much a [`Scrollback`] may hold.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// Lines kept in all; `None` for as many as memory and disk allow.
+    pub max_lines: Option<usize>,
+    /// Bytes of lines kept in memory; older lines move to disk, so a flood
+    /// of long lines can't fill RAM. The newest line stays regardless.
+    pub max_ram_bytes: usize,
+    /// 0 keeps only the in-memory lines.
+    pub max_disk_bytes: u64,
+}
+
+/// Output history: recent lines in RAM, older ones spilled to anonymous
+/// temporary files. Disk use is capped; past the cap the oldest chunk is
+/// dropped, making it a ring. Indices run from the oldest retained line.
+pub struct Scrollback {
+    recent: VecDeque<String>,
+    ram_bytes: usize,
+    chunks: VecDeque<Chunk>,
+    /// Lines of the first chunk already dropped by the line limit.
+    skipped: usize,
+    limits: Limits,
+    disk_bytes: u64,
+    evicted: usize,
+    error: Option<String>,
+}
+
+/// What a [`Scrollback`] holds and where; see [`Scrollback::usage`].
+pub struct Usage {
+    pub ram_lines: usize,
+    pub ram_bytes: usize,
+    pub disk_lines: usize,
+    pub disk_bytes: u64,
+    pub limits: Limits,
+    /// Lines dropped for good.
+    pub evicted: usize,
+}
+
+struct Chunk {
+    file: File,
+    /// End offset of each line in `file`.
+    ends: Vec<u64>,
+}
+
+impl Chunk {
+    fn len_bytes(&self) -> u64 {
+        self.ends.last().copied().unwrap_or(0)
+    }
+
+    fn read(&self, i: usize) -> io::Result<String> {
+        let start = if i == 0 { 0 } else { self.ends[i - 1] };
+        let mut buf = vec![0u8; (self.ends[i] - start) as usize];
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut buf)?;
+        Ok(String::from_utf8_lossy(&buf).into_owned())
+    }
+}
+
+impl Scrollback {
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            recent: VecDeque::new(),
+            ram_bytes: 0,
+            chunks: VecDeque::new(),
+            skipped: 0,
+            limits,
+            disk_bytes: 0,
+            evicted: 0,
+            error: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.spilled() + self.recent.len()
+    }
+
+    /// Lines dropped from the front so far; `evicted() + idx` is a stable
+    /// line number.
+    pub fn evicted(&self) -> usize {
+        self.evicted
+    }
+
+    /// The first spill error, if any. Spilling stops after one.
+    pub fn take_error(&mut self) -> Option<String> {
+        self.error.take()
+    }
+
+    pub fn push(&mut self, line: String) {
+        self.ram_bytes += line.len();
+        self.recent.push_back(line);
+        while self.recent.len() > RAM_LINES
+            || (self.ram_bytes > self.limits.max_ram_bytes && self.recent.len() > 1)
+        {
+            if let Some(old) = self.recent.pop_front() {
+                self.ram_bytes -= old.len();
+                self.spill(old);
+            }
+        }
+        self.trim();
+    }
+
+    /// Puts `line` in place of the newest line, e.g. to count a repeat of
+    /// it.
+    pub fn replace_last(&mut self, line: String) {
+        if let Some(last) = self.recent.back_mut() {
+            self.ram_bytes = self.ram_bytes - last.len() + line.len();
+            *last = line;
+        }
+    }
+
+    /// Changes the line limit, dropping the oldest lines over a lower one.
+    pub fn set_max_lines(&mut self, max_lines: Option<usize>) {
+        self.limits.max_lines = max_lines;
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        let Some(max) = self.limits.max_lines else {
+            return;
+        };
+        while self.len() > max {
+            if let Some(chunk) = self.chunks.front() {
+                self.skipped += 1;
+                if self.skipped == chunk.ends.len() {
+                    self.disk_bytes -= chunk.len_bytes();
+                    self.chunks.pop_front();
+                    self.skipped = 0;
+                }
+            } else if let Some(old) = self.recent.pop_front() {
+                self.ram_bytes -= old.len();
+            }
+            self.evicted += 1;
+        }
+    }
+
+    pub fn get(&self, idx: usize) -> Option<Cow<'_, str>> {
+        let spilled = self.spilled();
+        if idx >= spilled {
+            return self.recent.get(idx - spilled).map(|s| Cow::Borrowed(s.as_str()));
+        }
+        let mut i = idx + self.skipped;
+        for chunk in &self.chunks {
+            if i < chunk.ends.len() {
+                let line = chunk
+                    .read(i)
+                    .unwrap_or_else(|e| format!("[scrollback read error: {e}]"));
+                return Some(Cow::Owned(line));
+            }
+            i -= chunk.ends.len();
+        }
+        None
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Cow<'_, str>> {
+        (0..self.len()).filter_map(|i| self.get(i))
+    }
+
+    pub fn usage(&self) -> Usage {
+        Usage {
+            ram_lines: self.recent.len(),
+            ram_bytes: self.ram_bytes,
+            disk_lines: self.spilled(),
+            disk_bytes: self.disk_bytes,
+            limits: self.limits,
+            evicted: self.evicted,
+        }
+    }
+
+    /// Empties the history, handing back what it held. Line numbers go on
+    /// from where they were, so the lines can be put back with `restore`.
+    pub fn take(&mut self) -> Scrollback {
+        let mut empty = Scrollback::new(self.limits);
+        empty.evicted = self.evicted + self.len();
+        std::mem::replace(self, empty)
+    }
+
+    /// Puts back what `take` handed out, ahead of the lines pushed since.
+    /// False, leaving things as they are, once some of those were dropped.
+    pub fn restore(&mut self, mut old: Scrollback) -> bool {
+        if old.evicted + old.len() != self.evicted {
+            return false;
+        }
+        for line in self.iter() {
+            old.push(line.into_owned());
+        }
+        if let Some(err) = self.error.take() {
+            old.error.get_or_insert(err);
+        }
+        *self = old;
+        true
+    }
+
+    fn spilled(&self) -> usize {
+        self.chunks.iter().map(|c| c.ends.len()).sum::<usize>() - self.skipped
+    }
+
+    fn spill(&mut self, line: String) {
+        if self.limits.max_disk_bytes == 0 {
+            self.evicted += 1;
+            return;
+        }
+        if let Err(e) = self.write_line(&line) {
+            self.error = Some(format!("scrollback spill failed, keeping RAM only: {e}"));
+            self.limits.max_disk_bytes = 0;
+            self.evicted += 1;
+            return;
+        }
+        while self.disk_bytes > self.limits.max_disk_bytes && self.chunks.len() > 1 {
+            if let Some(chunk) = self.chunks.pop_front() {
+                self.disk_bytes -= chunk.len_bytes();
+                self.evicted += chunk.ends.len() - self.skipped;
+                self.skipped = 0;
+            }
+        }
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let full = match self.chunks.back() {
+            Some(chunk) => chunk.ends.len() >= CHUNK_LINES,
+            None => true,
+        };
+        if full {
+            self.chunks.push_back(Chunk {
+                file: tempfile::tempfile()?,
+                ends: Vec::new(),
+            });
+        }
+        let Some(chunk) = self.chunks.back_mut() else {
+            return Ok(());
+        };
+        let mut file = &chunk.file;
+        file.seek(SeekFrom::Start(chunk.len_bytes()))?;
+        file.write_all(line.as_bytes())?;
+        chunk.ends.push(chunk.len_bytes() + line.len() as u64);
+        self.disk_bytes += line.len() as u64;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks `scrollback` against `model`, every line pushed so far.
+    fn check(scrollback: &Scrollback, model: &[String]) {
+        let kept = &model[scrollback.evicted()..];
+        assert_eq!(scrollback.len(), kept.len());
+        for (i, line) in kept.iter().enumerate() {
+            assert_eq!(scrollback.get(i).as_deref(), Some(line.as_str()), "line {i}");
+        }
+        assert_eq!(scrollback.get(kept.len()), None);
+        assert!(scrollback.iter().eq(kept.iter().map(|l| Cow::Borrowed(l.as_str()))));
+    }
+
+    #[test]
+    fn spills_and_evicts_in_line_order() {
+        let limits = Limits {
+            max_lines: None,
+            max_ram_bytes: 1000,
+            max_disk_bytes: 400_000,
+        };
+        let mut scrollback = Scrollback::new(limits);
+        let line = |n: usize| format!("{n}:{}", "x".repeat(n % 7));
+        let mut model: Vec<String> = Vec::new();
+
+        for n in 0..300 {
+            model.push(line(n));
+            scrollback.push(line(n));
+        }
+        assert!(scrollback.usage().disk_lines > 0);
+        check(&scrollback, &model);
+
+        // Over four chunks' worth, so the oldest go.
+        for n in 300..4 * CHUNK_LINES {
+            model.push(line(n));
+            scrollback.push(line(n));
+        }
+        assert!(scrollback.evicted() > 0);
+        check(&scrollback, &model);
+
+        // A line limit partway into the first chunk left.
+        assert!(scrollback.chunks.len() > 1);
+        scrollback.set_max_lines(Some(scrollback.len() - 10));
+        assert!(scrollback.skipped > 0);
+        check(&scrollback, &model);
+
+        let old = scrollback.take();
+        assert_eq!(scrollback.len(), 0);
+        for n in 4 * CHUNK_LINES..4 * CHUNK_LINES + 50 {
+            model.push(line(n));
+            scrollback.push(line(n));
+        }
+        check(&scrollback, &model);
+        assert!(scrollback.restore(old));
+        check(&scrollback, &model);
+    }
+}
@@ -1,105 +1,1179 @@
-use std::io::{Read, Write};
-use std::sync::mpsc::{self, Receiver, Sender};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io;
+use std::sync::mpsc::SendError;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock, PoisonError};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result};
+use serde::Deserialize;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::sync::Notify;
+use tokio_serial::{SerialPortBuilderExt, SerialStream};
 
 pub use serialport::{SerialPort, SerialPortInfo};
 
+use crate::ble::{self, NusPort};
+use crate::bluetooth::{self, RfcommPort};
+use crate::portlock::{self, PortLock};
+use crate::rfc2217::{self, TelnetPort};
+
+const MODEM_POLL_INTERVAL: Duration = Duration::from_millis(100);
+const STATS_INTERVAL: Duration = Duration::from_millis(250);
+const READ_BUFFER: usize = 16 * 1024;
+/// Largest piece of a write done in one go, so reads and progress reports
+/// carry on through a long send.
+const WRITE_CHUNK: usize = 512;
+
+/// Parity bit; frames are always 8 data bits and 1 stop bit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Parity {
+    #[default]
+    None,
+    Odd,
+    Even,
+}
+
+impl Parity {
+    /// Frame format in the usual shorthand, e.g. `8N1`.
+    pub fn frame(self) -> &'static str {
+        match self {
+            Parity::None => "8N1",
+            Parity::Odd => "8O1",
+            Parity::Even => "8E1",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Parity::None => "none",
+            Parity::Odd => "odd",
+            Parity::Even => "even",
+        }
+    }
+}
+
+/// Line settings applied when a port is opened.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PortSettings {
+    pub baud_rate: u32,
+    pub parity: Parity,
+    /// Half-duplex direction control; local ports only.
+    pub rs485: Option<Rs485>,
+    /// Keep other programs off a local port while it is open, and refuse
+    /// one locked by another; see [`portlock`](crate::portlock).
+    pub exclusive: bool,
+}
+
+impl PortSettings {
+    pub fn new(baud_rate: u32) -> Self {
+        Self {
+            baud_rate,
+            parity: Parity::None,
+            rs485: None,
+            exclusive: true,
+        }
+    }
+}
+
+/// RS-485 direction control for transceivers without automatic direction
+/// switching: RTS enables the driver for the duration of each write. On
+/// Linux the kernel's RS-485 mode is used when the driver supports it,
+/// otherwise the worker toggles RTS itself.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rs485 {
+    /// RTS level while transmitting; the other level is held otherwise.
+    pub rts_on_send: bool,
+    /// From enabling the driver to the first bit.
+    pub delay_before: Duration,
+    /// From the last bit leaving the UART to releasing the driver.
+    pub delay_after: Duration,
+}
+
+impl Default for Rs485 {
+    fn default() -> Self {
+        Self {
+            rts_on_send: true,
+            delay_before: Duration::ZERO,
+            delay_after: Duration::ZERO,
+        }
+    }
+}
+
+/// One step of a control-line sequence: set the lines given, then hold
+/// them for `hold` before the next step.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LineStep {
+    pub dtr: Option<bool>,
+    pub rts: Option<bool>,
+    pub hold: Duration,
+}
+
+/// Input control lines reported by the device side.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ModemLines {
+    pub cts: bool,
+    pub dsr: bool,
+    pub cd: bool,
+    pub ri: bool,
+}
+
+/// Running totals for the open port since it was opened.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct IoStats {
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub errors: u64,
+    /// Received bytes discarded because the caller fell behind; see
+    /// [`Overflow`].
+    pub dropped: u64,
+    /// Receive errors counted by the driver, for ports that report them.
+    pub line_errors: Option<LineErrors>,
+}
+
+/// Receive errors a UART driver counted since the port was opened (Linux
+/// `TIOCGICOUNT`); data with these is corrupted or lost before it reaches
+/// us.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LineErrors {
+    /// Bad stop bit: usually a baud rate mismatch or noise.
+    pub framing: u32,
+    pub parity: u32,
+    /// Bytes the UART received before the driver read the previous ones.
+    pub overrun: u32,
+    pub breaks: u32,
+    /// Bytes the driver's buffer had no room for.
+    pub buffer_overrun: u32,
+}
+
+impl LineErrors {
+    pub fn total(&self) -> u64 {
+        [self.framing, self.parity, self.overrun, self.breaks, self.buffer_overrun]
+            .iter()
+            .map(|&n| u64::from(n))
+            .sum()
+    }
+
+    /// Counts accumulated since `base`; the driver's counters run from when
+    /// the device appeared, not from the open.
+    fn since(self, base: LineErrors) -> LineErrors {
+        LineErrors {
+            framing: self.framing.wrapping_sub(base.framing),
+            parity: self.parity.wrapping_sub(base.parity),
+            overrun: self.overrun.wrapping_sub(base.overrun),
+            breaks: self.breaks.wrapping_sub(base.breaks),
+            buffer_overrun: self.buffer_overrun.wrapping_sub(base.buffer_overrun),
+        }
+    }
+}
+
+/// How far the worker is through the data handed to
+/// [`SerialHandle::write`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TxProgress {
+    /// Bytes written since the queue was last empty.
+    pub written: u64,
+    /// Bytes still waiting to be written.
+    pub queued: usize,
+}
+
+/// Which way bytes crossed the port.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Rx,
+    Tx,
+}
+
+impl Direction {
+    pub fn name(self) -> &'static str {
+        match self {
+            Direction::Rx => "RX",
+            Direction::Tx => "TX",
+        }
+    }
+}
+
+/// Why a port couldn't be opened, as far as it matters for fixing it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OpenFailure {
+    /// No access to the device file, e.g. not in the `dialout` group.
+    PermissionDenied,
+    /// Another program, or this one, has the port.
+    Busy,
+    /// No such device, e.g. unplugged.
+    NotFound,
+    Other,
+}
+
+impl OpenFailure {
+    pub fn of(e: &io::Error) -> Self {
+        if portlock::is_busy(e) {
+            return OpenFailure::Busy;
+        }
+        match e.kind() {
+            io::ErrorKind::PermissionDenied => OpenFailure::PermissionDenied,
+            io::ErrorKind::NotFound => OpenFailure::NotFound,
+            _ => OpenFailure::Other,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum SerialEvent {
     Opened,
+    /// Why the port couldn't be opened, with the system's error; follows
+    /// the `Error` saying so and precedes `Closed`.
+    OpenFailed(OpenFailure, String),
     Data(Vec<u8>),
+    ModemStatus(ModemLines),
+    /// Sent at most every `STATS_INTERVAL`, and only when something changed.
+    Stats(IoStats),
+    /// After each piece of data written.
+    TxProgress(TxProgress),
+    /// Everything handed to [`SerialHandle::write`] has been written and,
+    /// as far as the port can tell, sent.
+    TxDone,
     Error(String),
     Closed,
 }
 
+/// A byte stream the worker can drive: a local port, a network serial
+/// server or, in tests, a [`MockTransport`](crate::mock::MockTransport).
+/// The worker races `read` against incoming commands, so it must be
+/// cancel-safe. `read` returning 0 means the other end went away.
+pub trait SerialTransport {
+    fn read(&mut self, buf: &mut [u8]) -> impl Future<Output = io::Result<usize>>;
+    fn write_all(&mut self, data: &[u8]) -> impl Future<Output = io::Result<()>>;
+    fn set_dtr(&mut self, level: bool) -> impl Future<Output = io::Result<()>>;
+    fn set_rts(&mut self, level: bool) -> impl Future<Output = io::Result<()>>;
+    fn modem_lines(&mut self) -> impl Future<Output = io::Result<ModemLines>>;
+    /// Waits until written data has been sent, as far as the transport can
+    /// tell.
+    fn drain(&mut self) -> impl Future<Output = io::Result<()>>;
+    /// Holds the line in the break condition for `duration`.
+    fn send_break(&mut self, duration: Duration) -> impl Future<Output = io::Result<()>>;
+    /// The driver's running receive error counts; only local ports on
+    /// Linux have them.
+    fn line_errors(&mut self) -> io::Result<LineErrors> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+impl SerialTransport for SerialStream {
+    async fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        AsyncReadExt::read(self, buf).await
+    }
+
+    async fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+        AsyncWriteExt::write_all(self, data).await
+    }
+
+    async fn set_dtr(&mut self, level: bool) -> io::Result<()> {
+        Ok(self.write_data_terminal_ready(level)?)
+    }
+
+    async fn set_rts(&mut self, level: bool) -> io::Result<()> {
+        Ok(self.write_request_to_send(level)?)
+    }
+
+    async fn modem_lines(&mut self) -> io::Result<ModemLines> {
+        Ok(ModemLines {
+            cts: self.read_clear_to_send()?,
+            dsr: self.read_data_set_ready()?,
+            cd: self.read_carrier_detect()?,
+            ri: self.read_ring_indicator()?,
+        })
+    }
+
+    async fn drain(&mut self) -> io::Result<()> {
+        // `tcdrain` on unix, `FlushFileBuffers` on Windows.
+        AsyncWriteExt::flush(self).await
+    }
+
+    async fn send_break(&mut self, duration: Duration) -> io::Result<()> {
+        self.set_break()?;
+        tokio::time::sleep(duration).await;
+        Ok(self.clear_break()?)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn line_errors(&mut self) -> io::Result<LineErrors> {
+        use std::os::fd::AsRawFd;
+
+        /// `struct serial_icounter_struct`.
+        #[repr(C)]
+        #[derive(Default)]
+        struct Icounter {
+            cts: libc::c_int,
+            dsr: libc::c_int,
+            rng: libc::c_int,
+            dcd: libc::c_int,
+            rx: libc::c_int,
+            tx: libc::c_int,
+            frame: libc::c_int,
+            overrun: libc::c_int,
+            parity: libc::c_int,
+            brk: libc::c_int,
+            buf_overrun: libc::c_int,
+            reserved: [libc::c_int; 9],
+        }
+
+        let mut counts = Icounter::default();
+        // SAFETY: TIOCGICOUNT fills in a `serial_icounter_struct`.
+        let result = unsafe { libc::ioctl(self.as_raw_fd(), libc::TIOCGICOUNT, &mut counts) };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(LineErrors {
+            framing: counts.frame as u32,
+            parity: counts.parity as u32,
+            overrun: counts.overrun as u32,
+            breaks: counts.brk as u32,
+            buffer_overrun: counts.buf_overrun as u32,
+        })
+    }
+}
+
+/// Requests handled by the serial worker.
+#[derive(Debug)]
+enum SerialCommand {
+    Write(Vec<u8>),
+    SetDtr(bool),
+    SetRts(bool),
+    Break(Duration),
+    Sequence(Vec<LineStep>),
+    Close,
+}
+
+/// Received data queued for the caller before the worker applies the
+/// overflow policy.
+pub const DEFAULT_RX_BUFFER: usize = 16 * 1024 * 1024;
+
+/// What happens to received data once the caller has fallen behind by the
+/// receive buffer's worth of it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Overflow {
+    /// Discard the oldest queued data to make room.
+    #[default]
+    DropOldest,
+    /// Discard data as it arrives until there is room again.
+    DropNewest,
+    /// Stop reading, with RTS deasserted so a device using hardware flow
+    /// control holds off, until the caller catches up.
+    Pause,
+}
+
+impl Overflow {
+    pub fn name(self) -> &'static str {
+        match self {
+            Overflow::DropOldest => "drop-oldest",
+            Overflow::DropNewest => "drop-newest",
+            Overflow::Pause => "pause",
+        }
+    }
+}
+
+/// Events on their way from the worker to the caller.
+struct Queue {
+    state: Mutex<QueueState>,
+    /// Signalled when an event is queued or the worker exits.
+    ready: Condvar,
+    /// Signalled when the caller takes data, for a paused worker.
+    space: Notify,
+    /// Called after each event the worker sends.
+    wake: OnceLock<Box<dyn Fn() + Send + Sync>>,
+}
+
+struct QueueState {
+    events: VecDeque<SerialEvent>,
+    /// Bytes in the queued `Data` events.
+    bytes: usize,
+    limit: usize,
+    overflow: Overflow,
+    /// Received bytes discarded since the port opened.
+    dropped: u64,
+    /// The worker has exited; nothing more will be queued.
+    closed: bool,
+    /// The caller dropped its end.
+    abandoned: bool,
+}
+
+impl Queue {
+    fn new() -> Self {
+        Self {
+            state: Mutex::new(QueueState {
+                events: VecDeque::new(),
+                bytes: 0,
+                limit: DEFAULT_RX_BUFFER,
+                overflow: Overflow::default(),
+                dropped: 0,
+                closed: false,
+                abandoned: false,
+            }),
+            ready: Condvar::new(),
+            space: Notify::new(),
+            wake: OnceLock::new(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// The worker's end of the event queue.
+struct EventSender {
+    queue: Arc<Queue>,
+    /// Bytes handed to [`SerialHandle::write`] and not written yet, shared
+    /// with the handle.
+    tx_queued: Arc<AtomicUsize>,
+}
+
+impl EventSender {
+    fn send(&self, event: SerialEvent) -> Result<(), SendError<SerialEvent>> {
+        {
+            let mut state = self.queue.lock();
+            if state.abandoned {
+                return Err(SendError(event));
+            }
+            match event {
+                SerialEvent::Data(data) => {
+                    let Some(data) = state.make_room(data) else {
+                        return Ok(());
+                    };
+                    state.bytes += data.len();
+                    state.events.push_back(SerialEvent::Data(data));
+                }
+                event => state.events.push_back(event),
+            }
+        }
+        self.queue.ready.notify_all();
+        if let Some(wake) = self.queue.wake.get() {
+            wake();
+        }
+        Ok(())
+    }
+
+    /// Whether a worker using [`Overflow::Pause`] should stop reading.
+    fn paused(&self) -> bool {
+        let state = self.queue.lock();
+        state.overflow == Overflow::Pause && state.bytes >= state.limit && !state.abandoned
+    }
+
+    fn dropped(&self) -> u64 {
+        self.queue.lock().dropped
+    }
+
+    fn tx_queued(&self) -> usize {
+        self.tx_queued.load(Ordering::Relaxed)
+    }
+
+    /// Takes `len` written or abandoned bytes off the write queue.
+    fn tx_taken(&self, len: usize) {
+        self.tx_queued.fetch_sub(len, Ordering::Relaxed);
+    }
+
+    /// Resolves once the caller has taken data since the last call.
+    async fn space(&self) {
+        self.queue.space.notified().await
+    }
+}
+
+impl Drop for EventSender {
+    fn drop(&mut self) {
+        self.queue.lock().closed = true;
+        self.queue.ready.notify_all();
+    }
+}
+
+impl QueueState {
+    /// Applies the overflow policy to `data` about to be queued; returns
+    /// what is left of it to queue.
+    fn make_room(&mut self, mut data: Vec<u8>) -> Option<Vec<u8>> {
+        if self.bytes + data.len() <= self.limit {
+            return Some(data);
+        }
+        match self.overflow {
+            // The worker stops reading once the limit is reached, so this
+            // only overshoots by one read.
+            Overflow::Pause => Some(data),
+            Overflow::DropNewest => {
+                self.dropped += data.len() as u64;
+                None
+            }
+            Overflow::DropOldest => {
+                if data.len() > self.limit {
+                    let cut = data.len() - self.limit;
+                    self.dropped += cut as u64;
+                    data.drain(..cut);
+                }
+                while self.bytes + data.len() > self.limit {
+                    let Some(i) = self.events.iter().position(|e| matches!(e, SerialEvent::Data(_)))
+                    else {
+                        break;
+                    };
+                    let Some(SerialEvent::Data(old)) = self.events.remove(i) else {
+                        unreachable!()
+                    };
+                    let excess = self.bytes + data.len() - self.limit;
+                    if old.len() > excess {
+                        // Keep the newer end of the chunk in place.
+                        self.events.insert(i, SerialEvent::Data(old[excess..].to_vec()));
+                        self.bytes -= excess;
+                        self.dropped += excess as u64;
+                    } else {
+                        self.bytes -= old.len();
+                        self.dropped += old.len() as u64;
+                    }
+                }
+                Some(data)
+            }
+        }
+    }
+}
+
+/// The caller's end of the event queue.
+pub struct Events {
+    queue: Arc<Queue>,
+}
+
+impl Events {
+    /// Next pending event, without blocking; `None` also once the worker
+    /// has exited.
+    pub fn try_recv(&self) -> Option<SerialEvent> {
+        let mut state = self.queue.lock();
+        self.pop(&mut state)
+    }
+
+    /// Waits up to `timeout` for the next event.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<SerialEvent> {
+        let deadline = Instant::now().checked_add(timeout);
+        let mut state = self.queue.lock();
+        loop {
+            if let Some(event) = self.pop(&mut state) {
+                return Some(event);
+            }
+            if state.closed {
+                return None;
+            }
+            state = match deadline {
+                Some(deadline) => {
+                    let left = deadline.checked_duration_since(Instant::now())?;
+                    match self.queue.ready.wait_timeout(state, left) {
+                        Ok((state, _)) => state,
+                        Err(e) => e.into_inner().0,
+                    }
+                }
+                None => self.queue.ready.wait(state).unwrap_or_else(PoisonError::into_inner),
+            };
+        }
+    }
+
+    /// Caps the received data left waiting at `limit` bytes, handling more
+    /// as `overflow` says.
+    pub fn set_overflow(&self, limit: usize, overflow: Overflow) {
+        let mut state = self.queue.lock();
+        state.limit = limit;
+        state.overflow = overflow;
+        drop(state);
+        // A paused worker rechecks.
+        self.queue.space.notify_one();
+    }
+
+    /// Has the worker call `wake` after every event from now on; only the
+    /// first call takes effect.
+    pub fn set_wake(&self, wake: Box<dyn Fn() + Send + Sync>) {
+        let _ = self.queue.wake.set(wake);
+    }
+
+    fn pop(&self, state: &mut QueueState) -> Option<SerialEvent> {
+        let event = state.events.pop_front()?;
+        if let SerialEvent::Data(data) = &event {
+            state.bytes -= data.len();
+            self.queue.space.notify_one();
+        }
+        Some(event)
+    }
+}
+
+impl Drop for Events {
+    fn drop(&mut self) {
+        self.queue.lock().abandoned = true;
+        self.queue.space.notify_one();
+    }
+}
+
+/// Cloneable sender of commands to the worker.
+#[derive(Clone)]
 pub struct SerialHandle {
-    tx: Sender<Vec<u8>>,
-    close_tx: Sender<()>,
+    tx: UnboundedSender<SerialCommand>,
+    queued: Arc<AtomicUsize>,
 }
 
 impl SerialHandle {
+    /// Queues `data` to be written after everything before it; reads
+    /// carry on meanwhile, and the worker reports progress as
+    /// [`SerialEvent::TxProgress`] and [`SerialEvent::TxDone`].
     pub fn write(&self, data: Vec<u8>) -> Result<()> {
-        self.tx
-            .send(data)
-            .map_err(|e| anyhow::anyhow!("writer disconnected: {e}"))
+        let len = data.len();
+        self.queued.fetch_add(len, Ordering::Relaxed);
+        let result = self.send(SerialCommand::Write(data));
+        if result.is_err() {
+            self.queued.fetch_sub(len, Ordering::Relaxed);
+        }
+        result
+    }
+
+    /// Bytes written through any clone of this handle that the worker
+    /// hasn't written to the port yet.
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
     }
 
-    pub fn close(self) -> Result<()> {
-        let _ = self.close_tx.send(());
+    pub fn set_dtr(&self, level: bool) -> Result<()> {
+        self.send(SerialCommand::SetDtr(level))
+    }
+
+    pub fn set_rts(&self, level: bool) -> Result<()> {
+        self.send(SerialCommand::SetRts(level))
+    }
+
+    /// Steps DTR and RTS through `steps`, e.g. to reset a board into its
+    /// bootloader. Reads carry on meanwhile; a new sequence replaces one
+    /// still running.
+    pub fn run_sequence(&self, steps: Vec<LineStep>) -> Result<()> {
+        self.send(SerialCommand::Sequence(steps))
+    }
+
+    /// Sends a break after everything written so far.
+    pub fn send_break(&self, duration: Duration) -> Result<()> {
+        self.send(SerialCommand::Break(duration))
+    }
+
+    pub fn close(&self) -> Result<()> {
+        let _ = self.tx.send(SerialCommand::Close);
         Ok(())
     }
+
+    fn send(&self, cmd: SerialCommand) -> Result<()> {
+        self.tx
+            .send(cmd)
+            .map_err(|e| anyhow::anyhow!("writer disconnected: {e}"))
+    }
 }
 
+/// Friendly names seen by the last [`list_ports`], by port name.
+#[cfg(windows)]
+static FRIENDLY_NAMES: Mutex<std::collections::BTreeMap<String, String>> =
+    Mutex::new(std::collections::BTreeMap::new());
+
+/// Attached ports in natural order: `COM2` before `COM10`, `ttyUSB2`
+/// before `ttyUSB10`.
 pub fn list_ports() -> Result<Vec<SerialPortInfo>> {
-    let ports = serialport::available_ports().context("list available ports")?;
+    let mut ports = serialport::available_ports().context("list available ports")?;
+    ports.sort_by(|a, b| natural_key(&a.port_name).cmp(&natural_key(&b.port_name)));
+    #[cfg(windows)]
+    {
+        let names = crate::winports::friendly_names();
+        *FRIENDLY_NAMES.lock().unwrap_or_else(PoisonError::into_inner) = names;
+    }
     Ok(ports)
 }
 
-pub fn open_port(path: &str, baud_rate: u32) -> Result<(SerialHandle, Receiver<SerialEvent>)> {
-    let (event_tx, event_rx) = mpsc::channel::<SerialEvent>();
-    let (write_tx, write_rx) = mpsc::channel::<Vec<u8>>();
-    let (close_tx, close_rx) = mpsc::channel::<()>();
-
-    let path_string = path.to_string();
-
-    thread::spawn(move || {
-        let builder = serialport::new(path_string.clone(), baud_rate)
-            .timeout(Duration::from_millis(50));
-        match builder.open() {
-            Ok(mut port) => {
-                let _ = event_tx.send(SerialEvent::Opened);
-
-                loop {
-                    match write_rx.try_recv() {
-                        Ok(data) => {
-                            if let Err(e) = port.write_all(&data) {
-                                let _ = event_tx.send(SerialEvent::Error(format!(
-                                    "write error: {}",
-                                    e
-                                )));
+/// Where udev keeps persistent links to USB serial adapters.
+pub const BY_ID_DIR: &str = "/dev/serial/by-id";
+
+/// The `/dev/serial/by-id` link to `device`. It names the adapter by
+/// vendor, product and serial number, so it still leads to it after the
+/// adapter comes back as another `ttyUSB` number. `None` where udev keeps
+/// no such link.
+pub fn stable_path(device: &str) -> Option<String> {
+    let device = std::fs::canonicalize(device).ok()?;
+    std::fs::read_dir(BY_ID_DIR)
+        .ok()?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .find(|link| std::fs::canonicalize(link).is_ok_and(|target| target == device))
+        .map(|link| link.to_string_lossy().into_owned())
+}
+
+/// Folds each macOS `/dev/tty.*` and `/dev/cu.*` pair for the same
+/// adapter into one entry: the callout (`cu`) device, which opens without
+/// waiting for carrier detect, or with `prefer_callout` off the dial-in
+/// (`tty`) one.
+pub fn pair_callout(ports: &mut Vec<SerialPortInfo>, prefer_callout: bool) {
+    let (keep, fold) = if prefer_callout {
+        ("/dev/cu.", "/dev/tty.")
+    } else {
+        ("/dev/tty.", "/dev/cu.")
+    };
+    let kept: Vec<String> = ports
+        .iter()
+        .filter_map(|p| p.port_name.strip_prefix(keep))
+        .map(str::to_string)
+        .collect();
+    ports.retain(|p| match p.port_name.strip_prefix(fold) {
+        Some(name) => !kept.iter().any(|k| k == name),
+        None => true,
+    });
+}
+
+/// A port name split before its trailing number, compared as a number.
+fn natural_key(name: &str) -> (&str, u64, &str) {
+    let stem = name.trim_end_matches(|c: char| c.is_ascii_digit());
+    let number = name[stem.len()..].parse().unwrap_or(0);
+    (stem, number, name)
+}
+
+/// What to show beside a port's name: on Windows the name Device Manager
+/// gives it ("USB Serial Port (COM7)"), otherwise the USB manufacturer,
+/// product and serial number.
+pub fn describe_port(port: &SerialPortInfo) -> Option<String> {
+    #[cfg(windows)]
+    {
+        let names = FRIENDLY_NAMES.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(name) = names.get(&port.port_name) {
+            return Some(name.clone());
+        }
+    }
+    let serialport::SerialPortType::UsbPort(usb) = &port.port_type else {
+        return None;
+    };
+    let parts: Vec<&str> = [&usb.manufacturer, &usb.product, &usb.serial_number]
+        .into_iter()
+        .flatten()
+        .map(String::as_str)
+        .collect();
+    (!parts.is_empty()).then(|| parts.join(" "))
+}
+
+/// Opens `target` (a local device path, `rfc2217://host:port`,
+/// `telnet://host:port`, `bt://AA:BB:CC:DD:EE:FF` or `ble://...`) on a
+/// worker thread running its own async runtime. Events come back through [`Events`] so
+/// synchronous callers can poll them.
+pub fn open_port(target: &str, settings: PortSettings) -> Result<(SerialHandle, Events)> {
+    let target = target.to_string();
+    spawn_worker(move |cmd_rx, event_tx| run_worker(target, settings, cmd_rx, event_tx))
+}
+
+/// Runs the worker over an already open transport, e.g. a mock in tests.
+/// `name` only labels errors.
+pub fn open_transport<T: SerialTransport + Send + 'static>(
+    name: &str,
+    transport: T,
+) -> Result<(SerialHandle, Events)> {
+    let name = name.to_string();
+    spawn_worker(move |cmd_rx, event_tx| async move {
+        serve(&name, Ok(transport), None, None, cmd_rx, event_tx).await;
+    })
+}
+
+fn spawn_worker<F, Fut>(worker: F) -> Result<(SerialHandle, Events)>
+where
+    F: FnOnce(UnboundedReceiver<SerialCommand>, EventSender) -> Fut + Send + 'static,
+    Fut: Future<Output = ()>,
+{
+    let queue = Arc::new(Queue::new());
+    let queued = Arc::new(AtomicUsize::new(0));
+    let event_tx = EventSender {
+        queue: queue.clone(),
+        tx_queued: queued.clone(),
+    };
+    let (cmd_tx, cmd_rx) = tokio::sync::mpsc::unbounded_channel::<SerialCommand>();
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("start serial runtime")?;
+
+    thread::spawn(move || runtime.block_on(worker(cmd_rx, event_tx)));
+
+    let handle = SerialHandle { tx: cmd_tx, queued };
+    Ok((handle, Events { queue }))
+}
+
+async fn run_worker(
+    target: String,
+    settings: PortSettings,
+    cmd_rx: UnboundedReceiver<SerialCommand>,
+    event_tx: EventSender,
+) {
+    let remote = [("rfc2217://", rfc2217::Mode::Rfc2217), ("telnet://", rfc2217::Mode::Telnet)]
+        .into_iter()
+        .find_map(|(prefix, mode)| Some((target.strip_prefix(prefix)?, mode)));
+    if let Some((addr, mode)) = remote {
+        if settings.rs485.is_some() {
+            // Network latency makes RTS timing meaningless.
+            let _ = event_tx.send(SerialEvent::Error(
+                "RS-485 direction control is ignored for network ports".to_string(),
+            ));
+        }
+        let port = TelnetPort::connect(addr, mode, settings).await;
+        serve(&target, port, None, None, cmd_rx, event_tx).await;
+    } else if let Some(addr) = target.strip_prefix(bluetooth::PREFIX) {
+        let port = RfcommPort::connect(addr).await;
+        serve(&target, port, None, None, cmd_rx, event_tx).await;
+    } else if let Some(id) = target.strip_prefix(ble::PREFIX) {
+        let port = NusPort::connect(id).await;
+        serve(&target, port, None, None, cmd_rx, event_tx).await;
+    } else {
+        let (port, lock) = match open_local(&target, settings) {
+            Ok((port, lock)) => (Ok(port), lock),
+            Err(e) => (Err(e), None),
+        };
+        #[cfg(target_os = "linux")]
+        let rs485 = match (&port, settings.rs485) {
+            // The kernel switches RTS itself, with exact timing.
+            (Ok(port), Some(rs485)) if kernel_rs485(port, rs485).is_ok() => None,
+            _ => settings.rs485,
+        };
+        #[cfg(not(target_os = "linux"))]
+        let rs485 = settings.rs485;
+        serve(&target, port, lock, rs485, cmd_rx, event_tx).await;
+    }
+}
+
+fn open_local(
+    target: &str,
+    settings: PortSettings,
+) -> io::Result<(SerialStream, Option<PortLock>)> {
+    let lock = match settings.exclusive {
+        true => Some(PortLock::acquire(target)?),
+        false => None,
+    };
+    let parity = match settings.parity {
+        Parity::None => tokio_serial::Parity::None,
+        Parity::Odd => tokio_serial::Parity::Odd,
+        Parity::Even => tokio_serial::Parity::Even,
+    };
+    let port = tokio_serial::new(target, settings.baud_rate)
+        .parity(parity)
+        .exclusive(settings.exclusive)
+        .open_native_async()
+        .map_err(|e| match e.kind {
+            // What an exclusive lock held elsewhere or a Windows sharing
+            // violation comes back as.
+            tokio_serial::ErrorKind::NoDevice if exists(target) => portlock::busy(target),
+            _ => io::Error::from(e),
+        })?;
+    Ok((port, lock))
+}
+
+fn exists(target: &str) -> bool {
+    if cfg!(windows) {
+        let same = |p: &SerialPortInfo| p.port_name.eq_ignore_ascii_case(target);
+        list_ports().is_ok_and(|ports| ports.iter().any(same))
+    } else {
+        std::path::Path::new(target).exists()
+    }
+}
+
+/// Turns on the driver's RS-485 mode through `TIOCSRS485`; fails on ports
+/// whose driver lacks it, such as most USB adapters.
+#[cfg(target_os = "linux")]
+fn kernel_rs485(port: &SerialStream, rs485: Rs485) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    /// `struct serial_rs485` from `linux/serial.h`.
+    #[repr(C)]
+    struct SerialRs485 {
+        flags: u32,
+        delay_rts_before_send: u32,
+        delay_rts_after_send: u32,
+        padding: [u32; 5],
+    }
+    const SER_RS485_ENABLED: u32 = 1 << 0;
+    const SER_RS485_RTS_ON_SEND: u32 = 1 << 1;
+    const SER_RS485_RTS_AFTER_SEND: u32 = 1 << 2;
+
+    let level = if rs485.rts_on_send {
+        SER_RS485_RTS_ON_SEND
+    } else {
+        SER_RS485_RTS_AFTER_SEND
+    };
+    let ms = |d: Duration| u32::try_from(d.as_millis()).unwrap_or(u32::MAX);
+    let config = SerialRs485 {
+        flags: SER_RS485_ENABLED | level,
+        delay_rts_before_send: ms(rs485.delay_before),
+        delay_rts_after_send: ms(rs485.delay_after),
+        padding: [0; 5],
+    };
+    // SAFETY: the fd is open for the call and `config` matches the layout
+    // the ioctl expects.
+    let result = unsafe { libc::ioctl(port.as_raw_fd(), libc::TIOCSRS485, &config) };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+async fn set_lines<T: SerialTransport>(port: &mut T, step: LineStep) -> io::Result<()> {
+    if let Some(level) = step.dtr {
+        port.set_dtr(level).await?;
+    }
+    if let Some(level) = step.rts {
+        port.set_rts(level).await?;
+    }
+    Ok(())
+}
+
+/// Writes `data` with the driver enabled around it.
+async fn write_rs485<T: SerialTransport>(
+    port: &mut T,
+    data: &[u8],
+    rs485: Rs485,
+) -> io::Result<()> {
+    let direction = |e: io::Error| io::Error::new(e.kind(), format!("RS-485 direction: {e}"));
+    port.set_rts(rs485.rts_on_send).await.map_err(direction)?;
+    tokio::time::sleep(rs485.delay_before).await;
+    let result = match port.write_all(data).await {
+        Ok(()) => port.drain().await,
+        Err(e) => Err(e),
+    };
+    tokio::time::sleep(rs485.delay_after).await;
+    // Release the bus even if the write failed.
+    port.set_rts(!rs485.rts_on_send).await.map_err(direction)?;
+    result
+}
+
+/// Runs the worker loop over `port` until closed. `lock` is released with
+/// the port, before `Closed` goes out.
+async fn serve<T: SerialTransport>(
+    target: &str,
+    port: io::Result<T>,
+    lock: Option<PortLock>,
+    rs485: Option<Rs485>,
+    mut cmd_rx: UnboundedReceiver<SerialCommand>,
+    event_tx: EventSender,
+) {
+    let mut port = match port {
+        Ok(port) => port,
+        Err(e) => {
+            let _ = event_tx.send(SerialEvent::Error(format!("failed to open {}: {}", target, e)));
+            let _ = event_tx.send(SerialEvent::OpenFailed(OpenFailure::of(&e), e.to_string()));
+            let _ = event_tx.send(SerialEvent::Closed);
+            return;
+        }
+    };
+    let _ = event_tx.send(SerialEvent::Opened);
+    if let Some(rs485) = rs485 {
+        // Start out receiving.
+        if let Err(e) = port.set_rts(!rs485.rts_on_send).await {
+            let _ = event_tx.send(SerialEvent::Error(format!("set RTS failed: {}", e)));
+        }
+    }
+
+    let mut modem_lines: Option<ModemLines> = None;
+    let mut modem_supported = true;
+    let mut modem_poll = tokio::time::interval(MODEM_POLL_INTERVAL);
+    // Counts from the open, which later readings are taken relative to.
+    let mut line_error_base: Option<LineErrors> = None;
+    let mut line_errors_supported = true;
+    let mut line_error_poll = tokio::time::interval(STATS_INTERVAL);
+    let mut stats = IoStats::default();
+    let mut reported = stats;
+    let mut stats_tick = tokio::time::interval(STATS_INTERVAL);
+    let mut buf = vec![0u8; READ_BUFFER];
+    let mut sequence: VecDeque<LineStep> = VecDeque::new();
+    let sequence_timer = tokio::time::sleep(Duration::ZERO);
+    tokio::pin!(sequence_timer);
+    // Reading stops while the caller is behind under `Overflow::Pause`.
+    let mut paused = false;
+    // The RTS level last asked for, put back when reading resumes.
+    let mut rts = true;
+    // Commands not carried out yet, and progress through the writes among
+    // them.
+    let mut pending: VecDeque<SerialCommand> = VecDeque::new();
+    let mut tx_written = 0u64;
+
+    loop {
+        tokio::select! {
+            cmd = cmd_rx.recv() => match cmd {
+                Some(SerialCommand::Close) | None => break,
+                // Carried out in order below, between reads.
+                Some(cmd) => pending.push_back(cmd),
+            },
+            _ = std::future::ready(()), if !pending.is_empty() => {
+                let Some(cmd) = pending.pop_front() else { continue };
+                let mut tx_done = false;
+                let result = match cmd {
+                    SerialCommand::Write(mut data) => {
+                        // RS-485 turns the driver around once per write.
+                        let len = match rs485 {
+                            Some(_) => data.len(),
+                            None => data.len().min(WRITE_CHUNK),
+                        };
+                        let rest = data.split_off(len);
+                        let result = match rs485 {
+                            Some(rs485) => write_rs485(&mut port, &data, rs485).await,
+                            None => port.write_all(&data).await,
+                        };
+                        if result.is_ok() {
+                            stats.tx_bytes += len as u64;
+                            tx_written += len as u64;
+                            event_tx.tx_taken(len);
+                            if !rest.is_empty() {
+                                pending.push_front(SerialCommand::Write(rest));
                             }
+                            let progress = TxProgress {
+                                written: tx_written,
+                                queued: event_tx.tx_queued(),
+                            };
+                            let _ = event_tx.send(SerialEvent::TxProgress(progress));
+                        } else {
+                            event_tx.tx_taken(len + rest.len());
                         }
-                        Err(mpsc::TryRecvError::Empty) => {}
-                        Err(mpsc::TryRecvError::Disconnected) => break,
+                        tx_done = event_tx.tx_queued() == 0;
+                        result.map_err(|e| format!("write error: {}", e))
                     }
-
-                    let mut buf = [0u8; 4096];
-                    match port.read(&mut buf) {
-                        Ok(n) if n > 0 => {
-                            let _ = event_tx.send(SerialEvent::Data(buf[..n].to_vec()));
+                    SerialCommand::SetDtr(level) => port
+                        .set_dtr(level)
+                        .await
+                        .map_err(|e| format!("set DTR failed: {}", e)),
+                    SerialCommand::SetRts(level) => {
+                        rts = level;
+                        if paused && rs485.is_none() {
+                            // Applied on resuming.
+                            Ok(())
+                        } else {
+                            port.set_rts(level).await.map_err(|e| format!("set RTS failed: {}", e))
                         }
-                        Ok(_) => {}
-                        Err(ref e) if e.kind() == std::io::ErrorKind::TimedOut => {}
-                        Err(e) => {
-                            let _ = event_tx.send(SerialEvent::Error(format!("read error: {}", e)));
-                            break;
+                    }
+                    SerialCommand::Break(duration) => {
+                        let result = match port.drain().await {
+                            Ok(()) => port.send_break(duration).await,
+                            Err(e) => Err(e),
+                        };
+                        result.map_err(|e| format!("break failed: {}", e))
+                    }
+                    SerialCommand::Sequence(steps) => {
+                        sequence = steps.into();
+                        sequence_timer.as_mut().reset(tokio::time::Instant::now());
+                        Ok(())
+                    }
+                    SerialCommand::Close => break,
+                };
+                let result = match result {
+                    Ok(()) if tx_done && rs485.is_none() => {
+                        port.drain().await.map_err(|e| format!("write error: {}", e))
+                    }
+                    result => result,
+                };
+                if let Err(msg) = result {
+                    stats.errors += 1;
+                    let _ = event_tx.send(SerialEvent::Error(msg));
+                }
+                if tx_done {
+                    tx_written = 0;
+                    let _ = event_tx.send(SerialEvent::TxDone);
+                }
+            }
+            read = port.read(&mut buf), if !paused => match read {
+                Ok(0) => {
+                    let _ = event_tx.send(SerialEvent::Error("connection closed".to_string()));
+                    break;
+                }
+                Ok(n) => {
+                    stats.rx_bytes += n as u64;
+                    let _ = event_tx.send(SerialEvent::Data(buf[..n].to_vec()));
+                    stats.dropped = event_tx.dropped();
+                    if event_tx.paused() {
+                        paused = true;
+                        // Hold off a device using hardware flow control.
+                        if rs485.is_none() && rts {
+                            if let Err(e) = port.set_rts(false).await {
+                                let msg = format!("set RTS failed: {}", e);
+                                let _ = event_tx.send(SerialEvent::Error(msg));
+                            }
                         }
                     }
-
-                    if close_rx.try_recv().is_ok() {
-                        break;
+                }
+                Err(e) => {
+                    stats.errors += 1;
+                    let _ = event_tx.send(SerialEvent::Error(format!("read error: {}", e)));
+                    break;
+                }
+            },
+            _ = modem_poll.tick(), if modem_supported => match port.modem_lines().await {
+                Ok(lines) if modem_lines != Some(lines) => {
+                    modem_lines = Some(lines);
+                    let _ = event_tx.send(SerialEvent::ModemStatus(lines));
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    // Ptys and some adapters lack modem lines; report once.
+                    modem_supported = false;
+                    let _ = event_tx.send(SerialEvent::Error(format!(
+                        "modem status unavailable: {}",
+                        e
+                    )));
+                }
+            },
+            _ = line_error_poll.tick(), if line_errors_supported => match port.line_errors() {
+                Ok(counts) => {
+                    let base = *line_error_base.get_or_insert(counts);
+                    stats.line_errors = Some(counts.since(base));
+                }
+                // Most transports and drivers have none; nothing to report.
+                Err(_) => line_errors_supported = false,
+            },
+            _ = &mut sequence_timer, if !sequence.is_empty() => {
+                let Some(step) = sequence.pop_front() else { continue };
+                if let Err(e) = set_lines(&mut port, step).await {
+                    sequence.clear();
+                    stats.errors += 1;
+                    let msg = format!("line sequence failed: {}", e);
+                    let _ = event_tx.send(SerialEvent::Error(msg));
+                }
+                sequence_timer.as_mut().reset(tokio::time::Instant::now() + step.hold);
+            }
+            _ = event_tx.space(), if paused => {
+                if !event_tx.paused() {
+                    paused = false;
+                    if rs485.is_none() && rts {
+                        if let Err(e) = port.set_rts(true).await {
+                            let msg = format!("set RTS failed: {}", e);
+                            let _ = event_tx.send(SerialEvent::Error(msg));
+                        }
                     }
                 }
-
-                let _ = event_tx.send(SerialEvent::Closed);
             }
-            Err(e) => {
-                let _ = event_tx.send(SerialEvent::Error(format!(
-                    "failed to open {}: {}",
-                    path_string, e
-                )));
+            _ = stats_tick.tick(), if stats != reported => {
+                reported = stats;
+                let _ = event_tx.send(SerialEvent::Stats(stats));
             }
         }
-    });
+    }
 
-    let handle = SerialHandle {
-        tx: write_tx,
-        close_tx,
-    };
-    Ok((handle, event_rx))
+    // Writes still pending are abandoned.
+    event_tx.tx_queued.store(0, Ordering::Relaxed);
+    // So the port can be opened again as soon as `Closed` arrives.
+    drop(port);
+    drop(lock);
+    let _ = event_tx.send(SerialEvent::Stats(stats));
+    let _ = event_tx.send(SerialEvent::Closed);
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
 
+    #[test]
+    fn folds_macos_tty_and_cu_pairs_into_one_port() {
+        let port = |name: &str| SerialPortInfo {
+            port_name: name.to_string(),
+            port_type: serialport::SerialPortType::Unknown,
+        };
+        let names = ["/dev/cu.usbserial-1", "/dev/tty.usbserial-1", "/dev/tty.Bluetooth"];
+        let listed = |prefer_callout| {
+            let mut ports: Vec<SerialPortInfo> = names.iter().map(|n| port(n)).collect();
+            pair_callout(&mut ports, prefer_callout);
+            ports.into_iter().map(|p| p.port_name).collect::<Vec<_>>()
+        };
+        assert_eq!(listed(true), ["/dev/cu.usbserial-1", "/dev/tty.Bluetooth"]);
+        assert_eq!(listed(false), ["/dev/tty.usbserial-1", "/dev/tty.Bluetooth"]);
+    }
+}
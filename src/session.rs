@@ -0,0 +1,107 @@
+use std::time::Duration;
+
+use anyhow::Result;
+
+use crate::serial::{
+    self, Events, Overflow, PortSettings, SerialEvent, SerialHandle, SerialTransport,
+};
+
+/// An open connection to a local port or remote serial server.
+///
+/// Opening returns immediately; the worker thread reports
+/// [`SerialEvent::Opened`] once the port is actually usable, or an
+/// [`SerialEvent::Error`] followed by [`SerialEvent::Closed`] if it is not.
+/// Received data, modem line changes and I/O counters arrive as further
+/// events until [`SerialEvent::Closed`].
+///
+/// ```no_run
+/// use lazyserial::{SerialEvent, Session};
+///
+/// let session = Session::open("/dev/ttyUSB0", 115_200)?;
+/// session.handle().write(b"version\r\n".to_vec())?;
+/// while let Some(event) = session.recv_timeout(std::time::Duration::from_secs(1)) {
+///     if let SerialEvent::Data(bytes) = event {
+///         print!("{}", String::from_utf8_lossy(&bytes));
+///     }
+/// }
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub struct Session {
+    target: String,
+    handle: SerialHandle,
+    events: Events,
+}
+
+impl Session {
+    /// Opens `target`: a device path, `rfc2217://host:port`,
+    /// `telnet://host:port`, `bt://AA:BB:CC:DD:EE:FF` or a `ble://` device,
+    /// at `baud_rate` 8N1.
+    pub fn open(target: &str, baud_rate: u32) -> Result<Self> {
+        Self::open_with(target, PortSettings::new(baud_rate))
+    }
+
+    /// Like [`Session::open`], with explicit line settings.
+    pub fn open_with(target: &str, settings: PortSettings) -> Result<Self> {
+        let (handle, events) = serial::open_port(target, settings)?;
+        Ok(Self {
+            target: target.to_string(),
+            handle,
+            events,
+        })
+    }
+
+    /// Runs a session over an already open transport; `name` stands in
+    /// for the target.
+    pub fn with_transport<T: SerialTransport + Send + 'static>(
+        name: &str,
+        transport: T,
+    ) -> Result<Self> {
+        let (handle, events) = serial::open_transport(name, transport)?;
+        Ok(Self {
+            target: name.to_string(),
+            handle,
+            events,
+        })
+    }
+
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    /// Write handle; clone it to send from other threads.
+    pub fn handle(&self) -> &SerialHandle {
+        &self.handle
+    }
+
+    /// Next pending event, without blocking. `None` also once the worker
+    /// has exited.
+    pub fn try_event(&self) -> Option<SerialEvent> {
+        self.events.try_recv()
+    }
+
+    /// Calls `wake` from the worker thread after each event it queues from
+    /// now on, so a caller waiting on something else, such as terminal
+    /// input, can pick events up as they come instead of polling. Only the
+    /// first call takes effect.
+    pub fn set_wake(&self, wake: impl Fn() + Send + Sync + 'static) {
+        self.events.set_wake(Box::new(wake));
+    }
+
+    /// Caps received data waiting to be picked up at `limit` bytes
+    /// ([`DEFAULT_RX_BUFFER`](serial::DEFAULT_RX_BUFFER) until set);
+    /// `overflow` says what happens to more. Discarded bytes are counted in
+    /// [`IoStats::dropped`](serial::IoStats::dropped).
+    pub fn set_overflow(&self, limit: usize, overflow: Overflow) {
+        self.events.set_overflow(limit, overflow);
+    }
+
+    /// Waits up to `timeout` for the next event.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<SerialEvent> {
+        self.events.recv_timeout(timeout)
+    }
+
+    /// Asks the worker to close the port; it still sends `Closed`.
+    pub fn close(&self) -> Result<()> {
+        self.handle.close()
+    }
+}
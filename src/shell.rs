@@ -0,0 +1,152 @@
+//! Shell commands run in the background: `!command` input lines, whose
+//! output is sent to the port, and the filter received data is piped
+//! through.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// How often the exit of a command that closed its output is checked for.
+const EXIT_POLL: Duration = Duration::from_millis(20);
+/// Chunks of received data waiting for a filter to read them; beyond this
+/// they are dropped rather than holding up the UI.
+const FILTER_QUEUE: usize = 1024;
+
+#[derive(Debug)]
+pub enum ShellEvent {
+    /// A line the command printed, without its newline.
+    Stdout(String),
+    Stderr(String),
+    Exited(io::Result<ExitStatus>),
+}
+
+/// A running command. Its output is captured, so it can't draw over the
+/// screen.
+pub struct ShellCommand {
+    pub command: String,
+    pub rx: Receiver<ShellEvent>,
+    child: Arc<Mutex<Child>>,
+    /// Feeds a filter's stdin; other commands get none.
+    stdin: Option<SyncSender<Vec<u8>>>,
+    /// Stdout collected so far, when it is sent once the command exits.
+    pub output: Vec<String>,
+    /// Bytes not piped to a filter that fell behind.
+    pub dropped: u64,
+}
+
+impl ShellCommand {
+    /// Passes `data` to a filter's stdin.
+    pub fn write(&mut self, data: &[u8]) {
+        let Some(stdin) = &self.stdin else {
+            return;
+        };
+        match stdin.try_send(data.to_vec()) {
+            Ok(()) | Err(TrySendError::Disconnected(_)) => {}
+            Err(TrySendError::Full(data)) => self.dropped += data.len() as u64,
+        }
+    }
+
+    pub fn kill(&self) {
+        let _ = self.child.lock().unwrap().kill();
+    }
+}
+
+/// A command still running when it is let go of, e.g. on quit, would
+/// otherwise be left behind.
+impl Drop for ShellCommand {
+    fn drop(&mut self) {
+        self.kill();
+    }
+}
+
+/// Runs `command` with `sh -c` (`cmd /C` on Windows), stdin closed.
+pub fn spawn(command: &str) -> io::Result<ShellCommand> {
+    start(command, false)
+}
+
+/// Runs `command` as a filter: what [`ShellCommand::write`] is given goes
+/// to its stdin.
+pub fn spawn_filter(command: &str) -> io::Result<ShellCommand> {
+    start(command, true)
+}
+
+fn start(command: &str, filter: bool) -> io::Result<ShellCommand> {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    let mut child = shell
+        .arg(command)
+        .stdin(if filter { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+    let stdin = child.stdin.take().map(|mut pipe| {
+        let (stdin, chunks) = mpsc::sync_channel::<Vec<u8>>(FILTER_QUEUE);
+        thread::spawn(move || {
+            for chunk in chunks {
+                if pipe.write_all(&chunk).and_then(|()| pipe.flush()).is_err() {
+                    return;
+                }
+            }
+        });
+        stdin
+    });
+    let child = Arc::new(Mutex::new(child));
+    let (tx, rx) = mpsc::channel();
+
+    let err_tx = tx.clone();
+    let errors = thread::spawn(move || forward_lines(stderr, &err_tx, ShellEvent::Stderr));
+    let thread_child = child.clone();
+    thread::spawn(move || {
+        forward_lines(stdout, &tx, ShellEvent::Stdout);
+        let _ = errors.join();
+        // Not `wait`: that would hold the lock, and with it `kill`, for as
+        // long as a command that closed its output keeps running.
+        let status = loop {
+            match thread_child.lock().unwrap().try_wait() {
+                Ok(Some(status)) => break Ok(status),
+                Ok(None) => {}
+                Err(e) => break Err(e),
+            }
+            thread::sleep(EXIT_POLL);
+        };
+        let _ = tx.send(ShellEvent::Exited(status));
+    });
+
+    Ok(ShellCommand {
+        command: command.to_string(),
+        rx,
+        child,
+        stdin,
+        output: Vec::new(),
+        dropped: 0,
+    })
+}
+
+fn forward_lines(output: impl Read, tx: &Sender<ShellEvent>, event: fn(String) -> ShellEvent) {
+    let mut reader = BufReader::new(output);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        match reader.read_until(b'\n', &mut line) {
+            Ok(0) | Err(_) => return,
+            Ok(_) => {}
+        }
+        let text = String::from_utf8_lossy(&line);
+        let text = text.trim_end_matches('\n').trim_end_matches('\r');
+        if tx.send(event(text.to_string())).is_err() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,85 @@
+//! The session as it was at exit: the open port and its settings, the end
+//! of the scrollback, the AT command history, the input line and the
+//! filter. Kept in `session.toml` under the data directory, so an
+//! accidental quit can be undone at the next launch.
+
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+
+use crate::recent::RecentSession;
+
+/// Output lines kept; the rest of the scrollback is lost on exit.
+pub const TAIL_LINES: usize = 2000;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SavedState {
+    /// The port that was open, with its settings.
+    pub port: Option<RecentSession>,
+    pub output: Vec<String>,
+    pub history: Vec<String>,
+    pub input: String,
+    pub filter: Option<String>,
+    pub at_mode: bool,
+}
+
+impl SavedState {
+    /// Whether there is anything worth offering to restore.
+    pub fn is_empty(&self) -> bool {
+        self.port.is_none() && self.output.is_empty() && self.input.is_empty()
+    }
+}
+
+fn path() -> Option<PathBuf> {
+    dirs::data_local_dir().map(|d| d.join("lazyserial").join("session.toml"))
+}
+
+/// The state saved at the last exit, if any.
+pub fn load() -> Result<Option<SavedState>> {
+    let Some(path) = path().filter(|p| p.exists()) else {
+        return Ok(None);
+    };
+    let text = fs::read_to_string(&path).with_context(|| format!("read {}", path.display()))?;
+    toml::from_str(&text).with_context(|| format!("parse {}", path.display())).map(Some)
+}
+
+pub fn save(state: &SavedState) -> Result<()> {
+    let path = path().ok_or_else(|| anyhow!("no data directory"))?;
+    let mut doc = toml_edit::DocumentMut::new();
+    doc.insert("input", toml_edit::value(state.input.as_str()));
+    if let Some(filter) = &state.filter {
+        doc.insert("filter", toml_edit::value(filter.as_str()));
+    }
+    doc.insert("at_mode", toml_edit::value(state.at_mode));
+    doc.insert("history", strings(&state.history));
+    doc.insert("output", strings(&state.output));
+    if let Some(port) = &state.port {
+        doc.insert("port", toml_edit::Item::Table(port.to_table()));
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("create {}", dir.display()))?;
+    }
+    fs::write(&path, doc.to_string()).with_context(|| format!("write {}", path.display()))
+}
+
+/// Forgets the saved state once it has been restored or turned down.
+pub fn discard() -> Result<()> {
+    match path().filter(|p| p.exists()) {
+        Some(path) => fs::remove_file(&path).with_context(|| format!("remove {}", path.display())),
+        None => Ok(()),
+    }
+}
+
+/// One string per line, so the file stays readable.
+fn strings(items: &[String]) -> toml_edit::Item {
+    let mut array: toml_edit::Array = items.iter().map(String::as_str).collect();
+    for value in array.iter_mut() {
+        value.decor_mut().set_prefix("\n    ");
+    }
+    array.set_trailing("\n");
+    array.set_trailing_comma(true);
+    toml_edit::value(array)
+}
@@ -0,0 +1,440 @@
+//! Flashing through the STM32 system-memory bootloader over UART (ST
+//! AN3155): sync, mass erase, write, verify by reading back, then start the
+//! new firmware.
+//!
+//! Like [`Transfer`](crate::transfer::Transfer), a [`Flasher`] is fed
+//! received bytes and timer ticks and returns what to write to the port.
+//! The bootloader talks 8E1 and only runs when the chip was reset with
+//! BOOT0 high.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+
+use crate::transfer::TransferStatus;
+
+const SYNC: u8 = 0x7f;
+const ACK: u8 = 0x79;
+const NACK: u8 = 0x1f;
+
+const CMD_GET: u8 = 0x00;
+const CMD_GET_ID: u8 = 0x02;
+const CMD_READ: u8 = 0x11;
+const CMD_GO: u8 = 0x21;
+const CMD_WRITE: u8 = 0x31;
+const CMD_ERASE: u8 = 0x43;
+const CMD_EXTENDED_ERASE: u8 = 0x44;
+
+/// Where flash starts on every STM32.
+pub const FLASH_BASE: u32 = 0x0800_0000;
+/// Most the bootloader reads or writes per command.
+const BLOCK: usize = 256;
+/// The first sync byte also sets the bootloader's baud rate, so a lost one
+/// is worth repeating.
+const SYNC_ATTEMPTS: u32 = 5;
+const SYNC_TIMEOUT: Duration = Duration::from_millis(500);
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+/// A mass erase of a large part takes tens of seconds.
+const ERASE_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    Connecting,
+    Erasing,
+    Writing,
+    Verifying,
+    Starting,
+}
+
+impl Stage {
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Connecting => "connecting",
+            Stage::Erasing => "erasing",
+            Stage::Writing => "writing",
+            Stage::Verifying => "verifying",
+            Stage::Starting => "starting",
+        }
+    }
+}
+
+/// The reply being waited for; each follows a command or its arguments.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Phase {
+    Sync,
+    Get,
+    GetId,
+    EraseCmd,
+    Erase,
+    WriteCmd,
+    WriteAddr,
+    WriteData,
+    ReadCmd,
+    ReadAddr,
+    ReadData,
+    GoCmd,
+    GoAddr,
+}
+
+impl Phase {
+    fn what(self) -> &'static str {
+        match self {
+            Phase::Sync => "sync",
+            Phase::Get => "GET",
+            Phase::GetId => "GET ID",
+            Phase::EraseCmd | Phase::Erase => "erase",
+            Phase::WriteCmd | Phase::WriteAddr | Phase::WriteData => "write",
+            Phase::ReadCmd | Phase::ReadAddr | Phase::ReadData => "read",
+            Phase::GoCmd | Phase::GoAddr => "go",
+        }
+    }
+
+    fn timeout(self) -> Duration {
+        match self {
+            Phase::Sync => SYNC_TIMEOUT,
+            Phase::Erase => ERASE_TIMEOUT,
+            _ => REPLY_TIMEOUT,
+        }
+    }
+}
+
+pub struct Flasher {
+    pub path: PathBuf,
+    pub file_name: String,
+    /// Where the image goes and where it is started from.
+    pub address: u32,
+    pub stage: Stage,
+    pub status: TransferStatus,
+    /// Bootloader protocol version, e.g. `0x31` for 3.1.
+    pub version: Option<u8>,
+    pub product_id: Option<u16>,
+    /// Of the image, in the current write or verify pass.
+    pub bytes_done: u64,
+    /// Whether the image was read back and matched.
+    pub verified: bool,
+    image: Vec<u8>,
+    phase: Phase,
+    rx: Vec<u8>,
+    /// Command codes the bootloader supports, from GET.
+    commands: Vec<u8>,
+    /// Offset of the block in flight.
+    offset: usize,
+    sync_attempts: u32,
+    started: Instant,
+    sent_at: Instant,
+}
+
+impl Flasher {
+    /// Loads a raw binary to be written at `address`.
+    pub fn new(path: &Path, address: u32) -> Result<Self> {
+        let mut image = fs::read(path).with_context(|| format!("read {}", path.display()))?;
+        if image.is_empty() {
+            bail!("{} is empty", path.display());
+        }
+        // Writes must be a multiple of 4 bytes; 0xff is erased flash.
+        image.resize(image.len().next_multiple_of(4), 0xff);
+        let now = Instant::now();
+        Ok(Self {
+            path: path.to_path_buf(),
+            file_name: path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            address,
+            stage: Stage::Connecting,
+            status: TransferStatus::Running,
+            version: None,
+            product_id: None,
+            bytes_done: 0,
+            verified: false,
+            image,
+            phase: Phase::Sync,
+            rx: Vec::new(),
+            commands: Vec::new(),
+            offset: 0,
+            sync_attempts: 0,
+            started: now,
+            // Sends the first sync byte on the very first tick.
+            sent_at: now.checked_sub(SYNC_TIMEOUT).unwrap_or(now),
+        })
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.status != TransferStatus::Running
+    }
+
+    pub fn image_len(&self) -> u64 {
+        self.image.len() as u64
+    }
+
+    /// Through the current write or verify pass.
+    pub fn progress(&self) -> Option<f64> {
+        match self.stage {
+            Stage::Writing | Stage::Verifying => {
+                Some(self.bytes_done as f64 / self.image.len() as f64)
+            }
+            Stage::Starting => Some(1.0),
+            Stage::Connecting | Stage::Erasing => None,
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// One-line outcome for the output log once flashing ends.
+    pub fn summary(&self) -> String {
+        match &self.status {
+            TransferStatus::Running => self.stage.name().to_string(),
+            TransferStatus::Done => format!(
+                "flashed {} at 0x{:08x} ({} bytes, {}, {:.1}s) and started it",
+                self.path.display(),
+                self.address,
+                self.image.len(),
+                if self.verified { "verified" } else { "not verified: read is protected" },
+                self.elapsed().as_secs_f64()
+            ),
+            TransferStatus::Failed(reason) => {
+                format!("flashing failed while {}: {}", self.stage.name(), reason)
+            }
+            TransferStatus::Cancelled => {
+                format!("flashing cancelled while {}", self.stage.name())
+            }
+        }
+    }
+
+    /// Stops where it is; the bootloader needs no goodbye.
+    pub fn cancel(&mut self) {
+        if !self.is_finished() {
+            self.status = TransferStatus::Cancelled;
+        }
+    }
+
+    pub fn fail<S: Into<String>>(&mut self, reason: S) {
+        if !self.is_finished() {
+            self.status = TransferStatus::Failed(reason.into());
+        }
+    }
+
+    pub fn on_data(&mut self, bytes: &[u8]) -> Vec<u8> {
+        if self.is_finished() {
+            return Vec::new();
+        }
+        self.rx.extend_from_slice(bytes);
+        match self.reply() {
+            Ok(Some(reply)) => {
+                let out = self.advance(&reply);
+                if !out.is_empty() {
+                    self.sent_at = Instant::now();
+                }
+                out
+            }
+            Ok(None) => Vec::new(),
+            Err(reason) => {
+                self.fail(reason);
+                Vec::new()
+            }
+        }
+    }
+
+    pub fn on_tick(&mut self) -> Vec<u8> {
+        if self.is_finished() || self.sent_at.elapsed() < self.phase.timeout() {
+            return Vec::new();
+        }
+        if self.phase == Phase::Sync && self.sync_attempts < SYNC_ATTEMPTS {
+            self.sync_attempts += 1;
+            self.sent_at = Instant::now();
+            return vec![SYNC];
+        }
+        let reason = if self.phase == Phase::Sync {
+            "no reply from the bootloader (BOOT0 high, reset, 8E1?)".to_string()
+        } else {
+            format!("no reply to {}", self.phase.what())
+        };
+        self.fail(reason);
+        Vec::new()
+    }
+
+    /// The complete reply for the current phase, once it is all in.
+    fn reply(&mut self) -> Result<Option<Vec<u8>>, String> {
+        if self.phase == Phase::Sync {
+            // Skip whatever the port was saying before the bootloader ran.
+            // NACK means it was already synced.
+            return Ok(self
+                .rx
+                .iter()
+                .position(|&b| b == ACK || b == NACK)
+                .map(|i| self.rx.drain(..=i).collect()));
+        }
+        let Some(&first) = self.rx.first() else {
+            return Ok(None);
+        };
+        match first {
+            ACK => {}
+            NACK => return Err(format!("{} refused (NACK)", self.phase.what())),
+            other => {
+                return Err(format!("unexpected reply 0x{other:02x} to {}", self.phase.what()))
+            }
+        }
+        let len = match self.phase {
+            // ACK, N, N + 1 bytes, ACK.
+            Phase::Get | Phase::GetId => match self.rx.get(1) {
+                Some(&n) => 2 + usize::from(n) + 1 + 1,
+                None => return Ok(None),
+            },
+            Phase::ReadData => 1 + self.block_len(),
+            _ => 1,
+        };
+        if self.rx.len() < len {
+            return Ok(None);
+        }
+        let reply: Vec<u8> = self.rx.drain(..len).collect();
+        if matches!(self.phase, Phase::Get | Phase::GetId) && reply[len - 1] != ACK {
+            return Err(format!("{} reply not closed by ACK", self.phase.what()));
+        }
+        Ok(Some(reply))
+    }
+
+    /// Handles a complete reply and returns the next request.
+    fn advance(&mut self, reply: &[u8]) -> Vec<u8> {
+        match self.phase {
+            Phase::Sync => self.command(Phase::Get, CMD_GET),
+            Phase::Get => {
+                self.version = Some(reply[2]);
+                self.commands = reply[3..reply.len() - 1].to_vec();
+                if !self.commands.contains(&CMD_WRITE) {
+                    self.fail("bootloader has no write command");
+                    return Vec::new();
+                }
+                self.command(Phase::GetId, CMD_GET_ID)
+            }
+            Phase::GetId => {
+                if let [_, 1, hi, lo, _] = reply {
+                    self.product_id = Some(u16::from_be_bytes([*hi, *lo]));
+                }
+                self.stage = Stage::Erasing;
+                if self.commands.contains(&CMD_EXTENDED_ERASE) {
+                    self.command(Phase::EraseCmd, CMD_EXTENDED_ERASE)
+                } else if self.commands.contains(&CMD_ERASE) {
+                    self.command(Phase::EraseCmd, CMD_ERASE)
+                } else {
+                    self.fail("bootloader has no erase command");
+                    Vec::new()
+                }
+            }
+            Phase::EraseCmd => {
+                self.phase = Phase::Erase;
+                if self.commands.contains(&CMD_EXTENDED_ERASE) {
+                    vec![0xff, 0xff, 0x00]
+                } else {
+                    vec![0xff, 0x00]
+                }
+            }
+            Phase::Erase => {
+                self.stage = Stage::Writing;
+                self.offset = 0;
+                self.command(Phase::WriteCmd, CMD_WRITE)
+            }
+            Phase::WriteCmd => self.block_address(Phase::WriteAddr),
+            Phase::WriteAddr => {
+                self.phase = Phase::WriteData;
+                let data = &self.image[self.offset..self.offset + self.block_len()];
+                let n = (data.len() - 1) as u8;
+                let mut out = Vec::with_capacity(data.len() + 2);
+                out.push(n);
+                out.extend_from_slice(data);
+                out.push(data.iter().fold(n, |sum, b| sum ^ b));
+                out
+            }
+            Phase::WriteData => {
+                self.offset += self.block_len();
+                self.bytes_done = self.offset as u64;
+                if self.offset < self.image.len() {
+                    self.command(Phase::WriteCmd, CMD_WRITE)
+                } else if self.commands.contains(&CMD_READ) {
+                    self.stage = Stage::Verifying;
+                    self.offset = 0;
+                    self.bytes_done = 0;
+                    self.command(Phase::ReadCmd, CMD_READ)
+                } else {
+                    self.go()
+                }
+            }
+            Phase::ReadCmd => self.block_address(Phase::ReadAddr),
+            Phase::ReadAddr => {
+                self.phase = Phase::ReadData;
+                let n = (self.block_len() - 1) as u8;
+                vec![n, !n]
+            }
+            Phase::ReadData => {
+                let len = self.block_len();
+                let expected = &self.image[self.offset..self.offset + len];
+                if let Some(i) = expected.iter().zip(&reply[1..]).position(|(a, b)| a != b) {
+                    let at = self.address as usize + self.offset + i;
+                    self.fail(format!("verify mismatch at 0x{at:08x}"));
+                    return Vec::new();
+                }
+                self.offset += len;
+                self.bytes_done = self.offset as u64;
+                if self.offset < self.image.len() {
+                    self.command(Phase::ReadCmd, CMD_READ)
+                } else {
+                    self.verified = true;
+                    self.go()
+                }
+            }
+            Phase::GoCmd => {
+                self.phase = Phase::GoAddr;
+                address(self.address)
+            }
+            Phase::GoAddr => {
+                self.status = TransferStatus::Done;
+                Vec::new()
+            }
+        }
+    }
+
+    fn command(&mut self, phase: Phase, code: u8) -> Vec<u8> {
+        self.phase = phase;
+        vec![code, !code]
+    }
+
+    fn block_address(&mut self, phase: Phase) -> Vec<u8> {
+        self.phase = phase;
+        address(self.address + self.offset as u32)
+    }
+
+    fn go(&mut self) -> Vec<u8> {
+        self.stage = Stage::Starting;
+        self.command(Phase::GoCmd, CMD_GO)
+    }
+
+    fn block_len(&self) -> usize {
+        BLOCK.min(self.image.len() - self.offset)
+    }
+}
+
+/// Big-endian address followed by its XOR checksum.
+fn address(addr: u32) -> Vec<u8> {
+    let bytes = addr.to_be_bytes();
+    let mut out = bytes.to_vec();
+    out.push(bytes.iter().fold(0, |sum, b| sum ^ b));
+    out
+}
+
+/// Parses `path` or `path@address`, the address in hex (`0x` optional) and
+/// defaulting to [`FLASH_BASE`].
+pub fn parse_target(spec: &str) -> Result<(PathBuf, u32)> {
+    let Some((path, addr)) = spec.rsplit_once('@') else {
+        return Ok((PathBuf::from(spec), FLASH_BASE));
+    };
+    let digits = addr.trim().trim_start_matches("0x").trim_start_matches("0X");
+    let address =
+        u32::from_str_radix(digits, 16).with_context(|| format!("bad address {addr:?}"))?;
+    if address & 3 != 0 {
+        bail!("address 0x{address:08x} is not word aligned");
+    }
+    Ok((PathBuf::from(path.trim()), address))
+}
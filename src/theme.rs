@@ -0,0 +1,149 @@
+use anyhow::{anyhow, bail, Result};
+use ratatui::style::Color;
+
+use crate::config::ThemeConfig;
+use crate::highlight;
+
+/// Colors the UI draws with, picked from a named scheme and then adjusted
+/// by the `[theme]` section of the config.
+#[derive(Clone, Copy, Debug)]
+pub struct Theme {
+    /// Title badge, popup borders, key names.
+    pub accent: Color,
+    /// Text drawn on top of a colored badge.
+    pub badge_text: Color,
+    /// Borders of the main panes.
+    pub border: Color,
+    /// Background of the selected list entry.
+    pub selection: Color,
+    /// Settings in the header, prompts, headings and the focused input.
+    pub label: Color,
+    /// Port names.
+    pub text: Color,
+    /// Secondary text and inactive indicators.
+    pub dim: Color,
+    /// Open port, asserted lines and other good news.
+    pub ok: Color,
+    pub error: Color,
+    /// Paused and repeating badges.
+    pub busy: Color,
+    /// Script badge.
+    pub script: Color,
+    /// Sent and received data.
+    pub tx: Color,
+    pub rx: Color,
+}
+
+const DEFAULT: Theme = Theme {
+    accent: Color::Cyan,
+    badge_text: Color::Black,
+    border: Color::Reset,
+    selection: Color::Blue,
+    label: Color::Yellow,
+    text: Color::White,
+    dim: Color::DarkGray,
+    ok: Color::Green,
+    error: Color::Red,
+    busy: Color::Yellow,
+    script: Color::Magenta,
+    tx: Color::LightBlue,
+    rx: Color::Reset,
+};
+
+const LIGHT: Theme = Theme {
+    accent: Color::Blue,
+    badge_text: Color::White,
+    border: Color::Gray,
+    selection: Color::LightBlue,
+    label: Color::Magenta,
+    text: Color::Black,
+    dim: Color::Gray,
+    ok: Color::Green,
+    error: Color::Red,
+    busy: Color::Rgb(0xb5, 0x89, 0x00),
+    script: Color::Magenta,
+    tx: Color::Blue,
+    rx: Color::Reset,
+};
+
+const SOLARIZED: Theme = Theme {
+    accent: Color::Rgb(0x2a, 0xa1, 0x98),
+    badge_text: Color::Rgb(0x00, 0x2b, 0x36),
+    border: Color::Rgb(0x58, 0x6e, 0x75),
+    selection: Color::Rgb(0x07, 0x36, 0x42),
+    label: Color::Rgb(0xb5, 0x89, 0x00),
+    text: Color::Rgb(0x93, 0xa1, 0xa1),
+    dim: Color::Rgb(0x58, 0x6e, 0x75),
+    ok: Color::Rgb(0x85, 0x99, 0x00),
+    error: Color::Rgb(0xdc, 0x32, 0x2f),
+    busy: Color::Rgb(0xcb, 0x4b, 0x16),
+    script: Color::Rgb(0xd3, 0x36, 0x82),
+    tx: Color::Rgb(0x26, 0x8b, 0xd2),
+    rx: Color::Rgb(0x83, 0x94, 0x96),
+};
+
+const MONOCHROME: Theme = Theme {
+    accent: Color::White,
+    badge_text: Color::Black,
+    border: Color::Reset,
+    selection: Color::DarkGray,
+    label: Color::White,
+    text: Color::Reset,
+    dim: Color::DarkGray,
+    ok: Color::White,
+    error: Color::Gray,
+    busy: Color::White,
+    script: Color::Gray,
+    tx: Color::Gray,
+    rx: Color::Reset,
+};
+
+pub const NAMES: [&str; 4] = ["default", "light", "solarized", "monochrome"];
+
+impl Default for Theme {
+    fn default() -> Self {
+        DEFAULT
+    }
+}
+
+impl Theme {
+    pub fn named(name: &str) -> Result<Self> {
+        Ok(match name.to_ascii_lowercase().as_str() {
+            "default" => DEFAULT,
+            "light" => LIGHT,
+            "solarized" => SOLARIZED,
+            "monochrome" => MONOCHROME,
+            _ => bail!("theme: unknown scheme {name:?} (one of {})", NAMES.join(", ")),
+        })
+    }
+
+    /// The scheme named in `[theme]` with that section's colors on top.
+    pub fn from_config(config: &ThemeConfig) -> Result<Self> {
+        let mut theme = Self::named(&config.name)?;
+        for (key, value) in &config.colors {
+            let color = highlight::parse_color(value)
+                .map_err(|e| anyhow!("theme: {key}: {e}"))?;
+            *theme.slot(key)? = color;
+        }
+        Ok(theme)
+    }
+
+    fn slot(&mut self, key: &str) -> Result<&mut Color> {
+        Ok(match key {
+            "accent" => &mut self.accent,
+            "badge-text" => &mut self.badge_text,
+            "border" => &mut self.border,
+            "selection" => &mut self.selection,
+            "label" => &mut self.label,
+            "text" => &mut self.text,
+            "dim" => &mut self.dim,
+            "ok" => &mut self.ok,
+            "error" => &mut self.error,
+            "busy" => &mut self.busy,
+            "script" => &mut self.script,
+            "tx" => &mut self.tx,
+            "rx" => &mut self.rx,
+            _ => bail!("theme: unknown color {key:?}"),
+        })
+    }
+}
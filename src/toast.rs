@@ -0,0 +1,65 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant, SystemTime};
+
+/// How long a status note stays up.
+const INFO_TIME: Duration = Duration::from_secs(3);
+/// Errors stay longer, to be read.
+const ERROR_TIME: Duration = Duration::from_secs(8);
+/// Toasts shown at once; older ones make way.
+const MAX_SHOWN: usize = 4;
+/// Errors kept for the history popup.
+const MAX_HISTORY: usize = 500;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Level {
+    Info,
+    Error,
+}
+
+pub struct Toast {
+    pub level: Level,
+    pub text: String,
+    until: Instant,
+}
+
+/// Short-lived notes over the output for errors and status changes, with
+/// the errors also kept for a history popup.
+#[derive(Default)]
+pub struct Toasts {
+    shown: VecDeque<Toast>,
+    /// Oldest first, with when they happened.
+    pub errors: VecDeque<(SystemTime, String)>,
+}
+
+impl Toasts {
+    pub fn info(&mut self, text: impl Into<String>) {
+        self.show(Level::Info, text.into(), INFO_TIME);
+    }
+
+    pub fn error(&mut self, text: impl Into<String>) {
+        let text = text.into();
+        self.errors.push_back((SystemTime::now(), text.clone()));
+        if self.errors.len() > MAX_HISTORY {
+            self.errors.pop_front();
+        }
+        self.show(Level::Error, text, ERROR_TIME);
+    }
+
+    fn show(&mut self, level: Level, text: String, time: Duration) {
+        // A repeat just stays up longer rather than stacking.
+        self.shown.retain(|t| t.text != text);
+        self.shown.push_back(Toast {
+            level,
+            text,
+            until: Instant::now() + time,
+        });
+        if self.shown.len() > MAX_SHOWN {
+            self.shown.pop_front();
+        }
+    }
+
+    /// Toasts still up at `now`, oldest first.
+    pub fn shown(&self, now: Instant) -> impl Iterator<Item = &Toast> {
+        self.shown.iter().filter(move |t| t.until > now)
+    }
+}
@@ -0,0 +1,688 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+
+const SOH: u8 = 0x01;
+const STX: u8 = 0x02;
+const EOT: u8 = 0x04;
+const ACK: u8 = 0x06;
+const NAK: u8 = 0x15;
+const CAN: u8 = 0x18;
+const CRC_START: u8 = b'C';
+const SUB: u8 = 0x1a;
+
+const MAX_RETRIES: u32 = 10;
+const START_TIMEOUT: Duration = Duration::from_secs(60);
+const ACK_TIMEOUT: Duration = Duration::from_secs(10);
+const RECV_POLL_INTERVAL: Duration = Duration::from_secs(3);
+/// Number of 'C' polls a receiver sends before falling back to checksum mode.
+const CRC_ATTEMPTS: u32 = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    Xmodem,
+    Xmodem1k,
+    Ymodem,
+}
+
+impl Protocol {
+    pub fn name(self) -> &'static str {
+        match self {
+            Protocol::Xmodem => "XMODEM",
+            Protocol::Xmodem1k => "XMODEM-1K",
+            Protocol::Ymodem => "YMODEM",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Protocol::Xmodem => Protocol::Xmodem1k,
+            Protocol::Xmodem1k => Protocol::Ymodem,
+            Protocol::Ymodem => Protocol::Xmodem,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Send,
+    Receive,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TransferStatus {
+    Running,
+    Done,
+    Failed(String),
+    Cancelled,
+}
+
+/// What the sender is waiting on from the receiver.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SendState {
+    Start,
+    HeaderAck,
+    DataStart,
+    BlockAck,
+    EotAck,
+    BatchEndStart,
+    BatchEndAck,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RecvState {
+    Starting,
+    Receiving,
+}
+
+struct Sender {
+    state: SendState,
+    data: Vec<u8>,
+    offset: usize,
+    block_num: u8,
+    packet: Vec<u8>,
+}
+
+struct Receiver {
+    state: RecvState,
+    buffer: Vec<u8>,
+    expected: u8,
+    /// Whether a block has been taken since the start or the last EOT, so
+    /// there is one that could be sent again.
+    any_block: bool,
+    /// YMODEM: the next block 0 is a file header rather than a data block
+    /// numbered 0 after 255 others.
+    awaiting_header: bool,
+    polls: u32,
+    data: Vec<u8>,
+    file_size: Option<u64>,
+    saved: Vec<PathBuf>,
+}
+
+enum Role {
+    Send(Sender),
+    Receive(Receiver),
+}
+
+pub struct Transfer {
+    pub protocol: Protocol,
+    pub direction: Direction,
+    pub path: PathBuf,
+    pub file_name: String,
+    pub crc_mode: bool,
+    pub blocks: u32,
+    pub retries: u32,
+    pub naks: u32,
+    pub bytes_done: u64,
+    pub bytes_total: Option<u64>,
+    pub status: TransferStatus,
+    role: Role,
+    started: Instant,
+    last_activity: Instant,
+    cancel_count: u8,
+}
+
+impl Transfer {
+    pub fn send(protocol: Protocol, path: &Path) -> Result<Self> {
+        let data = fs::read(path).with_context(|| format!("read {}", path.display()))?;
+        let total = data.len() as u64;
+        let role = Role::Send(Sender {
+            state: SendState::Start,
+            data,
+            offset: 0,
+            block_num: 1,
+            packet: Vec::new(),
+        });
+        let mut transfer = Self::new(protocol, Direction::Send, path, role);
+        transfer.bytes_total = Some(total);
+        Ok(transfer)
+    }
+
+    /// Starts receiving into `path`: a file for XMODEM, a directory for YMODEM.
+    pub fn receive(protocol: Protocol, path: &Path) -> Self {
+        let role = Role::Receive(Receiver {
+            state: RecvState::Starting,
+            buffer: Vec::new(),
+            expected: if protocol == Protocol::Ymodem { 0 } else { 1 },
+            any_block: false,
+            awaiting_header: protocol == Protocol::Ymodem,
+            polls: 0,
+            data: Vec::new(),
+            file_size: None,
+            saved: Vec::new(),
+        });
+        Self::new(protocol, Direction::Receive, path, role)
+    }
+
+    fn new(protocol: Protocol, direction: Direction, path: &Path, role: Role) -> Self {
+        let now = Instant::now();
+        Self {
+            protocol,
+            direction,
+            path: path.to_path_buf(),
+            file_name: path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            crc_mode: true,
+            blocks: 0,
+            retries: 0,
+            naks: 0,
+            bytes_done: 0,
+            bytes_total: None,
+            status: TransferStatus::Running,
+            role,
+            started: now,
+            // Forces the receiver's first 'C' poll on the very first tick.
+            last_activity: now.checked_sub(RECV_POLL_INTERVAL).unwrap_or(now),
+            cancel_count: 0,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.status != TransferStatus::Running
+    }
+
+    pub fn progress(&self) -> Option<f64> {
+        let total = self.bytes_total?;
+        if total == 0 {
+            return Some(1.0);
+        }
+        Some((self.bytes_done as f64 / total as f64).clamp(0.0, 1.0))
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// One-line outcome used for the output log once the transfer ends.
+    pub fn summary(&self) -> String {
+        let verb = match self.direction {
+            Direction::Send => "sent",
+            Direction::Receive => "received",
+        };
+        let target = match &self.role {
+            Role::Receive(rx) if !rx.saved.is_empty() => rx
+                .saved
+                .iter()
+                .map(|p| p.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+            _ => self.path.display().to_string(),
+        };
+        match &self.status {
+            TransferStatus::Running => format!("{} in progress", self.protocol.name()),
+            TransferStatus::Done => format!(
+                "{} {} {} ({} bytes, {} blocks, {} retries, {:.1}s)",
+                self.protocol.name(),
+                verb,
+                target,
+                self.bytes_done,
+                self.blocks,
+                self.retries,
+                self.elapsed().as_secs_f64()
+            ),
+            TransferStatus::Failed(reason) => {
+                format!("{} transfer failed: {}", self.protocol.name(), reason)
+            }
+            TransferStatus::Cancelled => format!("{} transfer cancelled", self.protocol.name()),
+        }
+    }
+
+    /// Aborts the transfer, returning the CAN sequence to send to the peer.
+    pub fn cancel(&mut self) -> Vec<u8> {
+        if self.is_finished() {
+            return Vec::new();
+        }
+        self.status = TransferStatus::Cancelled;
+        vec![CAN; 8]
+    }
+
+    /// Aborts after a local or link failure without waiting on the peer.
+    pub fn fail<S: Into<String>>(&mut self, reason: S) {
+        if !self.is_finished() {
+            self.status = TransferStatus::Failed(reason.into());
+        }
+    }
+
+    pub fn on_data(&mut self, bytes: &[u8]) -> Vec<u8> {
+        if self.is_finished() {
+            return Vec::new();
+        }
+        self.last_activity = Instant::now();
+        let mut out = Vec::new();
+        match self.role {
+            Role::Send(_) => {
+                for &b in bytes {
+                    self.sender_byte(b, &mut out);
+                    if self.is_finished() {
+                        break;
+                    }
+                }
+            }
+            Role::Receive(ref mut rx) => {
+                rx.buffer.extend_from_slice(bytes);
+                self.receiver_process(&mut out);
+            }
+        }
+        out
+    }
+
+    pub fn on_tick(&mut self) -> Vec<u8> {
+        if self.is_finished() {
+            return Vec::new();
+        }
+        let mut out = Vec::new();
+        let idle = self.last_activity.elapsed();
+        match &mut self.role {
+            Role::Send(tx) => {
+                if tx.state == SendState::Start {
+                    if self.started.elapsed() >= START_TIMEOUT {
+                        self.status = TransferStatus::Failed("receiver never started".into());
+                    }
+                } else if idle >= ACK_TIMEOUT {
+                    self.retries += 1;
+                    if self.retries > MAX_RETRIES {
+                        self.status = TransferStatus::Failed("timed out waiting for ACK".into());
+                        out.extend_from_slice(&[CAN; 8]);
+                    } else {
+                        out.extend_from_slice(&tx.packet);
+                        self.last_activity = Instant::now();
+                    }
+                }
+            }
+            Role::Receive(rx) => match rx.state {
+                RecvState::Starting => {
+                    if idle >= RECV_POLL_INTERVAL {
+                        if rx.polls >= CRC_ATTEMPTS * 2 + 2 {
+                            self.status = TransferStatus::Failed("sender never started".into());
+                        } else {
+                            if rx.polls >= CRC_ATTEMPTS && self.protocol == Protocol::Xmodem {
+                                self.crc_mode = false;
+                            }
+                            out.push(if self.crc_mode { CRC_START } else { NAK });
+                            rx.polls += 1;
+                            self.last_activity = Instant::now();
+                        }
+                    }
+                }
+                RecvState::Receiving => {
+                    if idle >= ACK_TIMEOUT {
+                        self.retries += 1;
+                        rx.buffer.clear();
+                        if self.retries > MAX_RETRIES {
+                            self.status = TransferStatus::Failed("timed out waiting for data".into());
+                            out.extend_from_slice(&[CAN; 8]);
+                        } else {
+                            self.naks += 1;
+                            out.push(NAK);
+                            self.last_activity = Instant::now();
+                        }
+                    }
+                }
+            },
+        }
+        out
+    }
+
+    fn block_size(&self) -> usize {
+        match self.protocol {
+            Protocol::Xmodem1k | Protocol::Ymodem if self.crc_mode => 1024,
+            _ => 128,
+        }
+    }
+
+    fn sender_byte(&mut self, b: u8, out: &mut Vec<u8>) {
+        if b == CAN {
+            self.cancel_count += 1;
+            if self.cancel_count >= 2 {
+                self.status = TransferStatus::Failed("cancelled by receiver".into());
+            }
+            return;
+        }
+        self.cancel_count = 0;
+
+        let Role::Send(tx) = &mut self.role else {
+            return;
+        };
+        match (tx.state, b) {
+            (SendState::Start, CRC_START | NAK) => {
+                self.crc_mode = b == CRC_START;
+                if self.protocol == Protocol::Ymodem {
+                    let header = ymodem_header(&self.file_name, tx.data.len());
+                    tx.packet = build_packet(0, &header, 128, self.crc_mode, 0);
+                    tx.state = SendState::HeaderAck;
+                    out.extend_from_slice(&tx.packet);
+                } else {
+                    self.send_next_block(out);
+                }
+            }
+            (SendState::HeaderAck, ACK) => {
+                self.blocks += 1;
+                tx.state = SendState::DataStart;
+            }
+            (SendState::DataStart, CRC_START) => {
+                self.send_next_block(out);
+            }
+            (SendState::BlockAck, ACK) => {
+                self.blocks += 1;
+                self.bytes_done = tx.offset as u64;
+                tx.block_num = tx.block_num.wrapping_add(1);
+                self.send_next_block(out);
+            }
+            (SendState::EotAck, ACK) => {
+                if self.protocol == Protocol::Ymodem {
+                    tx.state = SendState::BatchEndStart;
+                } else {
+                    self.status = TransferStatus::Done;
+                }
+            }
+            (SendState::EotAck, NAK) => {
+                out.push(EOT);
+            }
+            (SendState::BatchEndStart, CRC_START) => {
+                tx.packet = build_packet(0, &[], 128, self.crc_mode, 0);
+                tx.state = SendState::BatchEndAck;
+                out.extend_from_slice(&tx.packet);
+            }
+            (SendState::BatchEndAck, ACK) => {
+                self.status = TransferStatus::Done;
+            }
+            (SendState::HeaderAck | SendState::BlockAck | SendState::BatchEndAck, NAK) => {
+                self.naks += 1;
+                self.retries += 1;
+                if self.retries > MAX_RETRIES {
+                    self.status = TransferStatus::Failed("too many NAKs".into());
+                    out.extend_from_slice(&[CAN; 8]);
+                } else {
+                    out.extend_from_slice(&tx.packet);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn send_next_block(&mut self, out: &mut Vec<u8>) {
+        let size = self.block_size();
+        let crc_mode = self.crc_mode;
+        let Role::Send(tx) = &mut self.role else {
+            return;
+        };
+        if tx.offset >= tx.data.len() {
+            tx.packet = vec![EOT];
+            tx.state = SendState::EotAck;
+            out.push(EOT);
+            return;
+        }
+        let end = (tx.offset + size).min(tx.data.len());
+        // Small tails go out as 128-byte blocks to save padding.
+        let size = if end - tx.offset <= 128 { 128 } else { size };
+        tx.packet = build_packet(tx.block_num, &tx.data[tx.offset..end], size, crc_mode, SUB);
+        tx.offset = end;
+        tx.state = SendState::BlockAck;
+        out.extend_from_slice(&tx.packet);
+    }
+
+    fn receiver_process(&mut self, out: &mut Vec<u8>) {
+        loop {
+            let Role::Receive(rx) = &mut self.role else {
+                return;
+            };
+            let Some(&first) = rx.buffer.first() else {
+                return;
+            };
+            match first {
+                SOH | STX => {
+                    let size = if first == STX { 1024 } else { 128 };
+                    let len = 3 + size + if self.crc_mode { 2 } else { 1 };
+                    if rx.buffer.len() < len {
+                        return;
+                    }
+                    let packet: Vec<u8> = rx.buffer.drain(..len).collect();
+                    rx.state = RecvState::Receiving;
+                    self.cancel_count = 0;
+                    self.receiver_packet(&packet, size, out);
+                    if self.is_finished() {
+                        return;
+                    }
+                }
+                EOT => {
+                    rx.buffer.remove(0);
+                    self.cancel_count = 0;
+                    out.push(ACK);
+                    if let Err(e) = self.save_received() {
+                        self.status = TransferStatus::Failed(e.to_string());
+                        return;
+                    }
+                    if self.protocol == Protocol::Ymodem {
+                        if let Role::Receive(rx) = &mut self.role {
+                            rx.expected = 0;
+                            rx.any_block = false;
+                            rx.awaiting_header = true;
+                        }
+                        out.push(CRC_START);
+                    } else {
+                        self.status = TransferStatus::Done;
+                        return;
+                    }
+                }
+                CAN => {
+                    rx.buffer.remove(0);
+                    self.cancel_count += 1;
+                    if self.cancel_count >= 2 {
+                        self.status = TransferStatus::Failed("cancelled by sender".into());
+                        return;
+                    }
+                }
+                _ => {
+                    // Line noise between packets.
+                    rx.buffer.remove(0);
+                }
+            }
+        }
+    }
+
+    fn receiver_packet(&mut self, packet: &[u8], size: usize, out: &mut Vec<u8>) {
+        let crc_mode = self.crc_mode;
+        let Role::Receive(rx) = &mut self.role else {
+            return;
+        };
+        let num = packet[1];
+        let payload = &packet[3..3 + size];
+        let valid = packet[2] == !num && verify_trailer(payload, &packet[3 + size..], crc_mode);
+        if !valid {
+            self.naks += 1;
+            self.retries += 1;
+            out.push(NAK);
+            return;
+        }
+
+        if rx.any_block && num == rx.expected.wrapping_sub(1) {
+            // Our ACK for the previous block got lost; acknowledge again.
+            out.push(ACK);
+            return;
+        }
+        if num != rx.expected {
+            self.status = TransferStatus::Failed(format!(
+                "out of sequence block {} (expected {})",
+                num, rx.expected
+            ));
+            out.extend_from_slice(&[CAN; 8]);
+            return;
+        }
+
+        rx.any_block = true;
+        if rx.awaiting_header {
+            let name_end = payload.iter().position(|&b| b == 0).unwrap_or(payload.len());
+            if name_end == 0 {
+                // Empty header closes the batch.
+                out.push(ACK);
+                self.status = TransferStatus::Done;
+                return;
+            }
+            let name = String::from_utf8_lossy(&payload[..name_end]).into_owned();
+            let meta = &payload[(name_end + 1).min(payload.len())..];
+            let size_str: String = meta
+                .iter()
+                .take_while(|b| b.is_ascii_digit())
+                .map(|&b| b as char)
+                .collect();
+            rx.file_size = size_str.parse().ok();
+            self.bytes_total = rx.file_size.map(|s| self.bytes_done + s);
+            self.file_name = Path::new(&name)
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or(name);
+            rx.data.clear();
+            rx.expected = 1;
+            rx.awaiting_header = false;
+            self.blocks += 1;
+            out.push(ACK);
+            out.push(CRC_START);
+            return;
+        }
+
+        rx.data.extend_from_slice(payload);
+        rx.expected = rx.expected.wrapping_add(1);
+        self.blocks += 1;
+        self.bytes_done += size as u64;
+        if let Some(total) = self.bytes_total {
+            self.bytes_done = self.bytes_done.min(total);
+        }
+        out.push(ACK);
+    }
+
+    fn save_received(&mut self) -> Result<()> {
+        let protocol = self.protocol;
+        let Role::Receive(rx) = &mut self.role else {
+            return Ok(());
+        };
+        let mut data = std::mem::take(&mut rx.data);
+        let target = if protocol == Protocol::Ymodem {
+            if let Some(size) = rx.file_size.take() {
+                data.truncate(size as usize);
+            }
+            fs::create_dir_all(&self.path)
+                .with_context(|| format!("create {}", self.path.display()))?;
+            self.path.join(&self.file_name)
+        } else {
+            while data.last() == Some(&SUB) {
+                data.pop();
+                self.bytes_done = self.bytes_done.saturating_sub(1);
+            }
+            self.path.clone()
+        };
+        fs::write(&target, &data).with_context(|| format!("write {}", target.display()))?;
+        rx.saved.push(target);
+        Ok(())
+    }
+}
+
+fn ymodem_header(name: &str, len: usize) -> Vec<u8> {
+    let mut header = Vec::with_capacity(128);
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(len.to_string().as_bytes());
+    header.truncate(127);
+    header
+}
+
+fn build_packet(num: u8, payload: &[u8], size: usize, crc_mode: bool, pad: u8) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(size + 5);
+    packet.push(if size == 1024 { STX } else { SOH });
+    packet.push(num);
+    packet.push(!num);
+    let start = packet.len();
+    packet.extend_from_slice(payload);
+    packet.resize(start + size, pad);
+    if crc_mode {
+        let crc = crc16_xmodem(&packet[start..]);
+        packet.extend_from_slice(&crc.to_be_bytes());
+    } else {
+        packet.push(checksum(&packet[start..]));
+    }
+    packet
+}
+
+fn verify_trailer(payload: &[u8], trailer: &[u8], crc_mode: bool) -> bool {
+    if crc_mode {
+        trailer == crc16_xmodem(payload).to_be_bytes()
+    } else {
+        trailer == [checksum(payload)]
+    }
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |acc, b| acc.wrapping_add(*b))
+}
+
+fn crc16_xmodem(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for &b in data {
+        crc ^= (b as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs a sender and a receiver against each other until both finish.
+    fn loopback(protocol: Protocol, data: &[u8]) -> Vec<u8> {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source.bin");
+        fs::write(&source, data).unwrap();
+        let target = dir.path().join("received");
+        let mut sender = Transfer::send(protocol, &source).unwrap();
+        let mut receiver = Transfer::receive(protocol, &target);
+
+        let mut to_sender = receiver.on_tick();
+        while !(sender.is_finished() && receiver.is_finished()) {
+            assert!(!to_sender.is_empty(), "{protocol:?} stalled");
+            let to_receiver = sender.on_data(&to_sender);
+            to_sender = receiver.on_data(&to_receiver);
+        }
+        assert_eq!(sender.status, TransferStatus::Done, "{protocol:?} sender");
+        assert_eq!(receiver.status, TransferStatus::Done, "{protocol:?} receiver");
+        match protocol {
+            Protocol::Ymodem => fs::read(target.join("source.bin")).unwrap(),
+            _ => fs::read(&target).unwrap(),
+        }
+    }
+
+    #[test]
+    fn crc_matches_the_xmodem_check_value() {
+        assert_eq!(crc16_xmodem(b"123456789"), 0x31c3);
+    }
+
+    #[test]
+    fn round_trips_past_block_number_255() {
+        let data: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+        for protocol in [Protocol::Xmodem, Protocol::Xmodem1k, Protocol::Ymodem] {
+            assert!(data == loopback(protocol, &data), "{protocol:?}");
+        }
+    }
+
+    #[test]
+    fn acks_block_255_again_once_the_count_wrapped() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut receiver = Transfer::receive(Protocol::Xmodem, &dir.path().join("received"));
+        for num in 1..=255u8 {
+            assert_eq!(receiver.on_data(&build_packet(num, &[num], 128, true, SUB)), [ACK]);
+        }
+        assert_eq!(receiver.on_data(&build_packet(255, &[255], 128, true, SUB)), [ACK]);
+        assert_eq!(receiver.status, TransferStatus::Running);
+        assert_eq!(receiver.on_data(&build_packet(0, &[0], 128, true, SUB)), [ACK]);
+        assert_eq!(receiver.blocks, 256);
+    }
+}
@@ -1,138 +1,2058 @@
+use std::time::Instant;
+
+use lazyserial::checksum::{self, Algorithm};
+use lazyserial::decoder::Monitor;
+use lazyserial::linktest::LinkTest;
+use lazyserial::nmea;
+use lazyserial::stm32::Flasher;
+use lazyserial::transfer::{self, Transfer};
+use lazyserial::{LineErrors, Session};
 use ratatui::layout::{Constraint, Direction, Layout, Rect};
 use ratatui::prelude::*;
-use ratatui::style::{Color, Modifier, Style};
+use ratatui::style::{Modifier, Style};
 use ratatui::text::{Line, Span, Text};
 use ratatui::widgets::*;
-use unicode_width::UnicodeWidthStr;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
-use crate::app::{AppState, Focus};
+use crate::ansi::{self, AnsiMode};
+use crate::app::{self, AppState, Focus, ModalKind, OpenError};
+use crate::at;
+use crate::autoresponse::Matcher;
+use crate::compare::{Compare, Side, Stamped};
+use crate::compose::Compose;
+use crate::config::{self, LayoutConfig, Split};
+use crate::export;
+use crate::hexdump;
+use crate::inspect::Inspector;
+use crate::keymap::{self, Action, Scope};
+use crate::macros;
+use crate::palette::Palette;
+use crate::ping::{self, Ping};
+use crate::plot::{self, Plotter};
+use crate::profile;
+use crate::theme::Theme;
+use crate::toast::{Level, Toasts};
 
-pub fn draw(frame: &mut Frame, app: &AppState) {
+/// Rows of the GPS summary pane, borders included.
+const GPS_HEIGHT: u16 = 11;
+
+/// Screen areas of the main panes, shared by drawing and mouse hit-testing.
+pub struct AppLayout {
+    pub header: Rect,
+    /// Everything between header and footer, which the panes divide.
+    pub body: Rect,
+    /// Empty while collapsed.
+    pub ports: Rect,
+    /// GPS summary beside the ports, while NMEA parsing is on.
+    pub gps: Option<Rect>,
+    pub output: Rect,
+    /// Hex bytes of the output lines beside them, while split.
+    pub hex: Option<Rect>,
+    /// Modbus packet list under the output, while the monitor is on.
+    pub packets: Option<Rect>,
+    /// Chart of numbers from received lines, while plotting.
+    pub plot: Option<Rect>,
+    pub footer: Rect,
+}
+
+pub fn layout(area: Rect, app: &AppState) -> AppLayout {
+    if app.zoomed {
+        let none = Rect::new(area.x, area.y, 0, 0);
+        let (output, hex) = split_hex(area, app);
+        return AppLayout {
+            header: none,
+            body: area,
+            ports: none,
+            gps: None,
+            output,
+            hex,
+            packets: None,
+            plot: None,
+            footer: none,
+        };
+    }
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints(
             [Constraint::Length(3), Constraint::Min(5), Constraint::Length(3)].as_ref(),
         )
-        .split(frame.size());
+        .split(area);
+    let body = chunks[1];
+    let collapsed = app.ports_collapsed();
+    let vertical = app.layout.direction == Split::Vertical;
+    let share = if collapsed && app.nmea.is_none() {
+        0
+    } else {
+        app.layout
+            .ports_width
+            .clamp(LayoutConfig::MIN_PORTS_WIDTH, LayoutConfig::MAX_PORTS_WIDTH)
+    };
+    let panes = Layout::default()
+        .direction(if vertical { Direction::Vertical } else { Direction::Horizontal })
+        .constraints([Constraint::Percentage(share), Constraint::Percentage(100 - share)])
+        .split(body);
+    // The GPS summary goes under the ports side by side, beside them
+    // stacked, and has the column to itself while they are collapsed.
+    let side = panes[0];
+    let (ports, gps) = match (app.nmea.is_some(), collapsed) {
+        (false, false) => (side, None),
+        (_, true) => (Rect::new(side.x, side.y, 0, 0), app.nmea.as_ref().map(|_| side)),
+        (true, false) if vertical => {
+            let split = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+                .split(side);
+            (split[0], Some(split[1]))
+        }
+        (true, false) => {
+            let split = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(3), Constraint::Length(GPS_HEIGHT)].as_ref())
+                .split(side);
+            (split[0], Some(split[1]))
+        }
+    };
+    // Panes stacked under the output share the column evenly with it,
+    // the output keeping at least 30%.
+    let extra = [app.packets.is_some(), app.plot.is_some()];
+    let count = extra.iter().filter(|&&on| on).count() as u16;
+    let share = 70u16.checked_div(count).unwrap_or(0);
+    let mut constraints = vec![Constraint::Percentage(100 - share * count)];
+    constraints.extend((0..count).map(|_| Constraint::Percentage(share)));
+    let split = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(constraints)
+        .split(panes[1]);
+    let mut below = split.iter().skip(1).copied();
+    let mut pane = |on: bool| if on { below.next() } else { None };
+    let packets = pane(extra[0]);
+    let plot = pane(extra[1]);
+    let (output, hex) = split_hex(split[0], app);
+    AppLayout {
+        header: chunks[0],
+        body,
+        ports,
+        gps,
+        output,
+        hex,
+        packets,
+        plot,
+        footer: chunks[2],
+    }
+}
+
+/// Halves the output area between text and hex while split.
+fn split_hex(area: Rect, app: &AppState) -> (Rect, Option<Rect>) {
+    if !app.hex_split || app.compare.is_some() {
+        return (area, None);
+    }
+    let halves = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(area);
+    (halves[0], Some(halves[1]))
+}
 
-    draw_header(frame, chunks[0], app);
-    draw_body(frame, chunks[1], app);
-    draw_footer(frame, chunks[2], app);
+/// The Ports pane width (or height, stacked) in percent for a border
+/// dragged to `column`, `row`.
+pub fn ports_share(areas: &AppLayout, split: Split, column: u16, row: u16) -> u16 {
+    let (at, start, size) = match split {
+        Split::Horizontal => (column, areas.body.x, areas.body.width),
+        Split::Vertical => (row, areas.body.y, areas.body.height),
+    };
+    let share = (u32::from(at.saturating_sub(start)) + 1) * 100 / u32::from(size.max(1));
+    (share as u16).clamp(LayoutConfig::MIN_PORTS_WIDTH, LayoutConfig::MAX_PORTS_WIDTH)
+}
+
+/// Whether `column`, `row` is on the border between the Ports and Output
+/// panes.
+pub fn on_ports_border(areas: &AppLayout, split: Split, column: u16, row: u16) -> bool {
+    let ports = areas.ports;
+    if ports.area() == 0 {
+        return false;
+    }
+    match split {
+        Split::Horizontal => {
+            (column + 1 == ports.right() || column == areas.output.x)
+                && row >= ports.y
+                && row < ports.bottom()
+        }
+        Split::Vertical => {
+            (row + 1 == ports.bottom() || row == areas.output.y)
+                && column >= areas.body.x
+                && column < areas.body.right()
+        }
+    }
+}
+
+pub fn draw(frame: &mut Frame, app: &AppState) {
+    let areas = layout(frame.size(), app);
+
+    if !app.zoomed {
+        draw_header(frame, areas.header, app);
+    }
+    if areas.ports.area() > 0 {
+        draw_ports(frame, areas.ports, app);
+    }
+    if let (Some(area), Some(tracker)) = (areas.gps, &app.nmea) {
+        draw_gps(frame, area, app, tracker);
+    }
+    match &app.compare {
+        Some(compare) => draw_compare(frame, areas.output, app, compare),
+        None => draw_output(frame, areas.output, app),
+    }
+    if let Some(area) = areas.hex {
+        draw_hex(frame, area, areas.output, app);
+    }
+    if let (Some(area), Some(monitor)) = (areas.packets, &app.packets) {
+        draw_packets(frame, area, app, monitor);
+    }
+    if let (Some(area), Some(plotter)) = (areas.plot, &app.plot) {
+        draw_plot(frame, area, app, plotter);
+    }
+    if !app.zoomed {
+        draw_footer(frame, areas.footer, app);
+    }
+    if let Some(err) = &app.open_error {
+        draw_open_error(frame, areas.output, app, err);
+    }
+    draw_toasts(frame, areas.output, app);
+
+    if let Some(transfer) = &app.transfer {
+        draw_transfer(frame, transfer, &app.theme);
+    }
+    if let Some(flash) = &app.flash {
+        draw_flash(frame, flash, &app.theme);
+    }
+    if let Some(selected) = app.auto_response_view {
+        draw_auto_responses(frame, app, selected);
+    }
+    if let Some(selected) = app.macro_view {
+        draw_macros(frame, app, selected);
+    }
+    if let Some(selected) = app.highlight_view {
+        draw_highlights(frame, app, selected);
+    }
+    if let Some(scroll) = app.help_view {
+        draw_help(frame, app, scroll);
+    }
+    if let Some(back) = app.error_view {
+        draw_error_view(frame, &app.toasts, back, &app.theme);
+    }
+    if let Some(compose) = &app.compose {
+        draw_compose(frame, compose, &app.theme);
+    }
+    if let Some(input) = &app.checksum_view {
+        draw_checksum(frame, input, &app.theme);
+    }
+    if let Some(ping) = &app.ping {
+        draw_ping(frame, ping, &app.theme);
+    }
+    if let Some(test) = &app.link_test {
+        draw_link_test(frame, test, &app.theme);
+    }
+    if let Some(inspector) = &app.inspector {
+        draw_inspector(frame, inspector, &app.theme);
+    }
+    if let Some(palette) = &app.palette {
+        draw_palette(frame, app, palette);
+    }
+    if app.prompt.is_some() {
+        draw_prompt(frame, app);
+    }
+    if let Some(modal) = &app.modal {
+        draw_modal(frame, &modal.title, &modal.kind, &app.theme);
+    }
+}
+
+fn centered_rect(width: u16, height: u16, area: Rect) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
 }
 
 fn draw_header(frame: &mut Frame, area: Rect, app: &AppState) {
+    let theme = &app.theme;
     let mut spans: Vec<Span> = Vec::new();
-    spans.push(Span::styled(" setial-tui ", Style::default().fg(Color::Black).bg(Color::Cyan).add_modifier(Modifier::BOLD)));
-    spans.push(Span::raw("  q:quit  TAB:focus  r:refresh  b/B:baud  Enter:open/close "));
     spans.push(Span::styled(
-        format!(" [baud:{}] ", app.baud_rate),
-        Style::default().fg(Color::Yellow),
+        " setial-tui ",
+        Style::default()
+            .fg(theme.badge_text)
+            .bg(theme.accent)
+            .add_modifier(Modifier::BOLD),
+    ));
+    spans.push(Span::raw(format!("  {} ", app.keymap.summary())));
+    spans.push(Span::styled(
+        format!(" [baud:{} {}] ", app.baud_rate, app.parity.frame()),
+        Style::default().fg(theme.label),
     ));
-    if let Some(idx) = app.selected_port {
+    spans.push(Span::styled(
+        format!("[eol:{}] ", app.line_ending.name()),
+        Style::default().fg(theme.label),
+    ));
+    let decoding = match (&app.packets, &app.defmt, &app.frames) {
+        (Some(monitor), _, _) => monitor.name,
+        (None, Some(_), _) => "defmt",
+        (None, None, Some(frames)) => frames.framing().name(),
+        (None, None, None) => app.decoder.encoding().name(),
+    };
+    spans.push(Span::styled(
+        format!("[{}] ", decoding),
+        Style::default().fg(theme.label),
+    ));
+    let port = app
+        .session
+        .as_ref()
+        .map(Session::target)
+        .or_else(|| app.selected_port.map(|idx| app.ports[idx].port_name.as_str()));
+    if let Some(port) = port {
         spans.push(Span::styled(
-            format!(" port:{} ", app.ports[idx].port_name),
-            Style::default().fg(Color::Green),
+            format!(" port:{} ", port),
+            Style::default().fg(theme.ok),
+        ));
+    }
+    if let Some(name) = &app.profile {
+        spans.push(Span::styled(
+            format!("profile:{} ", name),
+            Style::default().fg(theme.ok),
         ));
     }
     spans.push(Span::styled(
         if app.is_open { " OPEN " } else { " CLOSED " },
         if app.is_open {
-            Style::default().fg(Color::Black).bg(Color::Green)
+            Style::default().fg(theme.badge_text).bg(theme.ok)
         } else {
-            Style::default().fg(Color::Black).bg(Color::Red)
+            Style::default().fg(theme.badge_text).bg(theme.error)
         },
     ));
+    if app.is_open {
+        for (name, level) in [("DTR", app.dtr), ("RTS", app.rts)] {
+            spans.push(Span::raw(" "));
+            spans.push(Span::styled(
+                format!(" {} ", name),
+                if level {
+                    Style::default().fg(theme.badge_text).bg(theme.ok)
+                } else {
+                    Style::default().fg(theme.dim)
+                },
+            ));
+        }
+        if app.rs485.is_some() {
+            spans.push(Span::raw(" "));
+            spans.push(Span::styled(
+                " RS-485 ",
+                Style::default().fg(theme.badge_text).bg(theme.accent),
+            ));
+        }
+    }
+    if let Some(algorithm) = app.checksum {
+        spans.push(Span::raw(" "));
+        spans.push(Span::styled(
+            format!(" +{} ", algorithm.name()),
+            Style::default().fg(theme.badge_text).bg(theme.accent),
+        ));
+    }
+    if app.alerts.count > 0 {
+        let muted = if app.alerts.enabled { "" } else { " (off)" };
+        spans.push(Span::styled(
+            format!(" alerts:{}{muted} ", app.alerts.count),
+            Style::default().fg(theme.badge_text).bg(theme.error),
+        ));
+    }
+    if let Some(pending) = &app.paused_lines {
+        spans.push(Span::styled(
+            format!(" PAUSED +{} ", pending.len()),
+            Style::default().fg(theme.badge_text).bg(theme.busy),
+        ));
+    }
+    if let Some(repeat) = &app.repeat {
+        spans.push(Span::styled(
+            format!(" repeat:{}ms x{} ", repeat.interval.as_millis(), repeat.count),
+            Style::default().fg(theme.badge_text).bg(theme.busy),
+        ));
+    }
+    if let Some(gcode) = &app.gcode {
+        let state = if gcode.paused { "paused " } else { "" };
+        // A long move or heat-up keeps the firmware from answering.
+        let stalled = match gcode.waiting() {
+            Some(wait) if wait.as_secs() >= 5 => format!(" (ok? {}s)", wait.as_secs()),
+            _ => String::new(),
+        };
+        spans.push(Span::styled(
+            format!(
+                " gcode {state}{}/{} {:.0}%{stalled} ",
+                gcode.acked(),
+                gcode.total(),
+                gcode.progress() * 100.0
+            ),
+            Style::default().fg(theme.badge_text).bg(theme.busy),
+        ));
+    }
+    if let Some(paste) = &app.paste {
+        spans.push(Span::styled(
+            format!(" paste:{}/{} ", paste.sent, paste.sent + paste.lines.len()),
+            Style::default().fg(theme.badge_text).bg(theme.busy),
+        ));
+    }
+    if let Some(sniff) = &app.sniff {
+        spans.push(Span::styled(
+            format!(" sniff:{} ", sniff.session.target()),
+            Style::default().fg(theme.badge_text).bg(theme.accent),
+        ));
+    }
+    if let Some(recorder) = &app.recorder {
+        spans.push(Span::styled(
+            format!(" REC {} ", recorder.records),
+            Style::default().fg(theme.badge_text).bg(theme.error),
+        ));
+    }
+    if let Some(replay) = &app.replay {
+        spans.push(Span::styled(
+            format!(
+                " replay {}/{} {} ",
+                replay.position(),
+                replay.len(),
+                replay.speed.name()
+            ),
+            Style::default().fg(theme.badge_text).bg(theme.busy),
+        ));
+    }
+    if let Some(script) = &app.script {
+        spans.push(Span::styled(
+            format!(" script:{} ", script.name),
+            Style::default().fg(theme.badge_text).bg(theme.script),
+        ));
+    }
+    if let Some(bridge) = &app.bridge {
+        spans.push(Span::styled(
+            format!(
+                " {}:{} ({} clients) ",
+                if bridge.websocket { "ws" } else { "bridge" },
+                bridge.addr.port(),
+                bridge.client_count()
+            ),
+            Style::default().fg(theme.badge_text).bg(theme.accent),
+        ));
+    }
+    if let Some(mqtt) = &app.mqtt {
+        spans.push(Span::styled(
+            match mqtt.dropped {
+                0 => format!(" mqtt:{} ", mqtt.topic),
+                n => format!(" mqtt:{} ({n} dropped) ", mqtt.topic),
+            },
+            Style::default().fg(theme.badge_text).bg(theme.accent),
+        ));
+    }
+    if let Some(lines) = app.modem_lines {
+        spans.push(Span::raw(" "));
+        for (name, level) in [
+            ("CTS", lines.cts),
+            ("DSR", lines.dsr),
+            ("CD", lines.cd),
+            ("RI", lines.ri),
+        ] {
+            spans.push(Span::styled(
+                format!(" {} ", name),
+                if level {
+                    Style::default().fg(theme.badge_text).bg(theme.accent)
+                } else {
+                    Style::default().fg(theme.dim)
+                },
+            ));
+        }
+    }
 
-    let block = Block::default().borders(Borders::ALL).title("Help");
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title("Help")
+        .border_style(Style::default().fg(theme.border));
     let p = Paragraph::new(Text::from(Line::from(spans))).block(block);
     frame.render_widget(p, area);
 }
 
-fn draw_body(frame: &mut Frame, area: Rect, app: &AppState) {
-    let chunks = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([Constraint::Percentage(30), Constraint::Percentage(70)].as_ref())
-        .split(area);
-
-    draw_ports(frame, chunks[0], app);
-    draw_output(frame, chunks[1], app);
-}
-
 fn draw_ports(frame: &mut Frame, area: Rect, app: &AppState) {
-    let items: Vec<ListItem> = app
-        .ports
+    let theme = &app.theme;
+    let visible = app.visible_ports();
+    let rows = port_rows(app, &visible);
+    let items: Vec<ListItem> = rows
         .iter()
-        .map(|p| {
+        .map(|row| {
+            let Some(i) = *row else {
+                return ListItem::new(Line::styled("Bluetooth", Style::default().fg(theme.label)));
+            };
+            let p = &app.ports[i];
+            let star = if profile::is_favorite(&app.favorites, p) { "* " } else { "  " };
             let mut line = vec![
-                Span::styled(&p.port_name, Style::default().fg(Color::White)),
+                Span::styled(star, Style::default().fg(theme.label)),
+                Span::styled(&p.port_name, Style::default().fg(theme.text)),
             ];
-            if let serialport::SerialPortType::UsbPort(info) = &p.port_type {
-                let tail = format!(
-                    "  {} {} {}",
-                    info.manufacturer.clone().unwrap_or_default(),
-                    info.product.clone().unwrap_or_default(),
-                    info.serial_number.clone().unwrap_or_default()
-                );
-                line.push(Span::styled(tail, Style::default().fg(Color::DarkGray)));
+            if let Some(note) = app.port_note(p) {
+                line.push(Span::styled(format!("  {note}"), Style::default().fg(theme.dim)));
             }
             ListItem::new(Line::from(line))
         })
         .collect();
 
+    let title = if app.port_filter.is_empty() && !app.port_filter_editing {
+        "Ports".to_string()
+    } else {
+        format!("Ports {}/{} /{}", visible.len(), app.ports.len(), app.port_filter)
+    };
+    let title_width = title.width() as u16;
     let list = List::new(items)
-        .block(Block::default().borders(Borders::ALL).title("Ports"))
-        .highlight_style(Style::default().bg(Color::Blue))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(title)
+                .border_style(Style::default().fg(theme.border)),
+        )
+        .highlight_style(Style::default().bg(theme.selection))
         .highlight_symbol("> ");
 
     let mut state = ListState::default();
-    state.select(app.selected_port);
+    state.select(app.selected_port.and_then(|i| rows.iter().position(|&r| r == Some(i))));
     frame.render_stateful_widget(list, area, &mut state);
 
-    if app.focus == Focus::Ports {
+    if app.focus == Focus::Ports && app.port_filter_editing {
+        let x = (area.x + 1 + title_width).min(area.right().saturating_sub(2));
+        frame.set_cursor(x, area.y);
+    } else if app.focus == Focus::Ports {
         frame.set_cursor(area.x + 1, area.y + 1);
     }
 }
 
+/// The Ports pane's rows: the visible ports, with a heading (`None`) above
+/// the Bluetooth devices.
+pub fn port_rows(app: &AppState, visible: &[usize]) -> Vec<Option<usize>> {
+    let mut rows: Vec<Option<usize>> = visible.iter().copied().map(Some).collect();
+    let bluetooth = visible.iter().position(|&i| app::is_bluetooth(&app.ports[i]));
+    if let Some(at) = bluetooth {
+        rows.insert(at, None);
+    }
+    rows
+}
+
+fn draw_gps(frame: &mut Frame, area: Rect, app: &AppState, gps: &nmea::Tracker) {
+    let theme = &app.theme;
+    let unknown = || "-".to_string();
+    let fix = gps.fix.map_or("-", nmea::Fix::name);
+    let fix_color = match gps.fix {
+        Some(nmea::Fix::None) => theme.error,
+        Some(_) => theme.ok,
+        None => theme.dim,
+    };
+    let when = match (&gps.date, &gps.time) {
+        (Some(date), Some(time)) => format!("{date} {time} UTC"),
+        (None, Some(time)) => format!("{time} UTC"),
+        _ => unknown(),
+    };
+    let coordinate = |value: Option<f64>, pos: char, neg: char| {
+        value.map_or_else(unknown, |v| {
+            format!("{:.6}° {}", v.abs(), if v < 0.0 { neg } else { pos })
+        })
+    };
+    let in_view: Vec<String> = gps
+        .satellites_in_view
+        .iter()
+        .map(|(talker, n)| format!("{talker} {n}"))
+        .collect();
+    let satellites = format!(
+        "{} used, {} in view{}",
+        gps.satellites_used.map_or_else(unknown, |n| n.to_string()),
+        gps.satellites_in_view_total(),
+        if in_view.len() > 1 { format!(" ({})", in_view.join(", ")) } else { String::new() }
+    );
+    let speed = gps.speed_knots.map_or_else(unknown, |kn| {
+        format!("{:.1} km/h ({kn:.1} kn)", kn * 1.852)
+    });
+    let dop = match (gps.hdop, gps.pdop) {
+        (Some(h), Some(p)) => format!("h {h:.1}  p {p:.1}"),
+        (Some(h), None) => format!("h {h:.1}"),
+        _ => unknown(),
+    };
+    let rows = [
+        ("Time", when),
+        ("Lat", coordinate(gps.latitude, 'N', 'S')),
+        ("Lon", coordinate(gps.longitude, 'E', 'W')),
+        ("Alt", gps.altitude.map_or_else(unknown, |m| format!("{m:.1} m"))),
+        ("Sats", satellites),
+        ("Speed", speed),
+        ("Course", gps.course.map_or_else(unknown, |c| format!("{c:.1}°"))),
+        ("DOP", dop),
+    ];
+    let mut lines = vec![Line::from(vec![
+        Span::styled(format!("{:<7}", "Fix"), Style::default().fg(theme.label)),
+        Span::styled(fix, Style::default().fg(fix_color)),
+    ])];
+    lines.extend(rows.into_iter().map(|(name, value)| {
+        Line::from(vec![
+            Span::styled(format!("{name:<7}"), Style::default().fg(theme.label)),
+            Span::raw(value),
+        ])
+    }));
+    let bad = if gps.bad_checksums > 0 {
+        Span::styled(
+            format!(" {} bad ", gps.bad_checksums),
+            Style::default().fg(theme.error),
+        )
+    } else {
+        Span::raw("")
+    };
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!("GPS [{} sentences]", gps.sentences))
+        .title(block::Title::from(bad).alignment(Alignment::Right))
+        .border_style(Style::default().fg(theme.border));
+    frame.render_widget(Paragraph::new(Text::from(lines)).block(block), area);
+}
+
+/// First visible row of the ports list. The list state is rebuilt every
+/// frame, so ratatui scrolls just far enough to keep the selection in view.
+pub fn port_list_offset(selected: Option<usize>, height: usize) -> usize {
+    selected.map_or(0, |s| s.saturating_sub(height.saturating_sub(1)))
+}
+
 fn draw_output(frame: &mut Frame, area: Rect, app: &AppState) {
-    let block = Block::default().borders(Borders::ALL).title("Output");
+    let theme = &app.theme;
+    let mut title = if app.follow {
+        "Output".to_string()
+    } else {
+        format!(
+            "Output [scrolled {} up, {}: follow]",
+            app.output_scroll,
+            app.keymap.label(Action::Follow)
+        )
+    };
+    if !app.wrap {
+        match app.output_hscroll {
+            0 => title.push_str(" [no wrap]"),
+            n => title.push_str(&format!(" [no wrap, +{n} cols]")),
+        }
+    }
+    if !app.bookmarks.is_empty() {
+        title.push_str(&format!(
+            " [{} bookmarks, {}/{}: jump]",
+            app.bookmarks.len(),
+            app.keymap.label(Action::NextBookmark),
+            app.keymap.label(Action::PrevBookmark)
+        ));
+    }
+    if let Some(filter) = &app.filter {
+        if filter.active {
+            title.push_str(&format!(" [filter: {} ({} lines)]", filter.expr(), filter.len()));
+        } else {
+            title.push_str(&format!(" [filter off: {}]", filter.expr()));
+        }
+    }
+    if let Some(pipe) = &app.pipe {
+        title.push_str(&format!(" [| {}]", pipe.command));
+    }
+    if app.zoomed {
+        title.push_str(&format!(" [zoomed, {}: back]", app.keymap.label(Action::Zoom)));
+    }
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(title)
+        .border_style(Style::default().fg(theme.border));
+    let inner = block.inner(area);
 
-    let height = area.height.saturating_sub(2) as usize; // borders
-    let total = app.output_lines.len();
-    let scroll_back = app.output_scroll as usize;
-    let start = total.saturating_sub(height + scroll_back);
-    let end = total.saturating_sub(scroll_back);
-    let visible = app.output_lines.iter().skip(start).take(end - start);
+    let selection = app.selection_range();
+    let evicted = app.output_lines.evicted();
+    let mut rows: Vec<Line> = Vec::new();
+    let mut last_idx = None;
+    for idx in output_rows(app, area) {
+        if last_idx == Some(idx) {
+            continue;
+        }
+        last_idx = Some(idx);
+        let text = app.output_lines.get(idx).unwrap_or_default();
+        let direction = app.directions.get(evicted + idx);
+        let mut line = output_line(app, &text, direction);
+        if app.chat && direction == Some(lazyserial::Direction::Tx) {
+            line = line.alignment(Alignment::Right);
+        }
+        if app.bookmarks.contains(evicted + idx) {
+            line.patch_style(Style::default().add_modifier(Modifier::UNDERLINED));
+        }
+        if selection.as_ref().is_some_and(|r| r.contains(&idx)) {
+            line.patch_style(Style::default().add_modifier(Modifier::REVERSED));
+        }
+        if app.wrap {
+            rows.extend(wrap_line(line, inner.width as usize));
+        } else {
+            rows.push(clip_line(line, app.output_hscroll, inner.width as usize));
+        }
+    }
+    // The topmost line may only partly fit; keep its tail.
+    let overflow = rows.len().saturating_sub(inner.height as usize);
+    let p = Paragraph::new(Text::from(rows.split_off(overflow))).block(block);
+    frame.render_widget(p, area);
+}
 
-    let text: Vec<Line> = visible
-        .map(|l| Line::from(Span::raw(l.clone())))
-        .collect();
-    let p = Paragraph::new(Text::from(text))
-        .block(block)
-        .wrap(Wrap { trim: false });
+/// `line` as shown, in the color of the `direction` it went over the port.
+fn output_line(
+    app: &AppState,
+    line: &str,
+    direction: Option<lazyserial::Direction>,
+) -> Line<'static> {
+    let mut shown = display_line(app, line);
+    let color = direction.map(|d| match d {
+        lazyserial::Direction::Tx => app.theme.tx,
+        lazyserial::Direction::Rx => app.theme.rx,
+    });
+    if let Some(color) = color {
+        // Colors the line brought along win.
+        for span in shown.spans.iter_mut().filter(|s| s.style.fg.is_none()) {
+            span.style.fg = Some(color);
+        }
+    }
+    app.highlighter.apply(shown)
+}
+
+/// `line` as the Output pane shows it, before highlighting.
+fn display_line(app: &AppState, line: &str) -> Line<'static> {
+    if app.control_chars {
+        return ansi::to_control_pictures(line, Style::default().fg(app.theme.dim));
+    }
+    // Line endings kept while control characters were shown.
+    let line = line.trim_end_matches(['\r', '\n']);
+    let line = match app.ansi_mode {
+        AnsiMode::Render => ansi::to_line(line, Style::default(), app.sanitize),
+        AnsiMode::Strip => Line::from(ansi::strip(line)),
+    };
+    if app.sanitize {
+        ansi::sanitize(line)
+    } else {
+        line
+    }
+}
+
+/// Maps each visible row of the Output pane (top to bottom) to the index
+/// of the `output_lines` entry drawn there.
+pub fn output_rows(app: &AppState, area: Rect) -> Vec<usize> {
+    let width = area.width.saturating_sub(2) as usize; // borders
+    let height = area.height.saturating_sub(2) as usize;
+    let total = app.view_len();
+    let end = total.saturating_sub(app.output_scroll.min(total.saturating_sub(1)));
+
+    let mut rows = Vec::with_capacity(height);
+    for pos in (0..end).rev() {
+        if rows.len() >= height {
+            break;
+        }
+        let Some(idx) = app.view_line(pos) else {
+            continue;
+        };
+        rows.resize(rows.len() + wrapped_rows(app, idx, width), idx);
+    }
+    rows.truncate(height);
+    rows.reverse();
+    rows
+}
+
+/// Rows output line `idx` takes in a pane `width` columns wide.
+fn wrapped_rows(app: &AppState, idx: usize, width: usize) -> usize {
+    if width == 0 || !app.wrap {
+        return 1;
+    }
+    let line = app.output_lines.get(idx).unwrap_or_default();
+    let line_width = display_line(app, &line).width();
+    line_width.div_ceil(width).max(1)
+}
+
+/// Shows the bytes of each output line in hex, level with the line in the
+/// text pane `text_area` so the two scroll together. Each line gets the
+/// rows its text takes; bytes that don't fit in them are cut with `…`.
+fn draw_hex(frame: &mut Frame, area: Rect, text_area: Rect, app: &AppState) {
+    let encoding = app.decoder.encoding();
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!("Hex [{}]", encoding.name()))
+        .border_style(Style::default().fg(app.theme.border));
+    let inner = block.inner(area);
+    let text_width = text_area.width.saturating_sub(2) as usize;
+    let per_row = (inner.width as usize + 1) / 3;
+
+    let selection = app.selection_range();
+    let evicted = app.output_lines.evicted();
+    let mut rows: Vec<Line> = Vec::new();
+    let mut last_idx = None;
+    for idx in output_rows(app, text_area) {
+        if last_idx == Some(idx) {
+            continue;
+        }
+        last_idx = Some(idx);
+        let text = app.output_lines.get(idx).unwrap_or_default();
+        let bytes = encoding.encode(&text);
+        let mut style = Style::default();
+        if app.bookmarks.contains(evicted + idx) {
+            style = style.add_modifier(Modifier::UNDERLINED);
+        }
+        if selection.as_ref().is_some_and(|r| r.contains(&idx)) {
+            style = style.add_modifier(Modifier::REVERSED);
+        }
+        let count = wrapped_rows(app, idx, text_width);
+        let mut chunks = bytes.chunks(per_row.max(1));
+        for row in 0..count {
+            let mut hex = chunks.next().map(hexdump::hex).unwrap_or_default();
+            if row + 1 == count && chunks.next().is_some() {
+                // Make room for the marker by dropping the last byte.
+                hex.truncate(hex.len().saturating_sub(3));
+                hex.push_str(" …");
+            }
+            rows.push(Line::styled(hex, style));
+        }
+    }
+    // Cut the topmost line the way the text pane does.
+    let overflow = rows.len().saturating_sub(inner.height as usize);
+    let p = Paragraph::new(Text::from(rows.split_off(overflow))).block(block);
     frame.render_widget(p, area);
 }
 
+/// Shows the main and the compared port's lines side by side, each pane
+/// ending at the same moment, or merged into one pane in arrival order.
+fn draw_compare(frame: &mut Frame, area: Rect, app: &AppState, compare: &Compare) {
+    let left = app.session.as_ref().map_or("?", Session::target);
+    let right = compare.session.target();
+    let mut scrolled = String::new();
+    if compare.scroll > 0 {
+        scrolled = format!(
+            " [scrolled {} up, {}: follow]",
+            compare.scroll,
+            app.keymap.label(Action::Follow)
+        );
+    }
+    let shown = compare.shown();
+    let block = |title: String| {
+        Block::default()
+            .borders(Borders::ALL)
+            .title(title)
+            .border_style(Style::default().fg(app.theme.border))
+    };
+    if compare.interleave {
+        let title = format!("Compare A: {left} / B: {right}{scrolled}");
+        draw_compared(frame, area, block(title), app, compare, &shown, true);
+        return;
+    }
+    let halves = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(area);
+    for (half, side, name) in [(halves[0], Side::Left, left), (halves[1], Side::Right, right)] {
+        let lines: Vec<&Stamped> = shown.iter().copied().filter(|l| l.side == side).collect();
+        let title = match side {
+            Side::Left => format!("{name}{scrolled}"),
+            Side::Right => name.to_string(),
+        };
+        draw_compared(frame, half, block(title), app, compare, &lines, false);
+    }
+}
+
+/// The last of `lines` that fit, each after its time since comparing began
+/// (and its port, `tagged`), cut at the pane edge so rows stay level.
+fn draw_compared(
+    frame: &mut Frame,
+    area: Rect,
+    block: Block,
+    app: &AppState,
+    compare: &Compare,
+    lines: &[&Stamped],
+    tagged: bool,
+) {
+    let inner = block.inner(area);
+    let skip = lines.len().saturating_sub(inner.height as usize);
+    let rows: Vec<Line> = lines[skip..]
+        .iter()
+        .map(|stamped| {
+            let at = stamped.at.saturating_duration_since(compare.started).as_secs_f64();
+            let mut spans =
+                vec![Span::styled(format!("{at:>9.3} "), Style::default().fg(app.theme.dim))];
+            if tagged {
+                let (tag, color) = match stamped.side {
+                    Side::Left => ("A ", app.theme.rx),
+                    Side::Right => ("B ", app.theme.tx),
+                };
+                spans.push(Span::styled(tag, Style::default().fg(color)));
+            }
+            let direction = Some(lazyserial::Direction::Rx);
+            spans.extend(output_line(app, &stamped.text, direction).spans);
+            clip_line(Line::from(spans), 0, inner.width as usize)
+        })
+        .collect();
+    frame.render_widget(Paragraph::new(Text::from(rows)).block(block), area);
+}
+
+/// Lists decoded packets, newest at the bottom, one per row, in the
+/// decoder's columns.
+fn draw_packets(frame: &mut Frame, area: Rect, app: &AppState, monitor: &Monitor) {
+    let theme = &app.theme;
+    let bad = match monitor.invalid() {
+        0 => String::new(),
+        n => format!(", {n} bad"),
+    };
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!("{} [{} packets{bad}]", monitor.title(), monitor.records.len()))
+        .border_style(Style::default().fg(theme.border));
+    let height = block.inner(area).height as usize;
+    let skip = monitor.records.len().saturating_sub(height);
+    let columns = monitor.columns();
+    let rows: Vec<Line> = monitor
+        .records
+        .iter()
+        .skip(skip)
+        .map(|r| {
+            let at = r.at.saturating_duration_since(monitor.started).as_secs_f64();
+            let color = match r.direction {
+                lazyserial::Direction::Rx => theme.rx,
+                lazyserial::Direction::Tx => theme.tx,
+            };
+            let mut spans = vec![
+                Span::styled(format!("{at:>9.3} "), Style::default().fg(theme.dim)),
+                Span::styled(format!("{} ", r.direction.name()), Style::default().fg(color)),
+            ];
+            for (column, field) in columns.iter().zip(&r.fields) {
+                spans.push(Span::raw(format!("{field:<width$} ", width = column.width)));
+            }
+            match r.valid {
+                Some(true) => spans.push(Span::styled("ok  ", Style::default().fg(theme.ok))),
+                Some(false) => spans.push(Span::styled("BAD ", Style::default().fg(theme.error))),
+                None => {}
+            }
+            spans.push(Span::styled(r.details.clone(), Style::default().fg(color)));
+            Line::from(spans)
+        })
+        .collect();
+    frame.render_widget(Paragraph::new(Text::from(rows)).block(block), area);
+}
+
+/// Colors of the plotted series, in order.
+const SERIES_COLORS: [Color; plot::MAX_SERIES] = [
+    Color::Cyan,
+    Color::Yellow,
+    Color::Magenta,
+    Color::Green,
+    Color::Red,
+    Color::LightBlue,
+    Color::LightYellow,
+    Color::White,
+];
+
+fn draw_plot(frame: &mut Frame, area: Rect, app: &AppState, plotter: &Plotter) {
+    let theme = &app.theme;
+    let visible = plotter.visible();
+    let (mut min, mut max) = (f64::INFINITY, f64::NEG_INFINITY);
+    for (_, points) in &visible {
+        for &(_, y) in points {
+            min = min.min(y);
+            max = max.max(y);
+        }
+    }
+    if min > max {
+        (min, max) = (0.0, 1.0);
+    }
+    // Leave a margin, and open up flat lines so they don't sit on an edge.
+    let pad = if max > min { (max - min) * 0.05 } else { min.abs().max(1.0) * 0.5 };
+    let (min, max) = (min - pad, max + pad);
+
+    let datasets: Vec<Dataset> = visible
+        .iter()
+        .zip(SERIES_COLORS)
+        .map(|((name, points), color)| {
+            Dataset::default()
+                .name(name.to_string())
+                .marker(symbols::Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(color))
+                .data(points)
+        })
+        .collect();
+    let [start, end] = plotter.x_bounds();
+    let label = |v: f64| Span::styled(format!("{v:.2}"), Style::default().fg(theme.dim));
+    let title = if plotter.is_paused() { "Plot [paused]" } else { "Plot" };
+    let chart = Chart::new(datasets)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(title)
+                .border_style(Style::default().fg(theme.border)),
+        )
+        .x_axis(Axis::default().bounds([start, end]))
+        .y_axis(
+            Axis::default()
+                .bounds([min, max])
+                .labels(vec![label(min), label((min + max) / 2.0), label(max)]),
+        )
+        .hidden_legend_constraints((Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)));
+    frame.render_widget(chart, area);
+}
+
+/// Hard-wraps a styled line at `width` display columns.
+fn wrap_line(line: Line<'static>, width: usize) -> Vec<Line<'static>> {
+    if width == 0 {
+        return vec![line];
+    }
+    let mut rows = vec![Vec::new()];
+    let mut col = 0;
+    for span in line.spans {
+        let mut chunk = String::new();
+        for c in span.content.chars() {
+            let w = unicode_width::UnicodeWidthChar::width(c).unwrap_or(0);
+            if col + w > width && col > 0 {
+                if !chunk.is_empty() {
+                    let text = std::mem::take(&mut chunk);
+                    rows.last_mut().unwrap().push(Span::styled(text, span.style));
+                }
+                rows.push(Vec::new());
+                col = 0;
+            }
+            chunk.push(c);
+            col += w;
+        }
+        if !chunk.is_empty() {
+            rows.last_mut().unwrap().push(Span::styled(chunk, span.style));
+        }
+    }
+    let alignment = line.alignment;
+    rows.into_iter().map(|spans| Line { alignment, ..Line::from(spans) }).collect()
+}
+
+/// The `width` columns of `line` starting `offset` columns in.
+fn clip_line(line: Line<'static>, offset: usize, width: usize) -> Line<'static> {
+    let mut spans = Vec::new();
+    let mut col = 0;
+    for span in line.spans {
+        let mut chunk = String::new();
+        for c in span.content.chars() {
+            let w = c.width().unwrap_or(0);
+            if col >= offset && col + w <= offset + width {
+                chunk.push(c);
+            }
+            col += w;
+        }
+        if !chunk.is_empty() {
+            spans.push(Span::styled(chunk, span.style));
+        }
+        if col >= offset + width {
+            break;
+        }
+    }
+    Line { alignment: line.alignment, ..Line::from(spans) }
+}
+
 fn draw_footer(frame: &mut Frame, area: Rect, app: &AppState) {
-    let title = match app.focus {
+    let theme = &app.theme;
+    let mut title = vec![Span::raw(match app.focus {
         Focus::Ports => "Ports",
         Focus::Output => "Output",
         Focus::Input => "Input",
-    };
-    let block = Block::default().borders(Borders::ALL).title(title);
+    })];
+    if app.at_mode {
+        title.push(Span::raw(" [AT"));
+        match &app.at.state {
+            Some(at::CommandState::Waiting { command, since }) => title.push(Span::styled(
+                format!(": {command} … {}s", since.elapsed().as_secs()),
+                Style::default().fg(theme.busy),
+            )),
+            Some(at::CommandState::Done {
+                command,
+                result,
+                ok,
+                took,
+            }) => title.push(Span::styled(
+                format!(": {command} → {result} in {} ms", took.as_millis()),
+                Style::default().fg(if *ok { theme.ok } else { theme.error }),
+            )),
+            None => {}
+        }
+        title.push(Span::raw("]"));
+    }
+    if app.broadcast.is_some() {
+        let more = app.broadcast_targets().len();
+        title.push(Span::styled(
+            format!(" [broadcast +{more}]"),
+            Style::default().fg(theme.accent),
+        ));
+    }
+    if let Some(running) = &app.shell {
+        title.push(Span::styled(
+            format!(" [$ {}]", running.command),
+            Style::default().fg(theme.busy),
+        ));
+    }
+    if app.input_literal {
+        title.push(Span::styled(" ^V", Style::default().fg(theme.busy)));
+    }
+    let stats = &app.throughput;
+    let mut summary = Line::from(vec![
+        Span::styled(
+            format!(
+                " RX {} ({}/s)",
+                format_bytes(stats.totals.rx_bytes as f64),
+                format_bytes(stats.rx_rate)
+            ),
+            Style::default().fg(theme.rx),
+        ),
+        Span::styled(
+            format!(
+                "  TX {} ({}/s)",
+                format_bytes(stats.totals.tx_bytes as f64),
+                format_bytes(stats.tx_rate)
+            ),
+            Style::default().fg(theme.tx),
+        ),
+        Span::raw(format!("  err {} ", stats.totals.errors)),
+    ]);
+    if let Some(tx) = app.tx_progress.filter(|tx| tx.queued > 0) {
+        // A long send still being written: how far along it is.
+        let total = tx.written + tx.queued as u64;
+        summary.spans.insert(
+            2,
+            Span::styled(
+                format!(
+                    " sending {:.0}% of {}",
+                    tx.written as f64 * 100.0 / total as f64,
+                    format_bytes(total as f64)
+                ),
+                Style::default().fg(theme.busy),
+            ),
+        );
+    }
+    if stats.totals.dropped > 0 {
+        summary.spans.push(Span::styled(
+            format!("dropped {} ", format_bytes(stats.totals.dropped as f64)),
+            Style::default().fg(theme.error),
+        ));
+    }
+    if let Some(errors) = stats.totals.line_errors.filter(|e| e.total() > 0) {
+        // Only the kinds that happened; the status popup lists them all.
+        let counts = line_error_counts(&errors)
+            .into_iter()
+            .filter(|&(_, n)| n > 0)
+            .map(|(name, n)| format!("{name} {n} "))
+            .collect::<String>();
+        summary.spans.push(Span::styled(counts, Style::default().fg(theme.error)));
+    }
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(Line::from(title))
+        .title(block::Title::from(summary).alignment(Alignment::Right))
+        .border_style(Style::default().fg(theme.border));
 
     let style = if app.focus == Focus::Input {
-        Style::default().fg(Color::Yellow)
+        Style::default().fg(theme.label)
     } else {
         Style::default()
     };
-    let p = Paragraph::new(app.input_buffer.as_str()).style(style).block(block);
+    let mut input = input_spans(&app.input_buffer, theme);
+    if app.at_mode && app.focus == Focus::Input {
+        if let Some(rest) = app.at.completion(&app.input_buffer) {
+            input.push(Span::styled(rest, Style::default().fg(theme.dim)));
+        }
+    }
+    // Scroll sideways once the cursor would run past the right border.
+    let column = input_width(&app.input_buffer[..app.input_cursor]);
+    let scroll = column.saturating_sub(area.width.saturating_sub(3));
+    let p = Paragraph::new(Line::from(input))
+        .style(style)
+        .block(block)
+        .scroll((0, scroll));
     frame.render_widget(p, area);
 
+    if app.at_mode && app.focus == Focus::Input {
+        draw_at_suggestions(frame, area, app);
+    }
+
     if app.focus == Focus::Input {
-        let x = area.x + 1 + app.input_buffer.width() as u16;
-        let y = area.y + 1;
-        frame.set_cursor(x, y);
+        frame.set_cursor(area.x + 1 + column - scroll, area.y + 1);
+    }
+}
+
+/// The input line with control characters shown as `^C` in the accent
+/// color, so they neither vanish nor reach the terminal.
+fn input_spans<'a>(text: &'a str, theme: &Theme) -> Vec<Span<'a>> {
+    let mut spans = Vec::new();
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        if let Some(name) = app::caret(c) {
+            if start < i {
+                spans.push(Span::raw(&text[start..i]));
+            }
+            spans.push(Span::styled(format!("^{name}"), Style::default().fg(theme.accent)));
+            start = i + 1;
+        }
+    }
+    if start < text.len() {
+        spans.push(Span::raw(&text[start..]));
+    }
+    spans
+}
+
+/// Columns `text` takes up as drawn by [`input_spans`].
+fn input_width(text: &str) -> u16 {
+    text.chars()
+        .map(|c| if app::caret(c).is_some() { 2 } else { c.width().unwrap_or(0) })
+        .sum::<usize>() as u16
+}
+
+/// AT commands matching the input, in a box just above it.
+fn draw_at_suggestions(frame: &mut Frame, footer: Rect, app: &AppState) {
+    let theme = &app.theme;
+    let suggestions = app.at.suggestions(&app.input_buffer);
+    if suggestions.is_empty() {
+        return;
+    }
+    let width = suggestions
+        .iter()
+        .map(|s| s.command.width() + s.help.width() + 4)
+        .max()
+        .unwrap_or(0)
+        .max(40)
+        .min(footer.width as usize) as u16;
+    let height = (suggestions.len() as u16 + 2).min(footer.y);
+    let area = Rect {
+        x: footer.x,
+        y: footer.y - height,
+        width,
+        height,
+    };
+    let command_width = suggestions.iter().map(|s| s.command.width()).max().unwrap_or(0);
+    let lines: Vec<Line> = suggestions
+        .iter()
+        .map(|s| {
+            Line::from(vec![
+                Span::styled(
+                    format!("{:<command_width$} ", s.command),
+                    Style::default().fg(theme.accent),
+                ),
+                Span::styled(s.help.clone(), Style::default().fg(theme.dim)),
+            ])
+        })
+        .collect();
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title("AT (Right completes, Up/Down history)")
+        .border_style(Style::default().fg(theme.accent));
+    frame.render_widget(Clear, area);
+    frame.render_widget(Paragraph::new(Text::from(lines)).block(block), area);
+}
+
+fn line_error_counts(errors: &LineErrors) -> [(&'static str, u32); 5] {
+    [
+        ("framing", errors.framing),
+        ("parity", errors.parity),
+        ("overrun", errors.overrun),
+        ("break", errors.breaks),
+        ("buffer overrun", errors.buffer_overrun),
+    ]
+}
+
+pub fn format_bytes(n: f64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = n;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{value:.0} {}", UNITS[0])
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+fn draw_prompt(frame: &mut Frame, app: &AppState) {
+    let Some(prompt) = &app.prompt else {
+        return;
+    };
+    let kind = ModalKind::Prompt(prompt.buffer.clone());
+    draw_modal(frame, &prompt.kind.title(), &kind, &app.theme);
+}
+
+/// A centered popup for a message, a yes / no question, a line of text or
+/// a choice from a list, sized to what it holds.
+pub fn draw_modal(frame: &mut Frame, title: &str, kind: &ModalKind, theme: &Theme) {
+    const WIDTH: u16 = 60;
+    let inner_width = usize::from(WIDTH - 2);
+    let (text, hint) = match kind {
+        ModalKind::Message(text) => (text.as_str(), "Enter: close"),
+        ModalKind::Confirm { text, .. } => (text.as_str(), "y / n, Left / Right, Enter"),
+        ModalKind::Select { text, .. } => (text.as_str(), "Up / Down or 1-9, Enter, Esc"),
+        ModalKind::Prompt(_) => ("", ""),
+    };
+    let mut lines: Vec<Line> = text.lines().map(|l| Line::from(l.to_string())).collect();
+    if !lines.is_empty() {
+        lines.push(Line::from(""));
+    }
+    let selected = Style::default().bg(theme.selection).add_modifier(Modifier::BOLD);
+    match kind {
+        ModalKind::Message(_) => {}
+        ModalKind::Confirm { yes, .. } => {
+            let button = |label: &'static str, on: bool| {
+                Span::styled(label, if on { selected } else { Style::default() })
+            };
+            lines.push(Line::from(vec![
+                button(" Yes ", *yes),
+                Span::raw("   "),
+                button(" No ", !*yes),
+            ]));
+        }
+        ModalKind::Select {
+            items,
+            selected: at,
+            ..
+        } => {
+            for (i, item) in items.iter().enumerate() {
+                let style = if i == *at { selected } else { Style::default() };
+                lines.push(Line::styled(format!(" {}. {item} ", i + 1), style));
+            }
+        }
+        ModalKind::Prompt(buffer) => lines.push(Line::from(buffer.clone())),
+    }
+    // Rows once long lines wrap; a prompt's line is cut instead, as the
+    // cursor stays on it.
+    let prompt = matches!(kind, ModalKind::Prompt(_));
+    let rows: usize = if prompt {
+        1
+    } else {
+        lines.iter().map(|l| l.width().max(1).div_ceil(inner_width)).sum()
+    };
+    let area = centered_rect(WIDTH, rows as u16 + 2, frame.size());
+    let mut block = Block::default()
+        .borders(Borders::ALL)
+        .title(title.to_string())
+        .border_style(Style::default().fg(theme.label));
+    if !hint.is_empty() {
+        block = block.title(
+            block::Title::from(Span::styled(format!(" {hint} "), Style::default().fg(theme.dim)))
+                .alignment(Alignment::Right)
+                .position(block::Position::Bottom),
+        );
+    }
+    let mut p = Paragraph::new(Text::from(lines)).block(block);
+    if !prompt {
+        p = p.wrap(Wrap { trim: false });
+    }
+    frame.render_widget(Clear, area);
+    frame.render_widget(p, area);
+
+    if let ModalKind::Prompt(buffer) = kind {
+        let x = area.x + 1 + buffer.width() as u16;
+        frame.set_cursor(x.min(area.right().saturating_sub(2)), area.y + 1);
+    }
+}
+
+fn draw_transfer(frame: &mut Frame, transfer: &Transfer, theme: &Theme) {
+    let area = centered_rect(56, 10, frame.size());
+    let verb = match transfer.direction {
+        transfer::Direction::Send => "Sending",
+        transfer::Direction::Receive => "Receiving",
+    };
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!("{} {} (Esc: cancel)", transfer.protocol.name(), verb))
+        .border_style(Style::default().fg(theme.accent));
+    let inner = block.inner(area);
+    frame.render_widget(Clear, area);
+    frame.render_widget(block, area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(4), Constraint::Length(1)].as_ref())
+        .split(inner);
+
+    let total = transfer
+        .bytes_total
+        .map(|t| format!(" / {}", t))
+        .unwrap_or_default();
+    let lines = vec![
+        Line::from(format!("File:    {}", transfer.file_name)),
+        Line::from(format!("Bytes:   {}{}", transfer.bytes_done, total)),
+        Line::from(format!(
+            "Blocks:  {}   Retries: {}   NAKs: {}",
+            transfer.blocks, transfer.retries, transfer.naks
+        )),
+        Line::from(format!(
+            "Mode:    {}   Elapsed: {:.1}s",
+            if transfer.crc_mode { "CRC-16" } else { "checksum" },
+            transfer.elapsed().as_secs_f64()
+        )),
+    ];
+    frame.render_widget(Paragraph::new(Text::from(lines)), chunks[0]);
+
+    let gauge = match transfer.progress() {
+        Some(ratio) => Gauge::default().ratio(ratio),
+        None => Gauge::default().ratio(0.0).label("waiting..."),
+    }
+    .gauge_style(Style::default().fg(theme.accent));
+    frame.render_widget(gauge, chunks[1]);
+}
+
+fn draw_flash(frame: &mut Frame, flash: &Flasher, theme: &Theme) {
+    let area = centered_rect(56, 8, frame.size());
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title("STM32 bootloader (Esc: cancel)")
+        .border_style(Style::default().fg(theme.accent));
+    let inner = block.inner(area);
+    frame.render_widget(Clear, area);
+    frame.render_widget(block, area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(4), Constraint::Length(1)].as_ref())
+        .split(inner);
+
+    let chip = match (flash.product_id, flash.version) {
+        (Some(pid), Some(v)) => format!("0x{pid:03x}, bootloader {}.{}", v >> 4, v & 0xf),
+        (None, Some(v)) => format!("bootloader {}.{}", v >> 4, v & 0xf),
+        _ => "-".to_string(),
+    };
+    let lines = vec![
+        Line::from(format!("File:    {} at 0x{:08x}", flash.file_name, flash.address)),
+        Line::from(format!("Chip:    {chip}")),
+        Line::from(format!(
+            "Stage:   {}   Elapsed: {:.1}s",
+            flash.stage.name(),
+            flash.elapsed().as_secs_f64()
+        )),
+        Line::from(format!("Bytes:   {} / {}", flash.bytes_done, flash.image_len())),
+    ];
+    frame.render_widget(Paragraph::new(Text::from(lines)), chunks[0]);
+
+    let gauge = match flash.progress() {
+        Some(ratio) => Gauge::default()
+            .ratio(ratio)
+            .label(format!("{} {:.0}%", flash.stage.name(), ratio * 100.0)),
+        None => Gauge::default().ratio(0.0).label(format!("{}...", flash.stage.name())),
     }
+    .gauge_style(Style::default().fg(theme.accent));
+    frame.render_widget(gauge, chunks[1]);
 }
 
+fn draw_auto_responses(frame: &mut Frame, app: &AppState, selected: usize) {
+    let theme = &app.theme;
+    let responder = &app.auto_responder;
+    let area = centered_rect(80, 14, frame.size());
+    let title = format!(
+        "Auto-responses [{}]  Space:toggle  a:all  Esc:close",
+        if responder.enabled { "on" } else { "off" }
+    );
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(title)
+        .border_style(Style::default().fg(theme.accent));
 
+    let items: Vec<ListItem> = if responder.rules.is_empty() {
+        vec![ListItem::new("no [[auto_response]] rules in config.toml")]
+    } else {
+        responder
+            .rules
+            .iter()
+            .map(|r| {
+                let pattern = match &r.matcher {
+                    Matcher::Substring(s) => format!("\"{}\"", s),
+                    Matcher::Regex(re) => format!("/{}/", re.as_str()),
+                };
+                let style = if r.enabled && responder.enabled {
+                    Style::default()
+                } else {
+                    Style::default().fg(theme.dim)
+                };
+                ListItem::new(Line::from(vec![
+                    Span::styled(
+                        format!("[{}] {:<12} ", if r.enabled { "x" } else { " " }, r.name),
+                        style,
+                    ),
+                    Span::styled(pattern, style.fg(theme.label)),
+                    Span::styled(
+                        format!(" -> {} ({} ms)", r.response.escape_debug(), r.delay.as_millis()),
+                        style,
+                    ),
+                ]))
+            })
+            .collect()
+    };
+
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(Style::default().bg(theme.selection));
+    let mut state = ListState::default();
+    if !responder.rules.is_empty() {
+        state.select(Some(selected));
+    }
+    frame.render_widget(Clear, area);
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+fn draw_macros(frame: &mut Frame, app: &AppState, selected: usize) {
+    let theme = &app.theme;
+    let area = centered_rect(70, macros::MACRO_COUNT as u16 + 2, frame.size());
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title("Macros  F1-F12:send  Enter:edit  Del:clear  r:repeat  Esc:close")
+        .border_style(Style::default().fg(theme.accent));
+    let items: Vec<ListItem> = (0..macros::MACRO_COUNT)
+        .map(|i| {
+            let (text, style) = match app.macros.get(i) {
+                Some(t) => (macros::escape(t), Style::default()),
+                None => ("(unbound)".to_string(), Style::default().fg(theme.dim)),
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(
+                    format!("{:<4}", macros::key_name(i)),
+                    Style::default().fg(theme.label),
+                ),
+                Span::styled(text, style),
+            ]))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(Style::default().bg(theme.selection));
+    let mut state = ListState::default();
+    state.select(Some(selected));
+    frame.render_widget(Clear, area);
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+fn draw_compose(frame: &mut Frame, compose: &Compose, theme: &Theme) {
+    let area = centered_rect(90, 20, frame.size());
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title("Compose  ^S:send  ^L:send line by line  Esc:close (keeps the text)")
+        .title(
+            block::Title::from(format!(" {}/{} ", compose.row + 1, compose.lines.len()))
+                .alignment(Alignment::Right),
+        )
+        .border_style(Style::default().fg(theme.accent));
+    let inner = block.inner(area);
+    // Scroll just enough to keep the cursor inside the box.
+    let column = compose.lines[compose.row][..compose.col].width() as u16;
+    let top = (compose.row as u16).saturating_sub(inner.height.saturating_sub(1));
+    let left = column.saturating_sub(inner.width.saturating_sub(1));
+    let lines: Vec<Line> = compose.lines.iter().map(|l| Line::from(l.as_str())).collect();
+    frame.render_widget(Clear, area);
+    frame.render_widget(Paragraph::new(lines).block(block).scroll((top, left)), area);
+    frame.set_cursor(inner.x + column - left, inner.y + compose.row as u16 - top);
+}
+
+fn draw_checksum(frame: &mut Frame, input: &str, theme: &Theme) {
+    let area = centered_rect(64, Algorithm::ALL.len() as u16 + 6, frame.size());
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title("Checksum calculator  hex bytes or \"text\"  Esc:close")
+        .border_style(Style::default().fg(theme.accent));
+    let inner = block.inner(area);
+    let label = Style::default().fg(theme.label);
+    let mut lines = vec![Line::from(vec![Span::styled("> ", label), Span::raw(input)])];
+    match checksum::parse_input(input) {
+        Ok(data) => {
+            let dim = Style::default().fg(theme.dim);
+            lines.push(Line::styled(format!("{} bytes", data.len()), dim));
+            lines.push(Line::default());
+            for algorithm in Algorithm::ALL {
+                let value = algorithm.compute(&data);
+                lines.push(Line::from(vec![
+                    Span::styled(format!("{:<14}", algorithm.name()), label),
+                    Span::raw(format!(
+                        "0x{value:0width$x}  {:<10}  sent as {}",
+                        value,
+                        hexdump::hex(&algorithm.bytes(&data)),
+                        width = algorithm.width() * 2
+                    )),
+                ]));
+            }
+        }
+        Err(e) => lines.push(Line::styled(e, Style::default().fg(theme.error))),
+    }
+    frame.render_widget(Clear, area);
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+    frame.set_cursor(inner.x + 2 + input.width() as u16, inner.y);
+}
+
+/// Toasts stacked in the top right corner of `area`, newest at the bottom.
+fn draw_toasts(frame: &mut Frame, area: Rect, app: &AppState) {
+    let theme = &app.theme;
+    let toasts: Vec<_> = app.toasts.shown(Instant::now()).collect();
+    if toasts.is_empty() {
+        return;
+    }
+    let max_width = area.width.saturating_sub(4).min(60) as usize;
+    let width = toasts.iter().map(|t| t.text.width() + 2).max().unwrap_or(0).min(max_width);
+    let errors = toasts.iter().any(|t| t.level == Level::Error);
+    let rect = Rect {
+        x: area.right().saturating_sub(width as u16 + 3),
+        y: area.y + 1,
+        width: (width as u16 + 2).min(area.width),
+        height: (toasts.len() as u16 + 2).min(area.height.saturating_sub(1)),
+    };
+    let lines: Vec<Line> = toasts
+        .iter()
+        .map(|t| {
+            let (mark, color) = match t.level {
+                Level::Error => ("! ", theme.error),
+                Level::Info => ("· ", theme.ok),
+            };
+            let text: String = format!("{mark}{}", t.text).chars().take(width).collect();
+            Line::styled(text, Style::default().fg(color))
+        })
+        .collect();
+    let mut block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(if errors { theme.error } else { theme.border }));
+    if errors {
+        let hint = format!(" {}: errors ", app.keymap.label(Action::Errors));
+        block = block.title(
+            block::Title::from(Span::styled(hint, Style::default().fg(theme.dim)))
+                .alignment(Alignment::Right)
+                .position(block::Position::Bottom),
+        );
+    }
+    frame.render_widget(Clear, rect);
+    frame.render_widget(Paragraph::new(Text::from(lines)).block(block), rect);
+}
+
+/// Why the last open failed and what might fix it, over the output until
+/// it is retried or dismissed.
+fn draw_open_error(frame: &mut Frame, area: Rect, app: &AppState, err: &OpenError) {
+    let theme = &app.theme;
+    let mut lines = vec![
+        Line::styled(err.message.clone(), Style::default().fg(theme.error)),
+        Line::default(),
+    ];
+    lines.extend(err.hints().into_iter().map(Line::raw));
+    let width = area.width.saturating_sub(4).min(70);
+    let inner = width.saturating_sub(2).max(1) as usize;
+    let rows: usize = lines.iter().map(|l| l.width().max(1).div_ceil(inner)).sum();
+    let height = (rows as u16 + 2).min(area.height);
+    let rect = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    };
+    let hint = format!(
+        " {}: retry  {}: dismiss ",
+        app.keymap.label(Action::Retry),
+        app.keymap.label(Action::Cancel)
+    );
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme.error))
+        .title(format!(" Couldn't open {} ", err.target))
+        .title(
+            block::Title::from(Span::styled(hint, Style::default().fg(theme.dim)))
+                .alignment(Alignment::Right)
+                .position(block::Position::Bottom),
+        );
+    frame.render_widget(Clear, rect);
+    frame.render_widget(
+        Paragraph::new(Text::from(lines)).wrap(Wrap { trim: false }).block(block),
+        rect,
+    );
+}
+
+/// Rows of errors the error history shows at once.
+pub const ERROR_VIEW_ROWS: usize = 20;
+
+/// The error history, `back` errors scrolled up from the newest.
+fn draw_error_view(frame: &mut Frame, toasts: &Toasts, back: usize, theme: &Theme) {
+    let area = centered_rect(100, ERROR_VIEW_ROWS as u16 + 2, frame.size());
+    let count = toasts.errors.len();
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!("Errors [{count}]  Up/Down: scroll  c: clear  Esc: close"))
+        .border_style(Style::default().fg(theme.accent));
+    let end = count.saturating_sub(back);
+    let start = end.saturating_sub(ERROR_VIEW_ROWS);
+    let mut lines: Vec<Line> = toasts
+        .errors
+        .range(start..end)
+        .map(|(at, text)| {
+            let at = format!("{}  ", export::iso8601(*at));
+            Line::from(vec![
+                Span::styled(at, Style::default().fg(theme.dim)),
+                Span::styled(text.clone(), Style::default().fg(theme.error)),
+            ])
+        })
+        .collect();
+    if lines.is_empty() {
+        lines.push(Line::styled("no errors", Style::default().fg(theme.dim)));
+    }
+    frame.render_widget(Clear, area);
+    frame.render_widget(Paragraph::new(Text::from(lines)).block(block), area);
+}
+
+fn draw_ping(frame: &mut Frame, ping: &Ping, theme: &Theme) {
+    let area = centered_rect(60, 11, frame.size());
+    let state = if ping.done() { "done" } else { "running" };
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!("Ping {}  {state}  Esc:close", ping.label))
+        .border_style(Style::default().fg(theme.accent));
+    let label = Style::default().fg(theme.label);
+    let ms = |d| format!("{} ms", ping::millis(d));
+    let mut lines = vec![
+        Line::from(vec![
+            Span::styled(format!("{:<10}", "probes"), label),
+            Span::raw(format!("{} of {}", ping.finished(), ping.count)),
+        ]),
+        Line::from(vec![
+            Span::styled(format!("{:<10}", "answered"), label),
+            Span::raw(ping.rtts.len().to_string()),
+        ]),
+        Line::from(vec![
+            Span::styled(format!("{:<10}", "lost"), label),
+            Span::styled(
+                ping.lost.to_string(),
+                Style::default().fg(if ping.lost > 0 { theme.error } else { theme.text }),
+            ),
+        ]),
+        Line::default(),
+    ];
+    match ping.stats() {
+        Some(stats) => {
+            for (name, value) in [
+                ("min", stats.min),
+                ("avg", stats.avg),
+                ("max", stats.max),
+                ("jitter", stats.jitter),
+            ] {
+                lines.push(Line::from(vec![
+                    Span::styled(format!("{name:<10}"), label),
+                    Span::raw(ms(value)),
+                ]));
+            }
+        }
+        None => lines.push(Line::styled("no answer yet", Style::default().fg(theme.dim))),
+    }
+    frame.render_widget(Clear, area);
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+fn draw_link_test(frame: &mut Frame, test: &LinkTest, theme: &Theme) {
+    let area = centered_rect(60, 12, frame.size());
+    let state = if test.is_finished() { "done" } else { "running" };
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!("Link test {}  {state}  Esc:close", test.pattern.name()))
+        .border_style(Style::default().fg(theme.accent));
+    let label = Style::default().fg(theme.label);
+    let report = test.report();
+    let count = |n: usize| {
+        let color = if n > 0 { theme.error } else { theme.text };
+        Span::styled(n.to_string(), Style::default().fg(color))
+    };
+    let ber = match report.bit_error_rate() {
+        Some(ber) => format!("{ber:.2e}"),
+        None => "-".to_string(),
+    };
+    let rows = [
+        ("sent", Span::raw(format!("{} of {} bytes", report.sent, test.total))),
+        ("received", Span::raw(format!("{} bytes", report.received))),
+        ("rate", Span::raw(format!("{}/s", format_bytes(test.throughput())))),
+        ("bit errors", count(report.bit_errors as usize)),
+        ("BER", Span::raw(ber)),
+        ("bad bytes", count(report.bad_bytes)),
+        ("dropped", count(report.dropped)),
+        ("extra", count(report.extra)),
+    ];
+    let mut lines: Vec<Line> = rows
+        .into_iter()
+        .map(|(name, value)| Line::from(vec![Span::styled(format!("{name:<12}"), label), value]))
+        .collect();
+    if !test.is_finished() && report.received == 0 && report.sent > 0 {
+        lines.push(Line::default());
+        lines.push(Line::styled(
+            "nothing back yet: is TX looped to RX?",
+            Style::default().fg(theme.dim),
+        ));
+    }
+    frame.render_widget(Clear, area);
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+fn draw_inspector(frame: &mut Frame, inspector: &Inspector, theme: &Theme) {
+    let area = centered_rect(66, 15, frame.size());
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title("Byte inspector  Left/Right:byte  Up/Down:row  Esc:close")
+        .border_style(Style::default().fg(theme.accent));
+    let label = Style::default().fg(theme.label);
+    let dim = Style::default().fg(theme.dim);
+    let end = (inspector.offset + 8).min(inspector.bytes.len());
+    let mut lines = vec![
+        Line::from(vec![
+            Span::styled(format!("offset 0x{:04x}", inspector.offset), label),
+            Span::styled(format!(" of {} bytes  ", inspector.bytes.len()), dim),
+            Span::raw(hexdump::hex(&inspector.bytes[inspector.offset..end])),
+        ]),
+        Line::default(),
+        Line::styled(format!("{:<6}{:<29}big-endian", "", "little-endian"), dim),
+    ];
+    for (name, le, be) in inspector.values() {
+        lines.push(Line::from(vec![
+            Span::styled(format!("{name:<6}"), label),
+            Span::raw(format!("{le:<29}{}", be.unwrap_or_default())),
+        ]));
+    }
+    frame.render_widget(Clear, area);
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+fn draw_highlights(frame: &mut Frame, app: &AppState, selected: usize) {
+    let theme = &app.theme;
+    let rules = &app.highlighter.rules;
+    let area = centered_rect(80, 14, frame.size());
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title("Highlights  Space:toggle  a:add  Enter:edit  Del:remove  Esc:close")
+        .border_style(Style::default().fg(theme.accent));
+
+    let items: Vec<ListItem> = if rules.is_empty() {
+        vec![ListItem::new("no highlight rules; press a to add one")]
+    } else {
+        rules
+            .iter()
+            .map(|r| {
+                let (mark, style) = if r.config.enabled {
+                    ("x", Style::default())
+                } else {
+                    (" ", Style::default().fg(theme.dim))
+                };
+                ListItem::new(Line::from(vec![
+                    Span::styled(format!("[{mark}] "), style),
+                    Span::styled(" sample ", r.style),
+                    Span::styled(format!(" {}", r.spec()), style),
+                ]))
+            })
+            .collect()
+    };
+
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(Style::default().bg(theme.selection));
+    let mut state = ListState::default();
+    if !rules.is_empty() {
+        state.select(Some(selected));
+    }
+    frame.render_widget(Clear, area);
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+/// Area of the help overlay on a `screen`-sized terminal.
+pub fn help_area(screen: Rect) -> Rect {
+    centered_rect(90, screen.height.saturating_sub(2), screen)
+}
+
+/// Contents of the help overlay: every binding from the keymap, the fixed
+/// keys, and the current settings.
+pub fn help_lines(app: &AppState) -> Vec<Line<'static>> {
+    let theme = &app.theme;
+    let heading = Style::default().fg(theme.label).add_modifier(Modifier::BOLD);
+    let key_style = Style::default().fg(theme.accent);
+    let entry = |keys: String, text: String| {
+        Line::from(vec![
+            Span::styled(format!("  {:<16}", keys), key_style),
+            Span::raw(text),
+        ])
+    };
+
+    let mut lines = Vec::new();
+    for scope in Scope::ALL {
+        let title = match scope {
+            Scope::Global => "Everywhere",
+            Scope::Ports => "Ports pane",
+            Scope::Output => "Output pane",
+            Scope::Input => "Input pane",
+        };
+        lines.push(Line::styled(title, heading));
+        for info in keymap::ACTIONS.iter().filter(|i| i.scope == scope) {
+            let keys = app.keymap.label(info.action);
+            let keys = if keys.is_empty() { "(unbound)".to_string() } else { keys };
+            lines.push(entry(keys, format!("{} [{}]", info.help, info.name)));
+        }
+        lines.push(Line::default());
+    }
+
+    lines.push(Line::styled("Fixed keys", heading));
+    for (keys, text) in [
+        ("F1-F12", "Send the bound macro"),
+        ("^C", "Quit immediately"),
+        ("M-<key>", "In the Input pane, a global key that is a plain character"),
+        ("Left/Right", "Move the input cursor (^Left/^Right by word, Home/End)"),
+        ("Del ^K", "Delete the character under the cursor / to the end of the line"),
+        ("^A q", "Leave raw terminal mode (^A ^A sends ^A)"),
+        ("Mouse", "Wheel scrolls, click focuses, drag selects output lines"),
+    ] {
+        lines.push(entry(keys.to_string(), text.to_string()));
+    }
+    lines.push(Line::default());
+
+    lines.push(Line::styled("Settings", heading));
+    let port = app
+        .session
+        .as_ref()
+        .map(|s| format!("{} (open)", s.target()))
+        .or_else(|| app.selected_port.map(|i| format!("{} (closed)", app.ports[i].port_name)))
+        .unwrap_or_else(|| "none".to_string());
+    let on_off = |on: bool| if on { "on" } else { "off" }.to_string();
+    let filter = match &app.filter {
+        Some(f) => format!("{} ({})", f.expr(), on_off(f.active)),
+        None => "none".to_string(),
+    };
+    let config = config::config_path()
+        .map(|p| p.display().to_string())
+        .unwrap_or_else(|| "none".to_string());
+    for (name, value) in [
+        ("Port", port),
+        ("Line", format!("{} {}", app.baud_rate, app.parity.frame())),
+        ("Line ending", app.line_ending.name().to_string()),
+        (
+            "RS-485",
+            app.rs485.map_or("off".to_string(), |r| {
+                format!(
+                    "RTS {} on send, {}/{} ms",
+                    if r.rts_on_send { "high" } else { "low" },
+                    r.delay_before.as_millis(),
+                    r.delay_after.as_millis()
+                )
+            }),
+        ),
+        (
+            "Line errors",
+            app.throughput.totals.line_errors.map_or("not reported".to_string(), |e| {
+                let counts: Vec<String> =
+                    line_error_counts(&e).iter().map(|(name, n)| format!("{name} {n}")).collect();
+                counts.join(", ")
+            }),
+        ),
+        ("Checksum", app.checksum.map_or("off", Algorithm::name).to_string()),
+        ("Profile", app.profile.clone().unwrap_or_else(|| "none".to_string())),
+        ("Encoding", app.decoder.encoding().name().to_string()),
+        ("defmt", on_off(app.defmt.is_some())),
+        ("ESP backtraces", on_off(app.esp.is_some())),
+        (
+            "Framing",
+            app.frames
+                .as_ref()
+                .map_or("none", |f| f.framing().name())
+                .to_string(),
+        ),
+        (
+            "Packet decoder",
+            app.packets.as_ref().map_or("off", |m| m.name).to_string(),
+        ),
+        ("NMEA", on_off(app.nmea.is_some())),
+        ("AT mode", on_off(app.at_mode)),
+        ("Plot", on_off(app.plot.is_some())),
+        (
+            "Virtual device",
+            app.virtual_device()
+                .map_or("none".to_string(), |(path, peer)| format!("{path} (peer {peer})")),
+        ),
+        (
+            "Sniff",
+            app.sniff
+                .as_ref()
+                .map_or("off".to_string(), |s| s.session.target().to_string()),
+        ),
+        (
+            "Compare",
+            app.compare
+                .as_ref()
+                .map_or("off".to_string(), |c| c.session.target().to_string()),
+        ),
+        (
+            "Broadcast",
+            app.broadcast.as_ref().map_or("off".to_string(), |_| {
+                let names: Vec<&str> =
+                    app.broadcast_targets().into_iter().map(Session::target).collect();
+                names.join(", ")
+            }),
+        ),
+        (
+            "Recording",
+            app.recorder
+                .as_ref()
+                .map_or("off".to_string(), |r| r.path.display().to_string()),
+        ),
+        ("ANSI", app.ansi_mode.name().to_string()),
+        ("Mouse", on_off(app.mouse)),
+        ("Auto-responses", on_off(app.auto_responder.enabled)),
+        ("Highlights", format!("{} rules", app.highlighter.rules.len())),
+        ("Filter", filter),
+        ("Config", config),
+    ] {
+        lines.push(entry(name.to_string(), value));
+    }
+    lines
+}
+
+fn draw_help(frame: &mut Frame, app: &AppState, scroll: u16) {
+    let theme = &app.theme;
+    let area = help_area(frame.size());
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title("Help  Up/Down/PgUp/PgDn:scroll  Esc:close")
+        .border_style(Style::default().fg(theme.accent));
+    let p = Paragraph::new(help_lines(app)).block(block).scroll((scroll, 0));
+    frame.render_widget(Clear, area);
+    frame.render_widget(p, area);
+}
+
+fn draw_palette(frame: &mut Frame, app: &AppState, palette: &Palette) {
+    let theme = &app.theme;
+    let area = centered_rect(70, 18, frame.size());
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title("Commands  Enter:run  Esc:close")
+        .border_style(Style::default().fg(theme.accent));
+    let inner = block.inner(area);
+    frame.render_widget(Clear, area);
+    frame.render_widget(block, area);
+    if inner.height < 2 {
+        return;
+    }
+    let (query_area, list_area) = (
+        Rect { height: 1, ..inner },
+        Rect {
+            y: inner.y + 1,
+            height: inner.height - 1,
+            ..inner
+        },
+    );
+    let query = Paragraph::new(format!("> {}", palette.query));
+    frame.render_widget(query, query_area);
+    let x = query_area.x + 2 + palette.query.width() as u16;
+    frame.set_cursor(x.min(query_area.right().saturating_sub(1)), query_area.y);
+
+    let entries = palette.entries(&app.keymap, &app.macros);
+    let keys_width = list_area.width.saturating_sub(3) as usize;
+    let items: Vec<ListItem> = entries
+        .iter()
+        .map(|e| {
+            let pad = keys_width.saturating_sub(e.label.width() + e.keys.width());
+            ListItem::new(Line::from(vec![
+                Span::raw(e.label.clone()),
+                Span::raw(" ".repeat(pad)),
+                Span::styled(e.keys.clone(), Style::default().fg(theme.dim)),
+            ]))
+        })
+        .collect();
+    let list = List::new(items)
+        .highlight_style(Style::default().bg(theme.selection))
+        .highlight_symbol("> ");
+    let mut state = ListState::default();
+    if !entries.is_empty() {
+        state.select(Some(palette.selected.min(entries.len() - 1)));
+    }
+    frame.render_stateful_widget(list, list_area, &mut state);
+}
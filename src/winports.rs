@@ -0,0 +1,190 @@
+//! COM port friendly names from SetupAPI, as Device Manager shows them
+//! ("USB Serial Port (COM7)", "Communications Port (COM1)"). The serial
+//! crate only reports them for USB adapters; this covers built-in, PCI and
+//! Bluetooth ports and modems too.
+
+use std::collections::BTreeMap;
+use std::ffi::c_void;
+use std::mem::{size_of, size_of_val};
+use std::ptr;
+
+/// `HDEVINFO` and `HKEY`: pointer-sized handles.
+type Handle = isize;
+
+const INVALID_HANDLE_VALUE: Handle = -1;
+const DIGCF_PRESENT: u32 = 0x2;
+const SPDRP_FRIENDLYNAME: u32 = 0xC;
+const DICS_FLAG_GLOBAL: u32 = 0x1;
+const DIREG_DEV: u32 = 0x1;
+const KEY_READ: u32 = 0x20019;
+const REG_SZ: u32 = 1;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Guid {
+    data1: u32,
+    data2: u16,
+    data3: u16,
+    data4: [u8; 8],
+}
+
+/// `GUID_DEVCLASS_PORTS` and `GUID_DEVCLASS_MODEM`, the classes that own
+/// COM port names.
+const CLASSES: [Guid; 2] = [
+    Guid {
+        data1: 0x4d36e978,
+        data2: 0xe325,
+        data3: 0x11ce,
+        data4: [0xbf, 0xc1, 0x08, 0x00, 0x2b, 0xe1, 0x03, 0x18],
+    },
+    Guid {
+        data1: 0x4d36e96d,
+        data2: 0xe325,
+        data3: 0x11ce,
+        data4: [0xbf, 0xc1, 0x08, 0x00, 0x2b, 0xe1, 0x03, 0x18],
+    },
+];
+
+/// `SP_DEVINFO_DATA`.
+#[repr(C)]
+struct DevInfoData {
+    cb_size: u32,
+    class_guid: Guid,
+    dev_inst: u32,
+    reserved: usize,
+}
+
+#[link(name = "setupapi")]
+extern "system" {
+    fn SetupDiGetClassDevsW(
+        class_guid: *const Guid,
+        enumerator: *const u16,
+        parent: *mut c_void,
+        flags: u32,
+    ) -> Handle;
+    fn SetupDiEnumDeviceInfo(set: Handle, index: u32, data: *mut DevInfoData) -> i32;
+    fn SetupDiGetDeviceRegistryPropertyW(
+        set: Handle,
+        data: *const DevInfoData,
+        property: u32,
+        value_type: *mut u32,
+        buffer: *mut u8,
+        buffer_size: u32,
+        required_size: *mut u32,
+    ) -> i32;
+    fn SetupDiOpenDevRegKey(
+        set: Handle,
+        data: *const DevInfoData,
+        scope: u32,
+        hw_profile: u32,
+        key_type: u32,
+        access: u32,
+    ) -> Handle;
+    fn SetupDiDestroyDeviceInfoList(set: Handle) -> i32;
+}
+
+#[link(name = "advapi32")]
+extern "system" {
+    fn RegQueryValueExW(
+        key: Handle,
+        name: *const u16,
+        reserved: *mut u32,
+        value_type: *mut u32,
+        data: *mut u8,
+        data_len: *mut u32,
+    ) -> i32;
+    fn RegCloseKey(key: Handle) -> i32;
+}
+
+/// Friendly names of the present COM ports, by port name (`COM7`).
+pub fn friendly_names() -> BTreeMap<String, String> {
+    let mut names = BTreeMap::new();
+    for class in &CLASSES {
+        // SAFETY: a valid GUID and null optional arguments; the set is
+        // destroyed below.
+        let set =
+            unsafe { SetupDiGetClassDevsW(class, ptr::null(), ptr::null_mut(), DIGCF_PRESENT) };
+        if set == INVALID_HANDLE_VALUE {
+            continue;
+        }
+        for index in 0.. {
+            let mut data = DevInfoData {
+                cb_size: size_of::<DevInfoData>() as u32,
+                class_guid: *class,
+                dev_inst: 0,
+                reserved: 0,
+            };
+            // SAFETY: `data` has its size set as the call requires.
+            if unsafe { SetupDiEnumDeviceInfo(set, index, &mut data) } == 0 {
+                break;
+            }
+            let port = port_name(set, &data);
+            if let (Some(port), Some(name)) = (port, friendly_name(set, &data)) {
+                names.insert(port, name);
+            }
+        }
+        // SAFETY: `set` came from SetupDiGetClassDevsW and is not used again.
+        unsafe { SetupDiDestroyDeviceInfoList(set) };
+    }
+    names
+}
+
+/// The device's `PortName` registry value, e.g. `COM7`.
+fn port_name(set: Handle, data: &DevInfoData) -> Option<String> {
+    // SAFETY: `set` and `data` describe an enumerated device; the key is
+    // closed below.
+    let key =
+        unsafe { SetupDiOpenDevRegKey(set, data, DICS_FLAG_GLOBAL, 0, DIREG_DEV, KEY_READ) };
+    if key == INVALID_HANDLE_VALUE {
+        return None;
+    }
+    let value: Vec<u16> = "PortName\0".encode_utf16().collect();
+    let mut buffer = [0u16; 64];
+    let mut len = size_of_val(&buffer) as u32;
+    let mut value_type = 0;
+    // SAFETY: `len` is the buffer's size in bytes and the name is
+    // NUL-terminated.
+    let status = unsafe {
+        RegQueryValueExW(
+            key,
+            value.as_ptr(),
+            ptr::null_mut(),
+            &mut value_type,
+            buffer.as_mut_ptr().cast(),
+            &mut len,
+        )
+    };
+    // SAFETY: opened above and not used again.
+    unsafe { RegCloseKey(key) };
+    if status != 0 || value_type != REG_SZ {
+        return None;
+    }
+    text(&buffer[..(len as usize / 2).min(buffer.len())])
+}
+
+fn friendly_name(set: Handle, data: &DevInfoData) -> Option<String> {
+    let mut buffer = [0u16; 256];
+    // SAFETY: the size passed is the buffer's in bytes.
+    let ok = unsafe {
+        SetupDiGetDeviceRegistryPropertyW(
+            set,
+            data,
+            SPDRP_FRIENDLYNAME,
+            ptr::null_mut(),
+            buffer.as_mut_ptr().cast(),
+            size_of_val(&buffer) as u32,
+            ptr::null_mut(),
+        )
+    };
+    if ok == 0 {
+        return None;
+    }
+    text(&buffer)
+}
+
+/// UTF-16 up to the first NUL; `None` if empty.
+fn text(wide: &[u16]) -> Option<String> {
+    let end = wide.iter().position(|&c| c == 0).unwrap_or(wide.len());
+    let text = String::from_utf16_lossy(&wide[..end]);
+    (!text.is_empty()).then_some(text)
+}
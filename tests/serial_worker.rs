@@ -0,0 +1,388 @@
+//! Drives the serial worker end to end through `Session`, with the mock
+//! transport standing in for a port.
+
+use std::io;
+use std::time::{Duration, Instant};
+
+use lazyserial::mock::{MockDevice, MockTransport};
+use lazyserial::{
+    IoStats, LineStep, ModemLines, OpenFailure, Overflow, SerialEvent, Session, TxProgress,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn open() -> (Session, MockDevice) {
+    open_with(None)
+}
+
+/// Modem lines must be set before opening: the worker stops polling them
+/// after the first failure.
+fn open_with(lines: Option<ModemLines>) -> (Session, MockDevice) {
+    let (transport, device) = MockTransport::pair();
+    device.set_modem_lines(lines);
+    let session = Session::with_transport("mock", transport).unwrap();
+    match session.recv_timeout(TIMEOUT) {
+        Some(SerialEvent::Opened) => {}
+        other => panic!("expected Opened, got {other:?}"),
+    }
+    (session, device)
+}
+
+/// Events up to and including the first one `done` accepts.
+fn events_until(session: &Session, done: impl Fn(&SerialEvent) -> bool) -> Vec<SerialEvent> {
+    let deadline = Instant::now() + TIMEOUT;
+    let mut events = Vec::new();
+    while Instant::now() < deadline {
+        if let Some(event) = session.recv_timeout(Duration::from_millis(50)) {
+            let finished = done(&event);
+            events.push(event);
+            if finished {
+                return events;
+            }
+        }
+    }
+    panic!("timed out; events so far: {events:?}");
+}
+
+/// Received bytes until `len` have arrived.
+fn read_data(session: &Session, len: usize) -> Vec<u8> {
+    let deadline = Instant::now() + TIMEOUT;
+    let mut data = Vec::new();
+    while data.len() < len {
+        assert!(Instant::now() < deadline, "got {} of {len} bytes", data.len());
+        if let Some(SerialEvent::Data(bytes)) = session.recv_timeout(Duration::from_millis(50)) {
+            data.extend(bytes);
+        }
+    }
+    data
+}
+
+fn last_stats(events: &[SerialEvent]) -> IoStats {
+    events
+        .iter()
+        .rev()
+        .find_map(|e| match e {
+            SerialEvent::Stats(stats) => Some(*stats),
+            _ => None,
+        })
+        .expect("no stats event")
+}
+
+fn is_closed(event: &SerialEvent) -> bool {
+    matches!(event, SerialEvent::Closed)
+}
+
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 31 % 251) as u8).collect()
+}
+
+#[test]
+fn reads_writes_and_closes() {
+    let (session, device) = open();
+    device.send(b"hello\r\n");
+    assert_eq!(read_data(&session, 7), b"hello\r\n");
+
+    session.handle().write(b"version\r\n".to_vec()).unwrap();
+    assert_eq!(device.received(9, TIMEOUT), b"version\r\n");
+
+    session.close().unwrap();
+    let events = events_until(&session, is_closed);
+    let stats = last_stats(&events);
+    assert_eq!((stats.rx_bytes, stats.tx_bytes), (7, 9));
+    assert!(session.recv_timeout(Duration::from_millis(100)).is_none());
+}
+
+#[test]
+fn sets_control_lines_and_reports_modem_status() {
+    let lines = ModemLines {
+        cts: true,
+        dsr: false,
+        cd: true,
+        ri: false,
+    };
+    let (session, device) = open_with(Some(lines));
+    let events = events_until(&session, |e| matches!(e, SerialEvent::ModemStatus(_)));
+    assert!(matches!(events.last(), Some(SerialEvent::ModemStatus(l)) if *l == lines));
+
+    session.handle().set_dtr(true).unwrap();
+    session.handle().set_rts(true).unwrap();
+    // Commands are handled in order, so once this write arrives both lines
+    // are set.
+    session.handle().write(b"x".to_vec()).unwrap();
+    device.received(1, TIMEOUT);
+    assert!(device.dtr() && device.rts());
+    device.set_modem_lines(Some(ModemLines { ri: true, ..lines }));
+    let events = events_until(&session, |e| matches!(e, SerialEvent::ModemStatus(_)));
+    assert!(matches!(events.last(), Some(SerialEvent::ModemStatus(l)) if l.ri));
+
+    session.handle().set_dtr(false).unwrap();
+    session.handle().write(b"x".to_vec()).unwrap();
+    device.received(1, TIMEOUT);
+    assert!(!device.dtr() && device.rts());
+}
+
+#[test]
+fn reports_missing_modem_lines_once() {
+    let (session, device) = open();
+    let events = events_until(&session, |e| matches!(e, SerialEvent::Error(_)));
+    assert!(
+        matches!(events.last(), Some(SerialEvent::Error(msg)) if msg.starts_with("modem status"))
+    );
+    // Another poll interval passes without a repeat.
+    device.send(b"ok");
+    std::thread::sleep(Duration::from_millis(300));
+    session.close().unwrap();
+    let events = events_until(&session, is_closed);
+    assert!(!events.iter().any(|e| matches!(e, SerialEvent::Error(_))));
+}
+
+#[test]
+fn disconnect_closes_the_session() {
+    let (session, device) = open();
+    device.send(b"bye");
+    device.disconnect();
+    let events = events_until(&session, is_closed);
+    assert!(events
+        .iter()
+        .any(|e| matches!(e, SerialEvent::Error(msg) if msg == "connection closed")));
+    assert_eq!(last_stats(&events).rx_bytes, 3);
+}
+
+#[test]
+fn read_error_closes_the_session() {
+    let (session, device) = open();
+    device.fail_read(io::ErrorKind::BrokenPipe);
+    let events = events_until(&session, is_closed);
+    assert!(events
+        .iter()
+        .any(|e| matches!(e, SerialEvent::Error(msg) if msg.starts_with("read error"))));
+    assert_eq!(last_stats(&events).errors, 1);
+}
+
+#[test]
+fn write_error_is_reported_and_the_session_continues() {
+    let (session, device) = open();
+    device.fail_writes(true);
+    session.handle().write(b"lost".to_vec()).unwrap();
+    let events = events_until(&session, |e| {
+        matches!(e, SerialEvent::Error(msg) if msg.starts_with("write error"))
+    });
+    assert!(!events.iter().any(is_closed));
+
+    device.fail_writes(false);
+    session.handle().write(b"kept".to_vec()).unwrap();
+    assert_eq!(device.received(4, TIMEOUT), b"kept");
+    session.close().unwrap();
+    let stats = last_stats(&events_until(&session, is_closed));
+    assert_eq!((stats.tx_bytes, stats.errors), (4, 1));
+}
+
+#[test]
+fn splits_chunks_larger_than_the_read_buffer() {
+    let (session, device) = open();
+    let data = pattern(100_000);
+    device.send(&data);
+    assert_eq!(read_data(&session, data.len()), data);
+}
+
+#[test]
+fn high_throughput_both_ways() {
+    const TOTAL: usize = 4 * 1024 * 1024;
+    let (session, device) = open();
+    let data = pattern(TOTAL);
+
+    for chunk in data.chunks(4096) {
+        device.send(chunk);
+    }
+    assert_eq!(read_data(&session, TOTAL), data);
+
+    for chunk in data.chunks(1000) {
+        session.handle().write(chunk.to_vec()).unwrap();
+    }
+    assert_eq!(device.received(TOTAL, TIMEOUT), data);
+
+    session.close().unwrap();
+    let stats = last_stats(&events_until(&session, is_closed));
+    assert_eq!((stats.rx_bytes, stats.tx_bytes), (TOTAL as u64, TOTAL as u64));
+}
+
+#[test]
+fn failing_to_open_reports_and_closes() {
+    let session = Session::open("/nonexistent/lazyserial-test", 115_200).unwrap();
+    let events = events_until(&session, is_closed);
+    assert!(matches!(&events[0], SerialEvent::Error(msg) if msg.starts_with("failed to open")));
+    assert!(matches!(&events[1], SerialEvent::OpenFailed(OpenFailure::NotFound, _)));
+}
+
+#[cfg(unix)]
+#[test]
+fn virtual_device_echoes_through_a_real_port() {
+    let device = lazyserial::loopback::VirtualDevice::create().unwrap();
+    let session = Session::open(device.path(), 115_200).unwrap();
+    events_until(&session, |e| matches!(e, SerialEvent::Opened));
+    session.handle().write(b"ping\n".to_vec()).unwrap();
+    assert_eq!(read_data(&session, 5), b"ping\n");
+}
+
+#[test]
+fn sends_a_break() {
+    let (session, device) = open();
+    session.handle().write(b"sync".to_vec()).unwrap();
+    session.handle().send_break(Duration::from_millis(25)).unwrap();
+    // Commands are handled in order, so the break is done once "x" arrives.
+    session.handle().write(b"x".to_vec()).unwrap();
+    assert_eq!(device.received(5, TIMEOUT), b"syncx");
+    assert_eq!(device.breaks(), [Duration::from_millis(25)]);
+}
+
+#[test]
+fn runs_a_line_sequence_while_reading() {
+    let (session, device) = open();
+    let hold = Duration::from_millis(300);
+    let steps = vec![
+        LineStep {
+            dtr: Some(true),
+            rts: Some(true),
+            hold,
+        },
+        LineStep {
+            dtr: Some(false),
+            ..LineStep::default()
+        },
+    ];
+    session.handle().run_sequence(steps).unwrap();
+    let wait_for = |done: &dyn Fn() -> bool| {
+        let deadline = Instant::now() + TIMEOUT;
+        while !done() {
+            assert!(Instant::now() < deadline, "line sequence stalled");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    };
+    wait_for(&|| device.dtr());
+    let started = Instant::now();
+    device.send(b"boot");
+    assert_eq!(read_data(&session, 4), b"boot");
+    assert!(started.elapsed() < hold, "read waited for the sequence");
+    wait_for(&|| !device.dtr());
+    assert!(started.elapsed() >= hold - Duration::from_millis(50));
+    assert!(device.rts());
+}
+
+#[test]
+fn wakes_the_caller_for_each_event() {
+    let (session, device) = open();
+    let (tx, rx) = std::sync::mpsc::channel();
+    session.set_wake(move || {
+        let _ = tx.send(());
+    });
+    device.send(b"ping");
+    // Only woken, never polled: every event must come with a wake.
+    let mut data = Vec::new();
+    while data.len() < 4 {
+        rx.recv_timeout(TIMEOUT).expect("no wake for received data");
+        while let Some(event) = session.try_event() {
+            if let SerialEvent::Data(bytes) = event {
+                data.extend(bytes);
+            }
+        }
+    }
+    assert_eq!(data, b"ping");
+
+    session.close().unwrap();
+    rx.recv_timeout(TIMEOUT).expect("no wake on close");
+    events_until(&session, is_closed);
+}
+
+#[test]
+fn drops_data_the_caller_falls_behind_on() {
+    let (session, device) = open();
+    session.set_overflow(10, Overflow::DropOldest);
+    device.send(&pattern(30));
+    std::thread::sleep(Duration::from_millis(100));
+    session.close().unwrap();
+    let events = events_until(&session, is_closed);
+    let data: Vec<u8> = events
+        .iter()
+        .filter_map(|e| match e {
+            SerialEvent::Data(bytes) => Some(bytes.clone()),
+            _ => None,
+        })
+        .flatten()
+        .collect();
+    assert_eq!(data, pattern(30)[20..]);
+    assert_eq!(last_stats(&events).dropped, 20);
+
+    let (session, device) = open();
+    session.set_overflow(10, Overflow::DropNewest);
+    device.send(b"0123456789");
+    device.send(b"dropped");
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(read_data(&session, 10), b"0123456789");
+    device.send(b"kept");
+    assert_eq!(read_data(&session, 4), b"kept");
+    session.close().unwrap();
+    assert_eq!(last_stats(&events_until(&session, is_closed)).dropped, 7);
+}
+
+#[test]
+fn pauses_reading_with_rts_off_until_caught_up() {
+    let (session, device) = open();
+    let wait_for = |done: &dyn Fn() -> bool| {
+        let deadline = Instant::now() + TIMEOUT;
+        while !done() {
+            assert!(Instant::now() < deadline, "timed out");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    };
+    session.set_overflow(10, Overflow::Pause);
+    session.handle().set_rts(true).unwrap();
+    wait_for(&|| device.rts());
+    device.send(&pattern(16));
+    wait_for(&|| !device.rts());
+    // Not read while paused, so nothing is lost.
+    device.send(&pattern(8));
+    assert_eq!(read_data(&session, 16), pattern(16));
+    wait_for(&|| device.rts());
+    assert_eq!(read_data(&session, 8), pattern(8));
+    session.close().unwrap();
+    assert_eq!(last_stats(&events_until(&session, is_closed)).dropped, 0);
+}
+
+#[test]
+fn reports_write_progress_and_completion() {
+    let (session, device) = open();
+    let data = pattern(3000);
+    session.handle().write(data.clone()).unwrap();
+    let events = events_until(&session, |e| matches!(e, SerialEvent::TxDone));
+    assert_eq!(device.received(3000, TIMEOUT), data);
+    assert_eq!(session.handle().queued(), 0);
+    let progress: Vec<TxProgress> = events
+        .iter()
+        .filter_map(|e| match e {
+            SerialEvent::TxProgress(progress) => Some(*progress),
+            _ => None,
+        })
+        .collect();
+    assert!(progress.len() > 1, "one step for the whole write: {progress:?}");
+    assert!(progress.iter().all(|p| p.written + p.queued as u64 == 3000));
+    assert_eq!(progress.last().map(|p| p.queued), Some(0));
+}
+
+#[cfg(unix)]
+#[test]
+fn refuses_a_port_that_is_already_open() {
+    let device = lazyserial::loopback::VirtualDevice::create().unwrap();
+    let first = Session::open(device.path(), 115_200).unwrap();
+    events_until(&first, |e| matches!(e, SerialEvent::Opened));
+    let second = Session::open(device.path(), 115_200).unwrap();
+    let events = events_until(&second, is_closed);
+    assert!(
+        events.iter().any(|e| matches!(e, SerialEvent::Error(e) if e.contains("already open"))),
+        "{events:?}"
+    );
+    first.close().unwrap();
+    events_until(&first, is_closed);
+    // The lock went with the port.
+    let third = Session::open(device.path(), 115_200).unwrap();
+    events_until(&third, |e| matches!(e, SerialEvent::Opened));
+}